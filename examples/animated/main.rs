@@ -5,26 +5,33 @@ use std::path::Path;
 
 use egui::{FontId, RichText};
 use glam::{Vec2, Vec3, Vec4};
-use hecs::{Entity, World};
+use hecs::World;
 use rccell::RcCell;
 use winit::keyboard::KeyCode;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
-use RustyBear_Engine::assets::assets::Assets;
+use RustyBear_Engine::assets::assets::{Assets, SPRITE_SHADER};
 use RustyBear_Engine::context::{Context, VisContext};
 use RustyBear_Engine::core::{Application, ModuleStack};
 use RustyBear_Engine::entities::animation2d::Animation2D;
 use RustyBear_Engine::entities::entities::Worlds;
-use RustyBear_Engine::entities::script::{ScriptHandle, Scriptable, Scripts};
+use RustyBear_Engine::entities::light2d::PointLight2D;
+use RustyBear_Engine::entities::particles::{ParticleEmitter2D, ParticleSystem};
+use RustyBear_Engine::entities::physics2d::{Kinematic, Velocity2D};
+use RustyBear_Engine::entities::script::{ScriptContext, Scriptable, Scripts};
 use RustyBear_Engine::entities::sprite::Sprite;
+use RustyBear_Engine::entities::timer::{Timer, TimerSystem, Timers};
 use RustyBear_Engine::entities::transform2d::Transform2D;
 use RustyBear_Engine::environment::config::Config;
 use RustyBear_Engine::event::{Event, EventType};
 use RustyBear_Engine::input::InputState;
 use RustyBear_Engine::logging;
 use RustyBear_Engine::render::camera::OrthographicCamera;
+use RustyBear_Engine::render::camera_controller::{CameraBindings, CameraController2D};
 use RustyBear_Engine::render::render2d::Renderer2D;
+use RustyBear_Engine::render::types::PipelineBaseConfig;
+use RustyBear_Engine::schedule::Schedule;
 use RustyBear_Engine::utils::Timestep;
 use RustyBear_Engine::window::Window;
 
@@ -33,13 +40,22 @@ pub struct AnimatedApp<'a> {
     assets: Assets,
     worlds: Worlds,
     scripts: Scripts,
+    //Runs physics, timers and animations.
+    schedule: Schedule,
     renderer: RcCell<Renderer2D>,
     camera: RcCell<OrthographicCamera>,
+    controller: RcCell<CameraController2D>,
 }
 
 impl<'a> Application<'a> for AnimatedApp<'a> {
-    fn on_event(&mut self, _event: &Event, _context: &mut Context) -> bool {
-        false
+    fn on_event(&mut self, event: &Event, context: &mut Context) -> bool {
+        //Sprites and transforms of the world live on the lost device, starting over is the simplest way back.
+        if let Event::DeviceRestored = event {
+            *self = AnimatedApp::new(context);
+            return false;
+        }
+
+        self.scripts.dispatch_event(event, &mut self.worlds)
     }
 
     fn render(
@@ -66,68 +82,108 @@ impl<'a> Application<'a> for AnimatedApp<'a> {
     }
 
     fn update(&mut self, delta: &Timestep, input_state: Ref<InputState>, context: &mut Context) {
-        let mut renderer = self.renderer.borrow_mut();
-        renderer.update_animations(&context.graphics, delta, &mut self.worlds);
+        let timers = self.schedule.resources().get::<TimerSystem>();
+
+        self.scripts.tick(
+            &context.graphics,
+            delta,
+            &mut self.worlds,
+            &input_state,
+            &mut self.assets,
+            timers.map_or(&[][..], TimerSystem::fired),
+        );
 
         if let Some(world) = self.worlds.get_mut() {
-            self.scripts.tick(&context.graphics, delta, world, &input_state);
+            ParticleSystem::update(world, delta);
         }
 
-        let mut cam = self.camera.borrow_mut();
+        self.worlds.despawn_marked(&mut self.scripts, &context.graphics);
 
-        if input_state.is_key_down(&KeyCode::KeyA) {
-            cam.inc_pos(Vec2::new(-(0.1 * delta.norm()), 0.0));
-        }
-
-        if input_state.is_key_down(&KeyCode::KeyD) {
-            cam.inc_pos(Vec2::new(0.1 * delta.norm(), 0.0));
-        }
-
-        if input_state.is_key_down(&KeyCode::Space) {
-            cam.inc_pos(Vec2::new(0.0, 0.1 * delta.norm()));
-        }
+        self.controller.borrow_mut().update(delta, &input_state);
+    }
 
-        if input_state.is_key_down(&KeyCode::ShiftLeft) {
-            cam.inc_pos(Vec2::new(0.0, -(0.1 * delta.norm())));
-        }
+    fn shutdown(&mut self, context: &mut Context) {
+        self.scripts.destroy_all(&context.graphics, &mut self.worlds);
     }
 
     fn quit(&mut self) {}
 
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        Some((&mut self.schedule, &mut self.worlds, &mut self.assets))
+    }
+
     fn get_stack(&mut self) -> &mut ModuleStack<'a> {
         &mut self.stack
     }
 }
 
+//Walks back and forth, the physics moves it with the velocity set here. Puffs smoke when turning
+//and a little every time its timer fires.
+const PUFF_TIMER: u32 = 0;
+
+#[derive(Clone)]
 struct Player {
-    dir: f32,
+    speed: f32,
 }
 
 impl Scriptable for Player {
     fn on_spawn(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
 
-    fn tick(
-        &mut self, _context: &VisContext, entity: hecs::Entity, delta: &Timestep,
-        world: &mut hecs::World, _input_state: &Ref<InputState>,
-        _new_scripts: &mut Vec<(ScriptHandle, Entity)>,
-    ) {
-        if let Ok(mut transform) = world.get::<&mut Transform2D>(entity) {
-            let add = if self.dir == 0.0 {
-                Vec3::new(0.01 * delta.norm(), 0.0, 0.0)
-            } else {
-                Vec3::new(-0.01 * delta.norm(), 0.0, 0.0)
-            };
-            transform.add_pos(add);
-
-            if transform.position().x > 2.0 {
-                self.dir = 1.0;
-            } else if transform.position().x < -2.0 {
-                self.dir = 0.0;
+    fn tick(&mut self, script: &mut ScriptContext, entity: hecs::Entity, world: &mut World) {
+        if script.timers_fired(entity).any(|tag| tag == PUFF_TIMER) {
+            if let Ok(mut smoke) = world.get::<&mut ParticleEmitter2D>(entity) {
+                smoke.burst(6);
             }
         }
 
-        if let Ok(mut anim) = world.get::<&mut Animation2D>(entity) {
-            anim.set_mirrored(self.dir == 1.0);
+        let Ok(x) = world.get::<&Transform2D>(entity).map(|transform| transform.position().x)
+        else {
+            return;
+        };
+
+        if let Ok(mut velocity) = world.get::<&mut Velocity2D>(entity) {
+            let before = velocity.linear.x;
+
+            if x > 2.0 {
+                velocity.linear.x = -self.speed;
+            } else if x < -2.0 {
+                velocity.linear.x = self.speed;
+            }
+
+            if velocity.linear.x != before {
+                if let Ok(mut smoke) = world.get::<&mut ParticleEmitter2D>(entity) {
+                    smoke.burst(24);
+                }
+            }
+
+            if let Ok(mut anim) = world.get::<&mut Animation2D>(entity) {
+                anim.set_mirrored(velocity.linear.x < 0.0);
+            }
+        }
+    }
+
+    fn on_destroy(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
+}
+
+//Flickers the PointLight2D of its entity like a torch.
+#[derive(Clone)]
+struct Torch {
+    time: f32,
+    intensity: f32,
+}
+
+impl Scriptable for Torch {
+    fn on_spawn(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
+
+    fn tick(&mut self, script: &mut ScriptContext, entity: hecs::Entity, world: &mut World) {
+        self.time += script.delta.seconds() as f32;
+
+        if let Ok(mut light) = world.get::<&mut PointLight2D>(entity) {
+            //A few unrelated sines look random enough.
+            let flicker = (self.time * 11.0).sin() * 0.12
+                + (self.time * 23.0 + 1.3).sin() * 0.08
+                + (self.time * 3.7).sin() * 0.05;
+            light.intensity = self.intensity * (1.0 + flicker);
         }
     }
 
@@ -147,31 +203,81 @@ impl<'a> AnimatedApp<'a> {
         }
 
         let mut scripts = Scripts::new();
-        let mut assets =
-            Assets::new(context.graphics.clone(), loc, (context.free_memory() / 2) as usize);
+        let mut assets = Assets::new(
+            context.graphics.clone(),
+            &context.rng,
+            loc,
+            (context.free_memory() / 2) as usize,
+        );
 
-        let mut worlds = Worlds::new();
+        let mut worlds = Worlds::new(&context.rng);
 
         let mut default = World::new();
 
         let default_texture = assets.request_asset("data/broom.fur", 0);
-        let player_script = Player { dir: 0.0 };
-        let player_script = scripts.add_script(Box::new(player_script));
+        let player_script = Player { speed: 1.0 };
+        let player_script = scripts.register(player_script);
 
-        let trans = Transform2D::new(&context.graphics, Vec3::new(-2.0, 0.0, 1.0), 0.0, Vec2::ONE);
+        let mut trans =
+            Transform2D::new(&context.graphics, Vec3::new(-2.0, 0.0, 1.0), 0.0, Vec2::ONE);
+        trans.set_interpolate(true);
         let anim = Animation2D::new(default_texture, 30, 12, false, true);
 
+        //Only bursts, triggered by the script.
+        let smoke_texture = assets.request_asset("data/white.fur", 0);
+        let smoke_rng = context.rng.derive("smoke");
+        let mut smoke = ParticleEmitter2D::new(&context.graphics, smoke_texture, smoke_rng);
+        smoke.set_enabled(false);
+        smoke.lifetime = 0.6..1.2;
+        smoke.speed = 0.2..0.6;
+        smoke.spread = std::f32::consts::FRAC_PI_2;
+        smoke.gravity = Vec2::new(0.0, 0.3);
+        smoke.start_color = Vec4::new(0.6, 0.6, 0.6, 0.8);
+        smoke.end_color = Vec4::new(0.4, 0.4, 0.4, 0.0);
+        smoke.start_size = 0.1;
+        smoke.end_size = 0.35;
+
         let player = default.spawn((
             trans,
             anim,
             Sprite::new(&context.graphics, default_texture, Vec4::ONE, None, None),
+            Velocity2D::new(Vec2::new(1.0, 0.0), 0.0),
+            Kinematic,
+            smoke,
+            Timers::new([Timer::every(1.5, PUFF_TIMER)]),
+        ));
+
+        //Additive glow, overlapping it brightens whatever is behind.
+        let white_ptr = assets.request_asset("data/white.fur", 0);
+        let glow = Transform2D::new(&context.graphics, Vec3::new(1.0, 0.0, 1.5), 0.0, Vec2::ONE);
+
+        default.spawn((
+            glow,
+            Sprite::new_custom(
+                &context.graphics,
+                *SPRITE_SHADER,
+                *SPRITE_SHADER,
+                white_ptr,
+                Vec4::new(1.0, 0.6, 0.1, 0.5),
+                None,
+                None,
+                Some(PipelineBaseConfig::additive()),
+            ),
         ));
 
-        scripts.attach(player_script, player);
+        //A torch lighting the scene, the rest of it stays dim.
+        let torch_script = scripts.register(Torch { time: 0.0, intensity: 1.6 });
+        let torch = default.spawn((
+            Transform2D::new(&context.graphics, Vec3::new(0.0, -0.5, 1.0), 0.0, Vec2::ONE),
+            PointLight2D::new(Vec3::new(1.0, 0.65, 0.3), 1.6, 2.5),
+        ));
 
         let default = worlds.add_world(default);
         worlds.start_world(default);
 
+        scripts.attach(player_script, default, player);
+        scripts.attach(torch_script, default, torch);
+
         let renderer = RcCell::new(Renderer2D::new(context, &mut assets));
         stack.subscribe(EventType::Layer, renderer.clone());
 
@@ -180,11 +286,33 @@ impl<'a> AnimatedApp<'a> {
         let white = assets.try_get(&white).unwrap();
 
         renderer.borrow_mut().set_background(&context.graphics, white, Vec4::ONE);
+        renderer.borrow_mut().set_ambient_light(Vec3::splat(0.35));
 
-        let camera = RcCell::new(OrthographicCamera::default());
+        let camera = RcCell::new(OrthographicCamera::new(context));
         stack.subscribe(EventType::Layer, camera.clone());
 
-        AnimatedApp { stack, assets, scripts, worlds, renderer, camera }
+        let bindings = CameraBindings {
+            up: vec![KeyCode::Space],
+            down: vec![KeyCode::ShiftLeft],
+            left: vec![KeyCode::KeyA],
+            right: vec![KeyCode::KeyD],
+        };
+
+        let controller = RcCell::new(
+            CameraController2D::new(camera.clone()).with_bindings(bindings).with_pan_speed(10.0),
+        );
+        stack.subscribe(EventType::Layer, controller.clone());
+
+        AnimatedApp {
+            stack,
+            assets,
+            scripts,
+            schedule: Schedule::new(),
+            worlds,
+            renderer,
+            camera,
+            controller,
+        }
     }
 }
 
@@ -197,12 +325,18 @@ fn main() {
     let mut config = Config::new(None);
     config.find_project(Path::new("examples/animated")).unwrap();
 
-    //Create the window from the config and create the context.
-    let window = Window::new("{}".to_string());
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
     window.native.set_ime_allowed(true);
     //window.native.set_cursor_visible(false);
 
-    let context = pollster::block_on(Context::new(window.native.clone(), config));
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
 
     //Create and init the application
     let myapp = AnimatedApp::new(&context);
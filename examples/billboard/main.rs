@@ -0,0 +1,261 @@
+#![allow(non_snake_case)]
+
+use std::cell::Ref;
+use std::path::Path;
+
+use glam::{Vec2, Vec3, Vec4};
+use hecs::World;
+use rccell::RcCell;
+use winit::keyboard::KeyCode;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+use RustyBear_Engine::assets::assets::Assets;
+use RustyBear_Engine::context::{Context, VisContext};
+use RustyBear_Engine::core::{Application, ModuleStack};
+use RustyBear_Engine::entities::entities::Worlds;
+use RustyBear_Engine::entities::script::{ScriptContext, Scriptable, Scripts};
+use RustyBear_Engine::entities::sprite::Sprite;
+use RustyBear_Engine::entities::timer::TimerSystem;
+use RustyBear_Engine::entities::transform2d::{Billboard2_5D, Transform2D};
+use RustyBear_Engine::environment::config::Config;
+use RustyBear_Engine::event::{Event, EventType};
+use RustyBear_Engine::input::InputState;
+use RustyBear_Engine::logging;
+use RustyBear_Engine::render::camera::{OrthographicCamera, MAX_SHEAR};
+use RustyBear_Engine::render::camera_controller::{CameraBindings, CameraController2D};
+use RustyBear_Engine::render::render2d::Renderer2D;
+use RustyBear_Engine::schedule::Schedule;
+use RustyBear_Engine::utils::Timestep;
+use RustyBear_Engine::window::Window;
+
+pub struct BillboardApp<'a> {
+    stack: ModuleStack<'a>,
+    assets: Assets,
+    worlds: Worlds,
+    scripts: Scripts,
+    schedule: Schedule,
+    renderer: RcCell<Renderer2D>,
+    camera: RcCell<OrthographicCamera>,
+    controller: RcCell<CameraController2D>,
+}
+
+impl<'a> Application<'a> for BillboardApp<'a> {
+    fn on_event(&mut self, event: &Event, context: &mut Context) -> bool {
+        //Sprites and transforms of the world live on the lost device, starting over is the simplest way back.
+        if let Event::DeviceRestored = event {
+            *self = BillboardApp::new(context);
+        }
+
+        false
+    }
+
+    fn render(
+        &mut self, view: &wgpu::TextureView, context: &mut Context, window: &winit::window::Window,
+    ) {
+        {
+            let mut renderer = self.renderer.borrow_mut();
+            let mut camera = self.camera.borrow_mut();
+
+            renderer.update_camera_buffer(
+                &context.graphics,
+                camera.view_projection().to_cols_array_2d(),
+            );
+            renderer.update_camera_shear(&context.graphics, camera.shear());
+            renderer.update_viewport(camera.viewport());
+
+            renderer.render(&mut self.assets, &mut self.worlds, context, view, window);
+        }
+    }
+
+    fn gui_render(&mut self, _view: &wgpu::TextureView, context: &mut Context) {
+        let mut camera = self.camera.borrow_mut();
+        let mut shear = camera.shear();
+
+        egui::Window::new("2.5D").show(context.egui.egui_ctx(), |ui| {
+            ui.add(egui::Slider::new(&mut shear, 0.0..=MAX_SHEAR).text("Shear"));
+            ui.label("WASD moves the character between the trees.");
+        });
+
+        camera.set_shear(shear);
+    }
+
+    fn update(&mut self, delta: &Timestep, input_state: Ref<InputState>, context: &mut Context) {
+        let timers = self.schedule.resources().get::<TimerSystem>();
+
+        self.scripts.tick(
+            &context.graphics,
+            delta,
+            &mut self.worlds,
+            &input_state,
+            &mut self.assets,
+            timers.map_or(&[][..], TimerSystem::fired),
+        );
+
+        self.worlds.despawn_marked(&mut self.scripts, &context.graphics);
+        self.controller.borrow_mut().update(delta, &input_state);
+    }
+
+    fn shutdown(&mut self, context: &mut Context) {
+        self.scripts.destroy_all(&context.graphics, &mut self.worlds);
+    }
+
+    fn quit(&mut self) {}
+
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        Some((&mut self.schedule, &mut self.worlds, &mut self.assets))
+    }
+
+    fn get_stack(&mut self) -> &mut ModuleStack<'a> {
+        &mut self.stack
+    }
+}
+
+struct Player {}
+
+impl Scriptable for Player {
+    fn on_spawn(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
+
+    fn tick(&mut self, script: &mut ScriptContext, entity: hecs::Entity, world: &mut World) {
+        let input_state = script.input;
+        let mut direction = Vec3::ZERO;
+
+        if input_state.is_key_down(&KeyCode::KeyW) {
+            direction.y += 1.0;
+        }
+
+        if input_state.is_key_down(&KeyCode::KeyS) {
+            direction.y -= 1.0;
+        }
+
+        if input_state.is_key_down(&KeyCode::KeyA) {
+            direction.x -= 1.0;
+        }
+
+        if input_state.is_key_down(&KeyCode::KeyD) {
+            direction.x += 1.0;
+        }
+
+        if let Ok(mut transform) = world.get::<&mut Transform2D>(entity) {
+            transform.add_pos(direction * 0.02 * script.delta.norm());
+        }
+    }
+
+    fn on_destroy(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
+}
+
+impl<'a> BillboardApp<'a> {
+    pub fn new(context: &Context) -> Self {
+        log::info!("Init Application");
+
+        let mut stack = ModuleStack::new();
+
+        let loc = context.config.project_config().location.clone().map(what::Location::File);
+
+        let mut scripts = Scripts::new();
+        let mut assets = Assets::new(
+            context.graphics.clone(),
+            &context.rng,
+            loc,
+            (context.free_memory() / 2) as usize,
+        );
+
+        let mut worlds = Worlds::new(&context.rng);
+        let mut default = World::new();
+
+        let white = assets.request_asset("data/white.fur", 0);
+
+        //The ground stays flat and far behind everything standing on it.
+        let ground = Transform2D::new(
+            &context.graphics,
+            Vec3::new(0.0, 0.0, -10.0),
+            0.0,
+            Vec2::new(4.0, 2.0),
+        );
+        let ground_tint = Vec4::new(0.3, 0.6, 0.3, 1.0);
+        default.spawn((ground, Sprite::new(&context.graphics, white, ground_tint, None, None)));
+
+        //Trees and the player stand upright. Whoever stands lower on the ground is in front.
+        for (i, x) in [-2.5, -1.0, 0.5, 2.0].iter().enumerate() {
+            let mut tree = Transform2D::new(
+                &context.graphics,
+                Vec3::new(*x, 1.0 - i as f32 * 0.6, 0.0),
+                0.0,
+                Vec2::new(0.3, 0.8),
+            );
+            tree.set_billboard(Some(Billboard2_5D::default()));
+
+            let tint = Vec4::new(0.2, 0.4, 0.1, 1.0);
+            default.spawn((tree, Sprite::new(&context.graphics, white, tint, None, None)));
+        }
+
+        let mut trans =
+            Transform2D::new(&context.graphics, Vec3::new(0.0, 0.0, 0.0), 0.0, Vec2::new(0.2, 0.4));
+        trans.set_billboard(Some(Billboard2_5D::default()));
+
+        let player = default.spawn((
+            trans,
+            Sprite::new(&context.graphics, white, Vec4::new(0.9, 0.2, 0.2, 1.0), None, None),
+        ));
+
+        let player_script = scripts.add_script(Box::new(Player {}));
+
+        let default = worlds.add_world(default);
+        worlds.start_world(default);
+        scripts.attach(player_script, default, player);
+
+        let renderer = RcCell::new(Renderer2D::new(context, &mut assets));
+        stack.subscribe(EventType::Layer, renderer.clone());
+
+        let mut camera = OrthographicCamera::new(context);
+        camera.set_shear(0.5);
+
+        let camera = RcCell::new(camera);
+        stack.subscribe(EventType::Layer, camera.clone());
+
+        //WASD belongs to the player, the camera only zooms.
+        let bindings = CameraBindings { up: vec![], down: vec![], left: vec![], right: vec![] };
+        let controller =
+            RcCell::new(CameraController2D::new(camera.clone()).with_bindings(bindings));
+        stack.subscribe(EventType::Layer, controller.clone());
+
+        BillboardApp {
+            stack,
+            assets,
+            scripts,
+            schedule: Schedule::new(),
+            worlds,
+            renderer,
+            camera,
+            controller,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
+fn main() {
+    logging::init();
+    println!();
+
+    //Create the config and init the example project.
+    let mut config = Config::new(None);
+    config.find_project(Path::new("examples/billboard")).unwrap();
+
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
+    window.native.set_ime_allowed(true);
+
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
+
+    //Create and init the application
+    let myapp = BillboardApp::new(&context);
+
+    //Move my app and window into the context. And run the app.
+    context.run(myapp, window);
+}
@@ -18,12 +18,18 @@ fn main() {
     let mut config = Config::new(None);
     config.find_project(Path::new("examples/hello_world")).unwrap();
 
-    //Create the window from the config and create the context.
-    let window = Window::new("{}".to_string());
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
     window.native.set_ime_allowed(true);
     window.native.set_cursor_visible(false);
 
-    let context = pollster::block_on(Context::new(window.native.clone(), config));
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
 
     //Create and init the application
     let myapp = RustyRuntime::new(&context);
@@ -5,6 +5,7 @@ use std::path::Path;
 
 use glam::Vec2;
 use rccell::RcCell;
+use winit::event::ElementState;
 use winit::keyboard::KeyCode;
 
 #[cfg(target_arch = "wasm32")]
@@ -13,13 +14,16 @@ use RustyBear_Engine::assets::assets::Assets;
 use RustyBear_Engine::context::Context;
 use RustyBear_Engine::core::{Application, ModuleStack};
 use RustyBear_Engine::entities::entities::Worlds;
+use RustyBear_Engine::entities::ldtk_entity::LevelBackground;
 use RustyBear_Engine::environment::config::Config;
 use RustyBear_Engine::event::{Event, EventType};
 use RustyBear_Engine::input::InputState;
 use RustyBear_Engine::logging;
 use RustyBear_Engine::render::camera::OrthographicCamera;
+use RustyBear_Engine::render::camera_controller::{CameraBindings, CameraController2D};
 use RustyBear_Engine::render::render2d::Renderer2D;
-use RustyBear_Engine::utils::Timestep;
+use RustyBear_Engine::schedule::Schedule;
+use RustyBear_Engine::utils::{Guid, Timestep};
 use RustyBear_Engine::window::Window;
 
 pub struct LDTKApp<'a> {
@@ -28,10 +32,43 @@ pub struct LDTKApp<'a> {
     worlds: Worlds,
     renderer: RcCell<Renderer2D>,
     camera: RcCell<OrthographicCamera>,
+    controller: RcCell<CameraController2D>,
+    schedule: Schedule,
+    //The level whose background the renderer shows.
+    background: Option<Guid>,
 }
 
 impl<'a> Application<'a> for LDTKApp<'a> {
-    fn on_event(&mut self, _event: &Event, _context: &mut Context) -> bool {
+    fn on_event(&mut self, event: &Event, context: &mut Context) -> bool {
+        //Tiles and transforms of the level live on the lost device, the file is loaded again.
+        if let Event::DeviceRestored = event {
+            *self = LDTKApp::new(context);
+            return false;
+        }
+
+        //Dropping another .ldtk file onto the window shows its first level.
+        if let Event::WorldDropped { world, path } = event {
+            log::info!("Dropped {:?}", path);
+            self.worlds.start_world(*world);
+        }
+
+        //L cycles through the levels of the file.
+        if let Event::KeyboardInput {
+            keycode: KeyCode::KeyL, state: ElementState::Pressed, ..
+        } = event
+        {
+            let levels = self.worlds.levels();
+            let current =
+                levels.iter().position(|level| Some(level.world) == self.worlds.current());
+            let next = current.map_or(0, |index| (index + 1) % levels.len());
+
+            if let Some(level) = levels.get(next).cloned() {
+                log::info!("Level {}", level.identifier);
+                self.worlds.start_level(&level.identifier);
+                self.controller.borrow_mut().jump_to(level.center());
+            }
+        }
+
         false
     }
 
@@ -41,6 +78,19 @@ impl<'a> Application<'a> for LDTKApp<'a> {
         {
             let mut renderer = self.renderer.borrow_mut();
 
+            if let Some(current) = self.worlds.current().filter(|c| self.background != Some(*c)) {
+                let world = self.worlds.get_world(current);
+                let background = world.and_then(|world| {
+                    world.query::<&LevelBackground>().iter().next().map(|(_, b)| b.clone())
+                });
+
+                if let Some(background) = background {
+                    renderer.apply_level_background(&background);
+                }
+
+                self.background = Some(current);
+            }
+
             renderer.update_camera_buffer(
                 &context.graphics,
                 self.camera.borrow_mut().view_projection().to_cols_array_2d(),
@@ -53,27 +103,15 @@ impl<'a> Application<'a> for LDTKApp<'a> {
     fn gui_render(&mut self, _view: &wgpu::TextureView, _context: &mut Context) {}
 
     fn update(&mut self, delta: &Timestep, input_state: Ref<InputState>, _context: &mut Context) {
-        let mut cam = self.camera.borrow_mut();
-
-        if input_state.is_key_down(&KeyCode::KeyD) {
-            cam.inc_pos(Vec2::new(-(0.01 * delta.norm()), 0.0));
-        }
-
-        if input_state.is_key_down(&KeyCode::KeyA) {
-            cam.inc_pos(Vec2::new(0.01 * delta.norm(), 0.0));
-        }
-
-        if input_state.is_key_down(&KeyCode::KeyS) {
-            cam.inc_pos(Vec2::new(0.0, 0.01 * delta.norm()));
-        }
-
-        if input_state.is_key_down(&KeyCode::KeyW) {
-            cam.inc_pos(Vec2::new(0.0, -(0.01 * delta.norm())));
-        }
+        self.controller.borrow_mut().update(delta, &input_state);
     }
 
     fn quit(&mut self) {}
 
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        Some((&mut self.schedule, &mut self.worlds, &mut self.assets))
+    }
+
     fn get_stack(&mut self) -> &mut ModuleStack<'a> {
         &mut self.stack
     }
@@ -91,10 +129,14 @@ impl<'a> LDTKApp<'a> {
             log::warn!("Project: {:?}", path);
         }
 
-        let mut assets =
-            Assets::new(context.graphics.clone(), loc, (context.free_memory() / 2) as usize);
+        let mut assets = Assets::new(
+            context.graphics.clone(),
+            &context.rng,
+            loc,
+            (context.free_memory() / 2) as usize,
+        );
 
-        let worlds = Worlds::from_ldtk_file(&context.graphics,
+        let worlds = Worlds::from_ldtk_file(&context.graphics, &context.rng,
             &context.config.project_config().location.clone(),
             &mut assets, "examples/ldtk/data/test.ldtk").expect(
 			"Failed to load ldtk file. Make sure you have the test.ldtk file in the examples/ldtk folder",
@@ -103,15 +145,35 @@ impl<'a> LDTKApp<'a> {
         let renderer = RcCell::new(Renderer2D::new(context, &mut assets));
         stack.subscribe(EventType::Layer, renderer.clone());
 
-        let mut cam = OrthographicCamera::default();
+        let mut cam = OrthographicCamera::new(context);
         let pos = cam.position();
         cam.set_position(Vec2::new(pos.x, 0.5));
         let camera = RcCell::new(cam);
         stack.subscribe(EventType::Layer, camera.clone());
 
-        // Set it to bottom of the screen
-
-        LDTKApp { stack, assets, worlds, renderer, camera }
+        //The level scrolls under the camera, so the keys move it the other way.
+        let bindings = CameraBindings {
+            up: vec![KeyCode::KeyS],
+            down: vec![KeyCode::KeyW],
+            left: vec![KeyCode::KeyD],
+            right: vec![KeyCode::KeyA],
+        };
+
+        let controller = RcCell::new(
+            CameraController2D::new(camera.clone()).with_bindings(bindings).with_pan_speed(1.0),
+        );
+        stack.subscribe(EventType::Layer, controller.clone());
+
+        LDTKApp {
+            stack,
+            assets,
+            worlds,
+            renderer,
+            camera,
+            controller,
+            schedule: Schedule::new(),
+            background: None,
+        }
     }
 }
 
@@ -123,13 +185,20 @@ fn main() {
     //Create the config and init the example project.
     let mut config = Config::new(None);
     config.find_project(Path::new("examples/ldtk")).unwrap();
+    config.engine_config_mut().load_dropped_files = true;
 
-    //Create the window from the config and create the context.
-    let window = Window::new("{}".to_string());
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
     window.native.set_ime_allowed(true);
     window.native.set_cursor_visible(false);
 
-    let context = pollster::block_on(Context::new(window.native.clone(), config));
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
 
     //Create and init the application
     let myapp = LDTKApp::new(&context);
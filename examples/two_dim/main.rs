@@ -5,9 +5,8 @@ use std::path::Path;
 
 use egui::{Color32, FontId, RichText};
 use glam::{Vec2, Vec3, Vec4};
-use hecs::{Entity, World};
+use hecs::World;
 use rccell::RcCell;
-use winit::keyboard::KeyCode;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -15,15 +14,18 @@ use RustyBear_Engine::assets::assets::Assets;
 use RustyBear_Engine::context::{Context, VisContext};
 use RustyBear_Engine::core::{Application, ModuleStack};
 use RustyBear_Engine::entities::entities::Worlds;
-use RustyBear_Engine::entities::script::{ScriptHandle, Scriptable, Scripts};
+use RustyBear_Engine::entities::script::{ScriptContext, Scriptable, Scripts};
 use RustyBear_Engine::entities::sprite::Sprite;
+use RustyBear_Engine::entities::timer::TimerSystem;
 use RustyBear_Engine::entities::transform2d::Transform2D;
 use RustyBear_Engine::environment::config::Config;
 use RustyBear_Engine::event::{Event, EventType};
 use RustyBear_Engine::input::InputState;
 use RustyBear_Engine::logging;
 use RustyBear_Engine::render::camera::OrthographicCamera;
+use RustyBear_Engine::render::camera_controller::CameraController2D;
 use RustyBear_Engine::render::render2d::Renderer2D;
+use RustyBear_Engine::schedule::Schedule;
 use RustyBear_Engine::utils::Timestep;
 use RustyBear_Engine::window::Window;
 
@@ -32,12 +34,19 @@ pub struct TwoDimApp<'a> {
     assets: Assets,
     worlds: Worlds,
     scripts: Scripts,
+    schedule: Schedule,
     renderer: RcCell<Renderer2D>,
     camera: RcCell<OrthographicCamera>,
+    controller: RcCell<CameraController2D>,
 }
 
 impl<'a> Application<'a> for TwoDimApp<'a> {
-    fn on_event(&mut self, _event: &Event, _context: &mut Context) -> bool {
+    fn on_event(&mut self, event: &Event, context: &mut Context) -> bool {
+        //Sprites and transforms of the world live on the lost device, starting over is the simplest way back.
+        if let Event::DeviceRestored = event {
+            *self = TwoDimApp::new(context);
+        }
+
         false
     }
 
@@ -72,31 +81,32 @@ impl<'a> Application<'a> for TwoDimApp<'a> {
     }
 
     fn update(&mut self, delta: &Timestep, input_state: Ref<InputState>, context: &mut Context) {
-        if let Some(world) = self.worlds.get_mut() {
-            self.scripts.tick(&context.graphics, delta, world, &input_state);
-        }
-
-        let mut cam = self.camera.borrow_mut();
-
-        if input_state.is_key_down(&KeyCode::KeyA) {
-            cam.inc_pos(Vec2::new(-(0.1 * delta.norm()), 0.0));
-        }
+        let timers = self.schedule.resources().get::<TimerSystem>();
+
+        self.scripts.tick(
+            &context.graphics,
+            delta,
+            &mut self.worlds,
+            &input_state,
+            &mut self.assets,
+            timers.map_or(&[][..], TimerSystem::fired),
+        );
 
-        if input_state.is_key_down(&KeyCode::KeyD) {
-            cam.inc_pos(Vec2::new(0.1 * delta.norm(), 0.0));
-        }
+        self.worlds.despawn_marked(&mut self.scripts, &context.graphics);
 
-        if input_state.is_key_down(&KeyCode::Space) {
-            cam.inc_pos(Vec2::new(0.0, 0.1 * delta.norm()));
-        }
+        self.controller.borrow_mut().update(delta, &input_state);
+    }
 
-        if input_state.is_key_down(&KeyCode::ShiftLeft) {
-            cam.inc_pos(Vec2::new(0.0, -(0.1 * delta.norm())));
-        }
+    fn shutdown(&mut self, context: &mut Context) {
+        self.scripts.destroy_all(&context.graphics, &mut self.worlds);
     }
 
     fn quit(&mut self) {}
 
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        Some((&mut self.schedule, &mut self.worlds, &mut self.assets))
+    }
+
     fn get_stack(&mut self) -> &mut ModuleStack<'a> {
         &mut self.stack
     }
@@ -107,13 +117,9 @@ struct Player {}
 impl Scriptable for Player {
     fn on_spawn(&mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut World) {}
 
-    fn tick(
-        &mut self, _context: &VisContext, entity: hecs::Entity, delta: &Timestep,
-        world: &mut hecs::World, _input_state: &Ref<InputState>,
-        _new_scripts: &mut Vec<(ScriptHandle, Entity)>,
-    ) {
+    fn tick(&mut self, script: &mut ScriptContext, entity: hecs::Entity, world: &mut World) {
         if let Ok(mut transform) = world.get::<&mut Transform2D>(entity) {
-            transform.add_pos(Vec3::new(0.01 * delta.norm(), 0.0, 0.0));
+            transform.add_pos(Vec3::new(0.01 * script.delta.norm(), 0.0, 0.0));
         }
     }
 
@@ -133,10 +139,14 @@ impl<'a> TwoDimApp<'a> {
         }
 
         let mut scripts = Scripts::new();
-        let mut assets =
-            Assets::new(context.graphics.clone(), loc, (context.free_memory() / 2) as usize);
+        let mut assets = Assets::new(
+            context.graphics.clone(),
+            &context.rng,
+            loc,
+            (context.free_memory() / 2) as usize,
+        );
 
-        let mut worlds = Worlds::new();
+        let mut worlds = Worlds::new(&context.rng);
 
         let mut default = World::new();
 
@@ -157,8 +167,6 @@ impl<'a> TwoDimApp<'a> {
             ),
         ));
 
-        scripts.attach(player_script, player);
-
         let trans = Transform2D::new(&context.graphics, Vec3::new(2.0, 0.0, 0.0), 0.0, Vec2::ONE);
 
         default.spawn((
@@ -174,14 +182,29 @@ impl<'a> TwoDimApp<'a> {
 
         let default = worlds.add_world(default);
         worlds.start_world(default);
+        scripts.attach(player_script, default, player);
 
         let renderer = RcCell::new(Renderer2D::new(context, &mut assets));
         stack.subscribe(EventType::Layer, renderer.clone());
 
-        let camera = RcCell::new(OrthographicCamera::default());
+        let camera = RcCell::new(OrthographicCamera::new(context));
         stack.subscribe(EventType::Layer, camera.clone());
 
-        TwoDimApp { stack, assets, scripts, worlds, renderer, camera }
+        //Bound in the actions of two_dim.rbe.
+        let controller =
+            RcCell::new(CameraController2D::new(camera.clone()).with_actions("move_x", "move_y"));
+        stack.subscribe(EventType::Layer, controller.clone());
+
+        TwoDimApp {
+            stack,
+            assets,
+            scripts,
+            schedule: Schedule::new(),
+            worlds,
+            renderer,
+            camera,
+            controller,
+        }
     }
 }
 
@@ -194,12 +217,18 @@ fn main() {
     let mut config = Config::new(None);
     config.find_project(Path::new("examples/two_dim")).unwrap();
 
-    //Create the window from the config and create the context.
-    let window = Window::new("{}".to_string());
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
     window.native.set_ime_allowed(true);
     window.native.set_cursor_visible(false);
 
-    let context = pollster::block_on(Context::new(window.native.clone(), config));
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
 
     //Create and init the application
     let myapp = TwoDimApp::new(&context);
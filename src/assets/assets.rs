@@ -1,5 +1,5 @@
 use bimap::BiMap;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use image::GenericImageView;
 use indicatif::{ProgressBar, ProgressStyle};
 use once_cell::sync::Lazy;
@@ -7,18 +7,23 @@ use rayon::prelude::*;
 
 use crate::context::VisContext;
 use crate::logging;
+use crate::random::Rng;
 use crate::render::material::GenericMaterial;
 use crate::render::types::BindGroupEntry;
 use crate::utils::{Guid, GuidGenerator};
 
 use std::any::Any;
 use std::hash::Hash;
+use std::path::Path;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc};
 
+use super::audio::AudioClip;
 use super::buffer::UniformBuffer;
-use super::shader::Shader;
+use super::shader::{Shader, FRAGMENT_ENTRY, VERTEX_ENTRY};
+use super::streaming::StreamLevels;
 use super::texture::{Sampler, Texture2D, TextureArray};
+use super::types::{AssetError, AssetKind, AssetStats, TypedAsset};
 
 pub enum AssetType {
     TextureArray(TextureArray),
@@ -27,6 +32,21 @@ pub enum AssetType {
     Uniforms(UniformBuffer),
     Sampler(Sampler),
     GenericMaterial(GenericMaterial),
+    AudioClip(AudioClip),
+}
+
+impl AssetType {
+    pub fn kind(&self) -> AssetKind {
+        match self {
+            AssetType::TextureArray(_) => AssetKind::TextureArray,
+            AssetType::Texture2D(_) => AssetKind::Texture2D,
+            AssetType::Shader(_) => AssetKind::Shader,
+            AssetType::Uniforms(_) => AssetKind::Uniforms,
+            AssetType::Sampler(_) => AssetKind::Sampler,
+            AssetType::GenericMaterial(_) => AssetKind::GenericMaterial,
+            AssetType::AudioClip(_) => AssetKind::AudioClip,
+        }
+    }
 }
 
 static LOADING_STYLE: Lazy<ProgressStyle> = Lazy::new(|| {
@@ -41,6 +61,14 @@ static LOADING_SPINNER_STYLE: Lazy<ProgressStyle> = Lazy::new(|| {
 
 pub static SPRITE_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x1)));
 pub static BACKGROUND_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x2)));
+pub static LOADING_TEXTURE: Lazy<Ptr<Texture2D>> = Lazy::new(|| Ptr::new(Guid::new(0x3)));
+pub static POST_PROCESS_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x4)));
+pub static SOFT_SPRITE_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x5)));
+//The same for a multisampled depth buffer.
+pub static SOFT_SPRITE_SHADER_MS: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x6)));
+pub static TRANSITION_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x7)));
+pub static LIGHTING_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x8)));
+pub static PARTICLE_SHADER: Lazy<Ptr<Shader>> = Lazy::new(|| Ptr::new(Guid::new(0x9)));
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub struct GenPtr {
@@ -99,26 +127,49 @@ impl<T> Ptr<T> {
     }
 }
 
+//Only this many failures are kept for the event log if nobody collects them.
+const MAX_LOGGED_FAILURES: usize = 256;
+
+//Path, guid, priority, the mips to upload for streamed textures and the device to load onto.
+//The device travels with the request, it changes if the old one was lost.
+type AssetRequest = (String, Guid, usize, Option<StreamLevels>, Arc<VisContext>);
+
 pub struct Assets {
     gpu_cache: HashMap<Guid, AssetType>,
     path_cache: BiMap<Guid, String>,
+    expected_kinds: HashMap<Guid, AssetKind>,
+    //Kind mismatches that were logged already, each one is reported once.
+    mismatches: HashSet<(Guid, AssetKind)>,
+    failed: HashSet<Guid>,
+    //Failures since the engine last moved them into the event log.
+    newly_failed: Vec<(Guid, String)>,
+    //Failures past MAX_LOGGED_FAILURES, reported as a count when they are collected.
+    dropped_failures: usize,
+    sizes: HashMap<Guid, usize>,
+    pending: HashSet<Guid>,
+    evicted: HashSet<Guid>,
+    max_size: usize,
     generator: GuidGenerator,
 
-    request_sender: Sender<(String, Guid, usize)>,
-    asset_receiver: Receiver<(Guid, Result<AssetType, String>)>,
+    context: Arc<VisContext>,
+    request_sender: Sender<AssetRequest>,
+    asset_receiver: Receiver<(Guid, Result<(AssetType, usize), String>)>,
 }
 
 impl Assets {
-    pub fn new(context: Arc<VisContext>, loc: Option<what::Location>, max_size: usize) -> Self {
-        type InChannel = (Sender<(String, Guid, usize)>, Receiver<(String, Guid, usize)>);
+    //Guids of assets without path come from the rng, see Worlds::new.
+    pub fn new(
+        context: Arc<VisContext>, rng: &Rng, loc: Option<what::Location>, max_size: usize,
+    ) -> Self {
+        type InChannel = (Sender<AssetRequest>, Receiver<AssetRequest>);
         type OutChannel = (
-            Sender<(Guid, Result<AssetType, String>)>,
-            Receiver<(Guid, Result<AssetType, String>)>,
+            Sender<(Guid, Result<(AssetType, usize), String>)>,
+            Receiver<(Guid, Result<(AssetType, usize), String>)>,
         );
 
         let gpu_cache = HashMap::new();
         let path_cache = BiMap::new();
-        let generator = GuidGenerator::new();
+        let generator = GuidGenerator::with_stream(rng.derive("asset_guids"));
 
         let (in_sender, in_receiver): InChannel = mpsc::channel();
         let (out_sender, out_receiver): OutChannel = mpsc::channel();
@@ -126,8 +177,18 @@ impl Assets {
         let mut assets = Assets {
             gpu_cache,
             path_cache,
+            expected_kinds: HashMap::new(),
+            mismatches: HashSet::new(),
+            failed: HashSet::new(),
+            newly_failed: Vec::new(),
+            dropped_failures: 0,
+            sizes: HashMap::new(),
+            pending: HashSet::new(),
+            evicted: HashSet::new(),
+            max_size,
             generator,
 
+            context: context.clone(),
             request_sender: in_sender,
             asset_receiver: out_receiver,
         };
@@ -135,19 +196,30 @@ impl Assets {
         assets.register_static(&context);
 
         rayon::spawn(move || {
-            let context = context.clone();
-
             let mut what = what::What::new(max_size, loc);
 
-            while let Ok((path, guid, priority)) = in_receiver.recv() {
+            while let Ok((path, guid, priority, streamed, context)) = in_receiver.recv() {
                 let out_sender = out_sender.clone();
-                let context = context.clone();
+
+                //Files outside of the project, see request_file.
+                if Path::new(&path).is_absolute() {
+                    rayon::spawn(move || {
+                        let result = Self::load_file(&context, &path, streamed).map(|asset| {
+                            log::info!("Loaded asset: {}", path);
+                            let bytes = Self::byte_estimate(&asset);
+                            (asset, bytes)
+                        });
+                        let _ = out_sender.send((guid, result));
+                    });
+                    continue;
+                }
 
                 match what.load_asset(path.clone(), priority) {
                     Ok(asset) => {
                         rayon::spawn(move || {
-                            if let Some(asset) = Self::load_asset(&context, asset, guid) {
-                                let _ = out_sender.send((guid, Ok(asset)));
+                            if let Some(asset) = Self::load_asset(&context, asset, guid, streamed) {
+                                let bytes = Self::byte_estimate(&asset);
+                                let _ = out_sender.send((guid, Ok((asset, bytes))));
                                 log::info!("Loaded asset: {}", path);
                             } else {
                                 let _ = out_sender
@@ -176,6 +248,7 @@ impl Assets {
         .unwrap();
 
         self.gpu_cache.insert(SPRITE_SHADER.guid, AssetType::Shader(sprite_shader));
+        self.sizes.insert(SPRITE_SHADER.guid, 0);
 
         let background_shader = Shader::new(
             context,
@@ -186,6 +259,89 @@ impl Assets {
         .unwrap();
 
         self.gpu_cache.insert(BACKGROUND_SHADER.guid, AssetType::Shader(background_shader));
+        self.sizes.insert(BACKGROUND_SHADER.guid, 0);
+
+        let post_process_shader = Shader::new(
+            context,
+            POST_PROCESS_SHADER.guid,
+            wgpu::ShaderSource::Wgsl(include_str!("postprocess.wgsl").into()),
+            what::ShaderStages::FRAGMENT | what::ShaderStages::VERTEX,
+        )
+        .unwrap();
+
+        self.gpu_cache.insert(POST_PROCESS_SHADER.guid, AssetType::Shader(post_process_shader));
+        self.sizes.insert(POST_PROCESS_SHADER.guid, 0);
+
+        let transition_shader = Shader::new(
+            context,
+            TRANSITION_SHADER.guid,
+            wgpu::ShaderSource::Wgsl(include_str!("transition.wgsl").into()),
+            what::ShaderStages::FRAGMENT | what::ShaderStages::VERTEX,
+        )
+        .unwrap();
+
+        self.gpu_cache.insert(TRANSITION_SHADER.guid, AssetType::Shader(transition_shader));
+        self.sizes.insert(TRANSITION_SHADER.guid, 0);
+
+        let lighting_shader = Shader::new(
+            context,
+            LIGHTING_SHADER.guid,
+            wgpu::ShaderSource::Wgsl(include_str!("lighting.wgsl").into()),
+            what::ShaderStages::FRAGMENT | what::ShaderStages::VERTEX,
+        )
+        .unwrap();
+
+        self.gpu_cache.insert(LIGHTING_SHADER.guid, AssetType::Shader(lighting_shader));
+        self.sizes.insert(LIGHTING_SHADER.guid, 0);
+
+        let particle_shader = Shader::new(
+            context,
+            PARTICLE_SHADER.guid,
+            wgpu::ShaderSource::Wgsl(include_str!("particles.wgsl").into()),
+            what::ShaderStages::FRAGMENT | what::ShaderStages::VERTEX,
+        )
+        .unwrap();
+
+        self.gpu_cache.insert(PARTICLE_SHADER.guid, AssetType::Shader(particle_shader));
+        self.sizes.insert(PARTICLE_SHADER.guid, 0);
+
+        let soft_source = include_str!("soft_sprite.wgsl");
+
+        for (guid, source) in [
+            (SOFT_SPRITE_SHADER.guid, soft_source.to_string()),
+            (
+                SOFT_SPRITE_SHADER_MS.guid,
+                soft_source.replace("texture_depth_2d", "texture_depth_multisampled_2d"),
+            ),
+        ] {
+            let soft_shader = Shader::new(
+                context,
+                guid,
+                wgpu::ShaderSource::Wgsl(source.into()),
+                what::ShaderStages::FRAGMENT,
+            )
+            .unwrap();
+
+            self.gpu_cache.insert(guid, AssetType::Shader(soft_shader));
+            self.sizes.insert(guid, 0);
+        }
+
+        //Grey checkerboard shown while a texture is still streaming in.
+        const SIZE: u32 = 64;
+        const CELL: u32 = 8;
+
+        let pixels: Vec<u8> = (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let (x, y) = (i % SIZE, i / SIZE);
+                let shade = if (x / CELL + y / CELL) % 2 == 0 { 0xC0 } else { 0x80 };
+                [shade, shade, shade, 0xFF]
+            })
+            .collect();
+
+        let loading_texture =
+            Texture2D::new(context, Some("loading_texture"), (SIZE, SIZE), &pixels);
+        self.gpu_cache.insert(LOADING_TEXTURE.guid, AssetType::Texture2D(loading_texture));
+        self.sizes.insert(LOADING_TEXTURE.guid, (SIZE * SIZE * 4) as usize);
     }
 
     fn request_id<S: Into<String> + AsRef<str>>(&mut self, path: S) -> Guid {
@@ -202,13 +358,24 @@ impl Assets {
         self.gpu_cache.contains_key(&ptr.guid)
     }
 
+    pub fn is_failed(&self, ptr: &GenPtr) -> bool {
+        self.failed.contains(&ptr.guid)
+    }
+
+    pub fn is_pending(&self, ptr: &GenPtr) -> bool {
+        !self.exist(ptr) && !self.is_failed(ptr)
+    }
+
     /*Register an already created asset in the asset manager. This is necessary when you need to reference assets via a Ptr<T>. */
-    pub fn consume_asset<S: Into<String> + AsRef<str>, T>(
+    pub fn consume_asset<S: Into<String> + AsRef<str>, T: TypedAsset>(
         &mut self, asset: AssetType, path: Option<S>,
     ) -> Ptr<T> {
         let guid =
             if let Some(path) = path { self.request_id(path) } else { self.generator.generate() };
 
+        self.report_mismatch(guid, T::KIND, asset.kind());
+        self.sizes.insert(guid, Self::byte_estimate(&asset));
+
         match asset {
             AssetType::TextureArray(texture_array) => {
                 self.gpu_cache.insert(guid, AssetType::TextureArray(texture_array));
@@ -229,6 +396,9 @@ impl Assets {
             AssetType::GenericMaterial(material) => {
                 self.gpu_cache.insert(guid, AssetType::GenericMaterial(material));
             }
+            AssetType::AudioClip(clip) => {
+                self.gpu_cache.insert(guid, AssetType::AudioClip(clip));
+            }
         }
 
         Ptr::new(guid)
@@ -240,10 +410,39 @@ impl Assets {
 
     pub fn update(&mut self) -> Result<(), Guid> {
         while let Ok(content_result) = self.asset_receiver.try_recv() {
-            if let (guid, Ok(content)) = content_result {
+            if let (guid, Ok((mut content, bytes))) = content_result {
+                self.pending.remove(&guid);
+
+                //Deleted while it was still loading.
+                if self.evicted.remove(&guid) {
+                    continue;
+                }
+
+                if let Some(expected) = self.expected_kinds.get(&guid).copied() {
+                    self.report_mismatch(guid, expected, content.kind());
+                }
+
+                //A reloaded texture has to look new to the sprites bound to the old one.
+                if let (Some(AssetType::Texture2D(old)), AssetType::Texture2D(new)) =
+                    (self.gpu_cache.get(&guid), &mut content)
+                {
+                    new.replaces(old);
+                }
+
                 self.gpu_cache.insert(guid, content);
+                self.sizes.insert(guid, bytes);
             } else if let (guid, Err(error)) = content_result {
+                self.pending.remove(&guid);
+
+                if self.evicted.remove(&guid) {
+                    continue;
+                }
+
                 log::error!("{}", error);
+                self.failed.insert(guid);
+
+                self.record_failure(guid, error);
+
                 return Err(guid);
             }
         }
@@ -251,12 +450,62 @@ impl Assets {
         Ok(())
     }
 
+    //Streamed textures drawn up close load their finer mips from the file again. Over budget, the
+    //ones that were not drawn are loaded with their resident mips only. update swaps them in.
+    pub fn update_streaming(&mut self) {
+        let over_budget = self.stats().texture_bytes > self.max_size;
+        let mut requests = Vec::new();
+
+        for (guid, asset) in self.gpu_cache.iter_mut() {
+            let AssetType::Texture2D(texture) = asset else {
+                continue;
+            };
+
+            if self.pending.contains(guid) {
+                continue;
+            }
+
+            if let Some(levels) = texture.stream_request(over_budget) {
+                requests.push((*guid, levels));
+            }
+        }
+
+        for (guid, levels) in requests {
+            if let Some(path) = self.path_cache.get_by_left(&guid).cloned() {
+                self.send_request(&path, guid, 0, Some(levels));
+            }
+        }
+    }
+
+    fn record_failure(&mut self, guid: Guid, error: String) {
+        if self.newly_failed.len() < MAX_LOGGED_FAILURES {
+            self.newly_failed.push((guid, error));
+        } else {
+            self.dropped_failures += 1;
+        }
+    }
+
+    pub(crate) fn take_failed(&mut self) -> Vec<(Guid, String)> {
+        if self.dropped_failures > 0 {
+            log::warn!(
+                "{} more asset failures were not kept for the event log.",
+                std::mem::take(&mut self.dropped_failures)
+            );
+        }
+
+        std::mem::take(&mut self.newly_failed)
+    }
+
     pub fn wait_for(&mut self, ptr: &GenPtr) {
         let spinner = logging::install_bar(ProgressBar::new_spinner()).unwrap();
         spinner.set_style(LOADING_SPINNER_STYLE.clone());
         spinner.set_message(format!("Loading asset: {}", self.asset_path(ptr.guid).unwrap()));
 
         while !self.gpu_cache.contains_key(&ptr.guid) {
+            if self.failed.contains(&ptr.guid) {
+                return;
+            }
+
             if let Err(err) = self.update() {
                 if ptr.guid == err {
                     return;
@@ -269,24 +518,102 @@ impl Assets {
         spinner.finish_with_message("Done!");
     }
 
-    pub fn request_asset<T, S: Into<String> + AsRef<str>>(
+    pub fn request_asset<T: TypedAsset, S: Into<String> + AsRef<str>>(
         &mut self, path: S, priority: usize,
     ) -> Ptr<T> {
-        let path = path.as_ref();
+        self.request(path.as_ref(), priority, None)
+    }
+
+    //An image anywhere on disk instead of in the project, e.g. one dropped onto the window. Relative
+    //paths start at the working directory.
+    pub fn request_file(&mut self, path: impl AsRef<Path>, priority: usize) -> Ptr<Texture2D> {
+        let path = match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => path.as_ref().to_path_buf(),
+        };
+
+        self.request(&path.to_string_lossy(), priority, None)
+    }
 
+    //For files of unknown kind, e.g. dropped ones. Nothing is expected, so nothing can mismatch.
+    pub(crate) fn request_any(&mut self, path: &str, priority: usize) -> GenPtr {
         let guid = self.request_id(path);
 
-        if self.gpu_cache.contains_key(&guid) {
+        if !self.gpu_cache.contains_key(&guid) && !self.pending.contains(&guid) {
+            self.send_request(path, guid, priority, None);
+        }
+
+        GenPtr { guid }
+    }
+
+    //For huge textures (e.g. parallax backgrounds). Only the smallest resident_levels mips are
+    //uploaded at first, finer mips follow when the renderer samples the texture up close.
+    pub fn request_streamed(
+        &mut self, path: impl AsRef<str>, priority: usize, resident_levels: u32,
+    ) -> Ptr<Texture2D> {
+        let levels = StreamLevels { resident_levels, top: None };
+        self.request(path.as_ref(), priority, Some(levels))
+    }
+
+    fn request<T: TypedAsset>(
+        &mut self, path: &str, priority: usize, streamed: Option<StreamLevels>,
+    ) -> Ptr<T> {
+        let guid = self.request_id(path);
+        self.expected_kinds.insert(guid, T::KIND);
+
+        if let Some(actual) = self.gpu_cache.get(&guid).map(AssetType::kind) {
+            self.report_mismatch(guid, T::KIND, actual);
             return Ptr::new(guid);
         }
 
-        if let Err(error) = self.request_sender.send((path.to_owned(), guid, priority)) {
+        if self.send_request(path, guid, priority, streamed) {
+            log::info!("Requested asset: {}", path);
+        }
+
+        Ptr::new(guid)
+    }
+
+    fn send_request(
+        &mut self, path: &str, guid: Guid, priority: usize, streamed: Option<StreamLevels>,
+    ) -> bool {
+        let request = (path.to_owned(), guid, priority, streamed, self.context.clone());
+
+        if let Err(error) = self.request_sender.send(request) {
             log::error!(
                 "Failed to send asset request. Is the asset manager online? Error: {}",
                 error
             );
-        } else {
-            log::info!("Requested asset: {}", path);
+            return false;
+        }
+
+        self.pending.insert(guid);
+        true
+    }
+
+    //Decodes the file right away, audio doesn't go through the asset loader. Loading a path twice
+    //returns the clip loaded first.
+    pub fn load_audio(&mut self, path: &str) -> Ptr<AudioClip> {
+        let guid = self.request_id(path);
+        self.expected_kinds.insert(guid, AssetKind::AudioClip);
+
+        if let Some(actual) = self.gpu_cache.get(&guid).map(AssetType::kind) {
+            self.report_mismatch(guid, AssetKind::AudioClip, actual);
+            return Ptr::new(guid);
+        }
+
+        match AudioClip::from_file(path) {
+            Ok(clip) => {
+                self.sizes.insert(guid, clip.byte_size());
+                self.gpu_cache.insert(guid, AssetType::AudioClip(clip));
+                self.failed.remove(&guid);
+                log::info!("Loaded asset: {}", path);
+            }
+            Err(error) => {
+                log::error!("{}", error);
+                self.failed.insert(guid);
+
+                self.record_failure(guid, error);
+            }
         }
 
         Ptr::new(guid)
@@ -295,13 +622,17 @@ impl Assets {
     //This currently does expend the lifetime of the mutable borrow to the lifetime of the returned reference.
     //Won't get fixed until polonius is stable.
     //Use wait_for() instead.
-    pub fn get<T: 'static>(&mut self, ptr: &Ptr<T>) -> Option<&T> {
+    pub fn get<T: TypedAsset>(&mut self, ptr: &Ptr<T>) -> Option<&T> {
         let here = self.gpu_cache.contains_key(&ptr.guid);
 
         if !here {
             self.wait_for(&(*ptr).into());
         }
 
+        if let Some(actual) = self.gpu_cache.get(&ptr.guid).map(AssetType::kind) {
+            self.report_mismatch(ptr.guid, T::KIND, actual);
+        }
+
         self.gpu_cache.get(&ptr.guid).and_then(|asset| match asset {
             AssetType::TextureArray(texture_array) => {
                 (texture_array as &dyn Any).downcast_ref::<T>()
@@ -311,6 +642,7 @@ impl Assets {
             AssetType::Uniforms(uniforms) => (uniforms as &dyn Any).downcast_ref::<T>(),
             AssetType::Sampler(sampler) => (sampler as &dyn Any).downcast_ref::<T>(),
             AssetType::GenericMaterial(material) => (material as &dyn Any).downcast_ref::<T>(),
+            AssetType::AudioClip(clip) => (clip as &dyn Any).downcast_ref::<T>(),
         })
     }
 
@@ -324,6 +656,7 @@ impl Assets {
             AssetType::Uniforms(uniforms) => (uniforms as &mut dyn Any).downcast_mut::<T>(),
             AssetType::Sampler(sampler) => (sampler as &mut dyn Any).downcast_mut::<T>(),
             AssetType::GenericMaterial(material) => (material as &mut dyn Any).downcast_mut::<T>(),
+            AssetType::AudioClip(clip) => (clip as &mut dyn Any).downcast_mut::<T>(),
         })
     }
 
@@ -337,9 +670,31 @@ impl Assets {
             AssetType::Uniforms(uniforms) => (uniforms as &dyn Any).downcast_ref::<T>(),
             AssetType::Sampler(sampler) => (sampler as &dyn Any).downcast_ref::<T>(),
             AssetType::GenericMaterial(material) => (material as &dyn Any).downcast_ref::<T>(),
+            AssetType::AudioClip(clip) => (clip as &dyn Any).downcast_ref::<T>(),
         })
     }
 
+    //Same as try_get, but tells apart a missing asset from one of the wrong type.
+    pub fn get_checked<T: TypedAsset>(&self, ptr: &Ptr<T>) -> Result<&T, AssetError> {
+        let asset = self.gpu_cache.get(&ptr.guid).ok_or(AssetError::NotLoaded(ptr.guid))?;
+
+        if asset.kind() != T::KIND {
+            return Err(self.mismatch(ptr.guid, T::KIND, asset.kind()));
+        }
+
+        self.try_get(ptr).ok_or(AssetError::NotLoaded(ptr.guid))
+    }
+
+    fn mismatch(&self, guid: Guid, expected: AssetKind, actual: AssetKind) -> AssetError {
+        AssetError::Mismatch { path: self.asset_path(guid).cloned(), expected, actual }
+    }
+
+    fn report_mismatch(&mut self, guid: Guid, expected: AssetKind, actual: AssetKind) {
+        if expected != actual && self.mismatches.insert((guid, expected)) {
+            log::error!("{}", self.mismatch(guid, expected, actual));
+        }
+    }
+
     pub fn try_get_entry(&self, ptr: &GenPtr) -> Option<&dyn BindGroupEntry> {
         self.gpu_cache.get(&ptr.guid).and_then(|asset| match asset {
             AssetType::TextureArray(texture_array) => Some(texture_array as &dyn BindGroupEntry),
@@ -347,15 +702,196 @@ impl Assets {
             AssetType::Shader(_shader) => None,
             AssetType::Uniforms(uniforms) => Some(uniforms as &dyn BindGroupEntry),
             AssetType::Sampler(sampler) => Some(sampler as &dyn BindGroupEntry),
-            AssetType::GenericMaterial(_) => None,
+            AssetType::GenericMaterial(_) | AssetType::AudioClip(_) => None,
         })
     }
 
-    pub fn delete_asset(&mut self, guid: Guid) {
-        self.gpu_cache.remove(&guid);
+    pub fn is_static(guid: Guid) -> bool {
+        guid == SPRITE_SHADER.guid
+            || guid == BACKGROUND_SHADER.guid
+            || guid == LOADING_TEXTURE.guid
+            || guid == POST_PROCESS_SHADER.guid
+            || guid == SOFT_SPRITE_SHADER.guid
+            || guid == SOFT_SPRITE_SHADER_MS.guid
+            || guid == TRANSITION_SHADER.guid
+            || guid == LIGHTING_SHADER.guid
+            || guid == PARTICLE_SHADER.guid
     }
 
-    fn load_asset(context: &VisContext, asset: what::Asset, guid: Guid) -> Option<AssetType> {
+    //Removes the asset and forgets its path, so a later request_asset loads it again.
+    pub fn delete_asset(&mut self, guid: Guid) -> bool {
+        if Self::is_static(guid) {
+            log::warn!(
+                "Refusing to delete static asset {:?}. Everything using it would break.",
+                guid
+            );
+            return false;
+        }
+
+        if self.pending.remove(&guid) {
+            self.evicted.insert(guid);
+        }
+
+        self.path_cache.remove_by_left(&guid);
+        self.expected_kinds.remove(&guid);
+        self.mismatches.retain(|(mismatched, _)| *mismatched != guid);
+        self.failed.remove(&guid);
+        self.sizes.remove(&guid);
+        self.gpu_cache.remove(&guid).is_some()
+    }
+
+    pub fn delete<T>(&mut self, ptr: &Ptr<T>) -> bool {
+        self.delete_asset(ptr.guid)
+    }
+
+    pub fn delete_by_path(&mut self, path: &str) -> bool {
+        match self.path_cache.get_by_right(path).copied() {
+            Some(guid) => self.delete_asset(guid),
+            None => false,
+        }
+    }
+
+    //Loads the file again and swaps it in once done. Pointers to the asset stay valid,
+    //the old version is used until the new one arrived.
+    pub fn reload(&mut self, path: &str) -> bool {
+        let Some(guid) = self.path_cache.get_by_right(path).copied() else {
+            return false;
+        };
+
+        if Self::is_static(guid) || self.pending.contains(&guid) {
+            return false;
+        }
+
+        //Decoded in place, the old clip is gone if that fails.
+        if self.expected_kinds.get(&guid) == Some(&AssetKind::AudioClip) {
+            self.gpu_cache.remove(&guid);
+            self.load_audio(path);
+            return self.gpu_cache.contains_key(&guid);
+        }
+
+        //Streamed textures come back with the mips they have now.
+        let streamed = match self.gpu_cache.get(&guid) {
+            Some(AssetType::Texture2D(texture)) => texture.stream_levels(),
+            _ => None,
+        };
+
+        if !self.send_request(path, guid, 0, streamed) {
+            return false;
+        }
+
+        self.failed.remove(&guid);
+        log::info!("Reloading asset: {}", path);
+        true
+    }
+
+    //Moves everything onto a new device after the old one was lost. Assets with a path are loaded
+    //again and their pointers stay valid, assets without one (e.g. consumed ones) are gone.
+    pub fn rebuild(&mut self, context: Arc<VisContext>) {
+        //Audio clips don't live on the device, they stay as they are.
+        let audio: HashSet<Guid> = self
+            .gpu_cache
+            .iter()
+            .filter(|(_, asset)| asset.kind() == AssetKind::AudioClip)
+            .map(|(guid, _)| *guid)
+            .collect();
+
+        let streamed = |guid: &Guid| match self.gpu_cache.get(guid) {
+            Some(AssetType::Texture2D(texture)) => texture.stream_levels(),
+            _ => None,
+        };
+
+        let reload: HashMap<Guid, (String, Option<StreamLevels>)> = self
+            .gpu_cache
+            .keys()
+            .chain(self.pending.iter())
+            .filter(|guid| !Self::is_static(**guid) && !audio.contains(*guid))
+            .filter_map(|guid| {
+                let path = self.path_cache.get_by_left(guid)?;
+                Some((*guid, (path.clone(), streamed(guid))))
+            })
+            .collect();
+
+        //Loads still in flight finish on the old device. Their results are dropped like those of
+        //deleted assets, the new request follows right behind them.
+        self.evicted.extend(self.pending.drain());
+        self.gpu_cache.retain(|guid, _| audio.contains(guid));
+        self.sizes.retain(|guid, _| audio.contains(guid));
+        self.failed.clear();
+
+        self.context = context.clone();
+        self.register_static(&context);
+
+        for (guid, (path, streamed)) in reload {
+            self.send_request(&path, guid, 0, streamed);
+        }
+
+        log::info!("Rebuilt assets on the new device.");
+    }
+
+    pub fn stats(&self) -> AssetStats {
+        let mut stats = AssetStats {
+            pending: self.pending.len(),
+            max_size: self.max_size,
+            ..Default::default()
+        };
+
+        for (guid, asset) in self.gpu_cache.iter() {
+            let bytes = self.sizes.get(guid).copied().unwrap_or(0);
+
+            match asset.kind() {
+                AssetKind::Texture2D | AssetKind::TextureArray => stats.texture_bytes += bytes,
+                AssetKind::Uniforms => stats.buffer_bytes += bytes,
+                AssetKind::AudioClip => stats.audio_bytes += bytes,
+                _ => {}
+            }
+
+            *stats.counts.entry(asset.kind()).or_default() += 1;
+        }
+
+        stats
+    }
+
+    //Lists everything that is currently resident. Assets without a path yield an empty string.
+    pub fn iter_loaded(&self) -> impl Iterator<Item = (Guid, &str, AssetKind, usize)> + '_ {
+        self.gpu_cache.iter().map(|(guid, asset)| {
+            let path = self.asset_path(*guid).map(String::as_str).unwrap_or("");
+            (*guid, path, asset.kind(), self.sizes.get(guid).copied().unwrap_or(0))
+        })
+    }
+
+    fn byte_estimate(asset: &AssetType) -> usize {
+        match asset {
+            AssetType::TextureArray(texture_array) => {
+                let extend = texture_array.extend();
+                (extend.width * extend.height * extend.depth_or_array_layers * 4) as usize
+            }
+            AssetType::Texture2D(texture) => texture.byte_size(),
+            AssetType::Uniforms(uniforms) => uniforms.size(),
+            AssetType::AudioClip(clip) => clip.byte_size(),
+            AssetType::Shader(_) | AssetType::Sampler(_) | AssetType::GenericMaterial(_) => 0,
+        }
+    }
+
+    fn load_file(
+        context: &VisContext, path: &str, streamed: Option<StreamLevels>,
+    ) -> Result<AssetType, String> {
+        let data = std::fs::read(path)
+            .map_err(|error| format!("Failed to read {}. Error: {}", path, error))?;
+        let image = image::load_from_memory(&data)
+            .map_err(|error| format!("Failed to load texture {}. Error: {}", path, error))?;
+        let rgba = image.to_rgba8();
+
+        let texture = match streamed {
+            Some(levels) => Texture2D::new_streamed(context, None, &rgba, levels),
+            None => Texture2D::new(context, None, image.dimensions(), &rgba),
+        };
+
+        Ok(AssetType::Texture2D(texture))
+    }
+
+    fn load_asset(
+        context: &VisContext, asset: what::Asset, guid: Guid, streamed: Option<StreamLevels>,
+    ) -> Option<AssetType> {
         match asset {
             what::Asset::Texture(texture) => {
                 let texture_data = image::load_from_memory(&texture.data);
@@ -364,6 +900,12 @@ impl Assets {
                     Ok(image) => {
                         let rgba = image.to_rgba8();
 
+                        if let Some(levels) = streamed {
+                            return Some(AssetType::Texture2D(Texture2D::new_streamed(
+                                context, None, &rgba, levels,
+                            )));
+                        }
+
                         Some(AssetType::Texture2D(Texture2D::new(
                             context,
                             None,
@@ -397,7 +939,20 @@ impl Assets {
 
                         if let Ok(image) = image::load_from_memory(image) {
                             let rgba = image.to_rgba8();
-                            texture.upload(context, &rgba, i as u32);
+
+                            if rgba.dimensions() == (texture_array.size, texture_array.size) {
+                                texture.upload(context, &rgba, i as u32);
+                            } else {
+                                log::error!(
+                                    "Texture array layer {} is {}x{}, expected {}x{}. Loading error texture instead...",
+                                    i,
+                                    rgba.width(),
+                                    rgba.height(),
+                                    texture_array.size,
+                                    texture_array.size
+                                );
+                                texture.upload_error_texture(context, i as u32);
+                            }
                         } else {
                             log::error!("Failed to load texture. Loading error texture instead...");
                             texture.upload_error_texture(context, i as u32);
@@ -413,12 +968,17 @@ impl Assets {
                 Some(AssetType::TextureArray(texture))
             }
             what::Asset::Shader(shader) => {
+                //Recorded by the packer, e.g. main if it was compiled from GLSL.
+                let vertex = shader.vertex_entry.unwrap_or_else(|| VERTEX_ENTRY.to_string());
+                let fragment = shader.fragment_entry.unwrap_or_else(|| FRAGMENT_ENTRY.to_string());
+
                 if let Ok(shader) = Shader::new(
                     context,
                     guid,
                     wgpu::ShaderSource::SpirV(shader.data.into()),
                     shader.stages,
                 ) {
+                    let shader = shader.with_entry_points(vertex, fragment);
                     Some(AssetType::Shader(shader))
                 } else {
                     log::error!("Failed to load shader. Loading error shader instead.");
@@ -0,0 +1,41 @@
+#[cfg(not(target_arch = "wasm32"))]
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+
+//A sound decoded into memory, played through AudioEngine::play_clip. Long music is better off
+//streamed, see AudioEngine::play_background.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct AudioClip {
+    data: StaticSoundData,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AudioClip {
+    //Blocks until the whole file is decoded.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        StaticSoundData::from_file(path, StaticSoundSettings::default())
+            .map(|data| AudioClip { data })
+            .map_err(|error| format!("Failed to load audio clip {}. Error: {}", path, error))
+    }
+
+    pub(crate) fn data(&self) -> &StaticSoundData {
+        &self.data
+    }
+
+    pub fn byte_size(&self) -> usize {
+        std::mem::size_of_val(&*self.data.frames)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct AudioClip {}
+
+#[cfg(target_arch = "wasm32")]
+impl AudioClip {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        Err(format!("Failed to load audio clip {}. Audio is not supported on the web.", path))
+    }
+
+    pub fn byte_size(&self) -> usize {
+        0
+    }
+}
@@ -28,8 +28,14 @@ fn vertex_main(
     return out;
 }
 
+struct Background {
+    color: vec4<f32>,
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+};
+
 @group(0) @binding(0)
-var<uniform> color: vec4<f32>;
+var<uniform> background: Background;
 
 @group(0) @binding(1)
 var texture: texture_2d<f32>;
@@ -38,5 +44,8 @@ var texture_sampler: sampler;
 
 @fragment
 fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, texture_sampler, in.texture_coords) * color;
+    //Top of the screen is the top row of the image.
+    let screen = vec2<f32>(in.texture_coords.x, 1.0 - in.texture_coords.y);
+    let uv = screen * background.uv_scale + background.uv_offset;
+    return textureSample(texture, texture_sampler, uv) * background.color;
 }
\ No newline at end of file
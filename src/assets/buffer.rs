@@ -3,7 +3,7 @@ use std::num::NonZeroU64;
 use crate::context::VisContext;
 use crate::render::types::{BindGroupEntry, IndexBuffer, VertexBuffer, VertexLayout};
 
-use wgpu::util::DeviceExt;
+use wgpu::util::{DeviceExt, StagingBelt};
 
 #[derive(Debug)]
 pub struct UniformBuffer {
@@ -27,6 +27,18 @@ impl UniformBuffer {
         context.queue.write_buffer(&self.buffer, 0, data);
     }
 
+    //Records the copy into the encoder through the belt, instead of a queue write of its own.
+    //The belt has to be finished before the encoder is submitted.
+    pub fn update_buffer_staged(
+        &mut self, context: &VisContext, encoder: &mut wgpu::CommandEncoder,
+        belt: &mut StagingBelt, data: &[u8],
+    ) {
+        if let Some(size) = wgpu::BufferSize::new(data.len() as u64) {
+            belt.write_buffer(encoder, &self.buffer, 0, size, &context.device)
+                .copy_from_slice(data);
+        }
+    }
+
     pub fn size(&self) -> usize {
         self.size
     }
@@ -0,0 +1,65 @@
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+    //Rendered once per face, the instance is the layer of the cube map.
+    @builtin(instance_index) face: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texture_coords: vec2<f32>,
+    @location(1) @interpolate(flat) face: u32,
+};
+
+@vertex
+fn vertex_main(
+    mesh: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    //Generate a big triangle over the face. Texture coordinates start at the top left.
+    if mesh.vertex_index == 0u {
+        out.clip_position = vec4<f32>(-1.0, -1.0, 0.0, 1.0); // bottom left
+        out.texture_coords = vec2<f32>(0.0, 1.0);
+    } else if mesh.vertex_index == 1u {
+        out.clip_position = vec4<f32>(3.0, -1.0, 0.0, 1.0); // bottom right
+        out.texture_coords = vec2<f32>(2.0, 1.0);
+    } else {
+        out.clip_position = vec4<f32>(-1.0, 3.0, 0.0, 1.0); // top left
+        out.texture_coords = vec2<f32>(0.0, -1.0);
+    }
+
+    out.face = mesh.face;
+    return out;
+}
+
+@group(0) @binding(0)
+var panorama: texture_2d<f32>;
+@group(0) @binding(1)
+var panorama_sampler: sampler;
+
+const PI: f32 = 3.14159265359;
+
+//Direction through a texel of a face, in the layer order +X, -X, +Y, -Y, +Z, -Z.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let a = uv.x * 2.0 - 1.0;
+    let b = uv.y * 2.0 - 1.0;
+
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -b, -a); }
+        case 1u: { return vec3<f32>(-1.0, -b, a); }
+        case 2u: { return vec3<f32>(a, 1.0, b); }
+        case 3u: { return vec3<f32>(a, -1.0, -b); }
+        case 4u: { return vec3<f32>(a, -b, 1.0); }
+        default: { return vec3<f32>(-a, -b, -1.0); }
+    }
+}
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(face_direction(in.face, in.texture_coords));
+
+    //Longitude around the y axis, latitude from the top of the panorama.
+    let u = atan2(direction.x, -direction.z) / (2.0 * PI) + 0.5;
+    let v = acos(clamp(direction.y, -1.0, 1.0)) / PI;
+
+    return textureSampleLevel(panorama, panorama_sampler, vec2<f32>(u, v), 0.0);
+}
@@ -0,0 +1,68 @@
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vertex_main(
+    mesh: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    //Generate a big triangle over the viewport.
+    if mesh.vertex_index == 0u {
+        out.clip_position = vec4<f32>(-1.0, -1.0, 0.0, 1.0); // bottom left
+    } else if mesh.vertex_index == 1u {
+        out.clip_position = vec4<f32>(3.0, -1.0, 0.0, 1.0); // bottom right
+    } else {
+        out.clip_position = vec4<f32>(-1.0, 3.0, 0.0, 1.0); // top left
+    }
+
+    out.ndc = out.clip_position.xy;
+    return out;
+}
+
+struct PointLight {
+    position: vec2<f32>,
+    radius: f32,
+    falloff: f32,
+    //Color times intensity.
+    color: vec4<f32>,
+};
+
+//Has to match MAX_LIGHTS in render/types.rs.
+const MAX_LIGHTS: u32 = 32u;
+
+struct Lighting {
+    inverse_view_projection: mat4x4<f32>,
+    ambient: vec4<f32>,
+    count: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+    lights: array<PointLight, MAX_LIGHTS>,
+};
+
+@group(0) @binding(0)
+var<uniform> lighting: Lighting;
+
+//The output is multiplied with the lit world, so 1 leaves it as it is.
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let world = lighting.inverse_view_projection * vec4<f32>(in.ndc, 0.0, 1.0);
+    let position = world.xy / world.w;
+
+    var light = lighting.ambient.rgb;
+
+    for (var i = 0u; i < min(lighting.count, MAX_LIGHTS); i = i + 1u) {
+        let point = lighting.lights[i];
+        let distance = length(position - point.position);
+        let attenuation = pow(clamp(1.0 - distance / point.radius, 0.0, 1.0), point.falloff);
+        light += point.color.rgb * attenuation;
+    }
+
+    return vec4<f32>(light, 1.0);
+}
@@ -1,6 +1,9 @@
 pub mod assets;
+pub mod audio;
 pub mod buffer;
 pub mod ldtk;
 pub mod shader;
+pub mod sprite_sheet;
+pub mod streaming;
 pub mod texture;
 pub mod types;
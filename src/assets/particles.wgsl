@@ -0,0 +1,56 @@
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    // x: shear, y: number of sorting bands
+    params: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texture_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32,
+    particle: InstanceInput,
+) -> VertexOutput {
+    //Two triangles of a quad, counter clockwise.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.texture_coords = vec2<f32>(corner.x + 0.5, 0.5 - corner.y);
+    out.color = particle.color;
+
+    let world = particle.position.xy + corner * particle.size;
+    out.clip_position = camera.view_projection * vec4<f32>(world, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var texture: texture_2d<f32>;
+@group(0) @binding(1)
+var texture_sampler: sampler;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(texture, texture_sampler, in.texture_coords) * in.color;
+}
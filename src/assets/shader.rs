@@ -1,7 +1,9 @@
 use crate::{context::VisContext, utils::Guid};
+use once_cell::sync::Lazy;
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
+    sync::{Mutex, PoisonError},
 };
 
 use super::assets::Ptr;
@@ -58,10 +60,39 @@ impl<'a> ShaderVariant<'a> {
     }
 }
 
+//Entry points the engine's own WGSL shaders use.
+pub const VERTEX_ENTRY: &str = "vertex_main";
+pub const FRAGMENT_ENTRY: &str = "fragment_main";
+
+//Entry point names are interned when a shader is created, pipeline keys only hold their ids.
+static ENTRY_NAMES: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EntryPoint(u32);
+
+impl EntryPoint {
+    pub fn intern(name: &str) -> EntryPoint {
+        let mut names = ENTRY_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let index = match names.iter().position(|known| known == name) {
+            Some(index) => index,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        };
+
+        EntryPoint(index as u32)
+    }
+}
+
 pub struct Shader {
     module: wgpu::ShaderModule,
     stages: what::ShaderStages,
     guid: Guid,
+    vertex_entry: String,
+    fragment_entry: String,
+    entry_ids: (EntryPoint, EntryPoint),
 }
 
 impl Shader {
@@ -72,7 +103,38 @@ impl Shader {
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source });
 
-        Ok(Self { module, stages, guid })
+        Ok(Self {
+            module,
+            stages,
+            guid,
+            vertex_entry: VERTEX_ENTRY.to_string(),
+            fragment_entry: FRAGMENT_ENTRY.to_string(),
+            entry_ids: (EntryPoint::intern(VERTEX_ENTRY), EntryPoint::intern(FRAGMENT_ENTRY)),
+        })
+    }
+
+    //E.g. "main" for SPIR-V compiled from GLSL.
+    pub fn with_entry_points(
+        mut self, vertex: impl Into<String>, fragment: impl Into<String>,
+    ) -> Self {
+        self.vertex_entry = vertex.into();
+        self.fragment_entry = fragment.into();
+        self.entry_ids =
+            (EntryPoint::intern(&self.vertex_entry), EntryPoint::intern(&self.fragment_entry));
+        self
+    }
+
+    pub fn vertex_entry(&self) -> &str {
+        &self.vertex_entry
+    }
+
+    pub fn fragment_entry(&self) -> &str {
+        &self.fragment_entry
+    }
+
+    //Vertex and fragment entry point, cheap to compare and hash.
+    pub fn entry_ids(&self) -> (EntryPoint, EntryPoint) {
+        self.entry_ids
     }
 
     pub fn change_guid(&mut self, guid: Guid) {
@@ -87,3 +149,17 @@ impl Shader {
         self.stages
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_points_are_interned_once() {
+        let main = EntryPoint::intern("main");
+
+        assert_eq!(EntryPoint::intern("main"), main);
+        assert_ne!(EntryPoint::intern(VERTEX_ENTRY), main);
+        assert_ne!(EntryPoint::intern(VERTEX_ENTRY), EntryPoint::intern(FRAGMENT_ENTRY));
+    }
+}
@@ -0,0 +1,56 @@
+// Fragment stage for soft sprites, used with the vertex stage of sprite.wgsl.
+// Fades the sprite out where it gets close to what is already in the depth buffer.
+// Custom soft shaders get the scene depth at @group(3) @binding(0): texture_depth_2d without MSAA,
+// texture_depth_multisampled_2d with it. Both are read with textureLoad.
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    // x: shear, y: number of sorting bands
+    params: vec4<f32>,
+};
+
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Has to match sprite.wgsl.
+const DEPTH_RANGE: f32 = 1000.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texture_coords: vec2<f32>,
+};
+
+struct SpriteColor {
+    tint: vec4<f32>,
+    opacity: f32,
+    // Distance in world z over which the sprite fades in.
+    soft_distance: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> color: SpriteColor;
+
+@group(0) @binding(1)
+var texture: texture_2d<f32>;
+@group(0) @binding(2)
+var texture_sampler: sampler;
+
+@group(3) @binding(0)
+var scene_depth: texture_depth_2d;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var sampled = textureSample(texture, texture_sampler, in.texture_coords) * color.tint;
+    sampled.a *= color.opacity;
+
+    // Back from depth to world z. Only meaningful within a sorting band, other bands are far away anyway.
+    let scene = textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0);
+    let distance = (scene - in.clip_position.z) * max(camera.params.y, 1.0) * 2.0 * DEPTH_RANGE;
+    sampled.a *= clamp(distance / max(color.soft_distance, 0.0001), 0.0, 1.0);
+
+    if sampled.a < 0.01 {
+        discard;
+    }
+
+    return sampled;
+}
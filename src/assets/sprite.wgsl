@@ -2,10 +2,18 @@
 
 struct CameraUniform {
     view_projection: mat4x4<f32>,
+    // x: shear, y: number of sorting bands
+    params: vec4<f32>,
+};
+
+struct TransformUniform {
+    matrix: mat4x4<f32>,
+    // x: billboard enabled, y: local y of the foot, z: sorting band
+    billboard: vec4<f32>,
 };
 
 @group(1) @binding(0)
-var<uniform> transform: mat4x4<f32>;
+var<uniform> transform: TransformUniform;
 
 @group(2) @binding(0)
 var<uniform> camera: CameraUniform;
@@ -15,6 +23,9 @@ struct VertexInput {
     @location(1) texture_coords: vec2<f32>,
 };
 
+//World z in [-DEPTH_RANGE, DEPTH_RANGE] is mapped to depth within the sorting band. It does not affect x/y.
+const DEPTH_RANGE: f32 = 1000.0;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) texture_coords: vec2<f32>,
@@ -27,12 +38,33 @@ fn vertex_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.texture_coords = mesh.texture_coords;
-    out.clip_position = camera.view_projection * transform * vec4<f32>(mesh.position, 1.0);
+    var world = transform.matrix * vec4<f32>(mesh.position, 1.0);
+
+    // On a sheared ground plane, things further up are further back. Billboards use their foot for all vertices.
+    var ground_y = world.y;
+    if transform.billboard.x > 0.5 {
+        ground_y = (transform.matrix * vec4<f32>(mesh.position.x, transform.billboard.y, mesh.position.z, 1.0)).y;
+        // The projection squashes the ground by 1 - shear, the billboard keeps its height above the foot.
+        world.y = ground_y + (world.y - ground_y) / (1.0 - camera.params.x);
+    }
+
+    out.clip_position = camera.view_projection * vec4<f32>(world.xy, 0.0, 1.0);
+
+    // Higher bands are in front, z only orders within a band.
+    let z = world.z - camera.params.x * ground_y;
+    let in_band = clamp(0.5 + z / (2.0 * DEPTH_RANGE), 0.0, 1.0);
+    let depth = clamp(1.0 - (transform.billboard.z + in_band) / max(camera.params.y, 1.0), 0.0, 1.0);
+    out.clip_position.z = depth * out.clip_position.w;
     return out;
 }
 
+struct SpriteColor {
+    tint: vec4<f32>,
+    opacity: f32,
+};
+
 @group(0) @binding(0)
-var<uniform> color: vec4<f32>;
+var<uniform> color: SpriteColor;
 
 @group(0) @binding(1)
 var texture: texture_2d<f32>;
@@ -41,5 +73,13 @@ var texture_sampler: sampler;
 
 @fragment
 fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, texture_sampler, in.texture_coords) * color;
+    var sampled = textureSample(texture, texture_sampler, in.texture_coords) * color.tint;
+    sampled.a *= color.opacity;
+
+    //Fully transparent texels must not write depth.
+    if sampled.a < 0.01 {
+        discard;
+    }
+
+    return sampled;
 }
\ No newline at end of file
@@ -0,0 +1,316 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use super::assets::{Assets, Ptr};
+use super::texture::Texture2D;
+
+//Extension of the metadata written next to the source image, e.g. hero.png -> hero.sheet.json.
+pub const SHEET_EXTENSION: &str = "sheet.json";
+
+//How the image is cut. Either the size of one cell or the number of columns and rows, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetLayout {
+    Cells { width: u32, height: u32 },
+    Grid { columns: u32, rows: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clip {
+    pub name: String,
+    //Indices into the frames.
+    pub frames: Vec<usize>,
+}
+
+//Frame rects of a grid sprite sheet, with one suggested clip per row.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpriteSheet {
+    //Asset path of the image, relative to the sheet.
+    pub texture: String,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Frame>,
+    pub clips: Vec<Clip>,
+}
+
+#[derive(Debug)]
+pub enum SheetError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+    Empty,
+    //The cells do not fill the image along an axis. Size is the image size, used the pixels covered by the cells.
+    Mismatch { axis: &'static str, size: u32, cell: u32, margin: u32, spacing: u32, used: u32 },
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SheetError::Io(e) => write!(f, "Could not access the sheet. {}", e),
+            SheetError::Image(e) => write!(f, "Could not read the image. {}", e),
+            SheetError::Json(e) => write!(f, "Invalid sheet metadata. {}", e),
+            SheetError::Empty => write!(f, "The layout does not fit a single cell."),
+            SheetError::Mismatch { axis, size, cell, margin, spacing, used } => write!(
+                f,
+                "The {} of {}px is no whole number of {}px cells (margin {}, spacing {}): \
+                 the cells cover {}px, {}px are left over.",
+                axis,
+                size,
+                cell,
+                margin,
+                spacing,
+                used,
+                size.saturating_sub(*used)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+impl From<std::io::Error> for SheetError {
+    fn from(e: std::io::Error) -> Self {
+        SheetError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for SheetError {
+    fn from(e: image::ImageError) -> Self {
+        SheetError::Image(e)
+    }
+}
+
+impl From<serde_json::Error> for SheetError {
+    fn from(e: serde_json::Error) -> Self {
+        SheetError::Json(e)
+    }
+}
+
+//Cell size and count along one axis. margin is around the whole sheet, spacing between cells.
+fn fit_axis(
+    axis: &'static str, size: u32, layout: (Option<u32>, Option<u32>), margin: u32, spacing: u32,
+) -> Result<(u32, u32), SheetError> {
+    //Margins and spacings come from the user, they may be anything up to u32::MAX.
+    let inner = margin
+        .checked_mul(2)
+        .and_then(|margins| size.checked_sub(margins))
+        .ok_or(SheetError::Empty)?;
+
+    let (cell, count) = match layout {
+        (Some(cell), _) => {
+            if cell == 0 {
+                return Err(SheetError::Empty);
+            }
+            let count = (inner as u64 + spacing as u64) / (cell as u64 + spacing as u64);
+            (cell, count as u32)
+        }
+        (_, Some(count)) => {
+            if count == 0 {
+                return Err(SheetError::Empty);
+            }
+            let cells = inner.saturating_sub((count - 1).saturating_mul(spacing));
+            (cells / count, count)
+        }
+        (None, None) => return Err(SheetError::Empty),
+    };
+
+    if cell == 0 || count == 0 {
+        return Err(SheetError::Empty);
+    }
+
+    let used = 2 * margin as u64 + count as u64 * cell as u64 + (count as u64 - 1) * spacing as u64;
+
+    if used != size as u64 {
+        let used = u32::try_from(used).unwrap_or(u32::MAX);
+        return Err(SheetError::Mismatch { axis, size, cell, margin, spacing, used });
+    }
+
+    Ok((cell, count))
+}
+
+impl SpriteSheet {
+    //Cuts an image of the given size into a grid. Frames go row by row, clips are named row_0..n.
+    pub fn slice(
+        texture: impl Into<String>, size: (u32, u32), layout: SheetLayout, margin: u32,
+        spacing: u32,
+    ) -> Result<SpriteSheet, SheetError> {
+        let (x_layout, y_layout) = match layout {
+            SheetLayout::Cells { width, height } => ((Some(width), None), (Some(height), None)),
+            SheetLayout::Grid { columns, rows } => ((None, Some(columns)), (None, Some(rows))),
+        };
+
+        let (width, columns) = fit_axis("width", size.0, x_layout, margin, spacing)?;
+        let (height, rows) = fit_axis("height", size.1, y_layout, margin, spacing)?;
+
+        let mut frames = Vec::new();
+        let mut clips = Vec::new();
+
+        for row in 0..rows {
+            let first = frames.len();
+
+            for column in 0..columns {
+                frames.push(Frame {
+                    x: margin + column * (width + spacing),
+                    y: margin + row * (height + spacing),
+                    width,
+                    height,
+                });
+            }
+
+            clips.push(Clip {
+                name: format!("row_{}", row),
+                frames: (first..frames.len()).collect(),
+            });
+        }
+
+        Ok(SpriteSheet { texture: texture.into(), width: size.0, height: size.1, frames, clips })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SpriteSheet, SheetError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SheetError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn clip(&self, name: &str) -> Option<&Clip> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+
+    //Texture coordinates (min, max) of a frame.
+    pub fn uv(&self, frame: usize) -> Option<(Vec2, Vec2)> {
+        let frame = self.frames.get(frame)?;
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let min = Vec2::new(frame.x as f32, frame.y as f32) / size;
+        let max = Vec2::new((frame.x + frame.width) as f32, (frame.y + frame.height) as f32) / size;
+        Some((min, max))
+    }
+
+    pub fn clip_uvs(&self, name: &str) -> Option<Vec<(Vec2, Vec2)>> {
+        self.clip(name)?.frames.iter().map(|frame| self.uv(*frame)).collect()
+    }
+
+    //Asset path of the image, sheet is the asset path of the sheet itself.
+    pub fn texture_path(&self, sheet: &str) -> String {
+        match sheet.rfind(|c| c == '/' || c == '\\') {
+            Some(end) => format!("{}/{}", &sheet[..end], self.texture),
+            None => self.texture.clone(),
+        }
+    }
+
+    //The image is an asset like any other, hot reloading it keeps the sheet working as long as the
+    //grid stays. sheet is the asset path of the sheet, see texture_path.
+    pub fn request_texture(&self, assets: &mut Assets, sheet: &str) -> Ptr<Texture2D> {
+        assets.request_asset(self.texture_path(sheet), 0)
+    }
+}
+
+pub fn sheet_path(image: &Path) -> PathBuf {
+    image.with_extension(SHEET_EXTENSION)
+}
+
+//Slices the png at path and writes the metadata next to it. Returns where it was written.
+pub fn import_sheet(
+    path: &Path, layout: SheetLayout, margin: u32, spacing: u32,
+) -> Result<(PathBuf, SpriteSheet), SheetError> {
+    let size = image::image_dimensions(path)?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let sheet = SpriteSheet::slice(name, size, layout, margin, spacing)?;
+    let target = sheet_path(path);
+    sheet.save(&target)?;
+
+    log::info!(
+        "Imported {} as {} frames in {} clips to {}",
+        path.display(),
+        sheet.frames.len(),
+        sheet.clips.len(),
+        target.display()
+    );
+
+    Ok((target, sheet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_is_next_to_the_sheet() {
+        let sheet = SpriteSheet::slice(
+            "hero.png",
+            (32, 16),
+            SheetLayout::Grid { columns: 2, rows: 1 },
+            0,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(sheet.texture_path("textures/chars/hero.sheet.json"), "textures/chars/hero.png");
+        assert_eq!(sheet.texture_path("hero.sheet.json"), "hero.png");
+    }
+
+    #[test]
+    fn huge_margin_is_empty() {
+        let sheet = SpriteSheet::slice(
+            "hero.png",
+            (32, 32),
+            SheetLayout::Cells { width: 16, height: 16 },
+            u32::MAX / 2 + 1,
+            0,
+        );
+
+        assert!(matches!(sheet, Err(SheetError::Empty)));
+    }
+
+    #[test]
+    fn huge_spacing_does_not_overflow() {
+        let sheet = SpriteSheet::slice(
+            "hero.png",
+            (32, 32),
+            SheetLayout::Grid { columns: 3, rows: 1 },
+            0,
+            u32::MAX,
+        );
+
+        assert!(matches!(sheet, Err(SheetError::Empty)));
+
+        let sheet = SpriteSheet::slice(
+            "hero.png",
+            (32, 32),
+            SheetLayout::Cells { width: u32::MAX, height: 16 },
+            0,
+            u32::MAX,
+        );
+
+        assert!(matches!(sheet, Err(SheetError::Empty)));
+    }
+
+    #[test]
+    fn margin_and_spacing_are_skipped() {
+        let sheet = SpriteSheet::slice(
+            "hero.png",
+            (37, 18),
+            SheetLayout::Cells { width: 16, height: 16 },
+            1,
+            3,
+        )
+        .unwrap();
+
+        assert_eq!(sheet.frames[1], Frame { x: 20, y: 1, width: 16, height: 16 });
+    }
+}
@@ -0,0 +1,203 @@
+use crate::context::VisContext;
+
+pub fn mip_count(dim: (u32, u32)) -> u32 {
+    32 - dim.0.max(dim.1).max(1).leading_zeros()
+}
+
+pub fn mip_size(dim: (u32, u32), level: u32) -> (u32, u32) {
+    ((dim.0 >> level).max(1), (dim.1 >> level).max(1))
+}
+
+//Bytes of a chain starting at level top.
+pub fn chain_bytes(dim: (u32, u32), top: u32) -> usize {
+    (top..mip_count(dim)).map(|level| mip_size(dim, level)).map(|(w, h)| (w * h * 4) as usize).sum()
+}
+
+//Creates a texture the size of mip level top, holding that level and every smaller one.
+//The mips are generated on the cpu from the full resolution source.
+pub fn create_chain(
+    context: &VisContext, name: Option<&str>, source: &image::RgbaImage, top: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let dim = source.dimensions();
+    let top = top.min(mip_count(dim) - 1);
+    let (width, height) = mip_size(dim, top);
+
+    let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+        label: name,
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: mip_count(dim) - top,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let mut level_image: Option<image::RgbaImage> = None;
+
+    for level in top..mip_count(dim) {
+        let (w, h) = mip_size(dim, level);
+
+        //Each level is filtered down from the previous one, level 0 is the source itself.
+        if level > 0 {
+            let previous = level_image.as_ref().unwrap_or(source);
+            level_image = Some(image::imageops::resize(
+                previous,
+                w,
+                h,
+                image::imageops::FilterType::Triangle,
+            ));
+        }
+
+        let data = level_image.as_ref().unwrap_or(source);
+
+        context.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: level - top,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * w),
+                rows_per_image: Some(h),
+            },
+            wgpu::Extent3d { width: w, height: h, depth_or_array_layers: 1 },
+        );
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+//Which mips a load of a streamed texture uploads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamLevels {
+    //The smallest mips, always on the GPU.
+    pub resident_levels: u32,
+    //Finest level to upload, None for the resident mips only.
+    pub top: Option<u32>,
+}
+
+//Keeps only the small mips of a big texture on the GPU. Finer ones are loaded from the asset file
+//again when they are sampled, the decoded image is not kept around.
+pub struct MipStream {
+    dim: (u32, u32),
+    resident_levels: u32,
+    //Finest level currently on the GPU.
+    top: u32,
+    //Coarsest top we fall back to under memory pressure.
+    min_top: u32,
+    //Finest level asked for since the last update.
+    wanted: Option<u32>,
+    //Requested from the loader, the texture is replaced once it arrives. Only one load at a time,
+    //a failed one stops the streaming until the texture is reloaded.
+    loading: Option<u32>,
+}
+
+impl MipStream {
+    pub fn new(dim: (u32, u32), levels: StreamLevels) -> Self {
+        let min_top = mip_count(dim).saturating_sub(levels.resident_levels.max(1));
+        let top = levels.top.map_or(min_top, |top| top.min(min_top));
+
+        MipStream {
+            dim,
+            resident_levels: levels.resident_levels,
+            top,
+            min_top,
+            wanted: None,
+            loading: None,
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dim
+    }
+
+    pub fn top(&self) -> u32 {
+        self.top
+    }
+
+    //What a reload of the texture has to upload to look the same.
+    pub fn levels(&self) -> StreamLevels {
+        StreamLevels { resident_levels: self.resident_levels, top: Some(self.top) }
+    }
+
+    pub fn bytes(&self) -> usize {
+        chain_bytes(self.dim, self.top)
+    }
+
+    //Called with the size the texture covers on screen, in pixels.
+    pub fn report_usage(&mut self, screen_width: f32) {
+        let width = self.dim.0 as f32;
+
+        let level = if screen_width <= 0.0 {
+            self.min_top
+        } else {
+            (width / screen_width).log2().floor().max(0.0) as u32
+        };
+
+        let level = level.min(self.min_top);
+        self.wanted = Some(self.wanted.map_or(level, |wanted| wanted.min(level)));
+    }
+
+    pub fn is_used(&self) -> bool {
+        self.wanted.is_some()
+    }
+
+    //The levels to load next: finer ones if they were asked for, the resident ones if unused
+    //textures have to give their fine mips back.
+    pub fn request(&mut self, evict: bool) -> Option<StreamLevels> {
+        let wanted = self.wanted.take();
+
+        if self.loading.is_some() {
+            return None;
+        }
+
+        let top = match wanted {
+            Some(wanted) if wanted < self.top => wanted,
+            None if evict && self.top < self.min_top => self.min_top,
+            _ => return None,
+        };
+
+        self.loading = Some(top);
+        Some(StreamLevels { resident_levels: self.resident_levels, top: Some(top) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mips(top: Option<u32>) -> MipStream {
+        //11 levels, the 3 smallest are resident.
+        MipStream::new((1024, 1024), StreamLevels { resident_levels: 3, top })
+    }
+
+    #[test]
+    fn finer_mips_are_requested_once() {
+        let mut stream = mips(None);
+        assert_eq!(stream.top(), 8);
+
+        stream.report_usage(512.0);
+        stream.report_usage(256.0);
+        assert_eq!(stream.request(false), Some(StreamLevels { resident_levels: 3, top: Some(1) }));
+
+        //Still loading.
+        stream.report_usage(1024.0);
+        assert_eq!(stream.request(false), None);
+    }
+
+    #[test]
+    fn unused_mips_are_only_given_back_over_budget() {
+        let mut stream = mips(Some(2));
+        assert_eq!(stream.request(false), None);
+        assert_eq!(stream.request(true), Some(StreamLevels { resident_levels: 3, top: Some(8) }));
+
+        let mut stream = mips(Some(2));
+        stream.report_usage(256.0);
+        assert_eq!(stream.request(true), None);
+    }
+}
@@ -1,10 +1,10 @@
-use once_cell::sync::OnceCell;
-
+use crate::assets::streaming::{self, MipStream, StreamLevels};
 use crate::context::VisContext;
 use crate::render::types::BindGroupEntry;
 
 pub struct TextureArray {
     extend: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
     texture: wgpu::Texture,
     current_view: Option<wgpu::TextureView>,
     sampler: wgpu::Sampler,
@@ -12,6 +12,12 @@ pub struct TextureArray {
 
 impl TextureArray {
     pub fn new(context: &VisContext, size: u32, layers: u32) -> Self {
+        TextureArray::with_format(context, size, layers, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    pub fn with_format(
+        context: &VisContext, size: u32, layers: u32, format: wgpu::TextureFormat,
+    ) -> Self {
         let extend = wgpu::Extent3d { width: size, height: size, depth_or_array_layers: layers };
 
         let texture = context.device.create_texture(&wgpu::TextureDescriptor {
@@ -20,8 +26,10 @@ impl TextureArray {
             sample_count: 1,
             size: extend,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         });
 
@@ -35,21 +43,150 @@ impl TextureArray {
             ..Default::default()
         });
 
-        TextureArray { extend, texture, current_view: None, sampler }
+        TextureArray { extend, format, texture, current_view: None, sampler }
+    }
+
+    //Builds a cube map from its faces in the layer order +X, -X, +Y, -Y, +Z, -Z.
+    //All faces have to be square and of the size of the first one, the others get the error texture.
+    pub fn from_faces(context: &VisContext, faces: [&image::RgbaImage; 6]) -> Self {
+        let size = faces[0].width();
+        let mut texture = TextureArray::new(context, size.max(1), 6);
+
+        for (layer, face) in faces.iter().enumerate() {
+            if face.dimensions() == (size, size) && size > 0 {
+                texture.upload(context, face, layer as u32);
+            } else {
+                log::error!(
+                    "Cube map face {} is {}x{}, expected {}x{}. Loading error texture instead...",
+                    layer,
+                    face.width(),
+                    face.height(),
+                    size,
+                    size
+                );
+                texture.upload_error_texture(context, layer as u32);
+            }
+        }
+
+        texture.finish_creation();
+        texture
+    }
+
+    //Resamples an equirectangular panorama into a cube map with faces of the given size, on the gpu.
+    //The cube map keeps the format of the panorama.
+    pub fn from_equirect(context: &VisContext, panorama: &Texture2D, size: u32) -> Self {
+        let format = panorama.texture().format();
+        let mut texture = TextureArray::with_format(context, size.max(1), 6, format);
+
+        let module = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Equirect Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("equirect.wgsl").into()),
+        });
+
+        let sampler = Sampler::two_dim(context);
+
+        let bind_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Equirect Layout"),
+                entries: &[Texture2D::layout_entry(0), Sampler::layout_entry(1)],
+            });
+
+        let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Equirect Group"),
+            layout: &bind_layout,
+            entries: &[panorama.group_entry(0), sampler.group_entry(1)],
+        });
+
+        let layout = context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        //Not made through the PipelineFactory, that one only knows the surface format.
+        let pipeline = context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Equirect Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState { module: &module, entry_point: "vertex_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Equirect") });
+
+        for face in 0..6 {
+            let view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Equirect Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, face..face + 1);
+        }
+
+        //Happens once at load time, not part of any frame.
+        context.queue.submit(Some(encoder.finish()));
+
+        texture.finish_creation();
+        texture
     }
 
     pub fn upload_error_texture(&self, context: &VisContext, layer: u32) {
+        //The error texture is rgba8, it can't go into arrays of other formats.
+        if self.format.block_size(None) != Some(4) {
+            log::error!("Can't load the error texture into a {:?} texture array.", self.format);
+            return;
+        }
+
         if let Ok(image) = image::load_from_memory_with_format(
             include_bytes!("../../resources/error.png"),
             image::ImageFormat::Png,
         ) {
-            let rgba = image.to_rgba8();
+            //The upload always covers the whole layer.
+            let rgba = image::imageops::resize(
+                &image.to_rgba8(),
+                self.extend.width,
+                self.extend.height,
+                image::imageops::FilterType::Nearest,
+            );
             self.upload(context, &rgba, layer);
         } else {
             panic!("Fatal. Error texture should always be loadable. This suggest you messed with the executable. Abort.");
         }
     }
 
+    //The buffer has to be in the format of the array.
     pub fn upload(&self, context: &VisContext, buffer: &[u8], layer: u32) {
         context.queue.write_texture(
             wgpu::ImageCopyTexture {
@@ -61,7 +198,7 @@ impl TextureArray {
             buffer,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * self.extend.width),
+                bytes_per_row: Some(self.format.block_size(None).unwrap_or(4) * self.extend.width),
                 rows_per_image: Some(self.extend.height),
             },
             wgpu::Extent3d {
@@ -75,7 +212,7 @@ impl TextureArray {
     pub fn finish_creation(&mut self) {
         self.current_view = Some(self.texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
-            format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            format: Some(self.format),
             dimension: Some(wgpu::TextureViewDimension::Cube),
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
@@ -101,6 +238,10 @@ impl TextureArray {
         self.extend
     }
 
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
     pub fn layout_entry(idx: u32) -> wgpu::BindGroupLayoutEntry {
         wgpu::BindGroupLayoutEntry {
             binding: idx,
@@ -191,6 +332,11 @@ impl BindGroupEntry for Sampler {
 pub struct Texture2D {
     texture: wgpu::Texture,
     view: wgpu::TextureView,
+    //Bumped whenever the view changes, so users rebuild their bind groups.
+    generation: u64,
+    streaming: Option<MipStream>,
+    //Has texels that are neither fully opaque nor fully transparent, so it needs blending.
+    translucent: bool,
 }
 
 impl Texture2D {
@@ -227,14 +373,102 @@ impl Texture2D {
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let translucent = Self::has_partial_alpha(bytes);
 
-        Texture2D { texture, view }
+        Texture2D { texture, view, generation: 0, streaming: None, translucent }
     }
 
-    pub fn error_texture(context: &VisContext) -> &Texture2D {
-        static ERROR_TEXTURE: OnceCell<Texture2D> = OnceCell::new();
+    //An empty texture to render into, in the surface format so the regular pipelines can draw to it.
+    pub fn new_render_target(
+        context: &VisContext, name: Option<&str>, dim: (u32, u32),
+    ) -> Texture2D {
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: name,
+            size: wgpu::Extent3d { width: dim.0, height: dim.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Texture2D { texture, view, generation: 0, streaming: None, translucent: false }
+    }
+
+    //Only the levels asked for are uploaded, finer ones are streamed in on demand. The image is
+    //dropped afterwards.
+    pub fn new_streamed(
+        context: &VisContext, name: Option<&str>, image: &image::RgbaImage, levels: StreamLevels,
+    ) -> Texture2D {
+        let translucent = Self::has_partial_alpha(image.as_raw());
+        let stream = MipStream::new(image.dimensions(), levels);
+        let (texture, view) = streaming::create_chain(context, name, image, stream.top());
+
+        Texture2D { texture, view, generation: 0, streaming: Some(stream), translucent }
+    }
+
+    //Fully transparent texels are discarded by the sprite shader, they don't need blending.
+    fn has_partial_alpha(rgba: &[u8]) -> bool {
+        rgba.chunks_exact(4).any(|texel| texel[3] > 0 && texel[3] < 255)
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        self.translucent
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    //Takes over from a previous version of the same asset, so bound sprites rebind.
+    pub(crate) fn replaces(&mut self, old: &Texture2D) {
+        self.generation = old.generation + 1;
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.streaming.is_some()
+    }
 
-        ERROR_TEXTURE.get_or_init(|| {
+    //Full resolution size, also for streamed textures that are not fully resident.
+    pub fn size(&self) -> (u32, u32) {
+        match &self.streaming {
+            Some(stream) => stream.dimensions(),
+            None => (self.texture.width(), self.texture.height()),
+        }
+    }
+
+    pub fn byte_size(&self) -> usize {
+        match &self.streaming {
+            Some(stream) => stream.bytes(),
+            None => (self.texture.width() * self.texture.height() * 4) as usize,
+        }
+    }
+
+    //Feedback from the renderer: the texture covers this many pixels in width on screen.
+    pub fn report_usage(&mut self, screen_width: f32) {
+        if let Some(stream) = &mut self.streaming {
+            stream.report_usage(screen_width);
+        }
+    }
+
+    //The mips to load from the file next, see MipStream::request. Under memory pressure unused
+    //textures drop to their resident mips.
+    pub(crate) fn stream_request(&mut self, evict: bool) -> Option<StreamLevels> {
+        self.streaming.as_mut().and_then(|stream| stream.request(evict))
+    }
+
+    pub(crate) fn stream_levels(&self) -> Option<StreamLevels> {
+        self.streaming.as_ref().map(|stream| stream.levels())
+    }
+
+    //Lives on the VisContext, a rebuilt device gets a fresh one.
+    pub fn error_texture(context: &VisContext) -> &Texture2D {
+        context.error_texture.get_or_init(|| {
             if let Ok(image) = image::load_from_memory_with_format(
                 include_bytes!("../../resources/error.png"),
                 image::ImageFormat::Png,
@@ -273,7 +507,7 @@ impl Texture2D {
 
                 let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-                Texture2D { texture, view }
+                Texture2D { texture, view, generation: 0, streaming: None, translucent: false }
             } else {
                 //For devs: Of course this can also happen while engine development. E.g. broken png in resources/
                 panic!("Fatal. Error texture should always be loadable. This suggest you messed with the executable. Abort.");
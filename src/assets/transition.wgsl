@@ -0,0 +1,76 @@
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texture_coords: vec2<f32>,
+};
+
+@vertex
+fn vertex_main(
+    mesh: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    //Generate a big triangle over the screen. Texture coordinates start at the top left.
+    if mesh.vertex_index == 0u {
+        out.clip_position = vec4<f32>(-1.0, -1.0, 0.0, 1.0); // bottom left
+        out.texture_coords = vec2<f32>(0.0, 1.0);
+    } else if mesh.vertex_index == 1u {
+        out.clip_position = vec4<f32>(3.0, -1.0, 0.0, 1.0); // bottom right
+        out.texture_coords = vec2<f32>(2.0, 1.0);
+    } else {
+        out.clip_position = vec4<f32>(-1.0, 3.0, 0.0, 1.0); // top left
+        out.texture_coords = vec2<f32>(0.0, -1.0);
+    }
+
+    return out;
+}
+
+struct TransitionUniform {
+    //0 shows only the outgoing world, 1 only the incoming one.
+    progress: f32,
+    //0 fade, 1 wipe left to right, 2 circular wipe, 3 fade through black.
+    style: u32,
+    aspect_ratio: f32,
+    padding: f32,
+};
+
+//The outgoing world. The incoming one is already in the target, the output is blended on top of it.
+@group(0) @binding(0)
+var outgoing: texture_2d<f32>;
+@group(0) @binding(1)
+var outgoing_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> transition: TransitionUniform;
+
+//Width of the soft edge of the wipes, in texture coordinates.
+const EDGE: f32 = 0.02;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(outgoing, outgoing_sampler, in.texture_coords).rgb;
+    let progress = clamp(transition.progress, 0.0, 1.0);
+
+    //How much of the outgoing world covers the incoming one.
+    var cover = 1.0 - progress;
+
+    if transition.style == 1u {
+        let edge = progress * (1.0 + 2.0 * EDGE) - EDGE;
+        cover = smoothstep(edge - EDGE, edge + EDGE, in.texture_coords.x);
+    } else if transition.style == 2u {
+        let scale = vec2<f32>(transition.aspect_ratio, 1.0);
+        let distance = length((in.texture_coords - 0.5) * scale);
+        let radius = progress * (length(scale * 0.5) + 2.0 * EDGE) - EDGE;
+        cover = smoothstep(radius - EDGE, radius + EDGE, distance);
+    } else if transition.style == 3u {
+        //The outgoing world fades to black, then the black fades out over the incoming one.
+        if progress < 0.5 {
+            return vec4<f32>(color * (1.0 - 2.0 * progress), 1.0);
+        }
+
+        return vec4<f32>(0.0, 0.0, 0.0, 2.0 - 2.0 * progress);
+    }
+
+    return vec4<f32>(color, cover);
+}
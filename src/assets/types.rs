@@ -1 +1,94 @@
+use std::fmt;
 
+use hashbrown::HashMap;
+
+use crate::render::material::GenericMaterial;
+use crate::utils::Guid;
+
+use super::audio::AudioClip;
+use super::buffer::UniformBuffer;
+use super::shader::Shader;
+use super::texture::{Sampler, Texture2D, TextureArray};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum AssetKind {
+    TextureArray,
+    Texture2D,
+    Shader,
+    Uniforms,
+    Sampler,
+    GenericMaterial,
+    AudioClip,
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+//Every type that can be stored in the asset manager knows which AssetType variant it belongs to.
+pub trait TypedAsset: 'static {
+    const KIND: AssetKind;
+}
+
+impl TypedAsset for TextureArray {
+    const KIND: AssetKind = AssetKind::TextureArray;
+}
+
+impl TypedAsset for Texture2D {
+    const KIND: AssetKind = AssetKind::Texture2D;
+}
+
+impl TypedAsset for Shader {
+    const KIND: AssetKind = AssetKind::Shader;
+}
+
+impl TypedAsset for UniformBuffer {
+    const KIND: AssetKind = AssetKind::Uniforms;
+}
+
+impl TypedAsset for Sampler {
+    const KIND: AssetKind = AssetKind::Sampler;
+}
+
+impl TypedAsset for GenericMaterial {
+    const KIND: AssetKind = AssetKind::GenericMaterial;
+}
+
+impl TypedAsset for AudioClip {
+    const KIND: AssetKind = AssetKind::AudioClip;
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct AssetStats {
+    pub texture_bytes: usize,
+    pub buffer_bytes: usize,
+    pub audio_bytes: usize,
+    pub counts: HashMap<AssetKind, usize>,
+    pub pending: usize,
+    pub max_size: usize,
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    NotLoaded(Guid),
+    Mismatch { path: Option<String>, expected: AssetKind, actual: AssetKind },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotLoaded(guid) => write!(f, "Asset {:?} is not loaded.", guid),
+            AssetError::Mismatch { path, expected, actual } => write!(
+                f,
+                "Asset {} was requested as {} but is a {}.",
+                path.as_deref().unwrap_or("<unnamed>"),
+                expected,
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
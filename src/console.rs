@@ -0,0 +1,1076 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::assets::assets::Assets;
+use crate::assets::sprite_sheet::{self, SheetLayout};
+use crate::assets::texture::Texture2D;
+use crate::assets::types::AssetKind;
+use crate::context::{Context, VisContext};
+use crate::entities::entities::Worlds;
+use crate::entities::sprite::Sprite;
+use crate::entities::transform2d::Transform2D;
+use crate::environment::config::Config;
+use crate::event::Event;
+use crate::event_log::EventLog;
+
+//In the config folder.
+const HISTORY_FILE: &str = "console_history.txt";
+const HISTORY_LENGTH: usize = 100;
+const OUTPUT_LENGTH: usize = 500;
+
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument { name: &'static str, value: String },
+    TooManyArguments(usize),
+    //The command needs something the application did not hand out, e.g. a world.
+    Unavailable(&'static str),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(name) => write!(f, "Unknown command {}", name),
+            CommandError::MissingArgument(name) => write!(f, "Missing argument <{}>", name),
+            CommandError::InvalidArgument { name, value } => {
+                write!(f, "Invalid value '{}' for <{}>", value, name)
+            }
+            CommandError::TooManyArguments(max) => write!(f, "Expected at most {} arguments", max),
+            CommandError::Unavailable(what) => write!(f, "Not available: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+//Parses the argument at index as T.
+pub fn arg<T: FromStr>(args: &[&str], index: usize, name: &'static str) -> Result<T, CommandError> {
+    let value = args.get(index).ok_or(CommandError::MissingArgument(name))?;
+    value.parse().map_err(|_| CommandError::InvalidArgument { name, value: value.to_string() })
+}
+
+//Accepts on/off, true/false and 1/0.
+pub fn switch(args: &[&str], index: usize, name: &'static str) -> Result<bool, CommandError> {
+    match *args.get(index).ok_or(CommandError::MissingArgument(name))? {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        value => Err(CommandError::InvalidArgument { name, value: value.to_string() }),
+    }
+}
+
+pub fn max_args(args: &[&str], max: usize) -> Result<(), CommandError> {
+    if args.len() > max {
+        return Err(CommandError::TooManyArguments(max));
+    }
+    Ok(())
+}
+
+//The parts of the context the engine commands use. Tests run the commands on a headless host.
+pub trait ConsoleHost {
+    //None without a device, sprites and scenes can't be created then.
+    fn graphics(&self) -> Option<Arc<VisContext>>;
+    fn set_time_scale(&mut self, scale: f64);
+    fn set_vsync(&mut self, vsync: bool);
+    fn set_debug_overlay(&mut self, visible: bool);
+    //Saves the next frame. Returns where, or None if the surface can't be captured.
+    fn screenshot(&mut self) -> Option<PathBuf>;
+    fn event_log(&mut self) -> &mut EventLog;
+}
+
+impl ConsoleHost for Context<'_> {
+    fn graphics(&self) -> Option<Arc<VisContext>> {
+        Some(self.graphics.clone())
+    }
+
+    fn set_time_scale(&mut self, scale: f64) {
+        Context::set_time_scale(self, scale);
+    }
+
+    fn set_vsync(&mut self, vsync: bool) {
+        Context::set_vsync(self, vsync);
+    }
+
+    fn set_debug_overlay(&mut self, visible: bool) {
+        Context::set_debug_overlay(self, visible);
+    }
+
+    fn screenshot(&mut self) -> Option<PathBuf> {
+        if !self.features.frame_capture {
+            return None;
+        }
+
+        let path = self.screenshot_path();
+        self.save_screenshot(path.clone());
+        Some(path)
+    }
+
+    fn event_log(&mut self) -> &mut EventLog {
+        &mut self.event_log
+    }
+}
+
+//What a command gets to work on. Worlds and assets are only there if the application hands
+//them out through Application::systems.
+pub struct ConsoleParams<'p> {
+    pub context: &'p mut dyn ConsoleHost,
+    pub worlds: Option<&'p mut Worlds>,
+    pub assets: Option<&'p mut Assets>,
+    //Dispatched to the layers after the command ran.
+    pub events: Vec<Event>,
+    output: Vec<String>,
+}
+
+impl<'p> ConsoleParams<'p> {
+    pub fn new(
+        context: &'p mut dyn ConsoleHost, worlds: Option<&'p mut Worlds>,
+        assets: Option<&'p mut Assets>,
+    ) -> Self {
+        ConsoleParams { context, worlds, assets, events: Vec::new(), output: Vec::new() }
+    }
+
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+    }
+
+    pub fn worlds(&mut self) -> Result<&mut Worlds, CommandError> {
+        self.worlds.as_deref_mut().ok_or(CommandError::Unavailable("worlds"))
+    }
+
+    pub fn assets(&mut self) -> Result<&mut Assets, CommandError> {
+        self.assets.as_deref_mut().ok_or(CommandError::Unavailable("assets"))
+    }
+
+    pub fn graphics(&self) -> Result<Arc<VisContext>, CommandError> {
+        self.context.graphics().ok_or(CommandError::Unavailable("graphics"))
+    }
+}
+
+pub trait ConsoleCommand {
+    //Single word, dots group related commands (e.g. assets.list).
+    fn name(&self) -> &'static str;
+    //Shown by help, should start with the usage.
+    fn help(&self) -> &'static str;
+    fn run(&mut self, args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError>;
+}
+
+type CommandFn = fn(&[&str], &mut ConsoleParams) -> Result<(), CommandError>;
+
+//The engine commands are plain functions.
+struct Builtin {
+    name: &'static str,
+    help: &'static str,
+    run: CommandFn,
+}
+
+impl ConsoleCommand for Builtin {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn help(&self) -> &'static str {
+        self.help
+    }
+
+    fn run(&mut self, args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+        (self.run)(args, params)
+    }
+}
+
+#[derive(Default)]
+pub struct Console {
+    commands: BTreeMap<&'static str, Box<dyn ConsoleCommand>>,
+    history: VecDeque<String>,
+    //Position while browsing the history with up/down, None while editing a new line.
+    history_index: Option<usize>,
+    output: VecDeque<String>,
+    input: String,
+    queued: Vec<String>,
+    open: bool,
+    //The input line takes the focus once after opening.
+    focus: bool,
+}
+
+impl Console {
+    //With the engine commands registered and the history of the last session.
+    pub fn new() -> Self {
+        let mut console = Console::default();
+
+        let builtins: [(&'static str, &'static str, CommandFn); 14] = [
+            ("assets.list", "assets.list - Lists all loaded assets", assets_list),
+            (
+                "assets.reload",
+                "assets.reload <path> - Loads an asset from disk again",
+                assets_reload,
+            ),
+            ("world.dump", "world.dump - Lists the entities of the current world", world_dump),
+            (
+                "world.spawn_sprite",
+                "world.spawn_sprite <texture> <x> <y> - Spawns a sprite in the current world",
+                world_spawn_sprite,
+            ),
+            (
+                "world.save",
+                "world.save <path> - Writes the current world to a scene file",
+                world_save,
+            ),
+            ("world.load", "world.load <path> - Loads a scene file and switches to it", world_load),
+            ("camera.pos", "camera.pos <x> <y> - Moves the 2D camera", camera_pos),
+            ("timescale", "timescale <f> - Scales the frame delta, 0 pauses", timescale),
+            ("vsync", "vsync on|off", vsync),
+            ("stats", "stats on|off - Shows the debug overlay", stats),
+            ("screenshot", "screenshot - Saves the next frame", screenshot),
+            ("events.list", "events.list - Lists the event log topics", events_list),
+            (
+                "events.tail",
+                "events.tail <topic>|off - Prints the events of a topic as they come in",
+                events_tail,
+            ),
+            (
+                "sheet.import",
+                "sheet.import <png> cells|grid <x> <y> [margin] [spacing] - Slices a sprite sheet",
+                sheet_import,
+            ),
+        ];
+
+        for (name, help, run) in builtins {
+            console.register(Builtin { name, help, run });
+        }
+
+        console.history = std::fs::read_to_string(Console::history_path())
+            .map(|history| history.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        console
+    }
+
+    //Replaces a command with the same name.
+    pub fn register(&mut self, command: impl ConsoleCommand + 'static) {
+        self.commands.insert(command.name(), Box::new(command));
+    }
+
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.keys().copied()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.focus = self.open;
+    }
+
+    //Key presses go to the open console only. Releases still go through, so keys held while
+    //opening it don't get stuck.
+    pub fn consumes(&self, event: &Event) -> bool {
+        self.open
+            && matches!(
+                event,
+                Event::KeyboardInput { state: winit::event::ElementState::Pressed, .. }
+            )
+    }
+
+    //Runs on the next frame, after the update stages.
+    pub fn submit(&mut self, line: impl Into<String>) {
+        self.queued.push(line.into());
+    }
+
+    pub fn has_queued(&self) -> bool {
+        !self.queued.is_empty()
+    }
+
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push_back(line.into());
+
+        while self.output.len() > OUTPUT_LENGTH {
+            self.output.pop_front();
+        }
+    }
+
+    //Returns the events the commands emitted.
+    pub fn execute_queued(
+        &mut self, context: &mut Context, worlds: Option<&mut Worlds>, assets: Option<&mut Assets>,
+    ) -> Vec<Event> {
+        let mut params = ConsoleParams::new(context, worlds, assets);
+
+        for line in std::mem::take(&mut self.queued) {
+            params.print(format!("> {}", line));
+
+            if let Err(error) = self.execute(&line, &mut params) {
+                params.print(error.to_string());
+            }
+        }
+
+        for line in std::mem::take(&mut params.output) {
+            self.print(line);
+        }
+
+        params.events
+    }
+
+    pub fn execute(&mut self, line: &str, params: &mut ConsoleParams) -> Result<(), CommandError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let Some((name, args)) = words.split_first() else {
+            return Ok(());
+        };
+
+        if *name == "help" {
+            for command in self.commands.values() {
+                params.print(command.help());
+            }
+            return Ok(());
+        }
+
+        let command = self
+            .commands
+            .get_mut(*name)
+            .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+
+        command.run(args, params)
+    }
+
+    //Completes the command name, or prints the candidates if there is more than one.
+    fn complete(&mut self) {
+        if self.input.contains(char::is_whitespace) {
+            return;
+        }
+
+        let candidates: Vec<&'static str> =
+            self.names().filter(|name| name.starts_with(self.input.as_str())).collect();
+
+        match candidates.as_slice() {
+            [] => {}
+            [name] => self.input = format!("{} ", name),
+            [first, ..] => {
+                let common = candidates.iter().fold(first.len(), |common, name| {
+                    first.chars().zip(name.chars()).take(common).take_while(|(a, b)| a == b).count()
+                });
+
+                self.input = first[..common].to_string();
+                self.print(candidates.join("  "));
+            }
+        }
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let last = self.history.len() - 1;
+
+        self.history_index = match (self.history_index, older) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index < last => Some(index + 1),
+            (Some(_), false) => None,
+        };
+
+        self.input =
+            self.history_index.map(|index| self.history[index].clone()).unwrap_or_default();
+    }
+
+    fn push_history(&mut self, line: &str) {
+        if self.history.back().map(String::as_str) != Some(line) {
+            self.history.push_back(line.to_string());
+        }
+
+        while self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+
+        self.history_index = None;
+    }
+
+    pub fn history_path() -> PathBuf {
+        Config::config_folder().join(HISTORY_FILE)
+    }
+
+    pub fn save_history(&self) {
+        let history = self.history.iter().map(String::as_str).collect::<Vec<_>>().join("\n");
+        let path = Console::history_path();
+
+        if let Err(e) = std::fs::create_dir_all(Config::config_folder())
+            .and_then(|_| std::fs::write(&path, history))
+        {
+            log::error!("Could not save console history to {}. Message: {}", path.display(), e);
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+
+        egui::Window::new("Console").open(&mut open).default_width(600.0).show(ctx, |ui| {
+            egui::ScrollArea::vertical().max_height(300.0).stick_to_bottom(true).show(ui, |ui| {
+                for line in self.output.iter() {
+                    ui.monospace(line);
+                }
+            });
+
+            ui.separator();
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY)
+                    .lock_focus(true),
+            );
+
+            if response.has_focus() {
+                let (tab, up, down) = ui.input(|i| {
+                    (
+                        i.key_pressed(egui::Key::Tab),
+                        i.key_pressed(egui::Key::ArrowUp),
+                        i.key_pressed(egui::Key::ArrowDown),
+                    )
+                });
+
+                if tab {
+                    self.complete();
+                } else if up || down {
+                    self.browse_history(up);
+                }
+            }
+
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.input);
+
+                if !line.trim().is_empty() {
+                    self.push_history(line.trim());
+                    self.submit(line.trim());
+                }
+
+                //Enter drops the focus, keep typing the next line.
+                self.focus = true;
+            }
+
+            if std::mem::take(&mut self.focus) {
+                response.request_focus();
+            }
+        });
+
+        self.open = open;
+    }
+}
+
+fn assets_list(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 0)?;
+
+    let assets = params.assets()?;
+    let mut lines: Vec<String> = assets
+        .iter_loaded()
+        .map(|(guid, path, kind, bytes)| {
+            format!("{:?} {:?} {} ({} KiB)", guid, kind, path, bytes / 1024)
+        })
+        .collect();
+
+    let stats = assets.stats();
+    lines.sort();
+    lines.push(format!(
+        "{} KiB textures, {} KiB buffers, {} KiB audio, {} pending",
+        stats.texture_bytes / 1024,
+        stats.buffer_bytes / 1024,
+        stats.audio_bytes / 1024,
+        stats.pending
+    ));
+
+    for line in lines {
+        params.print(line);
+    }
+
+    Ok(())
+}
+
+fn assets_reload(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let path: String = arg(args, 0, "path")?;
+
+    let assets = params.assets()?;
+    let kind = assets.iter_loaded().find(|(_, loaded, _, _)| *loaded == path).map(|e| e.2);
+
+    match kind {
+        Some(AssetKind::Texture2D) | Some(AssetKind::Shader) if assets.reload(&path) => {
+            params.print(format!("Reloading {}", path));
+            Ok(())
+        }
+        Some(_) => Err(CommandError::Unavailable("reloading this asset")),
+        None => Err(CommandError::InvalidArgument { name: "path", value: path }),
+    }
+}
+
+fn world_dump(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 0)?;
+
+    let assets = params.assets.as_deref();
+    let worlds = params.worlds.as_deref_mut().ok_or(CommandError::Unavailable("worlds"))?;
+    let world = worlds.get_mut().ok_or(CommandError::Unavailable("world"))?;
+    let mut lines = vec![format!("{} entities", world.len())];
+
+    for entity in world.iter() {
+        let mut line = format!("{:?}", entity.entity());
+
+        if let Some(transform) = entity.get::<&Transform2D>() {
+            let position = transform.position();
+            line.push_str(&format!(
+                " at ({:.2}, {:.2}, {:.2})",
+                position.x, position.y, position.z
+            ));
+        }
+
+        if let Some(sprite) = entity.get::<&Sprite<'static>>() {
+            let guid = sprite.texture().inner();
+            let path = assets.and_then(|assets| assets.asset_path(guid));
+
+            match path {
+                Some(path) => line.push_str(&format!(" sprite {}", path)),
+                None => line.push_str(&format!(" sprite {:?}", guid)),
+            }
+        }
+
+        lines.push(line);
+    }
+
+    for line in lines {
+        params.print(line);
+    }
+
+    Ok(())
+}
+
+fn world_spawn_sprite(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 3)?;
+    let texture: String = arg(args, 0, "texture")?;
+    let x: f32 = arg(args, 1, "x")?;
+    let y: f32 = arg(args, 2, "y")?;
+
+    //Checked up front, the sprite is dropped again otherwise.
+    params.worlds()?;
+    params.assets()?;
+    let graphics = params.graphics()?;
+    let texture = params.assets()?.request_asset::<Texture2D, _>(texture, 0);
+
+    let sprite = Sprite::new(&graphics, texture, Vec4::ONE, None, None);
+    let transform = Transform2D::new(&graphics, Vec3::new(x, y, 0.0), 0.0, Vec2::ONE);
+
+    let entity = params
+        .worlds()?
+        .spawn_labeled((transform, sprite), "console")
+        .ok_or(CommandError::Unavailable("world"))?;
+
+    params.print(format!("Spawned {:?}", entity));
+    Ok(())
+}
+
+fn world_save(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let path: String = arg(args, 0, "path")?;
+
+    let assets = params.assets.as_deref().ok_or(CommandError::Unavailable("assets"))?;
+    let worlds = params.worlds.as_deref().ok_or(CommandError::Unavailable("worlds"))?;
+    let guid = worlds.current().ok_or(CommandError::Unavailable("world"))?;
+
+    match worlds.save_world(guid, assets, &path) {
+        Ok(()) => params.print(format!("Saved the world to {}", path)),
+        Err(e) => params.print(e.to_string()),
+    }
+
+    Ok(())
+}
+
+fn world_load(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let path: String = arg(args, 0, "path")?;
+
+    let assets = params.assets.as_deref_mut().ok_or(CommandError::Unavailable("assets"))?;
+    let worlds = params.worlds.as_deref_mut().ok_or(CommandError::Unavailable("worlds"))?;
+    let graphics = params.context.graphics().ok_or(CommandError::Unavailable("graphics"))?;
+
+    match worlds.load_world(&graphics, assets, &path) {
+        Ok(guid) => {
+            worlds.start_world(guid);
+            params.print(format!("Loaded {}", path));
+        }
+        Err(e) => params.print(e.to_string()),
+    }
+
+    Ok(())
+}
+
+fn camera_pos(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 2)?;
+    let x: f32 = arg(args, 0, "x")?;
+    let y: f32 = arg(args, 1, "y")?;
+
+    params.events.push(Event::CameraMoveTo { x, y });
+    Ok(())
+}
+
+fn timescale(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let scale: f64 = arg(args, 0, "f")?;
+
+    if !scale.is_finite() || scale < 0.0 {
+        return Err(CommandError::InvalidArgument { name: "f", value: args[0].to_string() });
+    }
+
+    params.context.set_time_scale(scale);
+    Ok(())
+}
+
+fn vsync(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let vsync = switch(args, 0, "on|off")?;
+    params.context.set_vsync(vsync);
+    Ok(())
+}
+
+fn stats(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+    let visible = switch(args, 0, "on|off")?;
+    params.context.set_debug_overlay(visible);
+    Ok(())
+}
+
+fn screenshot(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 0)?;
+
+    let path = params.context.screenshot().ok_or(CommandError::Unavailable("frame capture"))?;
+    params.print(format!("Saving {}", path.display()));
+    Ok(())
+}
+
+fn events_list(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 0)?;
+
+    let log = params.context.event_log();
+    let mut lines: Vec<String> = log
+        .topics()
+        .map(|topic| {
+            format!("{} {} queued, {} dropped", topic.name(), log.len(topic), log.overflowed(topic))
+        })
+        .collect();
+
+    lines.sort();
+
+    for line in lines {
+        params.print(line);
+    }
+
+    Ok(())
+}
+
+fn events_tail(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 1)?;
+
+    match *args.first().ok_or(CommandError::MissingArgument("topic"))? {
+        "off" => params.context.event_log().set_tail(None),
+        topic => params.context.event_log().set_tail(Some(topic)),
+    }
+
+    Ok(())
+}
+
+fn sheet_import(args: &[&str], params: &mut ConsoleParams) -> Result<(), CommandError> {
+    max_args(args, 6)?;
+    let path: String = arg(args, 0, "png")?;
+    let (x, y): (u32, u32) = (arg(args, 2, "x")?, arg(args, 3, "y")?);
+
+    let layout = match *args.get(1).ok_or(CommandError::MissingArgument("cells|grid"))? {
+        "cells" => SheetLayout::Cells { width: x, height: y },
+        "grid" => SheetLayout::Grid { columns: x, rows: y },
+        value => {
+            return Err(CommandError::InvalidArgument { name: "cells|grid", value: value.into() })
+        }
+    };
+
+    let margin = if args.len() > 4 { arg(args, 4, "margin")? } else { 0 };
+    let spacing = if args.len() > 5 { arg(args, 5, "spacing")? } else { 0 };
+
+    match sprite_sheet::import_sheet(std::path::Path::new(&path), layout, margin, spacing) {
+        Ok((target, sheet)) => {
+            params.print(format!(
+                "Wrote {} frames in {} clips to {}",
+                sheet.frames.len(),
+                sheet.clips.len(),
+                target.display()
+            ));
+
+            //Pick up the new image right away if it is in use.
+            if params.assets.as_deref_mut().is_some_and(|assets| assets.reload(&sheet.texture)) {
+                params.print(format!("Reloading {}", sheet.texture));
+            }
+        }
+        Err(e) => params.print(e.to_string()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::ElementState;
+    use winit::keyboard::{KeyCode, ModifiersState};
+
+    use super::*;
+    use crate::event_log::Topic;
+    use crate::random::Rng;
+
+    fn key(state: ElementState) -> Event {
+        Event::KeyboardInput { keycode: KeyCode::KeyW, state, modifiers: ModifiersState::empty() }
+    }
+
+    //Records what the commands did, there is no window or device.
+    #[derive(Default)]
+    struct Headless {
+        time_scale: Option<f64>,
+        vsync: Option<bool>,
+        overlay: Option<bool>,
+        capture: bool,
+        screenshots: Vec<PathBuf>,
+        event_log: EventLog,
+    }
+
+    impl ConsoleHost for Headless {
+        fn graphics(&self) -> Option<Arc<VisContext>> {
+            None
+        }
+
+        fn set_time_scale(&mut self, scale: f64) {
+            self.time_scale = Some(scale);
+        }
+
+        fn set_vsync(&mut self, vsync: bool) {
+            self.vsync = Some(vsync);
+        }
+
+        fn set_debug_overlay(&mut self, visible: bool) {
+            self.overlay = Some(visible);
+        }
+
+        fn screenshot(&mut self) -> Option<PathBuf> {
+            if !self.capture {
+                return None;
+            }
+
+            let path = PathBuf::from(format!("screenshot_{}.png", self.screenshots.len()));
+            self.screenshots.push(path.clone());
+            Some(path)
+        }
+
+        fn event_log(&mut self) -> &mut EventLog {
+            &mut self.event_log
+        }
+    }
+
+    //Runs a line with the engine commands, returns the printed lines and emitted events too.
+    fn run(
+        host: &mut Headless, worlds: Option<&mut Worlds>, line: &str,
+    ) -> (Result<(), CommandError>, Vec<String>, Vec<Event>) {
+        let mut params = ConsoleParams::new(host, worlds, None);
+        let result = Console::new().execute(line, &mut params);
+        (result, params.output, params.events)
+    }
+
+    fn worlds_with(entities: usize) -> Worlds {
+        let mut worlds = Worlds::new(&Rng::from_seed(Some(1)));
+        let mut world = hecs::World::new();
+
+        for index in 0..entities {
+            world.spawn((index,));
+        }
+
+        let guid = worlds.add_world(world);
+        worlds.start_world(guid);
+        worlds
+    }
+
+    #[test]
+    fn assets_list_needs_assets() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "assets.list");
+        assert!(matches!(result, Err(CommandError::Unavailable("assets"))));
+
+        let (result, ..) = run(&mut host, None, "assets.list all");
+        assert!(matches!(result, Err(CommandError::TooManyArguments(0))));
+    }
+
+    #[test]
+    fn assets_reload_needs_a_path_and_assets() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "assets.reload");
+        assert!(matches!(result, Err(CommandError::MissingArgument("path"))));
+
+        let (result, ..) = run(&mut host, None, "assets.reload hero.png");
+        assert!(matches!(result, Err(CommandError::Unavailable("assets"))));
+    }
+
+    #[test]
+    fn world_dump_lists_the_entities_of_the_current_world() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "world.dump");
+        assert!(matches!(result, Err(CommandError::Unavailable("worlds"))));
+
+        let mut worlds = worlds_with(2);
+        let (result, output, _) = run(&mut host, Some(&mut worlds), "world.dump");
+        assert!(result.is_ok());
+        assert_eq!(output[0], "2 entities");
+        assert_eq!(output.len(), 3);
+
+        let mut empty = Worlds::new(&Rng::from_seed(Some(1)));
+        let (result, ..) = run(&mut host, Some(&mut empty), "world.dump");
+        assert!(matches!(result, Err(CommandError::Unavailable("world"))));
+    }
+
+    #[test]
+    fn world_spawn_sprite_checks_before_creating_the_sprite() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "world.spawn_sprite hero.png 1 2");
+        assert!(matches!(result, Err(CommandError::Unavailable("worlds"))));
+
+        let (result, ..) = run(&mut host, None, "world.spawn_sprite hero.png left 2");
+        assert!(matches!(result, Err(CommandError::InvalidArgument { name: "x", .. })));
+
+        let (result, ..) = run(&mut host, None, "world.spawn_sprite hero.png 1");
+        assert!(matches!(result, Err(CommandError::MissingArgument("y"))));
+
+        let mut worlds = worlds_with(0);
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.spawn_sprite hero.png 1 2");
+        assert!(matches!(result, Err(CommandError::Unavailable("assets"))));
+        assert_eq!(worlds.get().map(hecs::World::len), Some(0));
+    }
+
+    #[test]
+    fn world_save_needs_a_path_assets_and_worlds() {
+        let mut host = Headless::default();
+        let mut worlds = worlds_with(1);
+
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.save");
+        assert!(matches!(result, Err(CommandError::MissingArgument("path"))));
+
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.save level.json");
+        assert!(matches!(result, Err(CommandError::Unavailable("assets"))));
+
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.save a.json b.json");
+        assert!(matches!(result, Err(CommandError::TooManyArguments(1))));
+    }
+
+    #[test]
+    fn world_load_needs_a_path_assets_and_worlds() {
+        let mut host = Headless::default();
+        let mut worlds = worlds_with(0);
+
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.load");
+        assert!(matches!(result, Err(CommandError::MissingArgument("path"))));
+
+        let (result, ..) = run(&mut host, Some(&mut worlds), "world.load level.json");
+        assert!(matches!(result, Err(CommandError::Unavailable("assets"))));
+    }
+
+    #[test]
+    fn camera_pos_emits_a_move() {
+        let mut host = Headless::default();
+
+        let (result, _, events) = run(&mut host, None, "camera.pos 1 -2.5");
+        assert!(result.is_ok());
+        assert!(
+            matches!(events.as_slice(), [Event::CameraMoveTo { x, y }] if *x == 1.0 && *y == -2.5)
+        );
+
+        let (result, _, events) = run(&mut host, None, "camera.pos 1");
+        assert!(matches!(result, Err(CommandError::MissingArgument("y"))));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn timescale_accepts_only_finite_non_negative_scales() {
+        let mut host = Headless::default();
+
+        for line in ["timescale -1", "timescale nan", "timescale inf", "timescale fast"] {
+            let (result, ..) = run(&mut host, None, line);
+            assert!(
+                matches!(result, Err(CommandError::InvalidArgument { name: "f", .. })),
+                "{}",
+                line
+            );
+        }
+        assert_eq!(host.time_scale, None);
+
+        let (result, ..) = run(&mut host, None, "timescale 0");
+        assert!(result.is_ok());
+        assert_eq!(host.time_scale, Some(0.0));
+    }
+
+    #[test]
+    fn vsync_takes_a_switch() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "vsync maybe");
+        assert!(matches!(result, Err(CommandError::InvalidArgument { name: "on|off", .. })));
+        assert_eq!(host.vsync, None);
+
+        let (result, ..) = run(&mut host, None, "vsync off");
+        assert!(result.is_ok());
+        assert_eq!(host.vsync, Some(false));
+    }
+
+    #[test]
+    fn stats_toggles_the_overlay() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "stats");
+        assert!(matches!(result, Err(CommandError::MissingArgument("on|off"))));
+
+        run(&mut host, None, "stats 1").0.unwrap();
+        assert_eq!(host.overlay, Some(true));
+        run(&mut host, None, "stats false").0.unwrap();
+        assert_eq!(host.overlay, Some(false));
+    }
+
+    #[test]
+    fn screenshot_needs_frame_capture() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "screenshot");
+        assert!(matches!(result, Err(CommandError::Unavailable("frame capture"))));
+
+        host.capture = true;
+        let (result, output, _) = run(&mut host, None, "screenshot");
+        assert!(result.is_ok());
+        assert_eq!(host.screenshots, [PathBuf::from("screenshot_0.png")]);
+        assert_eq!(output, ["Saving screenshot_0.png"]);
+    }
+
+    #[test]
+    fn events_list_prints_every_topic_sorted() {
+        let mut host = Headless::default();
+        host.event_log.emit(Topic::WORLD_SWITCHED, 1);
+        host.event_log.emit(Topic::ASSET_FAILED, "hero.png");
+        host.event_log.emit(Topic::ASSET_FAILED, "tiles.png");
+
+        let (result, output, _) = run(&mut host, None, "events.list");
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            ["asset.failed 2 queued, 0 dropped", "world.switched 1 queued, 0 dropped"]
+        );
+    }
+
+    #[test]
+    fn events_tail_follows_a_topic_until_off() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "events.tail");
+        assert!(matches!(result, Err(CommandError::MissingArgument("topic"))));
+
+        run(&mut host, None, "events.tail world.switched").0.unwrap();
+        assert_eq!(host.event_log.tail(), Some("world.switched"));
+
+        run(&mut host, None, "events.tail off").0.unwrap();
+        assert_eq!(host.event_log.tail(), None);
+    }
+
+    #[test]
+    fn sheet_import_writes_the_sheet_next_to_the_png() {
+        let mut host = Headless::default();
+
+        let (result, ..) = run(&mut host, None, "sheet.import hero.png squares 2 1");
+        assert!(matches!(result, Err(CommandError::InvalidArgument { name: "cells|grid", .. })));
+
+        let (result, ..) = run(&mut host, None, "sheet.import hero.png grid two 1");
+        assert!(matches!(result, Err(CommandError::InvalidArgument { name: "x", .. })));
+
+        let png = std::env::temp_dir().join(format!("rustybear_sheet_{}.png", std::process::id()));
+        image::RgbaImage::new(32, 16).save(&png).unwrap();
+
+        let (result, output, _) =
+            run(&mut host, None, &format!("sheet.import {} grid 2 1", png.display()));
+        let target = sprite_sheet::sheet_path(&png);
+        let written = target.exists();
+
+        let _ = std::fs::remove_file(&png);
+        let _ = std::fs::remove_file(&target);
+
+        assert!(result.is_ok());
+        assert!(written);
+        assert!(output[0].starts_with("Wrote 2 frames"), "{}", output[0]);
+    }
+
+    #[test]
+    fn history_is_kept_in_the_config_folder() {
+        assert_eq!(Console::history_path(), Config::config_folder().join(HISTORY_FILE));
+    }
+
+    #[test]
+    fn focus_is_requested_only_when_opening() {
+        let mut console = Console::default();
+
+        console.toggle();
+        assert!(console.is_open() && console.focus);
+
+        console.focus = false;
+        console.toggle();
+        assert!(!console.is_open() && !console.focus);
+    }
+
+    #[test]
+    fn open_console_consumes_key_presses() {
+        let mut console = Console::default();
+        assert!(!console.consumes(&key(ElementState::Pressed)));
+
+        console.toggle();
+        assert!(console.consumes(&key(ElementState::Pressed)));
+        assert!(!console.consumes(&key(ElementState::Released)));
+        assert!(!console.consumes(&Event::CursorEntered));
+    }
+
+    #[test]
+    fn history_skips_repeats_and_is_browsed_newest_first() {
+        let mut console = Console::default();
+        console.push_history("stats on");
+        console.push_history("stats on");
+        console.push_history("vsync off");
+        assert_eq!(console.history.len(), 2);
+
+        console.browse_history(true);
+        assert_eq!(console.input, "vsync off");
+        console.browse_history(true);
+        assert_eq!(console.input, "stats on");
+        console.browse_history(false);
+        console.browse_history(false);
+        assert_eq!(console.input, "");
+    }
+
+    #[test]
+    fn completes_to_the_common_prefix() {
+        let mut console = Console::new();
+
+        console.input = "world.s".to_string();
+        console.complete();
+        assert_eq!(console.input, "world.s");
+
+        console.input = "world.sp".to_string();
+        console.complete();
+        assert_eq!(console.input, "world.spawn_sprite ");
+    }
+}
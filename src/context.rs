@@ -1,28 +1,108 @@
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use egui::ViewportInfo;
+use instant::Instant;
+use once_cell::sync::OnceCell;
+use serde_json::json;
 use sysinfo::{System, SystemExt};
 use wgpu::{PresentMode, TextureFormatFeatureFlags};
 use winit::dpi::PhysicalSize;
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::EventLoopWindowTarget;
-use winit::keyboard::{Key, NamedKey};
+use winit::event::{DeviceEvent, Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopWindowTarget};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use winit::window::CursorGrabMode;
 
+use crate::assets::assets::GenPtr;
+use crate::assets::texture::Texture2D;
+use crate::console::Console;
 use crate::core::{Application, ModuleStack};
-use crate::environment::config::Config;
-use crate::event;
-use crate::input::InputState;
-use crate::utils::Timestep;
-use crate::window::Window;
+use crate::entities::ldtk_entity::LdtkEntityRegistry;
+use crate::entities::transform2d;
+use crate::environment::config::{Config, DebugAction};
+use crate::environment::error::ContextError;
+use crate::event::{self, GamepadEventSource};
+use crate::event_log::{EventLog, Topic};
+use crate::input::{ActionMap, InputState};
+use crate::random::Rng;
+use crate::render::capture::FrameCapture;
+use crate::render::frame::FrameEncoder;
+use crate::schedule::Stage;
+use crate::utils::{FileUtils, FixedTimestep, Timestep};
+use crate::window::{Window, WindowConfig};
+
+//After a device loss, failed rebuilds are retried after this long, doubled every time.
+const MIN_REBUILD_WAIT: Duration = Duration::from_millis(250);
+const MAX_REBUILD_WAIT: Duration = Duration::from_secs(8);
 
 pub struct Features {
     pub texture_features: wgpu::TextureFormatFeatureFlags,
+    pub msaa: bool,
+    //Configured samples per pixel, see GraphicsConfiguration::msaa.
+    pub samples: u32,
+    //Set by the compatibility list, msaa stays off whatever is configured.
+    pub msaa_disabled: bool,
+    pub compressed_textures: bool,
+    pub frame_capture: bool,
+    //The depth buffer can be bound as a texture, e.g. for soft sprites. Not on WebGL2.
+    pub depth_sampling: bool,
+}
+
+impl Features {
+    //The configured samples if msaa is on and the surface format supports them, 1 otherwise.
+    pub fn sample_count(&self) -> u32 {
+        if self.msaa && self.supports_samples(self.samples) {
+            self.samples
+        } else {
+            1
+        }
+    }
+
+    //Whether a framebuffer with this many samples can be created, 1 always can.
+    pub fn supports_samples(&self, samples: u32) -> bool {
+        (samples == 1 || !self.msaa_disabled)
+            && self.texture_features.sample_count_supported(samples)
+    }
+}
+
+pub struct EngineInfo {
+    pub adapter: wgpu::AdapterInfo,
+    pub downlevel: wgpu::DownlevelFlags,
+    pub overrides: Vec<String>,
+    pub warnings: Vec<String>,
+    warnings_dismissed: bool,
 }
 
 pub struct VisContext {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub format: wgpu::TextureFormat,
+    //Shared per device objects, created on first use. See CameraBuffer::layout and Texture2D::error_texture.
+    pub(crate) camera_layout: OnceCell<wgpu::BindGroupLayout>,
+    pub(crate) error_texture: OnceCell<Texture2D>,
+}
+
+impl VisContext {
+    fn new(device: wgpu::Device, queue: wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        VisContext {
+            device,
+            queue,
+            format,
+            camera_layout: OnceCell::new(),
+            error_texture: OnceCell::new(),
+        }
+    }
+}
+
+//Everything that is created again if the device is lost.
+struct Graphics {
+    context: VisContext,
+    surface_config: wgpu::SurfaceConfiguration,
+    features: Features,
+    engine_info: EngineInfo,
 }
 
 pub struct Context<'a> {
@@ -33,67 +113,241 @@ pub struct Context<'a> {
     pub egui: egui_winit::State,
     pub config: Config,
     pub sysinfo: System,
+    pub rng: Rng,
+    pub console: Console,
+    pub event_log: EventLog,
+    //Shared with the InputState, rebinding takes effect with the next input event.
+    pub actions: rccell::RcCell<ActionMap>,
+    //Record render passes here, it is submitted once at the end of the frame.
+    pub frame: FrameEncoder,
+    engine_info: EngineInfo,
+    debug_overlay: bool,
+    last_hitch: Option<f64>,
+    capture: FrameCapture,
+    time_scale: f64,
+    //Time scale of the single frames and how many are left to run, Some while frame stepping.
+    frame_step: Option<(f64, u32)>,
+    //Captures the next frame with an attached graphics debugger.
+    gpu_capture: bool,
+    //Scaled delta of the current frame in ms.
+    frame_delta: f64,
+    fixed: FixedTimestep,
+    //How far the frame is between the last two fixed steps.
+    fixed_alpha: f32,
+    //Set by the device lost callback, the next frame rebuilds the graphics.
+    device_lost: Arc<AtomicBool>,
+    //When to try again after a failed rebuild and how long was waited. Some while the device is
+    //gone and the loss was announced.
+    rebuild_retry: Option<(Instant, Duration)>,
+    //Checked after every event, see request_exit.
+    exit_requested: bool,
+    window: Arc<winit::window::Window>,
+    //Physical pixels per logical pixel of the monitor the window is on.
+    scale_factor: f64,
+    modifiers: ModifiersState,
+    //Sizes the window took right away, they get no Resized event from winit.
+    pending_resize: Option<PhysicalSize<u32>>,
+    //Dropped files still loading, see EngineConfiguration::load_dropped_files.
+    dropped: Vec<(GenPtr, PathBuf)>,
+    //The touch acting as the mouse, see EngineConfiguration::touch_emulates_mouse.
+    mouse_touch: Option<u64>,
+    //Events of the game waiting for the next frame, see emit.
+    emitted: Vec<Arc<dyn Any + Send + Sync>>,
+    //When the last frame was requested, for GraphicsConfiguration::target_fps.
+    last_frame: Instant,
+    instance: wgpu::Instance,
 }
 
 impl<'a> Context<'a> {
-    pub async fn new(window: Arc<winit::window::Window>, config: Config) -> Context<'a> {
+    //The GPU is picked as configured in EngineConfiguration::graphics.
+    pub async fn new(
+        window: Arc<winit::window::Window>, config: Config,
+    ) -> Result<Context<'a>, ContextError> {
         let sysinfo = System::new_with_specifics(sysinfo::RefreshKind::new().with_memory());
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: config.engine_config().graphics.backends(),
             dx12_shader_compiler: Default::default(),
             ..Default::default()
         });
 
-        let surface: wgpu::Surface<'static> = instance.create_surface(window.clone()).unwrap();
+        let surface: wgpu::Surface<'static> =
+            instance.create_surface(window.clone()).map_err(ContextError::SurfaceCreation)?;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        let graphics =
+            Context::create_graphics(&instance, &surface, &config, window.inner_size()).await?;
+
+        surface.configure(&graphics.context.device, &graphics.surface_config);
+        let device_lost = Context::watch_device(&graphics.context.device);
+
+        //Create new egui context.
+        let egui = egui::Context::default();
+        let viewport_id = egui.viewport_id();
+        let egui = egui_winit::State::new(
+            egui,
+            viewport_id,
+            &window,
+            Some(window.scale_factor() as f32),
+            None,
+        );
+
+        let Graphics { context, surface_config, features, engine_info } = graphics;
+        let graphics = Arc::new(context);
+
+        Ok(Context {
+            frame: FrameEncoder::new(graphics.clone()),
+            graphics,
+            surface,
+            surface_config,
+            features,
+            egui,
+            rng: Rng::from_seed(config.project_config().random_seed),
+            sysinfo,
+            console: Console::new(),
+            event_log: EventLog::new(),
+            actions: rccell::RcCell::new(ActionMap::new(&config.project_config().actions)),
+            engine_info,
+            debug_overlay: false,
+            last_hitch: None,
+            capture: FrameCapture::default(),
+            time_scale: 1.0,
+            frame_step: None,
+            gpu_capture: false,
+            frame_delta: 0.0,
+            fixed: FixedTimestep::new(1000.0 / config.engine_config().fixed_rate.max(1.0)),
+            fixed_alpha: 0.0,
+            device_lost,
+            rebuild_retry: None,
+            exit_requested: false,
+            scale_factor: window.scale_factor(),
+            window,
+            modifiers: ModifiersState::empty(),
+            pending_resize: None,
+            dropped: Vec::new(),
+            mouse_touch: None,
+            emitted: Vec::new(),
+            last_frame: Instant::now(),
+            instance,
+            config,
+        })
+    }
+
+    async fn create_graphics(
+        instance: &wgpu::Instance, surface: &wgpu::Surface<'_>, config: &Config,
+        size: PhysicalSize<u32>,
+    ) -> Result<Graphics, ContextError> {
+        let adapter = Context::select_adapter(instance, surface, config).await?;
+
+        let adapter_info = adapter.get_info();
+        let downlevel = adapter.get_downlevel_capabilities().flags;
+
+        log::info!(
+            "Adapter: {} ({:?}, {:?})",
+            adapter_info.name,
+            adapter_info.backend,
+            adapter_info.device_type
+        );
+        log::info!("Driver: {} {}", adapter_info.driver, adapter_info.driver_info);
+        log::info!("Downlevel flags: {:?}", downlevel);
+
+        let mut engine_info = EngineInfo {
+            adapter: adapter_info,
+            downlevel,
+            overrides: Vec::new(),
+            warnings: Vec::new(),
+            warnings_dismissed: false,
+        };
 
         let capabilities = surface.get_capabilities(&adapter);
 
-        let format = capabilities
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(capabilities.formats[0]);
+        let (Some(first_format), Some(alpha_mode)) =
+            (capabilities.formats.first().copied(), capabilities.alpha_modes.first().copied())
+        else {
+            return Err(ContextError::Unsupported(format!(
+                "{} can't present to the window",
+                engine_info.adapter.name
+            )));
+        };
+
+        let format =
+            capabilities.formats.iter().copied().find(|f| f.is_srgb()).unwrap_or(first_format);
+
+        //Frames can only be read back if the surface allows copying from it.
+        let frame_capture = capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC);
+
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+
+        if frame_capture {
+            usage |= wgpu::TextureUsages::COPY_SRC;
+        }
+
+        let graphics_config = &config.engine_config().graphics;
+
+        //The auto modes work everywhere, the others only where the surface supports them.
+        let present_mode = match graphics_config.present_mode() {
+            Some(mode) if capabilities.present_modes.contains(&mode) => mode,
+            Some(wgpu::PresentMode::AutoNoVsync) => wgpu::PresentMode::AutoNoVsync,
+            _ => {
+                if !graphics_config.present_mode.eq_ignore_ascii_case("AutoVsync") {
+                    log::warn!(
+                        "Present mode {} is not available, using AutoVsync.",
+                        graphics_config.present_mode
+                    );
+                }
+
+                wgpu::PresentMode::AutoVsync
+            }
+        };
 
         let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format,
-            width: window.inner_size().width,
-            height: window.inner_size().height,
-            present_mode: wgpu::PresentMode::AutoVsync,
-            alpha_mode: capabilities.alpha_modes[0],
+            width: size.width,
+            height: size.height,
+            present_mode,
+            alpha_mode,
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
         let texture_features = adapter.get_texture_format_features(format).flags;
-        let mut features = Features { texture_features };
+        let mut features = Features {
+            texture_features,
+            msaa: graphics_config.msaa > 1,
+            samples: graphics_config.msaa,
+            msaa_disabled: false,
+            compressed_textures: true,
+            frame_capture,
+            depth_sampling: downlevel.contains(wgpu::DownlevelFlags::compliant()),
+        };
 
-        let (device, queue) = adapter
+        Context::apply_compat_list(config, &mut engine_info, &mut features);
+
+        let required_features = Context::activated_features(adapter.features(), &features);
+        let required_limits = if cfg!(target_arch = "wasm32") {
+            wgpu::Limits::downlevel_webgl2_defaults()
+        } else {
+            wgpu::Limits::default()
+        };
+
+        let device = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: Context::activated_features(adapter.features()),
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
+                    required_features,
+                    required_limits: required_limits.clone(),
                     label: None,
                 },
                 None,
             )
-            .await
-            .unwrap();
+            .await;
+
+        //The error alone rarely says which of them the adapter is missing.
+        let (device, queue) = device.map_err(|error| {
+            log::error!("Requested features: {:?}", required_features);
+            log::error!("Requested limits: {:?}", required_limits);
+            log::error!("Adapter limits: {:?}", adapter.limits());
+            ContextError::DeviceRequest(error)
+        })?;
 
         if !device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
             features.texture_features.set(TextureFormatFeatureFlags::MULTISAMPLE_X2, false);
@@ -101,52 +355,255 @@ impl<'a> Context<'a> {
             features.texture_features.set(TextureFormatFeatureFlags::MULTISAMPLE_X16, false);
         }
 
-        surface.configure(&device, &surface_config);
-
-        //Create new egui context.
-        let egui = egui::Context::default();
-        let viewport_id = egui.viewport_id();
-        let egui = egui_winit::State::new(
-            egui,
-            viewport_id,
-            &window,
-            Some(window.scale_factor() as f32),
-            None,
-        );
-
-        Context {
-            graphics: Arc::new(VisContext { device, queue, format }),
-            surface,
+        Ok(Graphics {
+            context: VisContext::new(device, queue, format),
             surface_config,
             features,
-            egui,
-            config,
-            sysinfo,
+            engine_info,
+        })
+    }
+
+    //The configured adapter if it can draw to the surface, otherwise whatever wgpu prefers.
+    async fn select_adapter(
+        instance: &wgpu::Instance, surface: &wgpu::Surface<'_>, config: &Config,
+    ) -> Result<wgpu::Adapter, ContextError> {
+        let graphics_config = &config.engine_config().graphics;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(name) = &graphics_config.adapter {
+            let pattern = name.to_lowercase();
+
+            let named = instance
+                .enumerate_adapters(graphics_config.backends())
+                .into_iter()
+                .filter(|adapter| adapter.is_surface_supported(surface))
+                .find(|adapter| adapter.get_info().name.to_lowercase().contains(&pattern));
+
+            match named {
+                Some(adapter) => return Ok(adapter),
+                None => log::warn!("No adapter named {} supports the window. Falling back.", name),
+            }
+        }
+
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: graphics_config.power_preference(),
+                compatible_surface: Some(surface),
+                force_fallback_adapter: false,
+            })
+            .await
+            .ok_or(ContextError::NoAdapter)
+    }
+
+    fn apply_compat_list(config: &Config, info: &mut EngineInfo, features: &mut Features) {
+        for entry in config.compat_config().entries.iter().filter(|e| e.matches(&info.adapter)) {
+            if entry.disable_msaa && !features.msaa_disabled {
+                features.msaa_disabled = true;
+                info.overrides.push(format!("MSAA disabled (matched '{}')", entry.adapter));
+            }
+
+            if entry.disable_compressed_textures && features.compressed_textures {
+                features.compressed_textures = false;
+                info.overrides
+                    .push(format!("Compressed textures disabled (matched '{}')", entry.adapter));
+            }
+
+            if let Some(warning) = &entry.warning {
+                info.warnings.push(warning.clone());
+            }
+        }
+
+        for applied in info.overrides.iter() {
+            log::warn!("Compatibility override applied: {}", applied);
+        }
+
+        for warning in info.warnings.iter() {
+            log::warn!("Compatibility warning for {}: {}", info.adapter.name, warning);
         }
     }
 
-    fn activated_features(supported_features: wgpu::Features) -> wgpu::Features {
+    fn activated_features(
+        supported_features: wgpu::Features, features: &Features,
+    ) -> wgpu::Features {
         let mut activated_features: wgpu::Features = wgpu::Features::empty();
 
         if supported_features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
             activated_features |= wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
         }
 
+        if features.compressed_textures
+            && supported_features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+        {
+            activated_features |= wgpu::Features::TEXTURE_COMPRESSION_BC;
+        }
+
+        //Only used if a renderer turns on gpu profiling.
+        if supported_features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            activated_features |= wgpu::Features::TIMESTAMP_QUERY;
+        }
+
         activated_features
     }
 
+    //Errors no scope caught are logged instead of panicking, after a gpu reset every call fails.
+    //Returns the flag the lost callback sets, one per device, so dropping the old one is no loss.
+    fn watch_device(device: &wgpu::Device) -> Arc<AtomicBool> {
+        let lost = Arc::new(AtomicBool::new(false));
+
+        device.on_uncaptured_error(Box::new(enclose! { (lost) move |error| {
+            if lost.load(Ordering::Acquire) {
+                log::warn!("GPU error on the lost device: {}", error);
+            } else {
+                log::error!("Uncaptured GPU error: {}", error);
+            }
+        }}));
+
+        device.set_device_lost_callback(enclose! { (lost) move |reason, message| {
+            if !matches!(reason, wgpu::DeviceLostReason::Dropped) {
+                log::error!("Graphics device lost ({:?}): {}", reason, message);
+                lost.store(true, Ordering::Release);
+            }
+        }});
+
+        lost
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Acquire)
+    }
+
+    //Requests adapter and device again and reconfigures the surface. Everything created on the old
+    //device is unusable, the engine sends Event::DeviceRestored afterwards so it is recreated.
+    pub fn rebuild_graphics(&mut self) -> bool {
+        let size = PhysicalSize::new(self.surface_config.width, self.surface_config.height);
+
+        let graphics = match pollster::block_on(Context::create_graphics(
+            &self.instance,
+            &self.surface,
+            &self.config,
+            size,
+        )) {
+            Ok(graphics) => graphics,
+            Err(error) => {
+                log::error!("{}", error);
+                return false;
+            }
+        };
+
+        let present_mode = self.surface_config.present_mode;
+        let dismissed = self.engine_info.warnings_dismissed;
+
+        self.graphics = Arc::new(graphics.context);
+        self.surface_config =
+            wgpu::SurfaceConfiguration { present_mode, ..graphics.surface_config };
+        self.features = graphics.features;
+        self.engine_info = EngineInfo { warnings_dismissed: dismissed, ..graphics.engine_info };
+
+        self.surface.configure(&self.graphics.device, &self.surface_config);
+        self.device_lost = Context::watch_device(&self.graphics.device);
+        self.frame = FrameEncoder::new(self.graphics.clone());
+        self.capture = FrameCapture::default();
+
+        log::info!("Rebuilt graphics on {}.", self.engine_info.adapter.name);
+        true
+    }
+
+    //Runs before a frame once the device is gone. Layers and the application hear about the loss
+    //once, then about the new device. Returns false if there is none yet, failed attempts are
+    //retried later and later.
+    fn recover_device(app: &mut impl Application<'a>, context: &mut Context<'a>) -> bool {
+        match context.rebuild_retry {
+            None => {
+                let lost = event::Event::DeviceLost;
+                app.get_stack().dispatch_event(event::EventType::Layer, &lost, context);
+                app.on_event(&lost, context);
+            }
+            Some((retry, _)) if Instant::now() < retry => return false,
+            Some(_) => {}
+        }
+
+        if !context.rebuild_graphics() {
+            let wait = context
+                .rebuild_retry
+                .map_or(MIN_REBUILD_WAIT, |(_, wait)| (wait * 2).min(MAX_REBUILD_WAIT));
+
+            log::warn!("No graphics device yet, trying again in {:?}.", wait);
+            context.rebuild_retry = Some((Instant::now() + wait, wait));
+            return false;
+        }
+
+        context.rebuild_retry = None;
+
+        let restored = event::Event::DeviceRestored;
+        app.get_stack().dispatch_event(event::EventType::Layer, &restored, context);
+        app.on_event(&restored, context);
+        true
+    }
+
+    pub fn engine_info(&self) -> &EngineInfo {
+        &self.engine_info
+    }
+
+    fn show_compat_warnings(&mut self) {
+        if self.engine_info.warnings.is_empty() || self.engine_info.warnings_dismissed {
+            return;
+        }
+
+        let mut open = true;
+
+        egui::Window::new("Compatibility warning").collapsible(false).open(&mut open).show(
+            self.egui.egui_ctx(),
+            |ui| {
+                for warning in self.engine_info.warnings.iter() {
+                    ui.label(warning);
+                }
+                ui.label("Updating your graphics driver may fix these issues.");
+            },
+        );
+
+        self.engine_info.warnings_dismissed = !open;
+    }
+
     pub fn run(mut self, mut app: impl Application<'a> + 'static, window: Window) {
         let mut gilrs = gilrs::Gilrs::new().unwrap();
 
         //Register an EventSubscriber which maintains a list of current KeyStates.
-        let input_state = rccell::RcCell::new(InputState::new());
+        let input_state = rccell::RcCell::new(
+            InputState::with_actions(self.actions.clone())
+                .with_pixels_per_line(self.config.engine_config().scroll_pixels_per_line)
+                .with_deadzone(self.config.engine_config().gamepad_deadzone)
+                .with_release_on_cursor_left(
+                    self.config.engine_config().release_mouse_on_cursor_left,
+                ),
+        );
         app.get_stack().subscribe(event::EventType::App, input_state.clone());
 
         //Time since last frame
-        let mut ts = Timestep::default();
+        let mut ts = Timestep::default().with_max_delta(self.config.engine_config().max_delta);
+
+        //Ordering problems should show up at startup, not in the middle of the game.
+        if let Some((schedule, _, _)) = app.systems() {
+            if let Err(error) = schedule.build() {
+                panic!("Invalid system schedule: {}", error);
+            }
+        }
+
+        //Taken out on exit, so it can be dropped before the device.
+        let mut app_slot = Some(app);
 
         let _ = window.event_loop.run(enclose! { (input_state) move |event, window_target|
         {
+            if let Event::LoopExiting = event {
+                if let Some(app) = app_slot.take() {
+                    self.shutdown(app, &window.native);
+                }
+                return;
+            }
+
+            let Some(app) = app_slot.as_mut() else {
+                return;
+            };
+
             let _handled = match event
             {
                 Event::WindowEvent { window_id, ref event }
@@ -159,13 +616,46 @@ impl<'a> Context<'a> {
                         WindowEvent::Resized(new_size) => {
                             self.resize(*new_size);
                         },
-                        /*WindowEvent::ScaleFactorChanged { new_inner_size, ..} => {
-                            self.resize(**new_inner_size);
-                        },*/
+                        WindowEvent::ModifiersChanged(modifiers) => {
+                            self.modifiers = modifiers.state();
+                        },
+                        WindowEvent::DroppedFile(path) if self.config.engine_config().load_dropped_files => {
+                            Context::load_dropped(app, path, &mut self);
+                        },
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            self.set_scale_factor(*scale_factor);
+                        },
                         WindowEvent::RedrawRequested => {
-                            app.update(ts.step_fwd(), input_state.borrow(), &mut self);
+                            if self.is_device_lost() && !Context::recover_device(app, &mut self) {
+                                return;
+                            }
+
+                            let delta = ts.step_fwd();
+
+                            if delta.clamped() {
+                                self.record_hitch(delta.raw_millis(), delta.max_delta());
+                            }
 
-                            match self.render(&window.native, &mut app) {
+                            *delta *= self.frame_scale();
+                            self.frame_delta = delta.millis();
+
+                            Context::poll_dropped(app, &mut self);
+                            Context::dispatch_emitted(app, &mut self);
+                            Context::run_systems(app, Stage::PreUpdate, delta, &input_state.borrow(), &mut self);
+                            Context::run_fixed_steps(app, delta, &input_state.borrow(), &mut self);
+                            Context::run_systems(app, Stage::Update, delta, &input_state.borrow(), &mut self);
+                            app.update(delta, input_state.borrow(), &mut self);
+                            Context::run_systems(app, Stage::PostUpdate, delta, &input_state.borrow(), &mut self);
+                            input_state.borrow_mut().end_frame();
+                            Context::log_engine_events(app, &mut self);
+
+                            if self.console.has_queued() {
+                                Context::run_console(app, &mut self);
+                            }
+
+                            Context::run_systems(app, Stage::PreRender, delta, &input_state.borrow(), &mut self);
+
+                            match self.render(&window.native, app) {
                                 Ok(_) => {}
                                 Err(wgpu::SurfaceError::Lost) => { self.resize(PhysicalSize { width: self.surface_config.width, height: self.surface_config.height }); },
                                 Err(wgpu::SurfaceError::OutOfMemory) => { window_target.exit(); },
@@ -178,25 +668,326 @@ impl<'a> Context<'a> {
                         _ => {}
                     }
 
-                    Context::dispatch_event(app.get_stack(), &window.native, event, window_target, &mut self);
-                    app.on_event(&event::to_event(event), &mut self)
+                    if *event == WindowEvent::CloseRequested {
+                        self.request_exit();
+                        true
+                    } else if Context::dispatch_debug_binding(app.get_stack(), event, &mut self) {
+                        true
+                    } else if self.console.consumes(&event::to_event(event, self.modifiers)) {
+                        true
+                    } else {
+                        Context::dispatch_event(app.get_stack(), &window.native, event, window_target, &mut self);
+                        let handled = app.on_event(&event::to_event(event, self.modifiers), &mut self);
+
+                        if let WindowEvent::Touch(touch) = event {
+                            if self.config.engine_config().touch_emulates_mouse {
+                                Context::emulate_mouse(app, touch, &mut self);
+                            }
+                        }
+
+                        handled
+                    }
+                },
+
+                Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                    let motion = event::Event::MouseMotion { delta_x: delta.0, delta_y: delta.1 };
+                    app.get_stack().dispatch_event(event::EventType::Layer, &motion, &mut self);
+                    app.on_event(&motion, &mut self)
                 },
 
                 Event::AboutToWait => {
-                    window.native.request_redraw();
+                    Context::pump_gamepad_events(app.get_stack(), &mut gilrs, &mut self);
+
+                    match self.frame_wait() {
+                        Some(wait) => window_target.set_control_flow(ControlFlow::wait_duration(wait)),
+                        None => {
+                            window_target.set_control_flow(ControlFlow::Wait);
+                            self.last_frame = Instant::now();
+                            window.native.request_redraw();
+                        }
+                    }
                     false
                 },
                 _ => {false}
             };
 
-            let gilrs_event_option = gilrs.next_event();
+            if let Some(size) = self.pending_resize.take() {
+                Context::apply_resize(app, size, &mut self);
+            }
 
-            if let Some(gilrs_event) = gilrs_event_option {
-                Context::dispatch_gamepad_event(app.get_stack(), &gilrs_event, window_target, &mut self);
+            if self.exit_requested {
+                Context::try_exit(app, window_target, &mut self);
             }
         }});
     }
 
+    fn run_systems(
+        app: &mut impl Application<'a>, stage: Stage, delta: &Timestep, input: &InputState,
+        context: &mut Context<'a>,
+    ) {
+        if let Some((schedule, worlds, assets)) = app.systems() {
+            schedule.run(stage, context, worlds, assets, input, delta);
+        }
+    }
+
+    //The step count is capped by the max delta, so slow steps can't pile up more and more of them.
+    fn run_fixed_steps(
+        app: &mut impl Application<'a>, delta: &Timestep, input: &InputState,
+        context: &mut Context<'a>,
+    ) {
+        for _ in 0..context.fixed.advance(delta) {
+            let step = context.fixed.step();
+
+            if let Some(world) = app.systems().and_then(|(_, worlds, _)| worlds.get_mut()) {
+                transform2d::snapshot_interpolated(world);
+            }
+
+            Context::run_systems(app, Stage::FixedUpdate, &step, input, context);
+            app.fixed_update(&step, context);
+        }
+
+        context.fixed_alpha = context.fixed.alpha() as f32;
+    }
+
+    //Moves what the engine noticed this frame into the event log and ends its frame.
+    fn log_engine_events(app: &mut impl Application<'a>, context: &mut Context<'a>) {
+        if let Some((_, worlds, assets)) = app.systems() {
+            if let Some((from, to)) = worlds.take_switched() {
+                context.event_log.emit(Topic::WORLD_SWITCHED, json!({ "from": from, "to": to }));
+            }
+
+            for entity in worlds.take_despawned() {
+                let entity = entity.to_bits().get();
+                context.event_log.emit(Topic::ENTITY_DESPAWNED, json!({ "entity": entity }));
+            }
+
+            for (guid, error) in assets.take_failed() {
+                let path = assets.asset_path(guid);
+                let payload = json!({ "guid": guid, "path": path, "error": error });
+                context.event_log.emit(Topic::ASSET_FAILED, payload);
+            }
+        }
+
+        for line in context.event_log.take_tailed() {
+            context.console.print(line);
+        }
+
+        context.event_log.next_frame();
+    }
+
+    //The console is taken out while its commands run, they get the whole context.
+    fn run_console(app: &mut impl Application<'a>, context: &mut Context<'a>) {
+        let mut console = std::mem::take(&mut context.console);
+
+        let events = match app.systems() {
+            Some((_, worlds, assets)) => {
+                console.execute_queued(context, Some(worlds), Some(assets))
+            }
+            None => console.execute_queued(context, None, None),
+        };
+
+        context.console = console;
+
+        for event in events.iter() {
+            app.get_stack().dispatch_event(event::EventType::Layer, event, context);
+        }
+    }
+
+    //Images are read from disk wherever they are, .fur files only through the project location.
+    fn load_dropped(app: &mut impl Application<'a>, path: &Path, context: &mut Context<'a>) {
+        let Some((_, worlds, assets)) = app.systems() else {
+            log::warn!("Dropped files are only loaded by applications that hand out systems.");
+            return;
+        };
+
+        let location = context.config.project_config().location.clone();
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("ldtk") => {
+                let registry = LdtkEntityRegistry::new();
+
+                match worlds.add_ldtk_file_with(
+                    &context.graphics,
+                    &location,
+                    assets,
+                    path,
+                    &registry,
+                ) {
+                    Ok(added) => {
+                        if let Some(world) = added.first().copied() {
+                            let dropped =
+                                event::Event::WorldDropped { world, path: path.to_path_buf() };
+                            app.get_stack().dispatch_event(
+                                event::EventType::Layer,
+                                &dropped,
+                                context,
+                            );
+                            app.on_event(&dropped, context);
+                        }
+                    }
+                    Err(error) => {
+                        log::error!("Failed to load dropped file {:?}. Error: {}", path, error)
+                    }
+                }
+            }
+            Some("fur") => {
+                match location.as_deref().and_then(|root| FileUtils::relative_to(path, root)) {
+                    Some(relative) => {
+                        let ptr = assets.request_any(&relative.to_string_lossy(), 0);
+                        context.dropped.push((ptr, path.to_path_buf()));
+                    }
+                    None => log::warn!("Dropped file {:?} is not part of the project.", path),
+                }
+            }
+            Some(extension) if image::ImageFormat::from_extension(extension).is_some() => {
+                let ptr = assets.request_file(path, 0).into();
+                context.dropped.push((ptr, path.to_path_buf()));
+            }
+            _ => log::warn!("Don't know how to load dropped file {:?}.", path),
+        }
+    }
+
+    //Sends AssetDropped for the dropped files that finished loading. Failed ones are in the event log.
+    fn poll_dropped(app: &mut impl Application<'a>, context: &mut Context<'a>) {
+        if context.dropped.is_empty() {
+            return;
+        }
+
+        let Some((_, _, assets)) = app.systems() else {
+            return;
+        };
+
+        let (done, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut context.dropped)
+            .into_iter()
+            .partition(|(ptr, _)| !assets.is_pending(ptr));
+        let loaded: Vec<_> = done.into_iter().filter(|(ptr, _)| assets.exist(ptr)).collect();
+        context.dropped = waiting;
+
+        for (ptr, path) in loaded {
+            let dropped = event::Event::AssetDropped { ptr, path };
+            app.get_stack().dispatch_event(event::EventType::Layer, &dropped, context);
+            app.on_event(&dropped, context);
+        }
+    }
+
+    //Tells everyone about a size winit applied without a Resized event, as if it had sent one.
+    fn apply_resize(
+        app: &mut impl Application<'a>, size: PhysicalSize<u32>, context: &mut Context<'a>,
+    ) {
+        context.resize(size);
+
+        let resized = event::Event::Resized { width: size.width, height: size.height };
+        app.get_stack().dispatch_event(event::EventType::Layer, &resized, context);
+        app.on_event(&resized, context);
+    }
+
+    //Queues an Event::Custom, e.g. for "player died". It goes to the layers and the app at the
+    //start of the next frame before anything is updated, in the order emitted. Events emitted
+    //while handling one wait for the frame after.
+    pub fn emit<T: Any + Send + Sync>(&mut self, event: T) {
+        self.emitted.push(Arc::new(event));
+    }
+
+    fn dispatch_emitted(app: &mut impl Application<'a>, context: &mut Context<'a>) {
+        for custom in std::mem::take(&mut context.emitted) {
+            let event = event::Event::Custom(custom);
+            app.get_stack().dispatch_event(event::EventType::Layer, &event, context);
+            app.on_event(&event, context);
+        }
+    }
+
+    //Sends the first finger down as the cursor and left mouse button until it is lifted. Other
+    //fingers are ignored meanwhile.
+    fn emulate_mouse(
+        app: &mut impl Application<'a>, touch: &winit::event::Touch, context: &mut Context<'a>,
+    ) {
+        use winit::event::{ElementState, MouseButton, TouchPhase};
+
+        if touch.phase == TouchPhase::Started && context.mouse_touch.is_none() {
+            context.mouse_touch = Some(touch.id);
+        } else if context.mouse_touch != Some(touch.id) {
+            return;
+        }
+
+        let mut events =
+            vec![event::Event::CursorMoved { x: touch.location.x, y: touch.location.y }];
+
+        match touch.phase {
+            TouchPhase::Started => events.push(event::Event::MouseInput {
+                mousecode: MouseButton::Left,
+                state: ElementState::Pressed,
+            }),
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                events.push(event::Event::MouseInput {
+                    mousecode: MouseButton::Left,
+                    state: ElementState::Released,
+                });
+                context.mouse_touch = None;
+            }
+            TouchPhase::Moved => {}
+        }
+
+        for event in events.iter() {
+            app.get_stack().dispatch_event(event::EventType::Layer, event, context);
+            app.on_event(event, context);
+        }
+    }
+
+    //Nobody consuming CloseRequested lets the loop exit, shutdown then calls Application::quit.
+    fn try_exit(
+        app: &mut impl Application<'a>, window_target: &EventLoopWindowTarget<()>,
+        context: &mut Context<'a>,
+    ) {
+        context.exit_requested = false;
+
+        let close = event::Event::CloseRequested;
+
+        if app.get_stack().dispatch_event(event::EventType::Layer, &close, context)
+            || app.on_event(&close, context)
+        {
+            log::info!("Exit was cancelled.");
+            return;
+        }
+
+        window_target.exit();
+    }
+
+    //Runs once when the event loop exits. The order matters: everything holding GPU resources
+    //has to be gone and the queue drained before the surface and device are dropped.
+    fn shutdown(&mut self, mut app: impl Application<'a>, window: &winit::window::Window) {
+        log::info!("Shutting down...");
+
+        let graphics = self.graphics.clone();
+
+        //Lets the application run script on_destroy for the active world and release its resources.
+        let release = move |context: &mut Context<'a>| {
+            app.shutdown(context);
+            app.quit();
+        };
+
+        //wgpu has no pipeline cache to persist yet. Settings changed at runtime (e.g. vsync or msaa)
+        //and the window state are kept for the next start.
+        let save = |context: &mut Context<'a>| {
+            if let Err(e) = context.config.save_engine_config() {
+                log::error!("Could not save the engine config. {}", e);
+            }
+
+            if let Err(e) = Config::save_window_config(&WindowConfig::from_window(window)) {
+                log::error!("Could not save the window state. {}", e);
+            }
+
+            context.console.save_history();
+            context.event_log.close_sink();
+        };
+
+        if let Some(error) = teardown(&graphics.device, self, release, save) {
+            log::error!("GPU error during shutdown: {}", error);
+        }
+
+        log::info!("Shutdown complete.");
+    }
+
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.surface_config.width = new_size.width;
@@ -210,6 +1001,11 @@ impl<'a> Context<'a> {
         &mut self, window: &winit::window::Window, app: &mut impl Application<'a>,
     ) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
+        let gpu_capture = std::mem::take(&mut self.gpu_capture);
+
+        if gpu_capture {
+            self.graphics.device.start_capture();
+        }
 
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -220,44 +1016,340 @@ impl<'a> Context<'a> {
         let input = self.egui.take_egui_input(window);
         self.egui.egui_ctx().begin_frame(input);
         app.gui_render(&view, self);
+        self.show_compat_warnings();
+        self.show_debug_overlay();
+        self.console.show(self.egui.egui_ctx());
 
         app.render(&view, self, window);
 
+        if self.capture.has_requests() {
+            self.capture.copy_frame(&self.graphics, &mut self.frame, &output.texture);
+        }
+
+        self.frame.end_frame();
+
+        if gpu_capture {
+            self.graphics.device.stop_capture();
+            log::info!("Captured a frame for the attached graphics debugger.");
+        }
+
         output.present();
+
+        self.capture.poll(&self.graphics);
         Ok(())
     }
 
+    //The callback gets the next presented frame as rgba, a frame or two after it was rendered.
+    pub fn capture_frame(&mut self, callback: impl FnOnce(image::RgbaImage) + Send + 'static) {
+        if !self.features.frame_capture {
+            log::warn!("Frame capture is not supported by this surface.");
+            return;
+        }
+
+        self.capture.request(callback);
+    }
+
+    //Writes the next frame to a png. Encoding happens on a worker thread.
+    pub fn save_screenshot(&mut self, path: impl Into<std::path::PathBuf>) {
+        if !self.features.frame_capture {
+            log::warn!("Frame capture is not supported by this surface.");
+            return;
+        }
+
+        self.capture.request_save(path.into());
+    }
+
+    pub(crate) fn screenshot_path(&self) -> std::path::PathBuf {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+
+        std::path::Path::new(&self.config.engine_config().screenshot_folder)
+            .join(format!("screenshot_{}.png", time))
+    }
+
+    //Also sets GraphicsConfiguration::present_mode, to be kept with Config::save_engine_config.
     pub fn set_vsync(&mut self, vsync: bool) {
         match vsync {
             true => self.surface_config.present_mode = PresentMode::AutoVsync,
             false => self.surface_config.present_mode = PresentMode::AutoNoVsync,
         }
 
+        self.config.engine_config_mut().graphics.present_mode =
+            format!("{:?}", self.surface_config.present_mode);
         self.surface.configure(&self.graphics.device, &self.surface_config);
     }
 
+    //None or 0 draws as fast as the present mode lets. Kept in GraphicsConfiguration::target_fps.
+    pub fn set_target_fps(&mut self, fps: Option<u32>) {
+        self.config.engine_config_mut().graphics.target_fps = fps.filter(|fps| *fps > 0);
+    }
+
+    pub fn target_fps(&self) -> Option<u32> {
+        self.config.engine_config().graphics.target_fps
+    }
+
+    //How long to wait before the next frame to stay below the target fps. None if it is due.
+    //Without a device only the next rebuild attempt is waited for.
+    fn frame_wait(&self) -> Option<Duration> {
+        if let Some((retry, _)) = self.rebuild_retry {
+            let now = Instant::now();
+            return (retry > now).then(|| retry - now);
+        }
+
+        let fps = self.target_fps().filter(|fps| *fps > 0)?;
+        let frame = Duration::from_secs_f64(1.0 / fps as f64);
+
+        frame.checked_sub(self.last_frame.elapsed()).filter(|wait| !wait.is_zero())
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    //The inner_size_writer is left alone, so winit picks the new size of the window. The surface
+    //and everyone else follow with the Resized winit sends after it.
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.egui.egui_ctx().set_pixels_per_point(scale_factor as f32);
+    }
+
     pub fn vsync(&self) -> bool {
         self.surface_config.present_mode == PresentMode::AutoVsync
     }
 
+    pub fn set_debug_overlay(&mut self, visible: bool) {
+        self.debug_overlay = visible;
+    }
+
+    //Closes the game once the current event is handled, like closing the window does. Layers and
+    //the application get Event::CloseRequested first, consuming it keeps the game running.
+    pub fn request_exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    //The window changes size through the usual Resized event, so renderers and cameras follow.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if self.is_fullscreen() != fullscreen {
+            Window::toggle_fullscreen(&self.window);
+        }
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.window.fullscreen().is_some()
+    }
+
+    pub fn set_window_title(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    //In physical pixels. Ignored in fullscreen and by platforms that don't let apps resize windows.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        if let Some(size) = self.window.request_inner_size(PhysicalSize::new(width, height)) {
+            self.pending_resize = Some(size);
+        }
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        let size = self.window.inner_size();
+        (size.width, size.height)
+    }
+
+    //The engine still hides it while the right mouse button is held.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.window.set_cursor_visible(visible);
+    }
+
+    //Locked keeps the cursor in place, Confined inside the window. Platforms only support one of
+    //them (Locked on macOS and the web, Confined on Windows), the other one is used as a fallback.
+    //Returns the mode in effect. Read the movement from InputState::mouse_motion while grabbed.
+    pub fn set_cursor_grab(&mut self, mode: CursorGrabMode) -> CursorGrabMode {
+        let fallback = match mode {
+            CursorGrabMode::Locked => CursorGrabMode::Confined,
+            CursorGrabMode::Confined => CursorGrabMode::Locked,
+            CursorGrabMode::None => CursorGrabMode::None,
+        };
+
+        for mode in [mode, fallback] {
+            match self.window.set_cursor_grab(mode) {
+                Ok(()) => return mode,
+                Err(error) => log::debug!("Cursor grab {:?} failed. {}", mode, error),
+            }
+        }
+
+        log::warn!("Could not grab the cursor.");
+        CursorGrabMode::None
+    }
+
+    //Multiplies every frame delta, 0 pauses everything driven by it. Ends frame stepping.
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.time_scale = scale;
+        self.frame_step = None;
+    }
+
+    //Pauses on the first call, every further one runs a single frame at the time scale there was.
+    pub fn step_frame(&mut self) {
+        match &mut self.frame_step {
+            Some((_, frames)) => *frames += 1,
+            None => {
+                self.frame_step = Some((self.time_scale, 0));
+                log::info!("Frame stepping. Step again to run a frame, Shift resumes.");
+            }
+        }
+    }
+
+    pub fn end_frame_step(&mut self) {
+        if self.frame_step.take().is_some() {
+            log::info!("Frame stepping ended.");
+        }
+    }
+
+    pub fn is_frame_stepping(&self) -> bool {
+        self.frame_step.is_some()
+    }
+
+    //What this frame's delta is scaled by, 0 while frame stepping holds.
+    fn frame_scale(&mut self) -> f64 {
+        match &mut self.frame_step {
+            Some((scale, frames)) if *frames > 0 => {
+                *frames -= 1;
+                *scale
+            }
+            Some(_) => 0.0,
+            None => self.time_scale,
+        }
+    }
+
+    //The next frame is recorded by an attached graphics debugger, e.g. RenderDoc or Xcode.
+    pub fn capture_gpu_frame(&mut self) {
+        self.gpu_capture = true;
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    //In steps per second.
+    pub fn set_fixed_rate(&mut self, rate: f64) {
+        self.fixed = FixedTimestep::new(1000.0 / rate.max(1.0));
+    }
+
+    //The delta update got this frame, for code that runs later, e.g. while rendering.
+    pub fn frame_delta(&self) -> Timestep {
+        Timestep::from(self.frame_delta)
+    }
+
+    //Between 0 and 1, how far the frame is from the last fixed step to the next one.
+    pub fn fixed_alpha(&self) -> f32 {
+        self.fixed_alpha
+    }
+
+    //Keep clamped frames visible, they still are hitches.
+    fn record_hitch(&mut self, raw: f64, max_delta: f64) {
+        log::warn!("Frame took {:.0} ms, clamped delta to {:.0} ms.", raw, max_delta);
+        self.last_hitch = Some(raw);
+    }
+
+    fn show_debug_overlay(&mut self) {
+        if !self.debug_overlay {
+            return;
+        }
+
+        let bindings = self.config.engine_config().debug_bindings.active();
+
+        egui::Window::new("Debug").show(self.egui.egui_ctx(), |ui| {
+            ui.label(format!(
+                "{} ({:?})",
+                self.engine_info.adapter.name, self.engine_info.adapter.backend
+            ));
+            ui.label(format!("VSync: {}", self.vsync()));
+
+            let stats = self.frame.stats();
+            ui.label(format!(
+                "Submissions: {} ({} command buffers)",
+                stats.submissions, stats.command_buffers
+            ));
+
+            if let Some(hitch) = self.last_hitch {
+                ui.label(format!("Last clamped frame: {:.0} ms", hitch));
+            }
+
+            ui.separator();
+
+            for (action, key) in bindings {
+                ui.label(format!("{:<10} {}", key, action));
+            }
+        });
+    }
+
+    //Engine shortcuts are matched here, before anything reaches the layers. Bound keys still go on
+    //to the layers and the InputState, only Alt+Enter is consumed.
+    fn dispatch_debug_binding(
+        apps: &mut ModuleStack, event: &WindowEvent, context: &mut Context,
+    ) -> bool {
+        let WindowEvent::KeyboardInput { event, .. } = event else {
+            return false;
+        };
+
+        let PhysicalKey::Code(keycode) = event.physical_key else {
+            return false;
+        };
+
+        //Alt+Enter toggles fullscreen like in most games, whatever the bindings say.
+        if keycode == KeyCode::Enter && context.modifiers.alt_key() {
+            if event.state == winit::event::ElementState::Pressed && !event.repeat {
+                context.set_fullscreen(!context.is_fullscreen());
+            }
+
+            return true;
+        }
+
+        let Some(action) = context.config.engine_config().debug_bindings.action_for(&keycode)
+        else {
+            return false;
+        };
+
+        if event.state != winit::event::ElementState::Pressed || event.repeat {
+            return false;
+        }
+
+        match action {
+            DebugAction::Exit => context.request_exit(),
+            DebugAction::ToggleVsync => context.set_vsync(!context.vsync()),
+            DebugAction::ToggleFullscreen => context.set_fullscreen(!context.is_fullscreen()),
+            DebugAction::DebugOverlay => context.debug_overlay = !context.debug_overlay,
+            DebugAction::Console => context.console.toggle(),
+            DebugAction::Screenshot if context.config.engine_config().screenshots => {
+                let path = context.screenshot_path();
+                context.save_screenshot(path);
+            }
+            DebugAction::FrameStep if context.modifiers.shift_key() => context.end_frame_step(),
+            DebugAction::FrameStep => context.step_frame(),
+            DebugAction::GpuCapture => context.capture_gpu_frame(),
+            DebugAction::Screenshot => {}
+        }
+
+        //Let the rest of the engine and the game react as well.
+        apps.dispatch_event(event::EventType::Layer, &event::Event::DebugAction(action), context);
+        false
+    }
+
     //These wrapper are just making the code structure more logical in my opinion.
     fn dispatch_event(
         apps: &mut ModuleStack, window: &winit::window::Window, event: &WindowEvent,
         window_target: &EventLoopWindowTarget<()>, context: &mut Context,
     ) -> bool {
-        let return_value =
-            apps.dispatch_event(event::EventType::Layer, &event::to_event(event), context);
+        let return_value = apps.dispatch_event(
+            event::EventType::Layer,
+            &event::to_event(event, context.modifiers),
+            context,
+        );
 
-        if *event == WindowEvent::CloseRequested || *event == WindowEvent::Destroyed {
+        if *event == WindowEvent::Destroyed {
             window_target.exit();
         }
 
-        if let WindowEvent::KeyboardInput { event, .. } = event {
-            if let Key::Named(NamedKey::Escape) = event.logical_key {
-                window_target.exit();
-            }
-        }
-
         if let WindowEvent::MouseInput { device_id: _, state, button } = *event {
             if button == winit::event::MouseButton::Right
                 && state == winit::event::ElementState::Pressed
@@ -279,10 +1371,96 @@ impl<'a> Context<'a> {
         self.sysinfo.free_memory()
     }
 
-    fn dispatch_gamepad_event(
-        apps: &mut ModuleStack, event: &gilrs::Event, _window_target: &EventLoopWindowTarget<()>,
-        context: &mut Context,
-    ) -> bool {
-        apps.dispatch_event(event::EventType::Layer, &event::to_gamepad_event(event), context)
+    fn pump_gamepad_events(
+        apps: &mut ModuleStack, source: &mut impl GamepadEventSource, context: &mut Context,
+    ) {
+        event::pump_gamepad_events(source, |gamepad_event| {
+            apps.dispatch_event(event::EventType::Layer, gamepad_event, context);
+        });
+    }
+}
+
+//The order of Context::shutdown, without the window so it can run on a headless device. release
+//drops everything holding GPU resources, the queue is drained before save keeps the settings.
+//Returns the GPU error raised while releasing, if any.
+pub(crate) fn teardown<S>(
+    device: &wgpu::Device, state: &mut S, release: impl FnOnce(&mut S), save: impl FnOnce(&mut S),
+) -> Option<wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    release(state);
+    device.poll(wgpu::Maintain::Wait);
+
+    let error = pollster::block_on(device.pop_error_scope());
+    save(state);
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //None without an adapter, e.g. on CI machines without a GPU or software renderer.
+    fn headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    #[test]
+    fn teardown_drains_the_queue_before_saving() {
+        let Some((device, queue)) = headless_device() else {
+            return;
+        };
+
+        let drained = Arc::new(AtomicBool::new(false));
+        let mut steps = Vec::new();
+
+        let release = |steps: &mut Vec<&str>| {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Teardown Test"),
+                size: 256,
+                usage: wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            queue.write_buffer(&buffer, 0, &[0; 256]);
+            queue.submit(None);
+
+            let drained = drained.clone();
+            queue.on_submitted_work_done(move || drained.store(true, Ordering::SeqCst));
+
+            drop(buffer);
+            steps.push("release");
+        };
+
+        let save = |steps: &mut Vec<&str>| {
+            assert!(drained.load(Ordering::SeqCst));
+            steps.push("save");
+        };
+
+        let error = teardown(&device, &mut steps, release, save);
+
+        assert!(error.is_none());
+        assert_eq!(steps, vec!["release", "save"]);
+    }
+
+    #[test]
+    fn teardown_reports_errors_of_the_release() {
+        let Some((device, _queue)) = headless_device() else {
+            return;
+        };
+
+        //Mapped buffers have to be a multiple of 4 bytes.
+        let release = |_: &mut ()| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Teardown Test"),
+                size: 3,
+                usage: wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: true,
+            });
+        };
+
+        assert!(teardown(&device, &mut (), release, |_| ()).is_some());
     }
 }
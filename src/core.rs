@@ -1,8 +1,11 @@
 use std::cell::Ref;
 
+use crate::assets::assets::Assets;
 use crate::context::Context;
-use crate::event::{Event, EventStack, EventSubscriber, EventType};
+use crate::entities::entities::Worlds;
+use crate::event::{Event, EventStack, EventSubscriber, EventType, SubscriptionId};
 use crate::input::InputState;
+use crate::schedule::Schedule;
 use crate::utils::Timestep;
 
 use rccell::RcCell;
@@ -23,14 +26,26 @@ pub trait Module {
 }
 
 pub trait Application<'a> {
+    //Returning true for Event::CloseRequested keeps the game running, see Context::request_exit.
     fn on_event(&mut self, event: &Event, context: &mut Context) -> bool;
     fn render(
         &mut self, view: &wgpu::TextureView, context: &mut Context, window: &winit::window::Window,
     );
     fn gui_render(&mut self, view: &wgpu::TextureView, context: &mut Context);
     fn update(&mut self, delta: &Timestep, input_state: Ref<InputState>, context: &mut Context);
+    //Zero or more times per frame with the fixed step of EngineConfiguration::fixed_rate, for physics
+    //and movement. Interpolated transforms are snapshot before it if the app hands out systems,
+    //otherwise call transform2d::snapshot_interpolated first.
+    fn fixed_update(&mut self, _delta: &Timestep, _context: &mut Context) {}
+    //Called once on exit while the device is still alive, e.g. to run script on_destroy. Before quit.
+    fn shutdown(&mut self, _context: &mut Context) {}
     fn quit(&mut self);
 
+    //Hand out a schedule and the data its systems work on, to have the engine run them around update.
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        None
+    }
+
     fn get_stack(&mut self) -> &mut ModuleStack<'a>;
 }
 
@@ -65,9 +80,30 @@ impl<'a> ModuleStack<'a> {
         }
     }
 
+    //Only holds on to the subscriber weakly, once all of its RcCells are dropped it is unsubscribed.
     pub fn subscribe(
         &mut self, event_type: EventType, subscriber: RcCell<impl EventSubscriber + 'a>,
-    ) {
-        self.events.push(event_type, enclose! { (subscriber) move |event: &Event, context: &mut Context| { subscriber.borrow_mut().on_event(event, context) }});
+    ) -> SubscriptionId {
+        let subscriber = subscriber.downgrade();
+
+        self.events.push_dropping(event_type, move |event: &Event, context: &mut Context| {
+            let subscriber = subscriber.upgrade()?;
+            let handled = subscriber.borrow_mut().on_event(event, context);
+            Some(handled)
+        })
+    }
+
+    //For subscribers nobody else needs to get at, they live as long as the subscription.
+    pub fn subscribe_owned(
+        &mut self, event_type: EventType, mut subscriber: impl EventSubscriber + 'a,
+    ) -> SubscriptionId {
+        self.events.push(event_type, move |event: &Event, context: &mut Context| {
+            subscriber.on_event(event, context)
+        })
+    }
+
+    //False if it was already unsubscribed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.events.remove(id)
     }
 }
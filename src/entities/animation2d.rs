@@ -1,18 +1,131 @@
 use crate::assets::assets::Ptr;
+use crate::assets::sprite_sheet::SpriteSheet;
 use crate::assets::texture::Texture2D;
 use crate::context::VisContext;
 use crate::entities::sprite::Sprite;
 use crate::utils::Timestep;
-use glam::Vec2;
+use glam::{Vec2, Vec4};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//Fades running in any world. The renderer skips looking for them while there are none.
+static ACTIVE_FADES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn any_fading() -> bool {
+    ACTIVE_FADES.load(Ordering::Relaxed) > 0
+}
+
+//The outgoing frame of a cross-fade. It is drawn as a second quad on top of the sprite with decreasing alpha.
+//The sprite is kept after the fade for the next one.
+pub struct AnimationFade {
+    sprite: Sprite<'static>,
+    duration: f64,
+    elapsed: f64,
+    active: bool,
+}
+
+//What the outgoing animation showed when the fade was requested.
+struct FadeRequest {
+    duration: f64,
+    uv: (Vec2, Vec2),
+    mirrored: bool,
+}
+
+impl AnimationFade {
+    fn set_active(&mut self, active: bool) {
+        if self.active != active {
+            self.active = active;
+
+            match active {
+                true => ACTIVE_FADES.fetch_add(1, Ordering::Relaxed),
+                false => ACTIVE_FADES.fetch_sub(1, Ordering::Relaxed),
+            };
+        }
+    }
+
+    pub fn sprite(&self) -> &Sprite<'static> {
+        &self.sprite
+    }
+
+    pub fn sprite_mut(&mut self) -> &mut Sprite<'static> {
+        &mut self.sprite
+    }
+
+    pub fn progress(&self) -> f32 {
+        (self.elapsed / self.duration).clamp(0.0, 1.0) as f32
+    }
+}
+
+impl Drop for AnimationFade {
+    fn drop(&mut self) {
+        self.set_active(false);
+    }
+}
+
+//A sheet of equally sized cells. Cells are numbered row by row, starting at the top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteGrid {
+    pub columns: u32,
+    pub rows: u32,
+    //Pixels cut off at every side of a cell, against bleeding from the neighbours.
+    pub padding: f32,
+}
+
+impl SpriteGrid {
+    pub fn new(columns: u32, rows: u32) -> Self {
+        SpriteGrid { columns: columns.max(1), rows: rows.max(1), padding: 0.0 }
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding.max(0.0);
+        self
+    }
+
+    //Texture rect (min, max) of a cell. The padding needs the texture size and is left out without it.
+    pub fn cell(&self, index: u32, texture_size: Option<Vec2>) -> (Vec2, Vec2) {
+        let cell_size = Vec2::new(1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        let padding = texture_size.map_or(Vec2::ZERO, |size| Vec2::splat(self.padding) / size);
+        let padding = padding.min(cell_size / 2.0);
+
+        let position = Vec2::new((index % self.columns) as f32, (index / self.columns) as f32);
+        let min = position * cell_size;
+        (min + padding, min + cell_size - padding)
+    }
+}
+
+struct GridFrames {
+    grid: SpriteGrid,
+    range: Range<u32>,
+    //The rects were last computed for this size.
+    texture_size: Option<Vec2>,
+}
+
+impl GridFrames {
+    fn rects(&self) -> Vec<(Vec2, Vec2)> {
+        self.range.clone().map(|index| self.grid.cell(index, self.texture_size)).collect()
+    }
+}
 
 pub struct Animation2D {
     frames: Ptr<Texture2D>,
     frames_per_second: f64,
     current_frame: f32,
     total_frames: f32,
+    //The frame on screen, None until the first one was shown.
+    shown_frame: Option<f32>,
     mirrored: bool,
     looped: bool,
+    paused: bool,
+    //Negative plays backwards.
+    speed: f32,
     delta: f64,
+    current_uv: (Vec2, Vec2),
+    //Frames cut from a sprite sheet. Without them the texture is a horizontal strip.
+    rects: Option<Vec<(Vec2, Vec2)>>,
+    //Source of the rects if they are cut from a grid, they follow the size of the bound texture.
+    grid: Option<GridFrames>,
+    fade: Option<AnimationFade>,
+    fade_request: Option<FadeRequest>,
 }
 
 impl Animation2D {
@@ -25,44 +138,335 @@ impl Animation2D {
             frames_per_second: frames_per_second as f64,
             total_frames: total_frames as f32,
             current_frame: 0.0,
+            shown_frame: None,
             mirrored,
             looped,
+            paused: false,
+            speed: 1.0,
             delta: 0.0,
+            current_uv: (Vec2::ZERO, Vec2::ONE),
+            rects: None,
+            grid: None,
+            fade: None,
+            fade_request: None,
         }
     }
 
+    //Plays a clip of a sprite sheet, e.g. row_0. None if the sheet has no such clip.
+    pub fn from_clip(
+        frames: Ptr<Texture2D>, sheet: &SpriteSheet, clip: &str, frames_per_second: u32,
+        mirrored: bool, looped: bool,
+    ) -> Option<Self> {
+        let rects = sheet.clip_uvs(clip).filter(|rects| !rects.is_empty())?;
+        let animation = Self::new(frames, frames_per_second, rects.len() as u32, mirrored, looped);
+        Some(animation.with_rects(rects))
+    }
+
+    //Plays these texture rects (min, max) instead of cutting the texture into a strip.
+    pub fn with_rects(mut self, rects: Vec<(Vec2, Vec2)>) -> Self {
+        self.total_frames = rects.len() as f32;
+        self.rects = Some(rects);
+        self.grid = None;
+        self
+    }
+
+    //Plays the cells in frame_range of a grid sheet. Without the texture size (in pixels) the padding
+    //is applied once the texture is bound to the sprite.
+    pub fn from_grid(
+        frames: Ptr<Texture2D>, frames_per_second: u32, grid: SpriteGrid, frame_range: Range<u32>,
+        texture_size: Option<Vec2>, looped: bool,
+    ) -> Self {
+        let cells = grid.columns * grid.rows;
+        let range = frame_range.start.min(cells)..frame_range.end.min(cells);
+
+        let grid = GridFrames { grid, range, texture_size };
+        let mut animation =
+            Self::new(frames, frames_per_second, 0, false, looped).with_rects(grid.rects());
+        animation.grid = Some(grid);
+        animation
+    }
+
+    //Back to the first frame, the last one when playing backwards.
     pub fn reset(&mut self) {
-        self.current_frame = 0.0;
+        self.current_frame =
+            if self.speed < 0.0 { (self.total_frames - 1.0).max(0.0) } else { 0.0 };
+        self.shown_frame = None;
         self.delta = 0.0;
     }
 
+    //Shows the frame on the next update and plays on from there.
+    pub fn play_from(&mut self, frame: u32) {
+        self.current_frame = (frame as f32).min((self.total_frames - 1.0).max(0.0));
+        self.paused = false;
+        self.delta = f64::MAX;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    //Multiplies the frame rate, e.g. 0.1 for a hit-stop. Zero holds the frame like a pause,
+    //negative values play backwards from the frame on screen.
+    pub fn set_speed(&mut self, speed: f32) {
+        let backwards = speed < 0.0;
+
+        if backwards != (self.speed < 0.0) {
+            self.current_frame = match self.shown_frame {
+                Some(shown) => self.wrap_frame(shown + if backwards { -1.0 } else { 1.0 }),
+                None if backwards => (self.total_frames - 1.0).max(0.0),
+                None => 0.0,
+            };
+        }
+
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn wrap_frame(&self, frame: f32) -> f32 {
+        match self.looped && self.total_frames >= 1.0 {
+            true => frame.rem_euclid(self.total_frames),
+            false => frame,
+        }
+    }
+
+    //Shows the first frame on the next update instead of waiting a frame duration.
+    pub fn start_immediately(mut self) -> Self {
+        self.delta = f64::MAX;
+        self
+    }
+
+    //Index of the frame shown next.
+    pub fn current_frame(&self) -> u32 {
+        self.current_frame.max(0.0) as u32
+    }
+
+    //The next update moves on to the next frame.
+    pub fn is_frame_due(&self) -> bool {
+        !self.paused && self.speed != 0.0 && self.delta > 1000.0 / self.frames_per_second
+    }
+
+    //Past the last frame (the first when playing backwards). Never for looped animations.
+    pub fn is_finished(&self) -> bool {
+        self.is_past_end() && self.delta >= 1000.0 / self.frames_per_second
+    }
+
+    fn is_past_end(&self) -> bool {
+        !self.looped && (self.current_frame >= self.total_frames || self.current_frame < 0.0)
+    }
+
     pub fn set_mirrored(&mut self, mirrored: bool) {
         self.mirrored = mirrored;
     }
 
+    pub fn mirrored(&self) -> bool {
+        self.mirrored
+    }
+
+    pub fn looped(&self) -> bool {
+        self.looped
+    }
+
+    pub fn frames_per_second(&self) -> u32 {
+        self.frames_per_second as u32
+    }
+
+    pub fn total_frames(&self) -> u32 {
+        self.total_frames as u32
+    }
+
+    pub fn rects(&self) -> Option<&[(Vec2, Vec2)]> {
+        self.rects.as_deref()
+    }
+
+    //Swap the strip texture but keep playing from the current frame. Frames are addressed by index,
+    //so any strip with the same layout works (see SkinSet).
+    pub fn set_frames(&mut self, frames: Ptr<Texture2D>) {
+        self.frames = frames;
+    }
+
+    pub fn frames(&self) -> &Ptr<Texture2D> {
+        &self.frames
+    }
+
+    //Cross-fades from the frame previous shows over duration (in ms), see Animator2D::play_with_fade.
+    //A fade still running in previous is cut off, its sprite is reused for the outgoing frame.
+    pub(crate) fn fade_from(&mut self, previous: &mut Animation2D, duration: f64) {
+        self.fade = previous.fade.take();
+
+        //previous may not have shown anything yet, then the sprite still shows what it faded from.
+        let (uv, mirrored) = match previous.fade_request.take() {
+            Some(request) if previous.shown_frame.is_none() => (request.uv, request.mirrored),
+            _ => (previous.current_uv, previous.mirrored),
+        };
+
+        self.fade_request = Some(FadeRequest { duration, uv, mirrored });
+    }
+
+    pub fn fade(&self) -> Option<&AnimationFade> {
+        self.fade.as_ref().filter(|fade| fade.active)
+    }
+
+    pub fn fade_mut(&mut self) -> Option<&mut AnimationFade> {
+        self.fade.as_mut().filter(|fade| fade.active)
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade().is_some() || self.fade_request.is_some()
+    }
+
     pub fn update(&mut self, context: &VisContext, delta: &Timestep, sprite: &mut Sprite) {
-        if !self.looped && self.current_frame >= self.total_frames {
+        //Paused while hidden, the coordinates would only be written for nothing.
+        if !sprite.is_visible() {
+            return;
+        }
+
+        if let Some(request) = self.fade_request.take() {
+            self.begin_fade(context, sprite, request);
+        }
+
+        if let Some(fade) = self.fade.as_mut().filter(|fade| fade.active) {
+            fade.elapsed += delta.millis();
+
+            if fade.elapsed >= fade.duration {
+                fade.set_active(false);
+            } else {
+                let tint = *sprite.tint();
+                let alpha = tint.w * (1.0 - fade.progress());
+                fade.sprite.set_tint(context, Vec4::new(tint.x, tint.y, tint.z, alpha));
+            }
+        }
+
+        if self.paused || self.speed == 0.0 {
+            return;
+        }
+
+        let elapsed = delta.millis() * self.speed.abs() as f64;
+
+        //Holds the last frame, is_finished once it was shown for a whole frame.
+        if self.is_past_end() {
+            self.delta += elapsed;
             return;
         }
 
         sprite.set_texture(self.frames);
 
+        //Padded cells are cut again once the size of the texture is known.
+        let texture_size = sprite.texture_size();
+        if let Some(grid) = self.grid.as_mut().filter(|grid| grid.grid.padding > 0.0) {
+            if texture_size.is_some() && grid.texture_size != texture_size {
+                grid.texture_size = texture_size;
+                self.rects = Some(grid.rects());
+            }
+        }
+
+        if self.total_frames < 1.0 {
+            return;
+        }
+
         if self.delta > 1000.0 / self.frames_per_second {
-            let mirror_value = if self.mirrored { 1.0 } else { 0.0 };
-
-            sprite.set_coords_quad(
-                context,
-                Vec2::new((1.0 / self.total_frames) * (self.current_frame + mirror_value), 0.0),
-                Vec2::new(
-                    (1.0 / self.total_frames) * (self.current_frame + 1.0 - mirror_value),
-                    1.0,
-                ),
-            );
-
-            self.current_frame = (self.current_frame + 1.0) % self.total_frames;
+            let (min, max) = match &self.rects {
+                Some(rects) => rects[self.current_frame as usize % rects.len()],
+                None => {
+                    let min = Vec2::new((1.0 / self.total_frames) * self.current_frame, 0.0);
+                    let max =
+                        Vec2::new((1.0 / self.total_frames) * (self.current_frame + 1.0), 1.0);
+                    (min, max)
+                }
+            };
+
+            //Mirroring is left to the sprite, so it adds up with its own flip.
+            sprite.set_frame(context, min, max, self.mirrored);
+            self.current_uv = (min, max);
+
+            self.shown_frame = Some(self.current_frame);
+
+            let step = if self.speed < 0.0 { -1.0 } else { 1.0 };
+            self.current_frame = self.wrap_frame(self.current_frame + step);
             self.delta = 0.0;
         } else {
-            self.delta += delta.millis();
+            self.delta += elapsed;
+        }
+    }
+
+    //Runs before the new strip is set, so the sprite still shows the outgoing frame.
+    fn begin_fade(&mut self, context: &VisContext, sprite: &Sprite, request: FadeRequest) {
+        if request.duration <= 0.0 {
+            if let Some(fade) = &mut self.fade {
+                fade.set_active(false);
+            }
+            return;
         }
+
+        let fade = self.fade.get_or_insert_with(|| AnimationFade {
+            sprite: Sprite::new(context, *sprite.texture(), *sprite.tint(), None, None),
+            duration: 0.0,
+            elapsed: 0.0,
+            active: false,
+        });
+
+        let (flip_x, flip_y) = sprite.flip();
+        fade.sprite.set_texture(*sprite.texture());
+        fade.sprite.set_tint(context, *sprite.tint());
+        fade.sprite.set_flip(context, flip_x, flip_y);
+        fade.sprite.set_frame(context, request.uv.0, request.uv.1, request.mirrored);
+        fade.duration = request.duration;
+        fade.elapsed = 0.0;
+        fade.set_active(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Guid;
+
+    fn strip(id: u64) -> Animation2D {
+        Animation2D::new(Ptr::new(Guid::new(id)), 8, 4, false, true)
+    }
+
+    #[test]
+    fn fade_starts_from_the_shown_frame() {
+        let mut idle = strip(1);
+        idle.current_uv = (Vec2::new(0.25, 0.0), Vec2::new(0.5, 1.0));
+        idle.shown_frame = Some(1.0);
+        idle.mirrored = true;
+
+        let mut run = strip(2);
+        run.fade_from(&mut idle, 100.0);
+
+        let request = run.fade_request.as_ref().unwrap();
+        assert_eq!(request.uv, idle.current_uv);
+        assert!(request.mirrored);
+        assert!(run.is_fading());
+    }
+
+    #[test]
+    fn interrupted_fade_starts_from_what_is_on_screen() {
+        let mut idle = strip(1);
+        idle.current_uv = (Vec2::new(0.25, 0.0), Vec2::new(0.5, 1.0));
+        idle.shown_frame = Some(1.0);
+
+        //run is replaced before it showed a frame, the sprite still shows idle.
+        let mut run = strip(2);
+        run.fade_from(&mut idle, 100.0);
+        let mut back = strip(1);
+        back.fade_from(&mut run, 50.0);
+
+        let request = back.fade_request.as_ref().unwrap();
+        assert_eq!(request.uv, (Vec2::new(0.25, 0.0), Vec2::new(0.5, 1.0)));
+        assert_eq!(request.duration, 50.0);
+        assert!(run.fade_request.is_none());
+    }
+
+    #[test]
+    fn nothing_fades_without_a_request() {
+        assert!(!strip(1).is_fading());
     }
 }
@@ -0,0 +1,276 @@
+use hashbrown::HashMap;
+
+use crate::assets::assets::Ptr;
+use crate::assets::texture::Texture2D;
+use crate::context::VisContext;
+use crate::entities::animation2d::Animation2D;
+use crate::entities::sprite::Sprite;
+use crate::utils::Timestep;
+
+//One horizontal strip, like a plain Animation2D.
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub texture: Ptr<Texture2D>,
+    pub frames_per_second: u32,
+    pub frame_count: u32,
+    pub looped: bool,
+    //The strip faces the other way, flipped on top of the mirroring of the animator.
+    pub mirrored: bool,
+}
+
+impl AnimationClip {
+    pub fn new(texture: Ptr<Texture2D>, frames_per_second: u32, frame_count: u32) -> Self {
+        AnimationClip { texture, frames_per_second, frame_count, looped: true, mirrored: false }
+    }
+
+    pub fn once(mut self) -> Self {
+        self.looped = false;
+        self
+    }
+
+    pub fn mirrored(mut self) -> Self {
+        self.mirrored = true;
+        self
+    }
+
+    fn animation(&self, mirrored: bool) -> Animation2D {
+        Animation2D::new(
+            self.texture,
+            self.frames_per_second,
+            self.frame_count,
+            mirrored != self.mirrored,
+            self.looped,
+        )
+        .start_immediately()
+    }
+}
+
+//Clips by name, e.g. idle, run and jump of a character.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationSet {
+    clips: HashMap<String, AnimationClip>,
+}
+
+impl AnimationSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: impl Into<String>, clip: AnimationClip) -> Self {
+        self.insert(name, clip);
+        self
+    }
+
+    pub fn insert(
+        &mut self, name: impl Into<String>, clip: AnimationClip,
+    ) -> Option<AnimationClip> {
+        self.clips.insert(name.into(), clip)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.clips.keys().map(String::as_str)
+    }
+}
+
+//Switches the sprite between the clips of a set. Use it instead of an Animation2D, not next to it.
+//Clips start over when switched to, the mirroring stays.
+pub struct Animator2D {
+    clips: AnimationSet,
+    current: Option<String>,
+    queued: Option<String>,
+    animation: Option<Animation2D>,
+    mirrored: bool,
+    //A looped clip showed its last frame, it is done once the first one is due again.
+    wrapped: bool,
+}
+
+impl Animator2D {
+    //Nothing plays until play is called.
+    pub fn new(clips: AnimationSet) -> Self {
+        Animator2D {
+            clips,
+            current: None,
+            queued: None,
+            animation: None,
+            mirrored: false,
+            wrapped: false,
+        }
+    }
+
+    pub fn with_clip(clips: AnimationSet, name: &str) -> Self {
+        let mut animator = Self::new(clips);
+        animator.play(name);
+        animator
+    }
+
+    //Switches right away, unless the clip is already playing. Finished clips start over.
+    //Drops what was queued. Returns false if there is no such clip.
+    pub fn play(&mut self, name: &str) -> bool {
+        self.switch(name).is_some()
+    }
+
+    //Like play, but cross-fades from the frame on screen over duration (in ms). If a fade is still
+    //running, it is cut off and the clip that was fading in becomes the outgoing one.
+    pub fn play_with_fade(&mut self, name: &str, duration: f64) -> bool {
+        let Some(previous) = self.switch(name) else {
+            return false;
+        };
+
+        if let (Some(animation), Some(mut previous)) = (&mut self.animation, previous) {
+            animation.fade_from(&mut previous, duration);
+        }
+
+        true
+    }
+
+    //None if there is no such clip. Otherwise the animation that was replaced, if any.
+    fn switch(&mut self, name: &str) -> Option<Option<Animation2D>> {
+        self.queued = None;
+
+        let finished = self.animation.as_ref().is_some_and(Animation2D::is_finished);
+        if self.current.as_deref() == Some(name) && !finished {
+            return Some(None);
+        }
+
+        let Some(clip) = self.clips.get(name) else {
+            log::warn!("The animation set has no clip {}.", name);
+            return None;
+        };
+
+        let previous = self.animation.replace(clip.animation(self.mirrored));
+        self.current = Some(name.to_string());
+        self.wrapped = false;
+        Some(previous)
+    }
+
+    //Plays the clip once the current one is done, looped ones at the end of their cycle.
+    pub fn queue(&mut self, name: &str) {
+        match self.animation {
+            Some(_) => self.queued = Some(name.to_string()),
+            None => {
+                self.play(name);
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn set_mirrored(&mut self, mirrored: bool) {
+        self.mirrored = mirrored;
+
+        let clip = self.current.as_deref().and_then(|name| self.clips.get(name));
+
+        if let (Some(animation), Some(clip)) = (&mut self.animation, clip) {
+            animation.set_mirrored(mirrored != clip.mirrored);
+        }
+    }
+
+    pub fn mirrored(&self) -> bool {
+        self.mirrored
+    }
+
+    pub fn clips(&self) -> &AnimationSet {
+        &self.clips
+    }
+
+    pub fn clips_mut(&mut self) -> &mut AnimationSet {
+        &mut self.clips
+    }
+
+    pub fn animation(&self) -> Option<&Animation2D> {
+        self.animation.as_ref()
+    }
+
+    pub fn animation_mut(&mut self) -> Option<&mut Animation2D> {
+        self.animation.as_mut()
+    }
+
+    pub fn update(&mut self, context: &VisContext, delta: &Timestep, sprite: &mut Sprite) {
+        let done = self.animation.as_ref().is_some_and(|animation| {
+            animation.is_finished() || (self.wrapped && animation.is_frame_due())
+        });
+
+        if done {
+            if let Some(next) = self.queued.take() {
+                self.play(&next);
+            }
+        }
+
+        let Some(animation) = &mut self.animation else {
+            return;
+        };
+
+        let (before, advancing) = (animation.current_frame(), animation.is_frame_due());
+        animation.update(context, delta, sprite);
+
+        if advancing && animation.looped() {
+            self.wrapped = match animation.speed() < 0.0 {
+                true => animation.current_frame() >= before,
+                false => animation.current_frame() <= before,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Guid;
+
+    fn strip(id: u64) -> Ptr<Texture2D> {
+        Ptr::new(Guid::new(id))
+    }
+
+    fn animator() -> Animator2D {
+        let clips = AnimationSet::new()
+            .with("idle", AnimationClip::new(strip(1), 8, 4))
+            .with("run", AnimationClip::new(strip(2), 12, 6));
+
+        Animator2D::with_clip(clips, "idle")
+    }
+
+    #[test]
+    fn fades_to_the_named_clip() {
+        let mut animator = animator();
+
+        assert!(animator.play_with_fade("run", 200.0));
+        assert_eq!(animator.current(), Some("run"));
+
+        let animation = animator.animation().unwrap();
+        assert!(*animation.frames() == strip(2));
+        assert_eq!(animation.frames_per_second(), 12);
+        assert!(animation.is_fading());
+    }
+
+    #[test]
+    fn fading_to_the_playing_clip_keeps_it() {
+        let mut animator = animator();
+
+        assert!(animator.play_with_fade("idle", 200.0));
+        assert!(!animator.animation().unwrap().is_fading());
+    }
+
+    #[test]
+    fn unknown_clip_keeps_the_current_one() {
+        let mut animator = animator();
+
+        assert!(!animator.play_with_fade("jump", 200.0));
+        assert_eq!(animator.current(), Some("idle"));
+        assert!(!animator.animation().unwrap().is_fading());
+    }
+
+    #[test]
+    fn play_cuts_a_fade_off() {
+        let mut animator = animator();
+        animator.play_with_fade("run", 200.0);
+
+        animator.play("idle");
+        assert!(!animator.animation().unwrap().is_fading());
+    }
+}
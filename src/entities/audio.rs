@@ -0,0 +1,113 @@
+use glam::Vec2;
+
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::audio::AudioClip;
+use crate::entities::transform2d::Transform2D;
+use crate::sound::{AudioEngine, PlayOptions, SoundHandle};
+
+//Plays its clip while playing is set, louder the closer it is to the AudioListener. Without a
+//listener in the world it plays at full volume, without a Transform2D it is heard as if it were
+//right at the listener. Stops when the entity is despawned.
+pub struct AudioSource {
+    pub clip: Ptr<AudioClip>,
+    //Amplitude at the listener, 1 is the volume of the clip.
+    pub volume: f32,
+    pub looped: bool,
+    //In world units. Silent from there on.
+    pub max_distance: f32,
+    //Cleared once a clip that isn't looped played to the end, set it again to replay.
+    pub playing: bool,
+    sound: Option<SoundHandle>,
+}
+
+impl AudioSource {
+    //Starts playing with the next AudioSystem::update.
+    pub fn new(clip: Ptr<AudioClip>, max_distance: f32) -> Self {
+        AudioSource { clip, volume: 1.0, looped: false, max_distance, playing: true, sound: None }
+    }
+
+    pub fn looped(mut self) -> Self {
+        self.looped = true;
+        self
+    }
+
+    //Volume and panning as heard from listener, both at full volume and centered without one.
+    fn mix(&self, position: Option<Vec2>, listener: Option<Vec2>) -> (f32, f32) {
+        let (Some(position), Some(listener)) = (position, listener) else {
+            return (self.volume, 0.5);
+        };
+
+        let offset = position - listener;
+        let max_distance = self.max_distance.max(f32::EPSILON);
+
+        //Squared, so the volume falls off quickly close to the listener and fades out slowly.
+        let falloff = (1.0 - offset.length() / max_distance).clamp(0.0, 1.0);
+
+        //Fully to one side halfway to max_distance. A source just next to the listener stays
+        //near the center instead of jumping from ear to ear.
+        let side = (offset.x / (max_distance * 0.5)).clamp(-1.0, 1.0);
+
+        (self.volume * falloff * falloff, 0.5 + side * 0.5)
+    }
+}
+
+impl Drop for AudioSource {
+    fn drop(&mut self) {
+        if let Some(sound) = self.sound.as_mut() {
+            sound.stop(0.0);
+        }
+    }
+}
+
+//The ears in the world, usually next to the camera or the player. Only the first one found counts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioListener;
+
+//Run by a Schedule with an AudioEngine in its resources.
+pub struct AudioSystem;
+
+impl AudioSystem {
+    //Starts and stops the sounds of every AudioSource and mixes them for the listener. Clips that
+    //are still loading are started once they are there.
+    pub fn update(world: &mut hecs::World, assets: &Assets, audio: &mut AudioEngine) {
+        let listener = world
+            .query_mut::<&Transform2D>()
+            .with::<&AudioListener>()
+            .into_iter()
+            .next()
+            .map(|(_, transform)| transform.global_position().truncate());
+
+        for (_, (source, transform)) in
+            world.query_mut::<(&mut AudioSource, Option<&Transform2D>)>()
+        {
+            if source.sound.as_ref().is_some_and(SoundHandle::is_stopped) {
+                source.sound = None;
+                source.playing = false;
+            }
+
+            let position = transform.map(|transform| transform.global_position().truncate());
+            let (volume, panning) = source.mix(position, listener);
+
+            match (source.playing, source.sound.as_mut()) {
+                (true, Some(sound)) => {
+                    sound.set_volume(volume);
+                    sound.set_panning(panning);
+                }
+                (true, None) => {
+                    if let Some(clip) = assets.try_get(&source.clip) {
+                        let mut options = PlayOptions::default().volume(volume).panning(panning);
+                        options.looped = source.looped;
+
+                        source.sound = audio.play_clip(clip, options);
+                        source.playing = source.sound.is_some();
+                    }
+                }
+                (false, Some(sound)) => {
+                    sound.stop(0.0);
+                    source.sound = None;
+                }
+                (false, None) => {}
+            }
+        }
+    }
+}
@@ -1,44 +1,261 @@
-use std::f32::consts::PI;
 use std::path::{Path, PathBuf};
 
-use glam::{Vec2, Vec3, Vec4};
+use glam::Vec2;
 use hashbrown::HashMap;
+use hecs_hierarchy::{Hierarchy, HierarchyMut};
 
-use crate::assets::texture::{Sampler, Texture2D};
-use crate::assets::{assets, ldtk};
+use crate::assets::assets;
 use crate::context::VisContext;
-use crate::entities::sprite::Sprite;
+use crate::entities::ldtk_entity::LdtkEntityRegistry;
+use crate::entities::loader::{self, LoadError, WorldBlueprint};
+use crate::entities::name::{Name, NameIndex, Tags};
+use crate::entities::scene::SceneError;
+use crate::entities::script::Scripts;
+#[cfg(feature = "stats")]
+use crate::entities::stats::WorldStats;
 use crate::entities::transform2d::Transform2D;
+use crate::random::Rng;
+use crate::render::transition::WorldTransition;
 use crate::utils::{Guid, GuidGenerator};
 
 //A collection of entities that represents a set of worlds.
 pub struct Worlds {
     worlds: HashMap<Guid, hecs::World>,
+    #[cfg(feature = "stats")]
+    stats: HashMap<Guid, WorldStats>,
     generator: GuidGenerator,
     current_world: Option<Guid>,
+    //For the event log, drained by the engine every frame.
+    switched: Option<(Option<Guid>, Guid)>,
+    despawned: Vec<hecs::Entity>,
+    //Despawns past MAX_LOGGED_DESPAWNS, reported as a count when they are collected.
+    dropped_despawns: usize,
+    //Picked up by the renderer, which runs the transition.
+    transition: Option<(Option<Guid>, WorldTransition)>,
+    levels: Vec<LdtkLevel>,
+    names: HashMap<Guid, NameIndex>,
 }
 
-impl Default for Worlds {
-    fn default() -> Self {
-        Self { worlds: HashMap::new(), generator: GuidGenerator::new(), current_world: None }
+//A level of the ldtk file the worlds were loaded from, each one has its own world.
+#[derive(Clone, Debug)]
+pub struct LdtkLevel {
+    pub identifier: String,
+    pub iid: String,
+    pub world: Guid,
+    //World position of the level in world units.
+    pub offset: Vec2,
+    //Min and max corner of the level, e.g. for CameraFollow.
+    pub bounds: (Vec2, Vec2),
+}
+
+impl LdtkLevel {
+    pub fn center(&self) -> Vec2 {
+        (self.bounds.0 + self.bounds.1) / 2.0
     }
 }
 
+//Marks an entity for Worlds::despawn_marked. Scripts queue it with Commands::despawn, they
+//can't reach the Worlds or the other scripts from tick.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Despawn;
+
+//Only this many despawns are kept for the event log if nobody collects them.
+const MAX_LOGGED_DESPAWNS: usize = 1024;
+
+//Spawns an entity into the current world and labels it with the call site.
+#[macro_export]
+macro_rules! spawn_tracked {
+    ($worlds:expr, $bundle:expr) => {
+        $worlds.spawn_labeled($bundle, concat!(file!(), ":", line!()))
+    };
+}
+
 impl Worlds {
-    pub fn new() -> Self {
-        Self::default()
+    //World guids come from the rng, pass Context::rng to have them the same in every replay.
+    pub fn new(rng: &Rng) -> Self {
+        Self {
+            worlds: HashMap::new(),
+            #[cfg(feature = "stats")]
+            stats: HashMap::new(),
+            generator: GuidGenerator::with_stream(rng.derive("world_guids")),
+            current_world: None,
+            switched: None,
+            despawned: Vec::new(),
+            dropped_despawns: 0,
+            transition: None,
+            levels: Vec::new(),
+            names: HashMap::new(),
+        }
     }
 
     pub fn add_world(&mut self, world: hecs::World) -> Guid {
         let guid = self.generator.generate();
+
+        #[cfg(feature = "stats")]
+        {
+            let mut stats = WorldStats::new();
+            stats.record_setup(world.len() as u64);
+            self.stats.insert(guid, stats);
+        }
+
         self.worlds.insert(guid, world);
         guid
     }
 
-    pub fn get_world(&mut self, guid: Guid) -> Option<&hecs::World> {
+    //Prefer the spawn_tracked! macro, which fills in the call site as label.
+    pub fn spawn_labeled(
+        &mut self, bundle: impl hecs::DynamicBundle, label: &'static str,
+    ) -> Option<hecs::Entity> {
+        let guid = self.current_world?;
+        let world = self.worlds.get_mut(&guid)?;
+        let entity = world.spawn(bundle);
+
+        #[cfg(feature = "entity_debug")]
+        let _ = world.insert_one(
+            entity,
+            crate::entities::stats::SpawnInfo { created: instant::Instant::now(), label },
+        );
+        #[cfg(not(feature = "entity_debug"))]
+        let _ = label;
+
+        #[cfg(feature = "stats")]
+        if let Some(stats) = self.stats.get_mut(&guid) {
+            stats.record_spawn();
+        }
+
+        Some(entity)
+    }
+
+    //Spawns into the current world with a Name, find it again with find_by_name.
+    pub fn spawn_named(
+        &mut self, bundle: impl hecs::DynamicBundle, name: impl Into<String>,
+    ) -> Option<hecs::Entity> {
+        let name = Name::new(name);
+        let entity = self.spawn_labeled(bundle, "spawn_named")?;
+        let guid = self.current_world?;
+
+        self.worlds.get_mut(&guid)?.insert_one(entity, name.clone()).ok()?;
+        self.names.entry(guid).or_default().insert_name(entity, name.as_str());
+        Some(entity)
+    }
+
+    //Like spawn_named, with Tags for find_all_by_tag as well.
+    pub fn spawn_tagged(
+        &mut self, bundle: impl hecs::DynamicBundle, name: impl Into<String>, tags: Tags,
+    ) -> Option<hecs::Entity> {
+        let entity = self.spawn_named(bundle, name)?;
+        let guid = self.current_world?;
+
+        self.worlds.get_mut(&guid)?.insert_one(entity, tags).ok()?;
+        Some(entity)
+    }
+
+    //An entity of the current world with that Name. None once it is despawned or renamed.
+    pub fn find_by_name(&mut self, name: &str) -> Option<hecs::Entity> {
+        let guid = self.current_world?;
+        let world = self.worlds.get(&guid)?;
+        self.names.entry(guid).or_default().find(world, name)
+    }
+
+    //Every entity of the current world whose Tags have the tag.
+    pub fn find_all_by_tag(&mut self, tag: &str) -> Vec<hecs::Entity> {
+        let Some(guid) = self.current_world else {
+            return Vec::new();
+        };
+
+        let Some(world) = self.worlds.get(&guid) else {
+            return Vec::new();
+        };
+
+        self.names.entry(guid).or_default().find_tagged(world, tag)
+    }
+
+    //Rescans the names on the next lookup. Not needed for correct results, lookups that miss rescan
+    //anyway, but saves that miss after many names changed at once.
+    pub fn mark_names_dirty(&mut self) {
+        if let Some(guid) = self.current_world {
+            self.names.entry(guid).or_default().mark_dirty();
+        }
+    }
+
+    //Runs on_destroy of the attached scripts and detaches them. Children in the Transform2D hierarchy
+    //go first, they may still look at their parent in their own on_destroy.
+    pub fn despawn(
+        &mut self, scripts: &mut Scripts, context: &VisContext, entity: hecs::Entity,
+    ) -> bool {
+        let Some(guid) = self.current_world else {
+            return false;
+        };
+
+        let children: Vec<hecs::Entity> = match self.worlds.get(&guid) {
+            Some(world) if world.contains(entity) => {
+                world.children::<Transform2D>(entity).collect()
+            }
+            _ => return false,
+        };
+
+        for child in children {
+            self.despawn(scripts, context, child);
+        }
+
+        let Some(world) = self.worlds.get_mut(&guid) else {
+            return false;
+        };
+
+        scripts.on_destroy(context, guid, entity, world);
+        scripts.detach_all(guid, entity);
+
+        //Keeps the sibling list of the parent intact.
+        let _ = world.detach::<Transform2D>(entity);
+        let despawned = world.despawn(entity).is_ok();
+
+        if despawned {
+            #[cfg(feature = "stats")]
+            if let Some(stats) = self.stats.get_mut(&guid) {
+                stats.record_despawn();
+            }
+
+            if self.despawned.len() < MAX_LOGGED_DESPAWNS {
+                self.despawned.push(entity);
+            } else {
+                self.dropped_despawns += 1;
+            }
+        }
+
+        despawned
+    }
+
+    //Despawns everything marked with Despawn in the current world. Call it after Scripts::tick.
+    pub fn despawn_marked(&mut self, scripts: &mut Scripts, context: &VisContext) -> usize {
+        let Some(world) = self.get_mut() else {
+            return 0;
+        };
+
+        let marked: Vec<hecs::Entity> =
+            world.query::<()>().with::<&Despawn>().iter().map(|(entity, _)| entity).collect();
+
+        //A marked child of a marked parent is already gone with its parent.
+        marked.into_iter().filter(|entity| self.despawn(scripts, context, *entity)).count()
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Option<&WorldStats> {
+        self.current_world.and_then(|guid| self.stats.get(&guid))
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats_mut(&mut self) -> Option<&mut WorldStats> {
+        self.current_world.and_then(|guid| self.stats.get_mut(&guid))
+    }
+
+    pub fn get_world(&self, guid: Guid) -> Option<&hecs::World> {
         self.worlds.get(&guid)
     }
 
+    pub fn get_world_mut(&mut self, guid: Guid) -> Option<&mut hecs::World> {
+        self.worlds.get_mut(&guid)
+    }
+
     pub fn get_mut(&mut self) -> Option<&mut hecs::World> {
         if let Some(guid) = self.current_world {
             self.worlds.get_mut(&guid)
@@ -55,120 +272,193 @@ impl Worlds {
         }
     }
 
+    //Drops a world and everything in it. The scripts of its entities are destroyed and detached.
+    pub fn remove_world(
+        &mut self, guid: Guid, scripts: &mut Scripts, context: &VisContext,
+    ) -> Option<hecs::World> {
+        let mut world = self.worlds.remove(&guid)?;
+        #[cfg(feature = "stats")]
+        self.stats.remove(&guid);
+        self.names.remove(&guid);
+        self.levels.retain(|level| level.world != guid);
+        scripts.destroy_world(context, guid, &mut world);
+
+        if self.current_world == Some(guid) {
+            self.current_world = None;
+        }
+
+        Some(world)
+    }
+
+    //Like start_world, but the scripts hear about it: on_world_exit for the entities of the
+    //previous world, on_spawn for the ones of the new world. Returns the previous world.
+    pub fn switch_to(
+        &mut self, guid: Guid, scripts: &mut Scripts, context: &VisContext,
+    ) -> Option<Guid> {
+        let previous = self.current_world;
+
+        if previous == Some(guid) {
+            return previous;
+        }
+
+        if !self.worlds.contains_key(&guid) {
+            log::warn!("Cannot switch to the unknown world {:?}.", guid);
+            return previous;
+        }
+
+        if let Some(previous) = previous {
+            if let Some(world) = self.worlds.get_mut(&previous) {
+                scripts.exit_world(context, previous, world);
+            }
+        }
+
+        self.start_world(guid);
+
+        if let Some(world) = self.worlds.get_mut(&guid) {
+            scripts.enter_world(context, guid, world);
+        }
+
+        previous
+    }
+
     pub fn start_world(&mut self, guid: Guid) {
+        if self.current_world != Some(guid) {
+            let from = self.switched.map_or(self.current_world, |(from, _)| from);
+            self.switched = Some((from, guid));
+        }
+
         self.current_world = Some(guid);
     }
 
-    pub fn from_ldtk_file<P: AsRef<Path>>(
-        context: &VisContext, loc: &Option<PathBuf>, assets: &mut assets::Assets, ldtk_file_path: P,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let file_content = std::fs::read(&ldtk_file_path)?;
-        let project: ldtk::Project = serde_json::from_slice(&file_content)?;
+    //Like start_world, but Renderer2D blends the outgoing world into the new one. Only the new world
+    //is current, so scripts and animations stop ticking the outgoing one right away.
+    pub fn transition_to(&mut self, guid: Guid, transition: WorldTransition) {
+        if self.current_world == Some(guid) {
+            return;
+        }
+
+        //Without an outgoing world the new one fades in over the background.
+        self.transition = Some((self.current_world, transition));
+        self.start_world(guid);
+    }
 
-        assert_eq!(project.worlds.len(), 0, "Ldtk Multi-worlds setting is not supported");
-        assert_eq!(project.levels.len(), 1, "Cannot have more than one level in a ldtk file");
+    pub fn current(&self) -> Option<Guid> {
+        self.current_world
+    }
 
-        assert_eq!(
-            project.json_version, "1.5.3",
-            "Ldtk version {} is not supported - only 1.5.3 is supported",
-            project.json_version
-        );
+    pub(crate) fn take_switched(&mut self) -> Option<(Option<Guid>, Guid)> {
+        self.switched.take()
+    }
 
-        let level = &project.levels[0];
+    pub(crate) fn take_transition(&mut self) -> Option<(Option<Guid>, WorldTransition)> {
+        self.transition.take()
+    }
 
-        let li = match &level.layer_instances {
-            Some(li) => li,
-            None => return Err("Level has no layer instances".into()),
-        };
+    pub(crate) fn take_despawned(&mut self) -> Vec<hecs::Entity> {
+        if self.dropped_despawns > 0 {
+            log::warn!(
+                "{} more despawns were not kept for the event log.",
+                std::mem::take(&mut self.dropped_despawns)
+            );
+        }
 
-        let layer_z_coord_offset = 0.99 / li.len() as f32;
-        let mut layer_z = 1.0;
-
-        let mut world = hecs::World::new();
-        for layer in li {
-            let (layer_texture, layer_texture_info) =
-                match (&layer.tileset_rel_path, layer.tileset_def_uid) {
-                    (Some(rp), Some(id)) => {
-                        let tileset_path = tileset_filepath(&ldtk_file_path, loc, &rp)?;
-
-                        let texture_info = project
-                            .defs
-                            .tilesets
-                            .iter()
-                            .find(|t| t.uid == id)
-                            .ok_or(format!("Tileset with id {} not found in ldtk file", id))?;
-
-                        let texture: assets::Ptr<Texture2D> =
-                            assets.request_asset(tileset_path.to_string_lossy(), 0);
-
-                        (texture, texture_info)
-                    }
-                    _ => return Err("Layer has no tileset".into()),
-                };
-
-            for tile in layer.grid_tiles.iter() {
-                // Calculate scale from c_wid and c_hei
-                let scale_x = 1.0 / (layer.c_wid as f32);
-                let scale_y = 1.0 / (layer.c_hei as f32);
-                let scale = scale_x.min(scale_y);
-                debug_assert!((0.0..=1.0).contains(&scale), "scale out of bounds");
-
-                // Calculate x and y coordinates from tile position
-
-                let x_grid_pos = (layer.px_total_offset_x + tile.px[0]) / layer.grid_size;
-                let y_grid_pos = (layer.px_total_offset_y + tile.px[1]) / layer.grid_size;
-
-                let x_coord = x_grid_pos as f32 * scale * 2.0 - 1.0;
-                let y_coord = y_grid_pos as f32 * scale * 2.0;
-
-                let transform = Transform2D::new(
-                    context,
-                    Vec3::new(x_coord, -y_coord, layer_z),
-                    PI,
-                    // Scale:
-                    Vec2::new(scale, scale),
-                );
-
-                // This is definitely correct:
-                let fanta = Sprite::new(
-                    context,
-                    layer_texture,
-                    Vec4::new(1.0, 1.0, 1.0, tile.a as f32),
-                    Some(&tile.coords_8(
-                        layer.grid_size,
-                        layer_texture_info.px_wid as f32,
-                        layer_texture_info.px_hei as f32,
-                    )),
-                    Some(Sampler::new(context)),
-                );
-
-                world.spawn((transform, fanta));
-            }
+        std::mem::take(&mut self.despawned)
+    }
+
+    //Writes the entities of a world to a scene file. See WorldBlueprint::from_world for what is
+    //kept, the file loads with load_world or a WorldLoadHandle.
+    pub fn save_world<P: AsRef<Path>>(
+        &self, guid: Guid, assets: &assets::Assets, path: P,
+    ) -> Result<(), SceneError> {
+        let world = self.worlds.get(&guid).ok_or(SceneError::UnknownWorld(guid))?;
+        WorldBlueprint::from_world(world, assets).save(path)
+    }
+
+    //Adds the world of a scene file. It is not started, see start_world. Blocks until it is
+    //created, use WorldLoadHandle::from_scene_file to load during gameplay.
+    pub fn load_world<P: AsRef<Path>>(
+        &mut self, context: &VisContext, assets: &mut assets::Assets, path: P,
+    ) -> Result<Guid, LoadError> {
+        let blueprint = WorldBlueprint::from_scene_file(path)?;
+        Ok(self.add_world(loader::instantiate(context, assets, blueprint)))
+    }
+
+    //Blocks until every level is created, the first one is started. Use WorldLoadHandle to load
+    //during gameplay.
+    pub fn from_ldtk_file<P: AsRef<Path>>(
+        context: &VisContext, rng: &Rng, loc: &Option<PathBuf>, assets: &mut assets::Assets,
+        ldtk_file_path: P,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let registry = LdtkEntityRegistry::new();
+        Self::from_ldtk_file_with(context, rng, loc, assets, ldtk_file_path, &registry)
+    }
 
-            for _entity in layer.entity_instances.iter() {}
+    //Like from_ldtk_file, the registry maps the ldtk entities to game code.
+    pub fn from_ldtk_file_with<P: AsRef<Path>>(
+        context: &VisContext, rng: &Rng, loc: &Option<PathBuf>, assets: &mut assets::Assets,
+        ldtk_file_path: P, registry: &LdtkEntityRegistry,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut worlds = Worlds::new(rng);
+        let added = worlds.add_ldtk_file_with(context, loc, assets, ldtk_file_path, registry)?;
 
-            layer_z -= layer_z_coord_offset;
+        match added.first() {
+            Some(first) => worlds.start_world(*first),
+            None => return Err("The ldtk file has no levels".into()),
         }
 
-        let mut worlds = Worlds::new();
-        let guid = worlds.add_world(world);
-        worlds.start_world(guid);
         Ok(worlds)
     }
-}
 
-fn tileset_filepath<P1: AsRef<Path>, P2: AsRef<Path>>(
-    ldtk_file_path: &P1, loc: &Option<PathBuf>, tileset_relative_path: &P2,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let mut path = std::path::Path::new(ldtk_file_path.as_ref());
-    if let Some(prefix) = &loc {
-        path = path.strip_prefix(prefix)?;
+    //Adds the levels of another ldtk file next to the ones there are, none of them is started.
+    //Returns their worlds in file order.
+    pub fn add_ldtk_file_with<P: AsRef<Path>>(
+        &mut self, context: &VisContext, loc: &Option<PathBuf>, assets: &mut assets::Assets,
+        ldtk_file_path: P, registry: &LdtkEntityRegistry,
+    ) -> Result<Vec<Guid>, Box<dyn std::error::Error>> {
+        let levels = WorldBlueprint::from_ldtk_levels(loc, ldtk_file_path)
+            .map_err(|e| -> Box<dyn std::error::Error> { e })?;
+
+        let mut added = Vec::with_capacity(levels.len());
+
+        for level in levels {
+            let world = loader::instantiate_with(context, assets, level.blueprint, registry);
+            let world = self.add_world(world);
+            self.levels.push(LdtkLevel {
+                identifier: level.identifier,
+                iid: level.iid,
+                world,
+                offset: level.offset,
+                bounds: level.bounds,
+            });
+            added.push(world);
+        }
+
+        Ok(added)
     }
 
-    let parent = path.parent().ok_or("Cannot get parent of ldtk file path")?;
-    // For the tileset_relative_path, we replace the extension (e.g. .png) with .fur
-    let tileset_relative_path = tileset_relative_path.as_ref();
-    let tileset_relative_path = tileset_relative_path.with_extension("fur");
+    //In file order.
+    pub fn levels(&self) -> &[LdtkLevel] {
+        &self.levels
+    }
+
+    //By identifier or iid.
+    pub fn level(&self, name: &str) -> Option<Guid> {
+        self.levels
+            .iter()
+            .find(|level| level.identifier == name || level.iid == name)
+            .map(|level| level.world)
+    }
 
-    Ok(parent.join(tileset_relative_path))
+    pub fn start_level(&mut self, name: &str) -> bool {
+        match self.level(name) {
+            Some(guid) => {
+                self.start_world(guid);
+                true
+            }
+            None => {
+                log::warn!("There is no level {}.", name);
+                false
+            }
+        }
+    }
 }
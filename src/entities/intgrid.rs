@@ -0,0 +1,147 @@
+use glam::Vec2;
+
+use crate::context::VisContext;
+use crate::entities::physics2d::Collider2D;
+use crate::entities::transform2d::Transform2D;
+
+//The values of an IntGrid layer of a ldtk level, 0 is an empty cell. Placed like the tiles: rows
+//grow downwards from the top left corner at origin, in world space.
+#[derive(Clone, Debug)]
+pub struct IntGrid {
+    pub identifier: String,
+    width: u32,
+    height: u32,
+    cell_size: f32,
+    origin: Vec2,
+    values: Vec<i32>,
+}
+
+//A rect of cells, (x, y) is the top left one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IntGrid {
+    //Missing values are empty, extra ones dropped.
+    pub fn new(
+        identifier: impl Into<String>, width: u32, height: u32, cell_size: f32, origin: Vec2,
+        mut values: Vec<i32>,
+    ) -> Self {
+        values.resize((width * height) as usize, 0);
+        IntGrid { identifier: identifier.into(), width, height, cell_size, origin, values }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    pub fn value(&self, x: u32, y: u32) -> Option<i32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(self.values[(y * self.width + x) as usize])
+    }
+
+    //None outside of the grid.
+    pub fn value_at(&self, position: Vec2) -> Option<i32> {
+        let (x, y) = self.world_to_cell(position)?;
+        self.value(x, y)
+    }
+
+    pub fn world_to_cell(&self, position: Vec2) -> Option<(u32, u32)> {
+        let local = (position - self.origin) / self.cell_size;
+        let (x, y) = (local.x.floor(), (-local.y).floor());
+
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+
+        Some((x as u32, y as u32))
+    }
+
+    //Center and half extents in world space.
+    pub fn rect_bounds(&self, rect: &CellRect) -> (Vec2, Vec2) {
+        let size = Vec2::new(rect.width as f32, rect.height as f32) * self.cell_size;
+        let corner = self.origin + Vec2::new(rect.x as f32, -(rect.y as f32)) * self.cell_size;
+
+        (corner + Vec2::new(size.x, -size.y) / 2.0, size / 2.0)
+    }
+
+    //Covers every cell holding one of the values with as few rects as it greedily can: each rect
+    //grows right first, then down as long as the whole row matches.
+    pub fn merged_rects(&self, values: &[i32]) -> Vec<CellRect> {
+        let mut taken = vec![false; self.values.len()];
+        let free = |taken: &[bool], x: u32, y: u32| {
+            let index = (y * self.width + x) as usize;
+            !taken[index] && values.contains(&self.values[index])
+        };
+
+        let mut rects = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !free(&taken, x, y) {
+                    continue;
+                }
+
+                let mut width = 1;
+                while x + width < self.width && free(&taken, x + width, y) {
+                    width += 1;
+                }
+
+                let mut height = 1;
+                while y + height < self.height
+                    && (x..x + width).all(|column| free(&taken, column, y + height))
+                {
+                    height += 1;
+                }
+
+                for row in y..y + height {
+                    for column in x..x + width {
+                        taken[(row * self.width + column) as usize] = true;
+                    }
+                }
+
+                rects.push(CellRect { x, y, width, height });
+            }
+        }
+
+        rects
+    }
+}
+
+//Spawns a Transform2D and Collider2D for every merged rect of the given values, for each IntGrid
+//in the world. Returns how many were spawned.
+pub fn spawn_colliders(context: &VisContext, world: &mut hecs::World, values: &[i32]) -> usize {
+    let bounds: Vec<(Vec2, Vec2)> = world
+        .query::<&IntGrid>()
+        .iter()
+        .flat_map(|(_, grid)| {
+            grid.merged_rects(values).iter().map(|rect| grid.rect_bounds(rect)).collect::<Vec<_>>()
+        })
+        .collect();
+
+    for (center, half_extents) in bounds.iter() {
+        let transform = Transform2D::new(context, center.extend(0.0), 0.0, Vec2::ONE);
+        world.spawn((transform, Collider2D::new(*half_extents)));
+    }
+
+    bounds.len()
+}
@@ -0,0 +1,12 @@
+//Sprites are drawn by (RenderLayer, OrderInLayer, z). Higher values are in front.
+//Keep z for parallax and 2.5D depth, use layers for the drawing order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RenderLayer(pub i32);
+
+//Breaks ties between sprites on the same layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderInLayer(pub i32);
+
+pub(crate) fn sort_key(layer: Option<&RenderLayer>, order: Option<&OrderInLayer>) -> (i32, i32) {
+    (layer.copied().unwrap_or_default().0, order.copied().unwrap_or_default().0)
+}
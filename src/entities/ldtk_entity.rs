@@ -0,0 +1,174 @@
+use glam::{Vec2, Vec4};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::assets::Assets;
+use crate::context::VisContext;
+
+//The value of a custom field of a ldtk entity.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    //Also multilines, file paths and enum values.
+    String(String),
+    //rgba 0..1, alpha is always 1.
+    Color([f32; 4]),
+    //Grid coordinates.
+    Point([i64; 2]),
+    //Iid of the referenced entity.
+    EntityRef(String),
+    Array(Vec<FieldValue>),
+    //Tiles and whatever has no variant, as written in the file.
+    Other(serde_json::Value),
+}
+
+impl FieldValue {
+    //field_type is the __type of the field instance, e.g. Int, Array<Point> or LocalEnum.Direction.
+    pub fn parse(field_type: &str, value: Option<&serde_json::Value>) -> FieldValue {
+        let Some(value) = value.filter(|value| !value.is_null()) else {
+            return FieldValue::Null;
+        };
+
+        if let Some(inner) = field_type.strip_prefix("Array<").and_then(|t| t.strip_suffix('>')) {
+            return match value.as_array() {
+                Some(values) => FieldValue::Array(
+                    values.iter().map(|value| Self::parse(inner, Some(value))).collect(),
+                ),
+                None => FieldValue::Other(value.clone()),
+            };
+        }
+
+        let parsed = match field_type {
+            "Int" => value.as_i64().map(FieldValue::Int),
+            "Float" => value.as_f64().map(FieldValue::Float),
+            "Bool" => value.as_bool().map(FieldValue::Bool),
+            "Color" => value.as_str().and_then(parse_color).map(FieldValue::Color),
+            "Point" => match (value.get("cx"), value.get("cy")) {
+                (Some(x), Some(y)) => {
+                    x.as_i64().zip(y.as_i64()).map(|(x, y)| FieldValue::Point([x, y]))
+                }
+                _ => None,
+            },
+            "EntityRef" => value
+                .get("entityIid")
+                .and_then(|iid| iid.as_str())
+                .map(|iid| FieldValue::EntityRef(iid.to_string())),
+            _ => value.as_str().map(|value| FieldValue::String(value.to_string())),
+        };
+
+        parsed.unwrap_or_else(|| FieldValue::Other(value.clone()))
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            FieldValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    //Ints are converted.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            FieldValue::Float(value) => Some(*value),
+            FieldValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            FieldValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::String(value) | FieldValue::EntityRef(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+//"#rrggbb"
+pub(crate) fn parse_color(hex: &str) -> Option<[f32; 4]> {
+    let hex = hex.strip_prefix('#')?;
+
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|value| value as f32 / 255.0)
+    };
+
+    Some([channel(0)?, channel(2)?, channel(4)?, 1.0])
+}
+
+//Spawned for every entity instance of a ldtk level, next to a Transform2D at its center and a Name
+//(plus Tags, if it has any) from the ldtk definition. The transform is scaled so a sprite covers the
+//entity.
+#[derive(Clone, Debug)]
+pub struct LdtkEntity {
+    pub identifier: String,
+    pub iid: String,
+    //Cell of the pivot in the grid of its layer.
+    pub grid: [i64; 2],
+    //In world units.
+    pub size: Vec2,
+    pub fields: HashMap<String, FieldValue>,
+}
+
+impl LdtkEntity {
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.get(name)
+    }
+}
+
+//Spawned once per ldtk level. Hand it to Renderer2D::apply_level_background when the level starts.
+//The background image is a sprite of the level, below its layers.
+#[derive(Clone, Debug)]
+pub struct LevelBackground {
+    //Linear.
+    pub color: Vec4,
+}
+
+pub type LdtkSpawner = Box<dyn Fn(&VisContext, &mut Assets, &mut hecs::World, hecs::Entity)>;
+
+//Maps entity identifiers to game code. The spawner runs once the generic entity (transform,
+//LdtkEntity, Name, Tags and the sprite of its tile, if any) exists and adds or replaces whatever it
+//needs, Names and Tags included.
+#[derive(Default)]
+pub struct LdtkEntityRegistry {
+    spawners: HashMap<String, LdtkSpawner>,
+}
+
+impl LdtkEntityRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self, identifier: impl Into<String>,
+        spawner: impl Fn(&VisContext, &mut Assets, &mut hecs::World, hecs::Entity) + 'static,
+    ) {
+        self.spawners.insert(identifier.into(), Box::new(spawner));
+    }
+
+    pub(crate) fn spawn(
+        &self, context: &VisContext, assets: &mut Assets, world: &mut hecs::World,
+        entity: hecs::Entity,
+    ) {
+        let identifier = match world.get::<&LdtkEntity>(entity) {
+            Ok(ldtk) => ldtk.identifier.clone(),
+            Err(_) => return,
+        };
+
+        if let Some(spawner) = self.spawners.get(&identifier) {
+            spawner(context, assets, world, entity);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use glam::Vec3;
+
+//A light at the position of the entity's Transform2D, picked up by the lighting pass of the Renderer2D.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight2D {
+    pub color: Vec3,
+    pub intensity: f32,
+    //In world units, nothing beyond it is lit.
+    pub radius: f32,
+    //Exponent of the falloff towards the radius. 1 is linear, larger values fall off faster.
+    pub falloff: f32,
+}
+
+impl Default for PointLight2D {
+    fn default() -> Self {
+        PointLight2D { color: Vec3::ONE, intensity: 1.0, radius: 1.0, falloff: 2.0 }
+    }
+}
+
+impl PointLight2D {
+    pub fn new(color: Vec3, intensity: f32, radius: f32) -> Self {
+        PointLight2D { color, intensity, radius, ..Default::default() }
+    }
+
+    pub fn with_falloff(mut self, falloff: f32) -> Self {
+        self.falloff = falloff.max(0.0);
+        self
+    }
+}
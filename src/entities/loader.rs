@@ -0,0 +1,1022 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+
+use glam::{Vec2, Vec3, Vec4};
+use hashbrown::HashMap;
+use hecs_hierarchy::HierarchyMut;
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::ldtk;
+use crate::assets::texture::{Sampler, Texture2D};
+use crate::context::VisContext;
+use crate::entities::animation2d::Animation2D;
+use crate::entities::entities::Worlds;
+use crate::entities::intgrid::IntGrid;
+use crate::entities::layer::{OrderInLayer, RenderLayer};
+use crate::entities::ldtk_entity::{
+    self, FieldValue, LdtkEntity, LdtkEntityRegistry, LevelBackground,
+};
+use crate::entities::name::{Name, Tags};
+use crate::entities::sprite::Sprite;
+use crate::entities::tilemap::{Tile, Tilemap};
+use crate::entities::transform2d::Transform2D;
+use crate::utils::{FileUtils, Guid};
+
+pub type LoadError = Box<dyn std::error::Error + Send + Sync>;
+
+//An entity with a transform, usually drawn as a sprite.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpriteBlueprint {
+    pub position: [f32; 3],
+    pub rotation: f32,
+    pub scale: [f32; 2],
+    //Index into WorldBlueprint::textures. None for a bare transform, e.g. to group children.
+    pub texture: Option<usize>,
+    pub tint: [f32; 4],
+    pub coords: Option<[f32; 8]>,
+    #[serde(default)]
+    pub layer: i32,
+    #[serde(default)]
+    pub soft_distance: Option<f32>,
+    //Horizontal and vertical.
+    #[serde(default)]
+    pub flip: (bool, bool),
+    #[serde(default)]
+    pub animation: Option<AnimationBlueprint>,
+    //Index into WorldBlueprint::sprites, the transform is relative to the parent.
+    #[serde(default)]
+    pub parent: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AnimationBlueprint {
+    //Index into WorldBlueprint::textures of the strip or sheet.
+    pub frames: usize,
+    pub frames_per_second: u32,
+    pub total_frames: u32,
+    pub mirrored: bool,
+    pub looped: bool,
+    //Texture rects (min x, min y, max x, max y) of a sprite sheet clip.
+    #[serde(default)]
+    pub rects: Option<Vec<[f32; 4]>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TileBlueprint {
+    pub x: u32,
+    pub y: u32,
+    pub id: i64,
+    //uv rect in the tileset (left, top, right, bottom), swapped for flipped tiles.
+    pub coords: [f32; 4],
+}
+
+//Position is the top left corner of the grid.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TilemapBlueprint {
+    pub position: [f32; 3],
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: f32,
+    //Index into WorldBlueprint::textures.
+    pub texture: usize,
+    pub opacity: f32,
+    #[serde(default)]
+    pub layer: i32,
+    //Tiles stacked on a cell are split into tilemaps drawn in this order within the layer.
+    #[serde(default)]
+    pub order: i32,
+    pub tiles: Vec<TileBlueprint>,
+}
+
+//An IntGrid layer, position is the top left corner of the grid.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IntGridBlueprint {
+    pub identifier: String,
+    pub position: [f32; 2],
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: f32,
+    pub values: Vec<i32>,
+}
+
+//An entity instance of a ldtk level. Position is the center of the entity, its pivot is already
+//applied.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityBlueprint {
+    pub identifier: String,
+    pub iid: String,
+    pub grid: [i64; 2],
+    pub position: [f32; 3],
+    pub size: [f32; 2],
+    #[serde(default)]
+    pub fields: Vec<(String, FieldValue)>,
+    //Of the entity definition.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    //Index into WorldBlueprint::textures and uv rect (left, top, right, bottom) of its tile.
+    #[serde(default)]
+    pub tile: Option<(usize, [f32; 4])>,
+    #[serde(default)]
+    pub layer: i32,
+}
+
+//Background of a ldtk level.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackgroundBlueprint {
+    //Linear, ready for the clear color.
+    pub color: [f32; 4],
+}
+
+//Plain data description of a world. Built off the main thread, no GPU resources involved.
+//Scene files are this struct as json, see Worlds::save_world.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WorldBlueprint {
+    pub textures: Vec<String>,
+    pub sprites: Vec<SpriteBlueprint>,
+    #[serde(default)]
+    pub tilemaps: Vec<TilemapBlueprint>,
+    #[serde(default)]
+    pub entities: Vec<EntityBlueprint>,
+    #[serde(default)]
+    pub int_grids: Vec<IntGridBlueprint>,
+    #[serde(default)]
+    pub background: Option<BackgroundBlueprint>,
+}
+
+//One level of a ldtk project.
+#[derive(Clone, Debug)]
+pub struct LevelBlueprint {
+    pub identifier: String,
+    pub iid: String,
+    //World position of the level in world units.
+    pub offset: Vec2,
+    //Min and max corner of the area covered by its layers.
+    pub bounds: (Vec2, Vec2),
+    pub blueprint: WorldBlueprint,
+}
+
+impl WorldBlueprint {
+    pub fn from_scene_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let file_content = std::fs::read(path)?;
+        let blueprint: WorldBlueprint = serde_json::from_slice(&file_content)?;
+        blueprint.validate()?;
+        Ok(blueprint)
+    }
+
+    //The first level of the project, see from_ldtk_levels for all of them.
+    pub fn from_ldtk_file<P: AsRef<Path>>(
+        loc: &Option<PathBuf>, ldtk_file_path: P,
+    ) -> Result<Self, LoadError> {
+        let mut levels = Self::from_ldtk_levels(loc, ldtk_file_path)?;
+
+        match levels.is_empty() {
+            true => Err("The ldtk file has no levels".into()),
+            false => Ok(levels.swap_remove(0).blueprint),
+        }
+    }
+
+    //Every level in file order. They share one scale, the biggest level spans -1..1 on its longer
+    //side, and are placed at their world position, so neighbours line up when merged.
+    pub fn from_ldtk_levels<P: AsRef<Path>>(
+        loc: &Option<PathBuf>, ldtk_file_path: P,
+    ) -> Result<Vec<LevelBlueprint>, LoadError> {
+        let file_content = std::fs::read(&ldtk_file_path)?;
+        let project: ldtk::Project = serde_json::from_slice(&file_content)?;
+
+        if !project.worlds.is_empty() {
+            return Err("Ldtk Multi-worlds setting is not supported".into());
+        }
+
+        if project.json_version != "1.5.3" {
+            return Err(format!(
+                "Ldtk version {} is not supported - only 1.5.3 is supported",
+                project.json_version
+            )
+            .into());
+        }
+
+        //World units per pixel.
+        let largest = project.levels.iter().map(|level| level.px_wid.max(level.px_hei)).max();
+        let unit = 2.0 / largest.unwrap_or(1).max(1) as f32;
+
+        project
+            .levels
+            .iter()
+            .map(|level| {
+                //Levels saved to separate files only keep their name and position in the project.
+                let external;
+                let level = match (&level.layer_instances, &level.external_rel_path) {
+                    (None, Some(rel_path)) => {
+                        external = external_level(&ldtk_file_path, rel_path)?;
+                        &external
+                    }
+                    _ => level,
+                };
+
+                Ok(LevelBlueprint {
+                    identifier: level.identifier.clone(),
+                    iid: level.iid.clone(),
+                    offset: level_offset(level, unit),
+                    bounds: level_bounds(level, unit),
+                    blueprint: Self::from_ldtk_level(&project, level, unit, loc, &ldtk_file_path)?,
+                })
+            })
+            .collect()
+    }
+
+    fn from_ldtk_level<P: AsRef<Path>>(
+        project: &ldtk::Project, level: &ldtk::Level, unit: f32, loc: &Option<PathBuf>,
+        ldtk_file_path: &P,
+    ) -> Result<Self, LoadError> {
+        let li = match &level.layer_instances {
+            Some(li) => li,
+            None => return Err(format!("Level {} has no layer instances", level.identifier).into()),
+        };
+
+        let mut blueprint = WorldBlueprint::default();
+        let offset = level_offset(level, unit);
+
+        //The image lies in the level, below all of its layers.
+        if let (Some(rp), Some(position)) = (&level.bg_rel_path, &level.bg_pos) {
+            let path = tileset_filepath(ldtk_file_path, loc, &rp)?;
+            let image_size = image::image_dimensions(&path)
+                .map(|(width, height)| Vec2::new(width as f32, height as f32))
+                .ok();
+            let texture = blueprint.texture_index(path.to_string_lossy());
+            let (min, max) = level_bounds(level, unit);
+
+            if let Some(sprite) =
+                background_sprite(position, Vec2::new(min.x, max.y), unit, texture, image_size)
+            {
+                blueprint.sprites.push(sprite);
+            }
+        }
+
+        //Ldtk colors are sRGB, the clear color is linear.
+        let color = ldtk_entity::parse_color(&level.bg_color).unwrap_or([0.0, 0.0, 0.0, 1.0]);
+        blueprint.background = Some(BackgroundBlueprint { color: srgb_to_linear(color) });
+
+        //Ldtk lists the top layer first.
+        for (index, layer) in li.iter().enumerate() {
+            let render_layer = (li.len() - 1 - index) as i32;
+
+            //Half a cell.
+            let scale = layer.grid_size as f32 * unit / 2.0;
+            let origin = layer_origin(layer, offset, unit);
+
+            let layer_offset =
+                Vec2::new(layer.px_total_offset_x as f32, -layer.px_total_offset_y as f32) * unit;
+
+            for instance in layer.entity_instances.iter() {
+                let entity = blueprint.ldtk_entity(
+                    project,
+                    instance,
+                    origin + layer_offset,
+                    unit,
+                    loc,
+                    ldtk_file_path,
+                )?;
+                blueprint.entities.push(EntityBlueprint { layer: render_layer, ..entity });
+            }
+
+            if layer.layer_instance_type == "IntGrid" {
+                let position = origin + layer_offset;
+
+                blueprint.int_grids.push(IntGridBlueprint {
+                    identifier: layer.identifier.clone(),
+                    position: position.to_array(),
+                    width: layer.c_wid as u32,
+                    height: layer.c_hei as u32,
+                    cell_size: scale * 2.0,
+                    values: layer.int_grid_csv.iter().map(|value| *value as i32).collect(),
+                });
+            }
+
+            let (texture, texture_info) = match (&layer.tileset_rel_path, layer.tileset_def_uid) {
+                (Some(rp), Some(id)) => {
+                    let tileset_path = tileset_filepath(ldtk_file_path, loc, &rp)?;
+
+                    let texture_info = project
+                        .defs
+                        .tilesets
+                        .iter()
+                        .find(|t| t.uid == id)
+                        .ok_or(format!("Tileset with id {} not found in ldtk file", id))?;
+
+                    (blueprint.texture_index(tileset_path.to_string_lossy()), texture_info)
+                }
+                //Entity layers and IntGrids without tiles.
+                _ if layer.layer_instance_type != "Tiles" => continue,
+                _ => return Err("Layer has no tileset".into()),
+            };
+
+            //A tilemap has one tile per cell and one tint. Tiles stacked on a cell go into the next
+            //tilemap up, tiles with their own alpha into one with that alpha. Both layers and
+            //auto layers list the tiles of a cell bottom to top.
+            let mut tilemaps: Vec<TilemapBlueprint> = Vec::new();
+            let mut stacked: HashMap<(i64, i64), i32> = HashMap::new();
+
+            for tile in layer.grid_tiles.iter().chain(layer.auto_layer_tiles.iter()) {
+                let x = (layer.px_total_offset_x + tile.px[0]) / layer.grid_size;
+                let y = (layer.px_total_offset_y + tile.px[1]) / layer.grid_size;
+
+                if x < 0 || y < 0 || tile.a <= 0.0 {
+                    continue;
+                }
+
+                let (width, height) = (texture_info.px_wid as f32, texture_info.px_hei as f32);
+
+                let mut left = tile.src[0] as f32 / width;
+                let mut top = tile.src[1] as f32 / height;
+                let mut right = (tile.src[0] + layer.grid_size) as f32 / width;
+                let mut bottom = (tile.src[1] + layer.grid_size) as f32 / height;
+
+                //Bit 0 flips x, bit 1 flips y.
+                if tile.f & 1 != 0 {
+                    std::mem::swap(&mut left, &mut right);
+                }
+
+                if tile.f & 2 != 0 {
+                    std::mem::swap(&mut top, &mut bottom);
+                }
+
+                let order = stacked.entry((x, y)).or_insert(0);
+                let opacity = layer.opacity as f32 * tile.a as f32;
+
+                let index =
+                    match tilemaps.iter().position(|t| t.order == *order && t.opacity == opacity) {
+                        Some(index) => index,
+                        None => {
+                            tilemaps.push(TilemapBlueprint {
+                                position: [origin.x, origin.y, 0.0],
+                                width: layer.c_wid as u32,
+                                height: layer.c_hei as u32,
+                                cell_size: scale * 2.0,
+                                texture,
+                                opacity,
+                                layer: render_layer,
+                                order: *order,
+                                tiles: Vec::new(),
+                            });
+                            tilemaps.len() - 1
+                        }
+                    };
+
+                *order += 1;
+
+                tilemaps[index].tiles.push(TileBlueprint {
+                    x: x as u32,
+                    y: y as u32,
+                    id: tile.t,
+                    coords: [left, top, right, bottom],
+                });
+            }
+
+            blueprint.tilemaps.extend(tilemaps);
+        }
+
+        Ok(blueprint)
+    }
+
+    //origin is the top left corner of the layer, including its offset.
+    fn ldtk_entity<P: AsRef<Path>>(
+        &mut self, project: &ldtk::Project, instance: &ldtk::EntityInstance, origin: Vec2,
+        unit: f32, loc: &Option<PathBuf>, ldtk_file_path: &P,
+    ) -> Result<EntityBlueprint, LoadError> {
+        let pair = |values: &[i64]| {
+            [values.first().copied().unwrap_or(0), values.get(1).copied().unwrap_or(0)]
+        };
+
+        let px = pair(&instance.px);
+        let px = Vec2::new(px[0] as f32, px[1] as f32);
+        let pivot = Vec2::new(
+            instance.pivot.first().copied().unwrap_or(0.5) as f32,
+            instance.pivot.get(1).copied().unwrap_or(0.5) as f32,
+        );
+        let size = Vec2::new(instance.width as f32, instance.height as f32);
+
+        //From the pivot to the center, y goes down in ldtk.
+        let center = px + (Vec2::splat(0.5) - pivot) * size;
+        let position = origin + Vec2::new(center.x, -center.y) * unit;
+
+        let tile = match &instance.tile {
+            Some(rect) => {
+                let tileset =
+                    project.defs.tilesets.iter().find(|t| t.uid == rect.tileset_uid).ok_or(
+                        format!("Tileset with id {} not found in ldtk file", rect.tileset_uid),
+                    )?;
+
+                match &tileset.rel_path {
+                    Some(rp) => {
+                        let path = tileset_filepath(ldtk_file_path, loc, rp)?;
+                        let (width, height) = (tileset.px_wid as f32, tileset.px_hei as f32);
+
+                        Some((
+                            self.texture_index(path.to_string_lossy()),
+                            [
+                                rect.x as f32 / width,
+                                rect.y as f32 / height,
+                                (rect.x + rect.w) as f32 / width,
+                                (rect.y + rect.h) as f32 / height,
+                            ],
+                        ))
+                    }
+                    None => None,
+                }
+            }
+            None => None,
+        };
+
+        Ok(EntityBlueprint {
+            identifier: instance.identifier.clone(),
+            iid: instance.iid.clone(),
+            grid: pair(&instance.grid),
+            position: [position.x, position.y, 0.0],
+            size: (size * unit).to_array(),
+            fields: instance
+                .field_instances
+                .iter()
+                .map(|field| {
+                    let value = FieldValue::parse(&field.field_instance_type, field.value.as_ref());
+                    (field.identifier.clone(), value)
+                })
+                .collect(),
+            tags: instance.tags.clone(),
+            tile,
+            layer: 0,
+        })
+    }
+
+    pub(crate) fn texture_index(&mut self, path: impl Into<String>) -> usize {
+        let path = path.into();
+
+        match self.textures.iter().position(|t| *t == path) {
+            Some(index) => index,
+            None => {
+                self.textures.push(path);
+                self.textures.len() - 1
+            }
+        }
+    }
+}
+
+//Creates the GPU side of a blueprint, a slice at a time.
+struct Instantiator {
+    blueprint: WorldBlueprint,
+    textures: Vec<Ptr<Texture2D>>,
+    world: hecs::World,
+    next: usize,
+    //The entities of the sprites so far, children are attached once all of them exist.
+    sprites: Vec<hecs::Entity>,
+    attached: bool,
+}
+
+impl Instantiator {
+    fn new(assets: &mut Assets, blueprint: WorldBlueprint) -> Self {
+        let textures: Vec<Ptr<Texture2D>> =
+            blueprint.textures.iter().map(|path| assets.request_asset(path, 0)).collect();
+
+        let mut world = hecs::World::new();
+
+        if let Some(background) = &blueprint.background {
+            world.spawn((LevelBackground { color: Vec4::from_array(background.color) },));
+        }
+
+        Instantiator { blueprint, textures, world, next: 0, sprites: Vec::new(), attached: false }
+    }
+
+    //Returns true once every sprite, tilemap, IntGrid and entity is spawned.
+    fn step(
+        &mut self, context: &VisContext, assets: &mut Assets, registry: &LdtkEntityRegistry,
+        budget: Option<Duration>,
+    ) -> bool {
+        let start = Instant::now();
+        let over_budget = || budget.is_some_and(|budget| start.elapsed() >= budget);
+
+        while let Some(sprite) = self.blueprint.sprites.get(self.next) {
+            let transform = Transform2D::new(
+                context,
+                Vec3::from_array(sprite.position),
+                sprite.rotation,
+                Vec2::from_array(sprite.scale),
+            );
+
+            let entity = self.world.spawn((transform, RenderLayer(sprite.layer)));
+
+            if let Some(texture) = sprite.texture {
+                let mut fanta = Sprite::new(
+                    context,
+                    self.textures[texture],
+                    Vec4::from_array(sprite.tint),
+                    sprite.coords.as_ref().map(|coords| coords.as_slice()),
+                    Some(Sampler::new(context)),
+                );
+                fanta.set_soft(context, sprite.soft_distance);
+                fanta.set_flip(context, sprite.flip.0, sprite.flip.1);
+                let _ = self.world.insert_one(entity, fanta);
+            }
+
+            if let Some(animation) = &sprite.animation {
+                let mut animation2d = Animation2D::new(
+                    self.textures[animation.frames],
+                    animation.frames_per_second,
+                    animation.total_frames,
+                    animation.mirrored,
+                    animation.looped,
+                );
+
+                if let Some(rects) = &animation.rects {
+                    animation2d = animation2d.with_rects(
+                        rects
+                            .iter()
+                            .map(|r| (Vec2::new(r[0], r[1]), Vec2::new(r[2], r[3])))
+                            .collect(),
+                    );
+                }
+
+                let _ = self.world.insert_one(entity, animation2d);
+            }
+
+            self.sprites.push(entity);
+            self.next += 1;
+
+            //Check the clock every few spawns only.
+            if self.next % 64 == 0 && over_budget() {
+                return false;
+            }
+        }
+
+        //Parents may come after their children. Blueprints not read from a file are checked here.
+        if !self.attached {
+            self.attached = true;
+
+            match self.blueprint.validate() {
+                Ok(()) => self.attach_children(),
+                Err(e) => log::warn!("The sprites are left unattached. {}", e),
+            }
+        }
+
+        while let Some(blueprint) =
+            self.blueprint.tilemaps.get(self.next - self.blueprint.sprites.len())
+        {
+            let transform =
+                Transform2D::new(context, Vec3::from_array(blueprint.position), 0.0, Vec2::ONE);
+
+            let mut tilemap = Tilemap::new(
+                context,
+                self.textures[blueprint.texture],
+                blueprint.width,
+                blueprint.height,
+                blueprint.cell_size,
+                Vec4::new(1.0, 1.0, 1.0, blueprint.opacity),
+            );
+
+            for tile in blueprint.tiles.iter() {
+                tilemap.set_tile(tile.x, tile.y, Some(Tile { id: tile.id, coords: tile.coords }));
+            }
+
+            //Build the chunk buffers now, instead of on the first frame.
+            tilemap.prepare(context);
+
+            self.world.spawn((
+                transform,
+                tilemap,
+                RenderLayer(blueprint.layer),
+                OrderInLayer(blueprint.order),
+            ));
+            self.next += 1;
+
+            if over_budget() {
+                return false;
+            }
+        }
+
+        let spawned = self.blueprint.sprites.len() + self.blueprint.tilemaps.len();
+
+        //Plain data, no need to check the clock.
+        while let Some(blueprint) = self.blueprint.int_grids.get(self.next - spawned) {
+            let grid = IntGrid::new(
+                blueprint.identifier.clone(),
+                blueprint.width,
+                blueprint.height,
+                blueprint.cell_size,
+                Vec2::from_array(blueprint.position),
+                blueprint.values.clone(),
+            );
+
+            self.world.spawn((grid,));
+            self.next += 1;
+        }
+
+        let spawned = spawned + self.blueprint.int_grids.len();
+
+        while let Some(blueprint) = self.blueprint.entities.get(self.next - spawned) {
+            let size = Vec2::from_array(blueprint.size);
+            //The sprite quad spans -1..1.
+            let transform =
+                Transform2D::new(context, Vec3::from_array(blueprint.position), 0.0, size / 2.0);
+
+            let ldtk = LdtkEntity {
+                identifier: blueprint.identifier.clone(),
+                iid: blueprint.iid.clone(),
+                grid: blueprint.grid,
+                size,
+                fields: blueprint.fields.iter().cloned().collect(),
+            };
+
+            let entity = self.world.spawn((
+                transform,
+                ldtk,
+                RenderLayer(blueprint.layer),
+                Name::new(blueprint.identifier.clone()),
+            ));
+
+            if !blueprint.tags.is_empty() {
+                let _ = self.world.insert_one(entity, Tags::new(blueprint.tags.iter().cloned()));
+            }
+
+            if let Some((texture, [left, top, right, bottom])) = blueprint.tile {
+                let coords = [left, bottom, right, top, left, top, right, bottom];
+                let fanta = Sprite::new(
+                    context,
+                    self.textures[texture],
+                    Vec4::ONE,
+                    Some(coords.as_slice()),
+                    Some(Sampler::new(context)),
+                );
+                let _ = self.world.insert_one(entity, fanta);
+            }
+
+            registry.spawn(context, assets, &mut self.world, entity);
+            self.next += 1;
+
+            if over_budget() {
+                break;
+            }
+        }
+
+        self.next >= self.len()
+    }
+
+    fn attach_children(&mut self) {
+        for (index, sprite) in self.blueprint.sprites.iter().enumerate() {
+            if let Some(parent) = sprite.parent {
+                let (child, parent_entity) = (self.sprites[index], self.sprites[parent]);
+
+                if let Err(e) = self.world.attach::<Transform2D>(child, parent_entity) {
+                    log::warn!("Could not attach sprite {} to {}: {:?}", index, parent, e);
+                }
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.blueprint.sprites.len()
+            + self.blueprint.tilemaps.len()
+            + self.blueprint.int_grids.len()
+            + self.blueprint.entities.len()
+    }
+
+    fn progress(&self) -> f32 {
+        if self.len() == 0 {
+            1.0
+        } else {
+            self.next as f32 / self.len() as f32
+        }
+    }
+}
+
+pub fn instantiate(
+    context: &VisContext, assets: &mut Assets, blueprint: WorldBlueprint,
+) -> hecs::World {
+    instantiate_with(context, assets, blueprint, &LdtkEntityRegistry::new())
+}
+
+//Runs the spawners of the registry for the ldtk entities of the blueprint.
+pub fn instantiate_with(
+    context: &VisContext, assets: &mut Assets, blueprint: WorldBlueprint,
+    registry: &LdtkEntityRegistry,
+) -> hecs::World {
+    let mut instantiator = Instantiator::new(assets, blueprint);
+    instantiator.step(context, assets, registry, None);
+    instantiator.world
+}
+
+enum LoadState {
+    Parsing(Receiver<Result<WorldBlueprint, LoadError>>),
+    Instantiating(Instantiator),
+    Done(Guid),
+    Failed(String),
+}
+
+//A world loading in the background. Call step() every frame, it spends at most the budget on spawning.
+pub struct WorldLoadHandle {
+    state: LoadState,
+    budget: Duration,
+    registry: LdtkEntityRegistry,
+}
+
+impl WorldLoadHandle {
+    pub fn from_ldtk_file(loc: Option<PathBuf>, path: PathBuf) -> Self {
+        Self::spawn(move || WorldBlueprint::from_ldtk_file(&loc, path))
+    }
+
+    pub fn from_scene_file(path: PathBuf) -> Self {
+        Self::spawn(move || WorldBlueprint::from_scene_file(path))
+    }
+
+    pub fn spawn(
+        parse: impl FnOnce() -> Result<WorldBlueprint, LoadError> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        rayon::spawn(move || {
+            let _ = sender.send(parse());
+        });
+
+        WorldLoadHandle {
+            state: LoadState::Parsing(receiver),
+            budget: Duration::from_millis(4),
+            registry: LdtkEntityRegistry::new(),
+        }
+    }
+
+    //Time spent creating GPU resources per step.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    //Spawners for the ldtk entities of the world.
+    pub fn with_registry(mut self, registry: LdtkEntityRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn step(&mut self, context: &VisContext, assets: &mut Assets, worlds: &mut Worlds) {
+        match &mut self.state {
+            LoadState::Parsing(receiver) => match receiver.try_recv() {
+                Ok(Ok(blueprint)) => {
+                    self.state = LoadState::Instantiating(Instantiator::new(assets, blueprint));
+                }
+                Ok(Err(e)) => self.state = LoadState::Failed(e.to_string()),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    self.state = LoadState::Failed("World loader thread died".to_string())
+                }
+            },
+            LoadState::Instantiating(instantiator) => {
+                if instantiator.step(context, assets, &self.registry, Some(self.budget)) {
+                    let world = std::mem::take(&mut instantiator.world);
+                    self.state = LoadState::Done(worlds.add_world(world));
+                }
+            }
+            LoadState::Done(_) | LoadState::Failed(_) => {}
+        }
+    }
+
+    //Parsing counts as the first 10%.
+    pub fn progress(&self) -> f32 {
+        match &self.state {
+            LoadState::Parsing(_) => 0.0,
+            LoadState::Instantiating(instantiator) => 0.1 + 0.9 * instantiator.progress(),
+            LoadState::Done(_) => 1.0,
+            LoadState::Failed(_) => 0.0,
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match &self.state {
+            LoadState::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    //The guid of the new world, once it is fully instantiated and added to the worlds.
+    pub fn finish(&self) -> Option<Guid> {
+        match &self.state {
+            LoadState::Done(guid) => Some(*guid),
+            _ => None,
+        }
+    }
+}
+
+//Relative to the project file. Unlike tilesets it is read from disk right away, not an asset.
+fn external_level<P: AsRef<Path>>(
+    ldtk_file_path: &P, rel_path: &str,
+) -> Result<ldtk::Level, LoadError> {
+    let parent = ldtk_file_path.as_ref().parent().ok_or("Cannot get parent of ldtk file path")?;
+    let path = parent.join(rel_path);
+
+    let level = std::fs::read(&path)
+        .map_err(LoadError::from)
+        .and_then(|content| Ok(serde_json::from_slice(&content)?));
+
+    level.map_err(|e| format!("Could not load level file {}: {}", path.display(), e).into())
+}
+
+fn tileset_filepath<P1: AsRef<Path>, P2: AsRef<Path>>(
+    ldtk_file_path: &P1, loc: &Option<PathBuf>, tileset_relative_path: &P2,
+) -> Result<PathBuf, LoadError> {
+    let mut path = ldtk_file_path.as_ref().to_path_buf();
+    if let Some(prefix) = &loc {
+        path = FileUtils::relative_to(&path, prefix).ok_or_else(|| {
+            format!("Ldtk file {} is not part of the project {}", path.display(), prefix.display())
+        })?;
+    }
+
+    let parent = path.parent().ok_or("Cannot get parent of ldtk file path")?;
+    // For the tileset_relative_path, we replace the extension (e.g. .png) with .fur
+    let tileset_relative_path = tileset_relative_path.as_ref();
+    let tileset_relative_path = tileset_relative_path.with_extension("fur");
+
+    Ok(parent.join(tileset_relative_path))
+}
+
+fn level_offset(level: &ldtk::Level, unit: f32) -> Vec2 {
+    Vec2::new(level.world_x as f32, -level.world_y as f32) * unit
+}
+
+//Top left corner of the layer, without its own offset.
+fn layer_origin(layer: &ldtk::LayerInstance, offset: Vec2, unit: f32) -> Vec2 {
+    let scale = layer.grid_size as f32 * unit / 2.0;
+    Vec2::new(-1.0 - scale + offset.x, scale + offset.y)
+}
+
+//The cropped background image as a sprite, top_left is the top left corner of the level. Ldtk
+//applies the pivot to top_left_px already. Without the image size the whole image is shown.
+fn background_sprite(
+    position: &ldtk::LevelBackgroundPosition, top_left: Vec2, unit: f32, texture: usize,
+    image_size: Option<Vec2>,
+) -> Option<SpriteBlueprint> {
+    let [x, y, width, height] = position.crop_rect.as_slice() else {
+        return None;
+    };
+    let crop = Vec2::new(*x as f32, *y as f32);
+    let crop_size = Vec2::new(*width as f32, *height as f32);
+
+    let scale = match position.scale.as_slice() {
+        [x, y] => Vec2::new(*x as f32, *y as f32),
+        _ => Vec2::ONE,
+    };
+    let corner = match position.top_left_px.as_slice() {
+        [x, y] => Vec2::new(*x as f32, *y as f32),
+        _ => Vec2::ZERO,
+    };
+
+    //In level pixels, y goes down in ldtk.
+    let size = crop_size * scale;
+    let center = corner + size / 2.0;
+
+    let coords = image_size.filter(|size| size.min_element() > 0.0).map(|image| {
+        let (left, top) = (crop.x / image.x, crop.y / image.y);
+        let (right, bottom) = ((crop.x + crop_size.x) / image.x, (crop.y + crop_size.y) / image.y);
+        [left, bottom, right, top, left, top, right, bottom]
+    });
+
+    Some(SpriteBlueprint {
+        position: [top_left.x + center.x * unit, top_left.y - center.y * unit, 0.0],
+        rotation: 0.0,
+        scale: (size * unit / 2.0).to_array(),
+        texture: Some(texture),
+        tint: [1.0; 4],
+        coords,
+        //Layers start at 0.
+        layer: -1,
+        soft_distance: None,
+        flip: (false, false),
+        animation: None,
+        parent: None,
+    })
+}
+
+fn srgb_to_linear(color: [f32; 4]) -> [f32; 4] {
+    let channel = |c: f32| match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    };
+
+    [channel(color[0]), channel(color[1]), channel(color[2]), color[3]]
+}
+
+//The union of the layers, or the level rect if it has none.
+fn level_bounds(level: &ldtk::Level, unit: f32) -> (Vec2, Vec2) {
+    let offset = level_offset(level, unit);
+    let layers = level.layer_instances.as_deref().unwrap_or_default();
+
+    let rects = layers.iter().map(|layer| {
+        let layer_offset =
+            Vec2::new(layer.px_total_offset_x as f32, -layer.px_total_offset_y as f32) * unit;
+        let top_left = layer_origin(layer, offset, unit) + layer_offset;
+        let size =
+            Vec2::new(layer.c_wid as f32, layer.c_hei as f32) * layer.grid_size as f32 * unit;
+
+        (Vec2::new(top_left.x, top_left.y - size.y), Vec2::new(top_left.x + size.x, top_left.y))
+    });
+
+    let union = rects.reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)));
+
+    union.unwrap_or_else(|| {
+        let size = Vec2::new(level.px_wid as f32, level.px_hei as f32) * unit;
+        let top_left = Vec2::new(-1.0 + offset.x, offset.y);
+        (Vec2::new(top_left.x, top_left.y - size.y), Vec2::new(top_left.x + size.x, top_left.y))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auto_layer() -> WorldBlueprint {
+        WorldBlueprint::from_ldtk_file(&None, "examples/ldtk/data/auto_layer.ldtk").unwrap()
+    }
+
+    fn coords(tilemap: &TilemapBlueprint, x: u32, y: u32) -> [f32; 4] {
+        tilemap.tiles.iter().find(|tile| tile.x == x && tile.y == y).unwrap().coords
+    }
+
+    #[test]
+    fn auto_layer_tiles_honor_flip_bits() {
+        let blueprint = auto_layer();
+        let tilemap = &blueprint.tilemaps[0];
+        let [left, top, right, bottom] = coords(tilemap, 0, 0);
+
+        assert_eq!(coords(tilemap, 1, 0), [right, top, left, bottom]);
+        assert_eq!(coords(tilemap, 2, 0), [left, bottom, right, top]);
+        assert_eq!(coords(tilemap, 3, 0), [right, bottom, left, top]);
+    }
+
+    #[test]
+    fn stacked_tiles_are_drawn_above_each_other() {
+        let blueprint = auto_layer();
+        let on_cell: Vec<_> = blueprint
+            .tilemaps
+            .iter()
+            .filter_map(|tilemap| {
+                let tile = tilemap.tiles.iter().find(|tile| tile.x == 0 && tile.y == 1)?;
+                Some((tilemap.order, tile.id))
+            })
+            .collect();
+
+        assert_eq!(on_cell, vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn tile_alpha_is_multiplied_into_the_tint() {
+        let blueprint = auto_layer();
+        let translucent: Vec<_> =
+            blueprint.tilemaps.iter().filter(|tilemap| tilemap.opacity < 1.0).collect();
+
+        assert_eq!(translucent.len(), 1);
+        assert_eq!(translucent[0].opacity, 0.5);
+        assert_eq!(translucent[0].order, 0);
+        assert_eq!(translucent[0].tiles.len(), 1);
+        assert_eq!((translucent[0].tiles[0].x, translucent[0].tiles[0].y), (1, 1));
+    }
+
+    #[test]
+    fn levels_know_their_offset_and_bounds() {
+        let levels =
+            WorldBlueprint::from_ldtk_levels(&None, "examples/ldtk/data/auto_layer.ldtk").unwrap();
+
+        //64x32 pixels span -1..1 horizontally, the 16 pixel cells are shifted by half a cell.
+        assert_eq!(levels[0].offset, Vec2::ZERO);
+        assert_eq!(levels[0].bounds, (Vec2::new(-1.25, -0.75), Vec2::new(0.75, 0.25)));
+
+        let levels =
+            WorldBlueprint::from_ldtk_levels(&None, "examples/ldtk/data/test.ldtk").unwrap();
+        let unit = 2.0 / 704.0;
+
+        assert_eq!(levels[0].offset, Vec2::new(-208.0 * unit, 0.0));
+        assert!((levels[0].bounds.1.x - levels[0].bounds.0.x - 704.0 * unit).abs() < 1e-5);
+    }
+
+    #[test]
+    fn background_images_are_placed_in_the_level() {
+        let position = ldtk::LevelBackgroundPosition {
+            crop_rect: vec![16.0, 0.0, 32.0, 16.0],
+            scale: vec![2.0, 2.0],
+            top_left_px: vec![8, 4],
+        };
+
+        let sprite =
+            background_sprite(&position, Vec2::new(-1.0, 1.0), 0.5, 0, Some(Vec2::splat(64.0)))
+                .unwrap();
+
+        //64x32 level pixels with the top left corner at (8, 4).
+        assert_eq!(sprite.position, [-1.0 + 40.0 * 0.5, 1.0 - 20.0 * 0.5, 0.0]);
+        assert_eq!(sprite.scale, [16.0, 8.0]);
+        assert_eq!(sprite.coords, Some([0.25, 0.25, 0.75, 0.0, 0.25, 0.0, 0.75, 0.25]));
+    }
+
+    #[test]
+    fn level_colors_are_made_linear() {
+        let color = srgb_to_linear([1.0, 0.5, 0.0, 0.5]);
+
+        assert_eq!([color[0], color[2], color[3]], [1.0, 0.0, 0.5]);
+        assert!((color[1] - 0.214).abs() < 1e-3);
+    }
+}
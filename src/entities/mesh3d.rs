@@ -0,0 +1,27 @@
+use crate::render::material::GenericMaterial;
+use crate::render::mesh::GenericMesh;
+
+//Drawn by the 3D Renderer at the Transform3D of its entity. Like sprites, the material is group 0,
+//the transform group 1 and the camera group 2.
+pub struct Mesh3D {
+    mesh: GenericMesh<'static>,
+    material: GenericMaterial,
+}
+
+impl Mesh3D {
+    pub fn new(mesh: GenericMesh<'static>, material: GenericMaterial) -> Self {
+        Mesh3D { mesh, material }
+    }
+
+    pub fn mesh(&self) -> &GenericMesh<'static> {
+        &self.mesh
+    }
+
+    pub fn mesh_mut(&mut self) -> &mut GenericMesh<'static> {
+        &mut self.mesh
+    }
+
+    pub fn material(&self) -> &GenericMaterial {
+        &self.material
+    }
+}
@@ -1,6 +1,28 @@
 pub mod animation2d;
+pub mod animator2d;
+pub mod audio;
 pub mod entities;
+pub mod intgrid;
+pub mod layer;
+pub mod ldtk_entity;
+pub mod light2d;
+pub mod loader;
+pub mod mesh3d;
+pub mod name;
+pub mod navigation;
+pub mod opacity;
+pub mod particles;
+pub mod physics2d;
+pub mod picking;
+pub mod prefab;
+pub mod scene;
 pub mod script;
+pub mod skin;
 pub mod sprite;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod tilemap;
+pub mod timer;
 pub mod transform;
 pub mod transform2d;
+pub mod tween;
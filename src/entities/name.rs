@@ -0,0 +1,142 @@
+use hashbrown::HashMap;
+
+//Lets Worlds::find_by_name find the entity. Names don't have to be unique, the first one found wins.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new(name: impl Into<String>) -> Self {
+        Name(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+//Groups of entities for Worlds::find_all_by_tag, e.g. "enemy" or "pickup".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+    pub fn new<S: Into<String>>(tags: impl IntoIterator<Item = S>) -> Self {
+        Tags(tags.into_iter().map(Into::into).collect())
+    }
+
+    pub fn has(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+}
+
+//Names of the entities of one world, tags are looked up in the world itself. Entries are checked
+//against the world on lookup, so despawned or renamed entities are never returned. A name that
+//isn't found rescans the world first, that picks up names added or changed without the index.
+#[derive(Default)]
+pub(crate) struct NameIndex {
+    names: HashMap<String, Vec<hecs::Entity>>,
+    dirty: bool,
+}
+
+impl NameIndex {
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn insert_name(&mut self, entity: hecs::Entity, name: &str) {
+        self.names.entry(name.to_string()).or_default().push(entity);
+    }
+
+    pub fn find(&mut self, world: &hecs::World, name: &str) -> Option<hecs::Entity> {
+        if self.dirty {
+            self.rescan(world);
+        }
+
+        if let Some(entity) = self.lookup(world, name) {
+            return Some(entity);
+        }
+
+        self.rescan(world);
+        self.lookup(world, name)
+    }
+
+    //Tags are changed in place more often than names and a tag has many entities, an index would
+    //have to be rescanned on every lookup anyway.
+    pub fn find_tagged(&self, world: &hecs::World, tag: &str) -> Vec<hecs::Entity> {
+        world
+            .query::<&Tags>()
+            .iter()
+            .filter(|(_, tags)| tags.has(tag))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    fn lookup(&mut self, world: &hecs::World, name: &str) -> Option<hecs::Entity> {
+        let named = |entity: &hecs::Entity| {
+            world.get::<&Name>(*entity).is_ok_and(|found| found.as_str() == name)
+        };
+
+        let entities = self.names.get_mut(name)?;
+        entities.retain(named);
+        entities.first().copied()
+    }
+
+    fn rescan(&mut self, world: &hecs::World) {
+        self.names.clear();
+
+        for (entity, name) in world.query::<&Name>().iter() {
+            self.insert_name(entity, name.as_str());
+        }
+
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_in_place_is_found() {
+        let mut world = hecs::World::new();
+        let mut index = NameIndex::default();
+
+        let entity = world.spawn((Name::new("old"),));
+        index.insert_name(entity, "old");
+        world.spawn((Name::new("other"),));
+
+        world.get::<&mut Name>(entity).unwrap().0 = "new".to_string();
+
+        assert_eq!(index.find(&world, "old"), None);
+        assert_eq!(index.find(&world, "new"), Some(entity));
+    }
+
+    #[test]
+    fn swapped_names_are_found() {
+        let mut world = hecs::World::new();
+        let mut index = NameIndex::default();
+
+        let a = world.spawn((Name::new("a"),));
+        let b = world.spawn((Name::new("b"),));
+        index.insert_name(a, "a");
+        index.insert_name(b, "b");
+
+        //Same number of names as before, only the lookups can tell.
+        world.get::<&mut Name>(a).unwrap().0 = "b".to_string();
+        world.get::<&mut Name>(b).unwrap().0 = "a".to_string();
+
+        assert_eq!(index.find(&world, "a"), Some(b));
+        assert_eq!(index.find(&world, "b"), Some(a));
+    }
+
+    #[test]
+    fn tags_changed_in_place_are_found() {
+        let mut world = hecs::World::new();
+        let index = NameIndex::default();
+
+        let entity = world.spawn((Tags::new(["enemy"]),));
+        world.get::<&mut Tags>(entity).unwrap().0.push("boss".to_string());
+
+        assert_eq!(index.find_tagged(&world, "boss"), vec![entity]);
+        assert_eq!(index.find_tagged(&world, "pickup"), Vec::new());
+    }
+}
@@ -0,0 +1,561 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::path::Path;
+
+use glam::Vec2;
+
+use crate::assets::ldtk;
+use crate::entities::loader::LoadError;
+use crate::entities::transform2d::Transform2D;
+use crate::utils::Timestep;
+
+//Walkable cells with a movement cost. None marks a blocked cell.
+//Cells are laid out like the ldtk loader places tiles: x goes right, y goes down in grid space and up in world space.
+#[derive(Clone, Debug)]
+pub struct NavGrid {
+    width: usize,
+    height: usize,
+    origin: Vec2,
+    cell_size: f32,
+    costs: Vec<Option<f32>>,
+}
+
+impl NavGrid {
+    //origin is the world position of the center of cell (0, 0).
+    pub fn new(width: usize, height: usize, origin: Vec2, cell_size: f32) -> Self {
+        NavGrid { width, height, origin, cell_size, costs: vec![Some(1.0); width * height] }
+    }
+
+    //Builds the grid from an IntGrid layer. cost maps an IntGrid value to a cost, None for walls.
+    pub fn from_ldtk_file<P: AsRef<Path>>(
+        ldtk_file_path: P, layer_identifier: &str, cost: impl Fn(i64) -> Option<f32>,
+    ) -> Result<Self, LoadError> {
+        let file_content = std::fs::read(ldtk_file_path)?;
+        let project: ldtk::Project = serde_json::from_slice(&file_content)?;
+
+        let layer = project
+            .levels
+            .first()
+            .and_then(|level| level.layer_instances.as_ref())
+            .and_then(|li| li.iter().find(|layer| layer.identifier == layer_identifier))
+            .ok_or(format!("IntGrid layer {} not found in ldtk file", layer_identifier))?;
+
+        let (width, height) = (layer.c_wid as usize, layer.c_hei as usize);
+
+        if layer.int_grid_csv.len() != width * height {
+            return Err(format!("Layer {} is not an IntGrid layer", layer_identifier).into());
+        }
+
+        //Same placement as the tiles, see WorldBlueprint::from_ldtk_file.
+        let scale = (1.0 / layer.c_wid as f32).min(1.0 / layer.c_hei as f32);
+        let offset_x = (layer.px_total_offset_x / layer.grid_size) as f32;
+        let offset_y = (layer.px_total_offset_y / layer.grid_size) as f32;
+        let origin = Vec2::new(offset_x * scale * 2.0 - 1.0, -offset_y * scale * 2.0);
+
+        let mut grid = NavGrid::new(width, height, origin, scale * 2.0);
+
+        for (i, value) in layer.int_grid_csv.iter().enumerate() {
+            grid.costs[i] = cost(*value).map(|cost| cost.max(f32::EPSILON));
+        }
+
+        Ok(grid)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_cost(&mut self, x: usize, y: usize, cost: Option<f32>) {
+        if x < self.width && y < self.height {
+            self.costs[y * self.width + x] = cost.map(|cost| cost.max(f32::EPSILON));
+        }
+    }
+
+    pub fn cost(&self, x: i64, y: i64) -> Option<f32> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+
+        self.costs[y as usize * self.width + x as usize]
+    }
+
+    pub fn is_walkable(&self, x: i64, y: i64) -> bool {
+        self.cost(x, y).is_some()
+    }
+
+    pub fn cell_center(&self, x: i64, y: i64) -> Vec2 {
+        self.origin + Vec2::new(x as f32, -(y as f32)) * self.cell_size
+    }
+
+    pub fn world_to_cell(&self, position: Vec2) -> (i64, i64) {
+        let local = (position - self.origin) / self.cell_size;
+        (local.x.round() as i64, (-local.y).round() as i64)
+    }
+
+    //Waypoints in world space, excluding the start and ending exactly at the goal.
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<Vec2>> {
+        let cells = self.find_cells(self.world_to_cell(start), self.world_to_cell(goal))?;
+        let cells = self.smooth(&cells);
+
+        let mut waypoints: Vec<Vec2> =
+            cells.iter().skip(1).map(|(x, y)| self.cell_center(*x, *y)).collect();
+
+        match waypoints.last_mut() {
+            Some(last) => *last = goal,
+            None => waypoints.push(goal),
+        }
+
+        Some(waypoints)
+    }
+
+    //A* over 8 neighbours. Diagonal moves may not cut past blocked corners.
+    pub fn find_cells(&self, start: (i64, i64), goal: (i64, i64)) -> Option<Vec<(i64, i64)>> {
+        if !self.is_walkable(start.0, start.1) || !self.is_walkable(goal.0, goal.1) {
+            return None;
+        }
+
+        let min_cost = self.costs.iter().flatten().copied().fold(f32::MAX, f32::min);
+        let heuristic = |(x, y): (i64, i64)| {
+            let (dx, dy) = ((x - goal.0).abs() as f32, (y - goal.1).abs() as f32);
+            (dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy)) * min_cost
+        };
+
+        let index = |(x, y): (i64, i64)| y as usize * self.width + x as usize;
+        let mut best = vec![f32::MAX; self.costs.len()];
+        let mut came_from = vec![usize::MAX; self.costs.len()];
+        let mut open = BinaryHeap::new();
+
+        best[index(start)] = 0.0;
+        open.push(OpenCell { estimate: heuristic(start), cell: start });
+
+        while let Some(OpenCell { cell, estimate }) = open.pop() {
+            let cost_so_far = best[index(cell)];
+
+            if cell == goal {
+                let mut path = vec![goal];
+                let mut current = index(goal);
+
+                while came_from[current] != usize::MAX {
+                    current = came_from[current];
+                    path.push(((current % self.width) as i64, (current / self.width) as i64));
+                }
+
+                path.reverse();
+                return Some(path);
+            }
+
+            //Stale heap entry, a cheaper way was found after it was pushed.
+            if estimate > cost_so_far + heuristic(cell) + f32::EPSILON {
+                continue;
+            }
+
+            for (dx, dy) in NEIGHBOURS {
+                let next = (cell.0 + dx, cell.1 + dy);
+
+                let Some(cost) = self.cost(next.0, next.1) else {
+                    continue;
+                };
+
+                let diagonal = dx != 0 && dy != 0;
+
+                if diagonal
+                    && (!self.is_walkable(cell.0 + dx, cell.1)
+                        || !self.is_walkable(cell.0, cell.1 + dy))
+                {
+                    continue;
+                }
+
+                let step = if diagonal { std::f32::consts::SQRT_2 } else { 1.0 };
+                let tentative = cost_so_far + step * cost;
+
+                if tentative < best[index(next)] {
+                    best[index(next)] = tentative;
+                    came_from[index(next)] = index(cell);
+                    open.push(OpenCell { estimate: tentative + heuristic(next), cell: next });
+                }
+            }
+        }
+
+        None
+    }
+
+    //Drops waypoints that can be skipped in a straight line.
+    pub fn smooth(&self, cells: &[(i64, i64)]) -> Vec<(i64, i64)> {
+        let Some(first) = cells.first() else {
+            return Vec::new();
+        };
+
+        let mut smoothed = vec![*first];
+        let mut anchor = 0;
+
+        while anchor + 1 < cells.len() {
+            let mut next = anchor + 1;
+
+            for candidate in (anchor + 2..cells.len()).rev() {
+                if self.line_of_sight(cells[anchor], cells[candidate]) {
+                    next = candidate;
+                    break;
+                }
+            }
+
+            smoothed.push(cells[next]);
+            anchor = next;
+        }
+
+        smoothed
+    }
+
+    //Walks every cell the segment touches. Cells pricier than both ends block, so smoothing
+    //never takes a shortcut through expensive terrain.
+    pub fn line_of_sight(&self, from: (i64, i64), to: (i64, i64)) -> bool {
+        let (Some(from_cost), Some(to_cost)) = (self.cost(from.0, from.1), self.cost(to.0, to.1))
+        else {
+            return false;
+        };
+
+        let limit = from_cost.max(to_cost);
+        let passable = |x: i64, y: i64| self.cost(x, y).is_some_and(|cost| cost <= limit);
+
+        let (nx, ny) = ((to.0 - from.0).abs(), (to.1 - from.1).abs());
+        let (sx, sy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (mut x, mut y) = from;
+        let (mut ix, mut iy) = (0, 0);
+
+        while ix < nx || iy < ny {
+            let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+
+            if decision == 0 {
+                //Exactly through a corner, both side cells have to be free.
+                if !passable(x + sx, y) || !passable(x, y + sy) {
+                    return false;
+                }
+
+                x += sx;
+                y += sy;
+                ix += 1;
+                iy += 1;
+            } else if decision < 0 {
+                x += sx;
+                ix += 1;
+            } else {
+                y += sy;
+                iy += 1;
+            }
+
+            if !passable(x, y) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+const NEIGHBOURS: [(i64, i64); 8] =
+    [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+#[derive(PartialEq)]
+struct OpenCell {
+    estimate: f32,
+    cell: (i64, i64),
+}
+
+impl Eq for OpenCell {}
+
+//Reversed, so the BinaryHeap pops the lowest estimate first.
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| self.cell.cmp(&other.cell))
+    }
+}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+//Moves the entity's Transform2D along a path to the goal. Speed is in world units per second, the
+//path is followed in world space and child transforms are moved through their parent.
+pub struct PathFollower {
+    speed: f32,
+    goal: Option<Vec2>,
+    path: Vec<Vec2>,
+    next: usize,
+    repath: bool,
+}
+
+impl PathFollower {
+    pub fn new(speed: f32) -> Self {
+        PathFollower { speed, goal: None, path: Vec::new(), next: 0, repath: false }
+    }
+
+    //The path is computed by Navigation::update, possibly a few frames later under load.
+    pub fn set_goal(&mut self, goal: Vec2) {
+        self.goal = Some(goal);
+        self.repath = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.goal = None;
+        self.path.clear();
+        self.repath = false;
+    }
+
+    pub fn goal(&self) -> Option<Vec2> {
+        self.goal
+    }
+
+    pub fn path(&self) -> &[Vec2] {
+        &self.path[self.next.min(self.path.len())..]
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.next < self.path.len()
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    //Walks distance along the path from position, both in world space. Returns where it ends up.
+    fn advance(&mut self, mut position: Vec2, mut distance: f32) -> Vec2 {
+        while distance > 0.0 && self.is_moving() {
+            let target = self.path[self.next];
+            let remaining = position.distance(target);
+
+            if remaining <= distance {
+                position = target;
+                distance -= remaining;
+                self.next += 1;
+            } else {
+                position += (target - position) / remaining * distance;
+                distance = 0.0;
+            }
+        }
+
+        position
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathEvent {
+    Arrived(hecs::Entity),
+    Unreachable(hecs::Entity),
+}
+
+//Owns the grid and runs the PathFollowers. Path requests are spread over frames. Put it into the
+//resources of a Schedule to have it run, the PathEvents arrive as Event::Custom.
+pub struct Navigation {
+    grid: NavGrid,
+    max_requests: usize,
+    queue: VecDeque<hecs::Entity>,
+}
+
+impl Navigation {
+    pub fn new(grid: NavGrid) -> Self {
+        Navigation { grid, max_requests: 8, queue: VecDeque::new() }
+    }
+
+    //How many paths are computed per frame at most.
+    pub fn with_max_requests(mut self, max_requests: usize) -> Self {
+        self.max_requests = max_requests.max(1);
+        self
+    }
+
+    pub fn grid(&self) -> &NavGrid {
+        &self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut NavGrid {
+        &mut self.grid
+    }
+
+    pub fn update(&mut self, world: &mut hecs::World, delta: &Timestep) -> Vec<PathEvent> {
+        let mut events = Vec::new();
+
+        for (entity, follower) in world.query_mut::<&mut PathFollower>() {
+            if follower.repath {
+                follower.repath = false;
+
+                if !self.queue.contains(&entity) {
+                    self.queue.push_back(entity);
+                }
+            }
+        }
+
+        for _ in 0..self.max_requests {
+            let Some(entity) = self.queue.pop_front() else {
+                break;
+            };
+
+            let Ok((transform, follower)) =
+                world.query_one_mut::<(&Transform2D, &mut PathFollower)>(entity)
+            else {
+                continue;
+            };
+
+            let Some(goal) = follower.goal else {
+                continue;
+            };
+
+            follower.next = 0;
+
+            match self.grid.find_path(transform.global_position().truncate(), goal) {
+                Some(path) => follower.path = path,
+                None => {
+                    follower.path.clear();
+                    follower.goal = None;
+                    events.push(PathEvent::Unreachable(entity));
+                }
+            }
+        }
+
+        let seconds = delta.seconds() as f32;
+
+        for (entity, (transform, follower)) in
+            world.query_mut::<(&mut Transform2D, &mut PathFollower)>()
+        {
+            if !follower.is_moving() {
+                continue;
+            }
+
+            let position = transform.global_position().truncate();
+            let position = follower.advance(position, follower.speed * seconds);
+            transform.set_global_position(position);
+
+            if !follower.is_moving() {
+                follower.goal = None;
+                events.push(PathEvent::Arrived(entity));
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follower(path: &[Vec2]) -> PathFollower {
+        let mut follower = PathFollower::new(1.0);
+        follower.goal = path.last().copied();
+        follower.path = path.to_vec();
+        follower
+    }
+
+    #[test]
+    fn advance_carries_over_waypoints() {
+        let mut follower = follower(&[Vec2::new(1.0, 0.0), Vec2::new(1.0, 2.0)]);
+
+        let position = follower.advance(Vec2::ZERO, 2.0);
+
+        assert_eq!(position, Vec2::new(1.0, 1.0));
+        assert_eq!(follower.path(), &[Vec2::new(1.0, 2.0)]);
+        assert!(follower.is_moving());
+    }
+
+    #[test]
+    fn advance_stops_at_the_goal() {
+        let mut follower = follower(&[Vec2::new(3.0, 4.0)]);
+
+        let position = follower.advance(Vec2::ZERO, 10.0);
+
+        assert_eq!(position, Vec2::new(3.0, 4.0));
+        assert!(!follower.is_moving());
+    }
+
+    #[test]
+    fn grid_path_is_walked_in_world_units() {
+        //Cells are 2 world units wide, the speed must not be scaled by them.
+        let grid = NavGrid::new(4, 1, Vec2::ZERO, 2.0);
+        let mut follower = PathFollower::new(1.0);
+        follower.path = grid.find_path(Vec2::ZERO, Vec2::new(6.0, 0.0)).unwrap();
+
+        let position = follower.advance(Vec2::ZERO, 3.0);
+
+        assert_eq!(position, Vec2::new(3.0, 0.0));
+        assert_eq!(follower.path().last(), Some(&Vec2::new(6.0, 0.0)));
+    }
+
+    //What find_cells charges for walking the cells, each step pays the cost of the cell it enters.
+    fn path_cost(grid: &NavGrid, cells: &[(i64, i64)]) -> f32 {
+        cells
+            .windows(2)
+            .map(|step| {
+                let (from, to) = (step[0], step[1]);
+                let length = match from.0 != to.0 && from.1 != to.1 {
+                    true => std::f32::consts::SQRT_2,
+                    false => 1.0,
+                };
+                length * grid.cost(to.0, to.1).unwrap()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn cheapest_path_goes_around_expensive_cells() {
+        //A pricey column with one cheap cell at the bottom.
+        let mut grid = NavGrid::new(5, 4, Vec2::ZERO, 1.0);
+        for y in 0..3 {
+            grid.set_cost(2, y, Some(20.0));
+        }
+
+        let cells = grid.find_cells((0, 0), (4, 0)).unwrap();
+
+        assert!(cells.contains(&(2, 3)));
+        assert!(cells.len() > 5);
+        assert!((path_cost(&grid, &cells) - (2.0 + 4.0 * std::f32::consts::SQRT_2)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn diagonals_do_not_cut_blocked_corners() {
+        let mut grid = NavGrid::new(2, 2, Vec2::ZERO, 1.0);
+        grid.set_cost(1, 0, None);
+        grid.set_cost(0, 1, None);
+
+        assert_eq!(grid.find_cells((0, 0), (1, 1)), None);
+        assert!(!grid.line_of_sight((0, 0), (1, 1)));
+
+        //With one side open the path goes around the corner.
+        grid.set_cost(0, 1, Some(1.0));
+
+        assert_eq!(grid.find_cells((0, 0), (1, 1)), Some(vec![(0, 0), (0, 1), (1, 1)]));
+        assert!(!grid.line_of_sight((0, 0), (1, 1)));
+    }
+
+    #[test]
+    fn smoothing_keeps_out_of_expensive_cells() {
+        let mut grid = NavGrid::new(3, 3, Vec2::ZERO, 1.0);
+        let detour = [(0, 1), (1, 0), (2, 1)];
+
+        assert_eq!(grid.smooth(&detour), vec![(0, 1), (2, 1)]);
+
+        grid.set_cost(1, 1, Some(5.0));
+
+        assert!(!grid.line_of_sight((0, 1), (2, 1)));
+        assert_eq!(grid.smooth(&detour), detour.to_vec());
+    }
+
+    #[test]
+    fn trivial_and_blocked_requests() {
+        let mut grid = NavGrid::new(3, 3, Vec2::ZERO, 1.0);
+
+        assert_eq!(grid.find_cells((1, 1), (1, 1)), Some(vec![(1, 1)]));
+        assert_eq!(
+            grid.find_path(Vec2::new(1.0, -1.0), Vec2::new(1.0, -1.0)),
+            Some(vec![Vec2::new(1.0, -1.0)])
+        );
+
+        grid.set_cost(0, 0, None);
+
+        assert_eq!(grid.find_cells((0, 0), (2, 2)), None);
+        assert_eq!(grid.find_cells((2, 2), (0, 0)), None);
+        assert_eq!(grid.find_cells((2, 2), (5, 5)), None);
+    }
+}
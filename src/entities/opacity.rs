@@ -0,0 +1,33 @@
+use hecs_hierarchy::Hierarchy;
+
+use crate::entities::transform2d::Transform2D;
+
+//Fades an entity and all of its children. Multiplied down the hierarchy, independent of the sprite tint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Opacity(pub f32);
+
+impl Default for Opacity {
+    fn default() -> Self {
+        Opacity(1.0)
+    }
+}
+
+//Product of all Opacity components from the entity up to the root.
+pub fn effective_opacity(world: &hecs::World, entity: hecs::Entity) -> f32 {
+    let mut opacity = 1.0;
+    let mut current = Some(entity);
+
+    while let Some(entity) = current {
+        if let Ok(own) = world.get::<&Opacity>(entity) {
+            opacity *= own.0.clamp(0.0, 1.0);
+        }
+
+        if opacity <= 0.0 {
+            return 0.0;
+        }
+
+        current = world.parent::<Transform2D>(entity).ok();
+    }
+
+    opacity
+}
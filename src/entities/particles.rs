@@ -0,0 +1,290 @@
+use std::ops::Range;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::assets::assets::{Ptr, PARTICLE_SHADER};
+use crate::assets::buffer::Vertices;
+use crate::assets::texture::{Sampler, Texture2D};
+use crate::context::VisContext;
+use crate::entities::transform2d::Transform2D;
+use crate::random::RandomStream;
+use crate::render::material::GenericMaterial;
+use crate::render::types::{BindGroupEntry, ParticleInstance};
+use crate::utils::Timestep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParticleBlend {
+    #[default]
+    Alpha,
+    //Overlapping particles add up, for fire, sparks and glow.
+    Additive,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    position: Vec3,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+}
+
+//Spawns particles at the position of its Transform2D (the origin without one). They live in world
+//space, moving the emitter doesn't drag the ones already out. Simulated by ParticleSystem and drawn
+//by the Renderer2D on top of the world, in one instanced draw call per emitter.
+pub struct ParticleEmitter2D {
+    //Particles per second while enabled.
+    pub rate: f32,
+    //In seconds. Start and end are picked from uniformly, a range like 1.0..1.0 is fine.
+    pub lifetime: Range<f32>,
+    //In world units per second.
+    pub speed: Range<f32>,
+    //Center of the cone the particles start in and half its opening angle, in radians.
+    //0 is to the right, PI / 2 up.
+    pub direction: f32,
+    pub spread: f32,
+    //Acceleration in world units per second squared.
+    pub gravity: Vec2,
+    //Lerped over the life of a particle.
+    pub start_color: Vec4,
+    pub end_color: Vec4,
+    //Width and height of a particle in world units.
+    pub start_size: f32,
+    pub end_size: f32,
+    pub blend: ParticleBlend,
+    //Nothing is spawned while that many are alive.
+    pub max_particles: usize,
+    enabled: bool,
+    bursts: usize,
+    //Fractions of a particle carried over between frames.
+    accumulator: f32,
+    particles: Vec<Particle>,
+    rng: RandomStream,
+    texture: Ptr<Texture2D>,
+    sampler: Sampler,
+    material: GenericMaterial,
+    instances: Option<Vertices<'static>>,
+    capacity: usize,
+    count: u32,
+    waiting: bool,
+    placeholder: bool,
+    generation: u64,
+}
+
+impl ParticleEmitter2D {
+    //Ten white particles a second, going up for a second and fading out. rng is split off the engine
+    //Rng, e.g. context.rng.stream("particles").split(), so replays spawn the same particles.
+    #[track_caller]
+    pub fn new(context: &VisContext, texture: Ptr<Texture2D>, rng: RandomStream) -> Self {
+        let sampler = Sampler::new(context);
+
+        let material = GenericMaterial::new(
+            context,
+            PARTICLE_SHADER.clone(),
+            PARTICLE_SHADER.clone(),
+            &[Texture2D::layout_entry(0), Sampler::layout_entry(1)],
+            &[Texture2D::error_texture(context).group_entry(0), sampler.group_entry(1)],
+            &[texture.into()],
+            None,
+        );
+
+        ParticleEmitter2D {
+            rate: 10.0,
+            lifetime: 1.0..1.0,
+            speed: 0.5..0.5,
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.0,
+            gravity: Vec2::ZERO,
+            start_color: Vec4::ONE,
+            end_color: Vec4::new(1.0, 1.0, 1.0, 0.0),
+            start_size: 0.1,
+            end_size: 0.1,
+            blend: ParticleBlend::Alpha,
+            max_particles: 1000,
+            enabled: true,
+            bursts: 0,
+            accumulator: 0.0,
+            particles: Vec::new(),
+            rng,
+            texture,
+            sampler,
+            material,
+            instances: None,
+            capacity: 0,
+            count: 0,
+            waiting: true,
+            placeholder: false,
+            generation: 0,
+        }
+    }
+
+    //For emitters that should look the same on every run, e.g. in replays.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = RandomStream::new(seed, 0);
+        self
+    }
+
+    //Disabled emitters spawn nothing, the particles already out live on.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.accumulator = 0.0;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    //Spawns that many at once with the next update, enabled or not. Meant for one-shot effects:
+    //a disabled emitter with rate 0 is a puff waiting to be triggered.
+    pub fn burst(&mut self, count: usize) {
+        self.bursts += count;
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    //Nothing alive and nothing more to come.
+    pub fn is_finished(&self) -> bool {
+        self.particles.is_empty() && self.bursts == 0 && (!self.enabled || self.rate <= 0.0)
+    }
+
+    pub fn texture(&self) -> &Ptr<Texture2D> {
+        &self.texture
+    }
+
+    pub fn set_texture(&mut self, texture: Ptr<Texture2D>) {
+        if self.texture != texture {
+            self.texture = texture;
+            self.waiting = true;
+        }
+    }
+
+    fn update(&mut self, origin: Vec3, delta: f32) {
+        //Dead particles are dropped in place, the Vec keeps its capacity for the next ones.
+        let gravity = self.gravity;
+        self.particles.retain_mut(|particle| {
+            particle.age += delta;
+            particle.velocity += gravity * delta;
+            particle.position += (particle.velocity * delta).extend(0.0);
+            particle.age < particle.lifetime
+        });
+
+        if self.enabled {
+            self.accumulator += self.rate.max(0.0) * delta;
+        }
+
+        let count = self.accumulator.floor() as usize + std::mem::take(&mut self.bursts);
+        self.accumulator = self.accumulator.fract();
+
+        let count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+        self.particles.reserve(count);
+
+        for _ in 0..count {
+            let particle = self.spawn(origin);
+            self.particles.push(particle);
+        }
+    }
+
+    fn spawn(&mut self, origin: Vec3) -> Particle {
+        let angle = self.direction + self.rng.range_f32(-self.spread..self.spread);
+        let speed = self.rng.range_f32(self.speed.clone());
+        let lifetime = self.rng.range_f32(self.lifetime.clone()).max(f32::EPSILON);
+
+        Particle {
+            position: origin,
+            velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+            age: 0.0,
+            lifetime,
+        }
+    }
+
+    //Uploads the particles for drawing, the buffer grows as needed. Returns the bytes uploaded.
+    pub(crate) fn prepare(&mut self, context: &VisContext) -> usize {
+        let instances: Vec<ParticleInstance> = self
+            .particles
+            .iter()
+            .map(|particle| {
+                let t = particle.age / particle.lifetime;
+
+                ParticleInstance {
+                    position: particle.position.to_array(),
+                    size: self.start_size + (self.end_size - self.start_size) * t,
+                    color: self.start_color.lerp(self.end_color, t).to_array(),
+                }
+            })
+            .collect();
+
+        self.count = instances.len() as u32;
+
+        if instances.is_empty() {
+            return 0;
+        }
+
+        let data: &[u8] = bytemuck::cast_slice(&instances);
+
+        match &mut self.instances {
+            Some(buffer) if self.capacity >= instances.len() => {
+                buffer.update_buffer(context, data);
+            }
+            _ => {
+                //Rounded up, so a growing effect doesn't reallocate every frame.
+                self.capacity = instances.len().next_power_of_two().max(64);
+                let mut contents =
+                    vec![0u8; self.capacity * std::mem::size_of::<ParticleInstance>()];
+                contents[..data.len()].copy_from_slice(data);
+                self.instances = Some(Vertices::new(context, &contents, ParticleInstance::LAYOUT));
+            }
+        }
+
+        data.len()
+    }
+
+    //Instances written by the last prepare.
+    pub(crate) fn instances(&self) -> Option<(&Vertices<'static>, u32)> {
+        self.instances.as_ref().filter(|_| self.count > 0).map(|buffer| (buffer, self.count))
+    }
+
+    pub fn material(&self) -> &GenericMaterial {
+        &self.material
+    }
+
+    pub(crate) fn bind(&mut self, context: &VisContext, texture: &Texture2D) {
+        if self.waiting || self.generation != texture.generation() {
+            self.material.update_group(
+                context,
+                &[texture.group_entry(0), self.sampler.group_entry(1)],
+                &[self.texture.into()],
+            );
+            self.waiting = false;
+            self.placeholder = false;
+            self.generation = texture.generation();
+        }
+    }
+
+    pub(crate) fn bind_placeholder(&mut self, context: &VisContext, placeholder: &Texture2D) {
+        if self.waiting && !self.placeholder {
+            self.material.update_group(
+                context,
+                &[placeholder.group_entry(0), self.sampler.group_entry(1)],
+                &[self.texture.into()],
+            );
+            self.placeholder = true;
+        }
+    }
+}
+
+pub struct ParticleSystem;
+
+impl ParticleSystem {
+    //Moves, ages and spawns the particles of every emitter.
+    pub fn update(world: &mut hecs::World, delta: &Timestep) {
+        let delta = delta.seconds() as f32;
+
+        for (_, (emitter, transform)) in
+            world.query_mut::<(&mut ParticleEmitter2D, Option<&Transform2D>)>()
+        {
+            let origin = transform.map(Transform2D::global_position).unwrap_or(Vec3::ZERO);
+            emitter.update(origin, delta);
+        }
+    }
+}
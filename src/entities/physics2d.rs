@@ -0,0 +1,186 @@
+use glam::Vec2;
+
+use crate::entities::transform2d::Transform2D;
+use crate::utils::Timestep;
+
+//In world units per second, the angular part in radians per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Velocity2D {
+    pub linear: Vec2,
+    pub angular: f32,
+}
+
+impl Velocity2D {
+    pub fn new(linear: Vec2, angular: f32) -> Self {
+        Velocity2D { linear, angular }
+    }
+}
+
+//Constant acceleration of a body in world units per second squared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gravity2D(pub Vec2);
+
+impl Default for Gravity2D {
+    fn default() -> Self {
+        Gravity2D(Vec2::new(0.0, -9.81))
+    }
+}
+
+//Fraction of the velocity lost per second, 0.0 keeps it forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Damping2D {
+    pub linear: f32,
+    pub angular: f32,
+}
+
+//Axis aligned box around the position of the Transform2D, in world units. The scale of the
+//transform is not applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Collider2D {
+    pub half_extents: Vec2,
+    pub offset: Vec2,
+}
+
+impl Collider2D {
+    pub fn new(half_extents: Vec2) -> Self {
+        Collider2D { half_extents, offset: Vec2::ZERO }
+    }
+
+    //position is the one of the transform.
+    pub fn contains(&self, position: Vec2, point: Vec2) -> bool {
+        let local = point - position - self.offset;
+        local.x.abs() <= self.half_extents.x && local.y.abs() <= self.half_extents.y
+    }
+}
+
+//The velocity belongs to whoever drives the body, usually a script. It is integrated,
+//but gravity and damping are left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Kinematic;
+
+pub struct PhysicsSystem;
+
+impl PhysicsSystem {
+    //Semi-implicit Euler, meant to be run with the steps of a FixedTimestep. Transforms are only
+    //moved through their setters, so the renderer picks the change up like any other.
+    pub fn step(world: &mut hecs::World, delta: &Timestep) {
+        let dt = delta.seconds() as f32;
+
+        if dt <= 0.0 {
+            return;
+        }
+
+        let mut query = world.query::<(
+            &mut Transform2D,
+            &mut Velocity2D,
+            Option<&Gravity2D>,
+            Option<&Damping2D>,
+            Option<&Kinematic>,
+        )>();
+
+        for (_, (transform, velocity, gravity, damping, kinematic)) in query.iter() {
+            let (offset, turn) =
+                Self::integrate(velocity, gravity, damping, kinematic.is_some(), dt);
+
+            //Resting bodies keep their transform clean, no uniform write for them.
+            if offset != Vec2::ZERO {
+                transform.add_pos(offset.extend(0.0));
+            }
+
+            if turn != 0.0 {
+                transform.add_rot(turn);
+            }
+        }
+    }
+
+    //Updates the velocity and returns how far the body moves and turns in dt seconds.
+    fn integrate(
+        velocity: &mut Velocity2D, gravity: Option<&Gravity2D>, damping: Option<&Damping2D>,
+        kinematic: bool, dt: f32,
+    ) -> (Vec2, f32) {
+        if !kinematic {
+            if let Some(gravity) = gravity {
+                velocity.linear += gravity.0 * dt;
+            }
+
+            if let Some(damping) = damping {
+                velocity.linear *= 1.0 / (1.0 + damping.linear.max(0.0) * dt);
+                velocity.angular *= 1.0 / (1.0 + damping.angular.max(0.0) * dt);
+            }
+        }
+
+        (velocity.linear * dt, velocity.angular * dt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gravity_is_applied_before_moving() {
+        let mut velocity = Velocity2D::default();
+        let gravity = Gravity2D(Vec2::new(0.0, -10.0));
+
+        let (offset, turn) =
+            PhysicsSystem::integrate(&mut velocity, Some(&gravity), None, false, 0.5);
+
+        assert_eq!(velocity.linear, Vec2::new(0.0, -5.0));
+        assert_eq!(offset, Vec2::new(0.0, -2.5));
+        assert_eq!(turn, 0.0);
+    }
+
+    #[test]
+    fn damping_slows_both_parts() {
+        let mut velocity = Velocity2D::new(Vec2::new(4.0, 0.0), 2.0);
+        let damping = Damping2D { linear: 1.0, angular: 3.0 };
+
+        PhysicsSystem::integrate(&mut velocity, None, Some(&damping), false, 1.0);
+
+        assert_eq!(velocity.linear, Vec2::new(2.0, 0.0));
+        assert_eq!(velocity.angular, 0.5);
+    }
+
+    #[test]
+    fn negative_damping_does_not_speed_up() {
+        let mut velocity = Velocity2D::new(Vec2::ONE, 1.0);
+        let damping = Damping2D { linear: -1.0, angular: -1.0 };
+
+        PhysicsSystem::integrate(&mut velocity, None, Some(&damping), false, 1.0);
+
+        assert_eq!(velocity, Velocity2D::new(Vec2::ONE, 1.0));
+    }
+
+    #[test]
+    fn kinematic_bodies_keep_their_velocity() {
+        let mut velocity = Velocity2D::new(Vec2::new(1.0, 0.0), 1.0);
+        let gravity = Gravity2D::default();
+        let damping = Damping2D { linear: 1.0, angular: 1.0 };
+
+        let (offset, turn) =
+            PhysicsSystem::integrate(&mut velocity, Some(&gravity), Some(&damping), true, 0.5);
+
+        assert_eq!(velocity, Velocity2D::new(Vec2::new(1.0, 0.0), 1.0));
+        assert_eq!(offset, Vec2::new(0.5, 0.0));
+        assert_eq!(turn, 0.5);
+    }
+
+    #[test]
+    fn resting_body_does_not_move() {
+        let mut velocity = Velocity2D::default();
+
+        let (offset, turn) = PhysicsSystem::integrate(&mut velocity, None, None, false, 1.0);
+
+        assert_eq!(offset, Vec2::ZERO);
+        assert_eq!(turn, 0.0);
+    }
+
+    #[test]
+    fn collider_contains_its_edges() {
+        let collider =
+            Collider2D { half_extents: Vec2::new(1.0, 0.5), offset: Vec2::new(1.0, 0.0) };
+
+        assert!(collider.contains(Vec2::ZERO, Vec2::new(2.0, 0.5)));
+        assert!(!collider.contains(Vec2::ZERO, Vec2::new(-0.5, 0.0)));
+    }
+}
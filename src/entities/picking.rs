@@ -0,0 +1,28 @@
+use glam::Vec2;
+
+use crate::entities::layer::{self, OrderInLayer, RenderLayer};
+use crate::entities::sprite::{Hidden, Sprite};
+use crate::entities::transform2d::Transform2D;
+
+//The visible sprite under a world point (see OrthographicCamera::screen_to_world), the one drawn
+//on top if they overlap. Sprites are hit on their whole quad, transparent texels included.
+pub fn pick_sprite(world: &hecs::World, world_pos: Vec2) -> Option<hecs::Entity> {
+    let mut query = world
+        .query::<(&Transform2D, &Sprite, Option<&RenderLayer>, Option<&OrderInLayer>)>()
+        .without::<&Hidden>();
+
+    query
+        .iter()
+        .filter(|(_, (transform, sprite, _, _))| {
+            //The quad spans -1..1 in the space of its transform, whatever part of the texture it shows.
+            let local = transform.world_to_local(world_pos);
+            sprite.is_visible() && local.is_finite() && local.abs().max_element() <= 1.0
+        })
+        //Same order as the renderer: layer, order in layer, then z.
+        .max_by(|(_, (a, _, a_layer, a_order)), (_, (b, _, b_layer, b_order))| {
+            layer::sort_key(*a_layer, *a_order)
+                .cmp(&layer::sort_key(*b_layer, *b_order))
+                .then(a.global_position().z.total_cmp(&b.global_position().z))
+        })
+        .map(|(entity, _)| entity)
+}
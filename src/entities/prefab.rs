@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use glam::{Vec2, Vec3};
+use hashbrown::HashMap;
+use hecs_hierarchy::HierarchyMut;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::assets::Assets;
+use crate::context::VisContext;
+use crate::entities::scene::{SceneComponents, SceneError};
+use crate::entities::transform2d::Transform2D;
+use crate::environment::config::Config;
+
+//Prefab definitions are found by this extension, e.g. data/goblin.prefab.json is the prefab goblin.
+pub const PREFAB_EXTENSION: &str = ".prefab.json";
+
+//An entity template, e.g. Transform2D + Sprite + Animation2D. Assets are referenced by path and
+//requested when the prefab is spawned.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Prefab {
+    #[serde(flatten)]
+    pub components: SceneComponents,
+    //Attached to the spawned entity, their transforms are relative to it.
+    #[serde(default)]
+    pub children: Vec<Prefab>,
+}
+
+//Per instance changes to the prefab.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PrefabOverrides {
+    pub position: Option<Vec3>,
+}
+
+impl PrefabOverrides {
+    pub fn at(position: Vec3) -> Self {
+        PrefabOverrides { position: Some(position) }
+    }
+}
+
+impl Prefab {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Prefab, SceneError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+//Spawns the prefab and its children, returns the root entity. A position override on a prefab
+//without transform gives it one.
+pub fn world_spawn_prefab(
+    world: &mut hecs::World, context: &VisContext, assets: &mut Assets, prefab: &Prefab,
+    overrides: PrefabOverrides,
+) -> hecs::Entity {
+    let root = prefab.components.spawn(world, context, assets);
+
+    if let Some(position) = overrides.position {
+        let moved = world
+            .get::<&mut Transform2D>(root)
+            .map(|mut transform| transform.set_position(position))
+            .is_ok();
+
+        if !moved {
+            let transform = Transform2D::new(context, position, 0.0, Vec2::ONE);
+            let _ = world.insert_one(root, transform);
+        }
+    }
+
+    for child in prefab.children.iter() {
+        let child = world_spawn_prefab(world, context, assets, child, PrefabOverrides::default());
+
+        if let Err(e) = world.attach::<Transform2D>(child, root) {
+            log::warn!("Could not attach a child of the prefab: {:?}", e);
+        }
+    }
+
+    root
+}
+
+//Prefabs by name. Usually kept next to the Worlds of the application.
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Every prefab file in the data folder of the project. Broken files are logged and skipped.
+    pub fn from_config(config: &Config) -> Self {
+        let mut library = PrefabLibrary::new();
+
+        match config.project_config().data_path() {
+            Some(folder) => {
+                let loaded = library.load_folder(&folder);
+                log::info!("Loaded {} prefabs from {}.", loaded, folder.display());
+            }
+            None => log::warn!("The project has no data folder to load prefabs from."),
+        }
+
+        library
+    }
+
+    //Searches subfolders as well. Returns how many prefabs were loaded.
+    pub fn load_folder(&mut self, folder: &Path) -> usize {
+        let Ok(entries) = std::fs::read_dir(folder) else {
+            log::warn!("Could not read the prefab folder {}.", folder.display());
+            return 0;
+        };
+
+        let mut loaded = 0;
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                loaded += self.load_folder(&path);
+                continue;
+            }
+
+            let Some(name) = prefab_name(&path) else {
+                continue;
+            };
+
+            match Prefab::load(&path) {
+                Ok(prefab) => {
+                    if self.prefabs.insert(name.clone(), prefab).is_some() {
+                        log::warn!("Prefab {} is defined twice, using {}.", name, path.display());
+                    }
+                    loaded += 1;
+                }
+                Err(e) => log::error!("Failed to load prefab {}. {}", path.display(), e),
+            }
+        }
+
+        loaded
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, prefab: Prefab) -> Option<Prefab> {
+        self.prefabs.insert(name.into(), prefab)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.prefabs.keys().map(String::as_str)
+    }
+
+    //None if there is no prefab with that name.
+    pub fn spawn(
+        &self, name: &str, world: &mut hecs::World, context: &VisContext, assets: &mut Assets,
+        overrides: PrefabOverrides,
+    ) -> Option<hecs::Entity> {
+        let prefab = self.prefabs.get(name)?;
+        Some(world_spawn_prefab(world, context, assets, prefab, overrides))
+    }
+}
+
+fn prefab_name(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    file_name.strip_suffix(PREFAB_EXTENSION).map(str::to_string)
+}
+
+pub fn prefab_path(folder: &Path, name: &str) -> PathBuf {
+    folder.join(format!("{}{}", name, PREFAB_EXTENSION))
+}
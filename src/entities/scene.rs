@@ -0,0 +1,393 @@
+use std::fmt;
+use std::path::Path;
+
+use glam::{Vec2, Vec3, Vec4};
+use hashbrown::HashMap;
+use hecs_hierarchy::Hierarchy;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::texture::Texture2D;
+use crate::context::VisContext;
+use crate::entities::animation2d::Animation2D;
+use crate::entities::layer::RenderLayer;
+use crate::entities::loader::{AnimationBlueprint, SpriteBlueprint, WorldBlueprint};
+use crate::entities::sprite::Sprite;
+use crate::entities::transform2d::Transform2D;
+use crate::utils::Guid;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneTransform {
+    pub position: [f32; 3],
+    pub rotation: f32,
+    pub scale: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneSprite {
+    //Asset path of the texture.
+    pub texture: String,
+    pub tint: [f32; 4],
+    pub coords: Option<[f32; 8]>,
+    #[serde(default)]
+    pub soft_distance: Option<f32>,
+    //Horizontal and vertical.
+    #[serde(default)]
+    pub flip: (bool, bool),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneAnimation {
+    //Asset path of the strip or sheet.
+    pub frames: String,
+    pub frames_per_second: u32,
+    pub total_frames: u32,
+    pub mirrored: bool,
+    pub looped: bool,
+    //Texture rects (min x, min y, max x, max y) of a sprite sheet clip.
+    #[serde(default)]
+    pub rects: Option<Vec<[f32; 4]>>,
+}
+
+//What an entity of a prefab is made of.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SceneComponents {
+    pub transform: Option<SceneTransform>,
+    pub sprite: Option<SceneSprite>,
+    pub animation: Option<SceneAnimation>,
+    #[serde(default)]
+    pub layer: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnknownWorld(Guid),
+    //The parent index of an entity is out of range or the parents form a cycle.
+    InvalidParent { entity: usize, parent: usize },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "Could not access the scene. {}", e),
+            SceneError::Json(e) => write!(f, "Invalid scene file. {}", e),
+            SceneError::UnknownWorld(guid) => write!(f, "There is no world {:?}.", guid),
+            SceneError::InvalidParent { entity, parent } => {
+                write!(f, "Entity {} has the invalid parent {}.", entity, parent)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(e: serde_json::Error) -> Self {
+        SceneError::Json(e)
+    }
+}
+
+fn texture_path(assets: &Assets, texture: &Ptr<Texture2D>) -> Option<String> {
+    assets.asset_path(texture.inner()).cloned()
+}
+
+impl SceneComponents {
+    //Textures are only requested, sprites show the loading texture until they arrived.
+    pub fn spawn(
+        &self, world: &mut hecs::World, context: &VisContext, assets: &mut Assets,
+    ) -> hecs::Entity {
+        let entity = world.spawn(());
+
+        if let Some(transform) = &self.transform {
+            let transform = Transform2D::new(
+                context,
+                Vec3::from_array(transform.position),
+                transform.rotation,
+                Vec2::from_array(transform.scale),
+            );
+            let _ = world.insert_one(entity, transform);
+        }
+
+        if let Some(sprite) = &self.sprite {
+            let texture = assets.request_asset(&sprite.texture, 0);
+            let coords = sprite.coords.as_ref().map(|coords| coords.as_slice());
+
+            let mut fanta =
+                Sprite::new(context, texture, Vec4::from_array(sprite.tint), coords, None);
+            fanta.set_soft(context, sprite.soft_distance);
+            fanta.set_flip(context, sprite.flip.0, sprite.flip.1);
+            let _ = world.insert_one(entity, fanta);
+        }
+
+        if let Some(animation) = &self.animation {
+            let frames = assets.request_asset(&animation.frames, 0);
+            let mut animation2d = Animation2D::new(
+                frames,
+                animation.frames_per_second,
+                animation.total_frames,
+                animation.mirrored,
+                animation.looped,
+            );
+
+            if let Some(rects) = &animation.rects {
+                animation2d = animation2d.with_rects(
+                    rects.iter().map(|r| (Vec2::new(r[0], r[1]), Vec2::new(r[2], r[3]))).collect(),
+                );
+            }
+
+            let _ = world.insert_one(entity, animation2d);
+        }
+
+        if let Some(layer) = self.layer {
+            let _ = world.insert_one(entity, RenderLayer(layer));
+        }
+
+        entity
+    }
+}
+
+//Scene files are WorldBlueprints. They are read like any other, with
+//WorldBlueprint::from_scene_file or a WorldLoadHandle.
+impl WorldBlueprint {
+    //Entities without a transform, sprite or animation are left out. What can't be described
+    //(custom materials, textures without a path) is skipped with a warning.
+    pub fn from_world(world: &hecs::World, assets: &Assets) -> WorldBlueprint {
+        let mut indices = HashMap::new();
+        let mut saved = Vec::new();
+
+        for entity in world.iter() {
+            let saveable = entity.has::<Transform2D>()
+                || entity.has::<Sprite<'static>>()
+                || entity.has::<Animation2D>();
+
+            if saveable {
+                indices.insert(entity.entity(), saved.len());
+                saved.push(entity.entity());
+            }
+        }
+
+        let mut blueprint = WorldBlueprint::default();
+
+        for entity in saved.iter() {
+            let entity = world.entity(*entity).unwrap();
+            let mut skipped = Vec::new();
+
+            let (position, rotation, scale) = match entity.get::<&Transform2D>() {
+                Some(transform) => (
+                    transform.position().to_array(),
+                    transform.rotation(),
+                    transform.scale().to_array(),
+                ),
+                None => ([0.0; 3], 0.0, [1.0; 2]),
+            };
+
+            let mut lowered = SpriteBlueprint {
+                position,
+                rotation,
+                scale,
+                texture: None,
+                tint: [1.0; 4],
+                coords: None,
+                layer: entity.get::<&RenderLayer>().map_or(0, |layer| layer.0),
+                soft_distance: None,
+                flip: (false, false),
+                animation: None,
+                parent: world
+                    .parent::<Transform2D>(entity.entity())
+                    .ok()
+                    .and_then(|parent| indices.get(&parent).copied()),
+            };
+
+            if let Some(sprite) = entity.get::<&Sprite<'static>>() {
+                match texture_path(assets, sprite.texture()) {
+                    _ if sprite.is_custom() => skipped.push("sprite with a custom material"),
+                    Some(texture) => {
+                        lowered.texture = Some(blueprint.texture_index(texture));
+                        lowered.tint = sprite.tint().to_array();
+                        lowered.coords = sprite.coords().copied();
+                        lowered.soft_distance = sprite.soft_distance();
+                        lowered.flip = sprite.flip();
+                    }
+                    None => skipped.push("sprite with a texture without path"),
+                }
+            }
+
+            if let Some(animation) = entity.get::<&Animation2D>() {
+                match texture_path(assets, animation.frames()) {
+                    Some(frames) => {
+                        lowered.animation = Some(AnimationBlueprint {
+                            frames: blueprint.texture_index(frames),
+                            frames_per_second: animation.frames_per_second(),
+                            total_frames: animation.total_frames(),
+                            mirrored: animation.mirrored(),
+                            looped: animation.looped(),
+                            rects: animation.rects().map(|rects| {
+                                rects
+                                    .iter()
+                                    .map(|(min, max)| [min.x, min.y, max.x, max.y])
+                                    .collect()
+                            }),
+                        })
+                    }
+                    None => skipped.push("animation with frames without path"),
+                }
+            }
+
+            if !skipped.is_empty() {
+                log::warn!(
+                    "Entity {:?} is saved without its {}.",
+                    entity.entity(),
+                    skipped.join(", ")
+                );
+            }
+
+            blueprint.sprites.push(lowered);
+        }
+
+        blueprint
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    //Every parent index is in range and there are no cycles.
+    pub(crate) fn validate(&self) -> Result<(), SceneError> {
+        for (entity, sprite) in self.sprites.iter().enumerate() {
+            let Some(parent) = sprite.parent else {
+                continue;
+            };
+
+            //Walking up more often than there are sprites means there is a cycle.
+            let mut current = Some(entity);
+            for _ in 0..=self.sprites.len() {
+                current = current.and_then(|index| self.sprites.get(index)?.parent);
+
+                if current.is_some_and(|index| index >= self.sprites.len()) {
+                    return Err(SceneError::InvalidParent { entity, parent });
+                }
+            }
+
+            if current.is_some() {
+                return Err(SceneError::InvalidParent { entity, parent });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(position: [f32; 3], parent: Option<usize>) -> SpriteBlueprint {
+        SpriteBlueprint {
+            position,
+            rotation: 0.5,
+            scale: [1.0, 2.0],
+            texture: None,
+            tint: [1.0; 4],
+            coords: None,
+            layer: 0,
+            soft_distance: None,
+            flip: (false, false),
+            animation: None,
+            parent,
+        }
+    }
+
+    //Like the two_dim example: a sheet animated player with a child on top of a background.
+    fn two_dim() -> WorldBlueprint {
+        let mut blueprint = WorldBlueprint::default();
+
+        let mut background = sprite([0.0, 0.0, 0.0], None);
+        background.texture = Some(blueprint.texture_index("textures/background.png"));
+        background.coords = Some([0.0, 0.0, 4.0, 0.0, 4.0, 4.0, 0.0, 4.0]);
+        background.soft_distance = Some(0.1);
+        background.flip = (true, false);
+        background.layer = -1;
+
+        let mut player = sprite([0.25, -0.5, 0.1], None);
+        player.animation = Some(AnimationBlueprint {
+            frames: blueprint.texture_index("textures/player.png"),
+            frames_per_second: 12,
+            total_frames: 2,
+            mirrored: true,
+            looped: true,
+            rects: Some(vec![[0.0, 0.0, 0.5, 1.0], [0.5, 0.0, 1.0, 1.0]]),
+        });
+
+        //The child comes before its parent, like entities spawned later and attached.
+        blueprint.sprites = vec![sprite([0.0, 0.2, 0.0], Some(2)), background, player];
+        blueprint
+    }
+
+    #[test]
+    fn saved_scenes_load_as_blueprints() {
+        let blueprint = two_dim();
+        let path =
+            std::env::temp_dir().join(format!("rustybear_scene_{}.json", std::process::id()));
+
+        blueprint.save(&path).unwrap();
+        let loaded = WorldBlueprint::from_scene_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&blueprint).unwrap()
+        );
+    }
+
+    #[test]
+    fn scenes_without_hierarchy_or_animations_still_load() {
+        let json = r#"{
+            "textures": ["textures/tree.png"],
+            "sprites": [
+                { "position": [0, 0, 0], "rotation": 0, "scale": [1, 1], "texture": 0,
+                  "tint": [1, 1, 1, 1], "coords": null }
+            ]
+        }"#;
+
+        let blueprint: WorldBlueprint = serde_json::from_str(json).unwrap();
+        let sprite = &blueprint.sprites[0];
+
+        assert_eq!(sprite.texture, Some(0));
+        assert!(sprite.parent.is_none() && sprite.animation.is_none());
+        assert_eq!((sprite.layer, sprite.flip), (0, (false, false)));
+    }
+
+    #[test]
+    fn parent_out_of_range_is_rejected() {
+        let blueprint = WorldBlueprint {
+            sprites: vec![sprite([0.0; 3], None), sprite([0.0; 3], Some(5))],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            blueprint.validate(),
+            Err(SceneError::InvalidParent { entity: 1, parent: 5 })
+        ));
+    }
+
+    #[test]
+    fn parent_cycle_is_rejected() {
+        let blueprint = WorldBlueprint {
+            sprites: vec![sprite([0.0; 3], Some(1)), sprite([0.0; 3], Some(0))],
+            ..Default::default()
+        };
+
+        assert!(matches!(blueprint.validate(), Err(SceneError::InvalidParent { .. })));
+    }
+}
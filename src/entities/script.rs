@@ -1,19 +1,96 @@
-use std::cell::Ref;
-
 use hashbrown::HashMap;
 use hecs::Entity;
 
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::audio::AudioClip;
+use crate::entities::entities::{Despawn, Worlds};
+use crate::entities::timer::TimerFired;
+use crate::event::Event;
 use crate::input::InputState;
+use crate::sound::{AudioEngine, PlayOptions};
+use crate::utils::Guid;
 use crate::{context::VisContext, utils::Timestep};
 
+//Everything a script gets in tick besides its entity and the world.
+pub struct ScriptContext<'a> {
+    pub context: &'a VisContext,
+    pub delta: &'a Timestep,
+    pub input: &'a InputState,
+    pub assets: &'a mut Assets,
+    pub commands: &'a mut Commands,
+    //What the TimerSystem fired this frame, for every entity.
+    pub timers: &'a [TimerFired],
+}
+
+impl ScriptContext<'_> {
+    //Tags of the timers of the entity that fired this frame, once per fire.
+    pub fn timers_fired(&self, entity: hecs::Entity) -> impl Iterator<Item = u32> + '_ {
+        self.timers.iter().filter(move |fired| fired.entity == entity).map(|fired| fired.tag)
+    }
+}
+
+//Changes a script can't make itself while the scripts are ticking. Applied by Scripts::tick
+//once every script ran.
+pub struct Commands {
+    spawns: hecs::CommandBuffer,
+    despawns: Vec<Entity>,
+    attached: Vec<(ScriptHandle, Entity)>,
+    sounds: Vec<(Ptr<AudioClip>, PlayOptions)>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands {
+            spawns: hecs::CommandBuffer::new(),
+            despawns: Vec::new(),
+            attached: Vec::new(),
+            sounds: Vec::new(),
+        }
+    }
+
+    //Spawn into the world directly instead if the entity is needed right away, e.g. to attach a script.
+    pub fn spawn(&mut self, bundle: impl hecs::DynamicBundle) {
+        self.spawns.spawn(bundle);
+    }
+
+    //Marks the entity with Despawn, Worlds::despawn_marked runs on_destroy and removes it.
+    pub fn despawn(&mut self, entity: Entity) {
+        self.despawns.push(entity);
+    }
+
+    pub fn attach_script(&mut self, script: ScriptHandle, entity: Entity) {
+        self.attached.push((script, entity));
+    }
+
+    //A one-shot effect, played by Scripts::play_sounds. There is no handle to stop it, use an
+    //AudioSource for that.
+    pub fn play_sound(&mut self, clip: Ptr<AudioClip>, options: PlayOptions) {
+        self.sounds.push((clip, options));
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub trait Scriptable {
     fn on_spawn(&mut self, context: &VisContext, entity: hecs::Entity, world: &mut hecs::World);
-    fn tick(
-        &mut self, context: &VisContext, entity: hecs::Entity, delta: &Timestep,
-        world: &mut hecs::World, input_state: &Ref<InputState>,
-        new_scripts: &mut Vec<(ScriptHandle, Entity)>,
-    );
+    fn tick(&mut self, script: &mut ScriptContext, entity: hecs::Entity, world: &mut hecs::World);
     fn on_destroy(&mut self, context: &VisContext, entity: hecs::Entity, world: &mut hecs::World);
+    //Worlds::switch_to moved away from the world of the entity. The world stays, on_spawn runs
+    //again once it is switched back to.
+    fn on_world_exit(
+        &mut self, _context: &VisContext, _entity: hecs::Entity, _world: &mut hecs::World,
+    ) {
+    }
+    //Through Scripts::dispatch_event. Returning true consumes the event, like an EventSubscriber.
+    fn on_event(
+        &mut self, _event: &Event, _entity: hecs::Entity, _world: &mut hecs::World,
+    ) -> bool {
+        false
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -21,74 +98,339 @@ pub struct ScriptHandle {
     id: u64,
 }
 
+type ScriptFactory = Box<dyn Fn() -> Box<dyn Scriptable>>;
+
+//One added or registered script. Entities with an instance own a clone of the prototype, the
+//others all run the shared script. hecs entities are only unique within their world, so every
+//attachment keeps the Guid of the world the entity lives in.
+struct Script {
+    prototype: Option<ScriptFactory>,
+    shared: Option<Box<dyn Scriptable>>,
+    //Everything attached, in the order it was attached.
+    entities: Vec<(Guid, hecs::Entity)>,
+    instances: HashMap<(Guid, hecs::Entity), Box<dyn Scriptable>>,
+}
+
+impl Script {
+    fn contains(&self, world: Guid, entity: hecs::Entity) -> bool {
+        self.entities.contains(&(world, entity))
+    }
+
+    fn for_each(&mut self, mut f: impl FnMut(&mut dyn Scriptable, Guid, hecs::Entity)) {
+        for (world, entity) in self.entities.iter() {
+            if let Some(instance) = self.instances.get_mut(&(*world, *entity)) {
+                f(instance.as_mut(), *world, *entity);
+            } else if let Some(shared) = &mut self.shared {
+                f(shared.as_mut(), *world, *entity);
+            }
+        }
+    }
+
+    //Only the entities attached in that world.
+    fn for_each_in(&mut self, world: Guid, mut f: impl FnMut(&mut dyn Scriptable, hecs::Entity)) {
+        self.for_each(|script, guid, entity| {
+            if guid == world {
+                f(script, entity);
+            }
+        });
+    }
+
+    fn retain(&mut self, f: impl Fn(Guid, hecs::Entity) -> bool) {
+        self.entities.retain(|(world, entity)| f(*world, *entity));
+        self.instances.retain(|(world, entity), _| f(*world, *entity));
+    }
+}
+
 pub struct Scripts {
     ids: HashMap<u64, u64>,
-    scripts: Vec<(Box<dyn Scriptable>, Vec<hecs::Entity>)>,
+    scripts: Vec<Script>,
     id_generator: u64,
+    sounds: Vec<(Ptr<AudioClip>, PlayOptions)>,
 }
 
 impl Scripts {
     pub fn new() -> Self {
-        Self { ids: HashMap::new(), scripts: Vec::new(), id_generator: 0 }
+        Self { ids: HashMap::new(), scripts: Vec::new(), id_generator: 0, sounds: Vec::new() }
     }
 
-    pub fn add_script(&mut self, script: Box<dyn Scriptable>) -> ScriptHandle {
+    fn insert(&mut self, script: Script) -> ScriptHandle {
         self.id_generator += 1;
         let id = self.id_generator;
-        self.scripts.push((script, Vec::new()));
+        self.scripts.push(script);
         self.ids.insert(id, self.scripts.len() as u64 - 1);
         ScriptHandle { id }
     }
 
-    pub fn attach(&mut self, script: ScriptHandle, entity: hecs::Entity) {
-        if let Some(index) = self.ids.get(&script.id) {
-            self.scripts[*index as usize].1.push(entity);
+    //Shared by every entity it is attached to, fields and all.
+    pub fn add_script(&mut self, script: Box<dyn Scriptable>) -> ScriptHandle {
+        self.insert(Script {
+            prototype: None,
+            shared: Some(script),
+            entities: Vec::new(),
+            instances: HashMap::new(),
+        })
+    }
+
+    //Every entity attached to it gets its own clone of the prototype.
+    pub fn register<S: Scriptable + Clone + 'static>(&mut self, prototype: S) -> ScriptHandle {
+        self.insert(Script {
+            prototype: Some(Box::new(move || Box::new(prototype.clone()) as Box<dyn Scriptable>)),
+            shared: None,
+            entities: Vec::new(),
+            instances: HashMap::new(),
+        })
+    }
+
+    //A fresh instance for registered scripts, added ones are shared. world is the Guid the entity
+    //lives in, see Worlds::add_world.
+    pub fn attach(&mut self, script: ScriptHandle, world: Guid, entity: hecs::Entity) {
+        let Some(index) = self.ids.get(&script.id) else {
+            return;
+        };
+
+        let script = &mut self.scripts[*index as usize];
+
+        if script.contains(world, entity) {
+            return;
+        }
+
+        if let Some(prototype) = &script.prototype {
+            script.instances.insert((world, entity), prototype());
         }
+
+        script.entities.push((world, entity));
     }
 
-    pub fn detach(&mut self, script: ScriptHandle, entity: hecs::Entity) {
+    //For scripts that coordinate across their entities. Registered ones share one extra clone of the
+    //prototype between everything attached this way.
+    pub fn attach_shared(&mut self, script: ScriptHandle, world: Guid, entity: hecs::Entity) {
+        let Some(index) = self.ids.get(&script.id) else {
+            return;
+        };
+
+        let script = &mut self.scripts[*index as usize];
+
+        if script.shared.is_none() {
+            script.shared = script.prototype.as_ref().map(|prototype| prototype());
+        }
+
+        script.instances.remove(&(world, entity));
+
+        if !script.contains(world, entity) {
+            script.entities.push((world, entity));
+        }
+    }
+
+    //Runs on_destroy for the attached entities still living in their world and drops the script.
+    //The handle is dead afterwards, the others stay valid.
+    pub fn remove_script(
+        &mut self, context: &VisContext, handle: ScriptHandle, worlds: &mut Worlds,
+    ) -> bool {
+        let Some(index) = self.ids.remove(&handle.id) else {
+            return false;
+        };
+
+        let mut script = self.scripts.swap_remove(index as usize);
+
+        //The last script moved into the gap.
+        let moved = self.scripts.len() as u64;
+        if let Some(moved_index) = self.ids.values_mut().find(|i| **i == moved) {
+            *moved_index = index;
+        }
+
+        script.for_each(|script, guid, entity| {
+            if let Some(world) = worlds.get_world_mut(guid).filter(|world| world.contains(entity)) {
+                script.on_destroy(context, entity, world);
+            }
+        });
+
+        true
+    }
+
+    //Detaches the entity from every script without running on_destroy, e.g. after despawning it by hand.
+    pub fn detach_all(&mut self, world: Guid, entity: hecs::Entity) {
+        for script in self.scripts.iter_mut() {
+            script.retain(|w, e| (w, e) != (world, entity));
+        }
+    }
+
+    //The entities attached to the script and the worlds they live in.
+    pub fn attached(&self, handle: ScriptHandle) -> &[(Guid, hecs::Entity)] {
+        match self.ids.get(&handle.id) {
+            Some(index) => &self.scripts[*index as usize].entities,
+            None => &[],
+        }
+    }
+
+    //Drops the instance of the entity, if it had one.
+    pub fn detach(&mut self, script: ScriptHandle, world: Guid, entity: hecs::Entity) {
         if let Some(index) = self.ids.get(&script.id) {
-            let script = &mut self.scripts[*index as usize];
-            script.1.retain(|e| *e != entity);
+            self.scripts[*index as usize].retain(|w, e| (w, e) != (world, entity));
         }
     }
 
+    //Every script the entity is attached to.
+    pub fn handles(&self, world: Guid, entity: hecs::Entity) -> Vec<ScriptHandle> {
+        self.ids
+            .iter()
+            .filter(|(_, index)| self.scripts[**index as usize].contains(world, entity))
+            .map(|(id, _)| ScriptHandle { id: *id })
+            .collect()
+    }
+
     pub fn on_spawn(
-        &mut self, context: &VisContext, target: hecs::Entity, world: &mut hecs::World,
+        &mut self, context: &VisContext, guid: Guid, target: hecs::Entity, world: &mut hecs::World,
     ) {
-        for (script, entities) in self.scripts.iter_mut() {
-            for entity in entities.iter() {
-                if *entity == target {
-                    script.on_spawn(context, *entity, world);
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if entity == target {
+                    script.on_spawn(context, entity, world);
                 }
-            }
+            });
         }
     }
 
+    //Runs the scripts of the current world. timers is what the TimerSystem fired this frame,
+    //empty if the game has none.
     pub fn tick(
-        &mut self, context: &VisContext, delta: &Timestep, world: &mut hecs::World,
-        input_state: &Ref<InputState>,
+        &mut self, context: &VisContext, delta: &Timestep, worlds: &mut Worlds,
+        input_state: &InputState, assets: &mut Assets, timers: &[TimerFired],
     ) {
-        let mut new_scripts: Vec<(ScriptHandle, Entity)> = Vec::new();
-        for (script, entities) in self.scripts.iter_mut() {
-            for entity in entities.iter() {
-                script.tick(context, *entity, delta, world, input_state, &mut new_scripts);
+        let Some(guid) = worlds.current() else {
+            return;
+        };
+
+        let Some(world) = worlds.get_world_mut(guid) else {
+            return;
+        };
+
+        let mut commands = Commands::new();
+        let mut script_context = ScriptContext {
+            context,
+            delta,
+            input: input_state,
+            assets,
+            commands: &mut commands,
+            timers,
+        };
+
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                script.tick(&mut script_context, entity, world)
+            });
+        }
+
+        self.apply(commands, guid, world);
+    }
+
+    fn apply(&mut self, mut commands: Commands, guid: Guid, world: &mut hecs::World) {
+        commands.spawns.run_on(world);
+
+        for entity in commands.despawns {
+            let _ = world.insert_one(entity, Despawn);
+        }
+
+        for (script, entity) in commands.attached {
+            self.attach(script, guid, entity);
+        }
+
+        self.sounds.append(&mut commands.sounds);
+    }
+
+    //The sounds the scripts asked for since the last call.
+    pub fn take_sounds(&mut self) -> Vec<(Ptr<AudioClip>, PlayOptions)> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    //Plays what the scripts asked for since the last call, after Scripts::tick.
+    pub fn play_sounds(&mut self, assets: &Assets, audio: &mut AudioEngine) {
+        for (clip, options) in self.take_sounds() {
+            audio.play_asset(assets, &clip, options);
+        }
+    }
+
+    //Call it from Application::on_event. Stops at the first script consuming the event, only the
+    //entities of the current world see it.
+    pub fn dispatch_event(&mut self, event: &Event, worlds: &mut Worlds) -> bool {
+        let Some(guid) = worlds.current() else {
+            return false;
+        };
+
+        let Some(world) = worlds.get_world_mut(guid) else {
+            return false;
+        };
+
+        let mut consumed = false;
+
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if !consumed && world.contains(entity) {
+                    consumed = script.on_event(event, entity, world);
+                }
+            });
+
+            if consumed {
+                break;
             }
         }
-        for (s, e) in new_scripts.into_iter() {
-            self.attach(s, e);
+
+        consumed
+    }
+
+    //Scripts are shared by all worlds, these only run for the entities attached in the world.
+    pub fn enter_world(&mut self, context: &VisContext, guid: Guid, world: &mut hecs::World) {
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if world.contains(entity) {
+                    script.on_spawn(context, entity, world);
+                }
+            });
+        }
+    }
+
+    pub fn exit_world(&mut self, context: &VisContext, guid: Guid, world: &mut hecs::World) {
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if world.contains(entity) {
+                    script.on_world_exit(context, entity, world);
+                }
+            });
+        }
+    }
+
+    //Calls on_destroy for the entities of a world that goes away and detaches them.
+    pub fn destroy_world(&mut self, context: &VisContext, guid: Guid, world: &mut hecs::World) {
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if world.contains(entity) {
+                    script.on_destroy(context, entity, world);
+                }
+            });
+
+            script.retain(|world, _| world != guid);
+        }
+    }
+
+    //Calls on_destroy for every attached entity in its own world, e.g. when the game shuts down.
+    pub fn destroy_all(&mut self, context: &VisContext, worlds: &mut Worlds) {
+        for script in self.scripts.iter_mut() {
+            script.for_each(|script, guid, entity| {
+                if let Some(world) = worlds.get_world_mut(guid) {
+                    script.on_destroy(context, entity, world);
+                }
+            });
         }
     }
 
     pub fn on_destroy(
-        &mut self, context: &VisContext, target: hecs::Entity, world: &mut hecs::World,
+        &mut self, context: &VisContext, guid: Guid, target: hecs::Entity, world: &mut hecs::World,
     ) {
-        for (script, entities) in self.scripts.iter_mut() {
-            for entity in entities.iter() {
-                if *entity == target {
-                    script.on_destroy(context, *entity, world);
+        for script in self.scripts.iter_mut() {
+            script.for_each_in(guid, |script, entity| {
+                if entity == target {
+                    script.on_destroy(context, entity, world);
                 }
-            }
+            });
         }
     }
 }
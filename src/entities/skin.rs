@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::texture::Texture2D;
+use crate::entities::animation2d::Animation2D;
+use crate::entities::sprite::Sprite;
+
+//Named texture variants of the same sprite (e.g. palette swaps). All variants must share the frame layout,
+//so animations keep working when the skin changes.
+#[derive(Default, Clone)]
+pub struct SkinSet {
+    skins: BTreeMap<String, Ptr<Texture2D>>,
+    current: Option<String>,
+}
+
+impl SkinSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, texture: Ptr<Texture2D>) {
+        self.skins.insert(name.into(), texture);
+    }
+
+    //Requests every variant up front, so switching later does not wait for the loader.
+    pub fn preload(assets: &mut Assets, variants: &[(&str, &str)], priority: usize) -> Self {
+        let mut set = Self::new();
+
+        for (name, path) in variants {
+            set.add(*name, assets.request_asset(*path, priority));
+        }
+
+        set
+    }
+
+    //Only the texture pointer changes. The current frame, its uvs and the mirroring stay as they are.
+    pub fn set_skin(
+        &mut self, name: &str, sprite: &mut Sprite, animation: Option<&mut Animation2D>,
+    ) -> bool {
+        let Some(texture) = self.skins.get(name) else {
+            log::warn!("Unknown skin: {}", name);
+            return false;
+        };
+
+        sprite.set_texture(*texture);
+
+        if let Some(animation) = animation {
+            animation.set_frames(*texture);
+        }
+
+        self.current = Some(name.to_string());
+        true
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&Ptr<Texture2D>> {
+        self.skins.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.skins.keys().map(|name| name.as_str())
+    }
+
+    //Skin picker for debug windows. Returns the newly selected skin, apply it with set_skin.
+    pub fn selector(&self, ui: &mut egui::Ui) -> Option<String> {
+        let mut selected = None;
+
+        egui::ComboBox::from_label("Skin").selected_text(self.current().unwrap_or("-")).show_ui(
+            ui,
+            |ui| {
+                for name in self.names() {
+                    if ui.selectable_label(self.current() == Some(name), name).clicked() {
+                        selected = Some(name.to_string());
+                    }
+                }
+            },
+        );
+
+        selected
+    }
+}
@@ -6,27 +6,126 @@ use crate::context::VisContext;
 
 use crate::render::material::GenericMaterial;
 use crate::render::mesh::GenericMesh;
-use crate::render::types::{BindGroupEntry, Vertex2D};
+use crate::render::types::{
+    BindGroupEntry, FragmentShader, MaterialLayout, PipelineBaseConfig, SpriteColorUniform,
+    Vertex2D, VertexShader,
+};
 use glam::{Vec2, Vec4};
 use std::mem::size_of;
 
 pub struct Sprite<'a> {
     texture: Ptr<Texture2D>,
     tint: Vec4,
+    opacity: f32,
     sampler: Sampler,
     buffer: UniformBuffer,
     material: GenericMaterial,
     mesh: GenericMesh<'a>,
     waiting: bool,
+    placeholder: bool,
+    generation: u64,
+    nine_slice: Option<NineSlice>,
+    soft_distance: Option<f32>,
+    //None for the whole texture. Unflipped, the flips are applied when the mesh is written.
+    coords: Option<[f32; 8]>,
+    flip: (bool, bool),
+    //Set by Animation2D, flips x on top of flip.
+    mirrored: bool,
+    visible: bool,
+    //Of the bound texture, None while it is loading.
+    texture_size: Option<Vec2>,
+    translucent_texture: bool,
+}
+
+//Texture coordinates of the whole texture, in the vertex order of the quad.
+const FULL_COORDS: [f32; 8] = [0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+
+//Hides the sprite of the entity, same as Sprite::set_visible(false) but without touching the sprite.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Hidden;
+
+//Borders are the insets in texture pixels (left, top, right, bottom).
+struct NineSlice {
+    borders: Vec4,
+    //Drawn size in pixels, the texture size if not set.
+    size: Option<Vec2>,
+    texture_size: Option<Vec2>,
+}
+
+impl NineSlice {
+    //4x4 grid, row major from the bottom. Positions span -1..1 like the plain quad.
+    fn vertices(&self) -> Vec<Vertex2D> {
+        let plain =
+            || Self::grid([-1.0, -1.0, 1.0, 1.0], [-1.0, -1.0, 1.0, 1.0], [0.0; 4], [0.0; 4]);
+
+        let (Some(texture_size), size) = (self.texture_size, self.size) else {
+            return plain();
+        };
+
+        let size = size.unwrap_or(texture_size);
+
+        //Nothing to slice, the divisions below would give NaN.
+        if size.min_element() <= 0.0 || texture_size.min_element() <= 0.0 {
+            return plain();
+        }
+        let (left, top, right, bottom) =
+            (self.borders.x, self.borders.y, self.borders.z, self.borders.w);
+
+        //Borders wider than the sprite shrink evenly, so opposite edges never overlap.
+        let fit_x = (size.x / (left + right)).min(1.0);
+        let fit_y = (size.y / (top + bottom)).min(1.0);
+
+        let xs =
+            [-1.0, -1.0 + 2.0 * left * fit_x / size.x, 1.0 - 2.0 * right * fit_x / size.x, 1.0];
+        let ys =
+            [-1.0, -1.0 + 2.0 * bottom * fit_y / size.y, 1.0 - 2.0 * top * fit_y / size.y, 1.0];
+        let us = [0.0, left / texture_size.x, 1.0 - right / texture_size.x, 1.0];
+        let vs = [1.0, 1.0 - bottom / texture_size.y, top / texture_size.y, 0.0];
+
+        Self::grid(xs, ys, us, vs)
+    }
+
+    fn grid(xs: [f32; 4], ys: [f32; 4], us: [f32; 4], vs: [f32; 4]) -> Vec<Vertex2D> {
+        (0..16)
+            .map(|i| Vertex2D {
+                position: [xs[i % 4], ys[i / 4], -0.0],
+                texture_coords: [us[i % 4], vs[i / 4]],
+            })
+            .collect()
+    }
+
+    fn indices() -> Vec<u16> {
+        let mut indices = Vec::with_capacity(54);
+
+        for row in 0..3 {
+            for column in 0..3 {
+                let bottom_left = row * 4 + column;
+                let (bottom_right, top_left, top_right) =
+                    (bottom_left + 1, bottom_left + 4, bottom_left + 5);
+                indices.extend_from_slice(&[
+                    bottom_left,
+                    top_right,
+                    top_left,
+                    bottom_left,
+                    bottom_right,
+                    top_right,
+                ]);
+            }
+        }
+
+        indices
+    }
 }
 
 impl<'a> Sprite<'a> {
+    #[track_caller]
     pub fn new_custom(
         context: &VisContext, vertex: Ptr<Shader>, fragment: Ptr<Shader>, texture: Ptr<Texture2D>,
         tint: Vec4, coords: Option<&[f32]>, sampler: Option<Sampler>,
+        base_config: Option<PipelineBaseConfig>,
     ) -> Self {
-        let mut buffer = UniformBuffer::new(context, size_of::<[f32; 4]>());
-        buffer.update_buffer(context, bytemuck::cast_slice(&tint.to_array()));
+        let mut buffer = UniformBuffer::new(context, size_of::<SpriteColorUniform>());
+        buffer.update_buffer(context, bytemuck::bytes_of(&Self::color_data(tint, 1.0, None)));
         let sampler = sampler.unwrap_or(Sampler::two_dim(context));
 
         let vertices = if let Some(coords) = coords {
@@ -61,11 +160,97 @@ impl<'a> Sprite<'a> {
                 Texture2D::error_texture(context).group_entry(1),
                 sampler.group_entry(2),
             ],
+            &[texture.into()],
+            base_config,
         );
 
-        Self { texture, sampler, tint, buffer, material, mesh, waiting: true }
+        Self {
+            texture,
+            sampler,
+            tint,
+            opacity: 1.0,
+            buffer,
+            material,
+            mesh,
+            waiting: true,
+            placeholder: false,
+            generation: 0,
+            nine_slice: None,
+            soft_distance: None,
+            coords: coords.and_then(|coords| coords.get(..8)?.try_into().ok()),
+            flip: (false, false),
+            mirrored: false,
+            visible: true,
+            texture_size: None,
+            translucent_texture: false,
+        }
     }
 
+    //Fades out over distance (in world z) towards whatever is behind it, instead of cutting
+    //hard into it. Drawn in the translucent pass. Looks like a plain sprite where the depth
+    //buffer can't be sampled.
+    #[track_caller]
+    pub fn new_soft(
+        context: &VisContext, texture: Ptr<Texture2D>, tint: Vec4, distance: f32,
+    ) -> Self {
+        let mut sprite = Self::new(context, texture, tint, None, None);
+        sprite.set_soft(context, Some(distance));
+        sprite
+    }
+
+    //With a custom fragment shader, it has to read the depth itself. See soft_sprite.wgsl.
+    pub fn set_soft(&mut self, context: &VisContext, distance: Option<f32>) {
+        if self.soft_distance != distance {
+            self.soft_distance = distance;
+            self.write_color(context);
+        }
+    }
+
+    pub fn soft_distance(&self) -> Option<f32> {
+        self.soft_distance
+    }
+
+    //Corners keep their size, edges stretch along one axis and the center along both.
+    //Borders are the insets in texture pixels (left, top, right, bottom). The slices are laid out
+    //for the texture size until set_size is called with the drawn size.
+    #[track_caller]
+    pub fn new_nine_slice(
+        context: &VisContext, texture: Ptr<Texture2D>, tint: Vec4, borders: Vec4,
+    ) -> Self {
+        let mut sprite = Self::new(context, texture, tint, None, None);
+        let nine_slice = NineSlice { borders, size: None, texture_size: None };
+
+        let vertices =
+            Vertices::new(context, bytemuck::cast_slice(&nine_slice.vertices()), Vertex2D::LAYOUT);
+        let indices = Indices::new(
+            context,
+            bytemuck::cast_slice(&NineSlice::indices()),
+            wgpu::IndexFormat::Uint16,
+        );
+
+        sprite.mesh = GenericMesh::new(vertices, indices, 54);
+        sprite.nine_slice = Some(nine_slice);
+        sprite
+    }
+
+    //The size the nine-slice sprite is drawn at, in pixels. Call it when the transform scale
+    //changes, the corners would be stretched otherwise. Does nothing for plain sprites.
+    pub fn set_size(&mut self, context: &VisContext, size: Vec2) {
+        if let Some(nine_slice) = &mut self.nine_slice {
+            if nine_slice.size != Some(size) {
+                nine_slice.size = Some(size);
+                self.write_nine_slice(context);
+            }
+        }
+    }
+
+    fn write_nine_slice(&mut self, context: &VisContext) {
+        if let Some(nine_slice) = &self.nine_slice {
+            self.mesh.update_vertices(context, bytemuck::cast_slice(&nine_slice.vertices()));
+        }
+    }
+
+    #[track_caller]
     pub fn new(
         context: &VisContext, texture: Ptr<Texture2D>, tint: Vec4, coords: Option<&[f32]>,
         sampler: Option<Sampler>,
@@ -78,26 +263,70 @@ impl<'a> Sprite<'a> {
             tint,
             coords,
             sampler,
+            None,
         )
     }
 
+    //Not for nine-slice sprites, their coordinates come from the borders. The flip stays.
     pub fn set_coords(&mut self, context: &VisContext, coords: &[f32]) {
-        let vertices = vec![
-            Vertex2D { position: [-1.0, -1.0, -0.0], texture_coords: [coords[0], coords[1]] },
-            Vertex2D { position: [1.0, 1.0, -0.0], texture_coords: [coords[2], coords[3]] },
-            Vertex2D { position: [-1.0, 1.0, -0.0], texture_coords: [coords[4], coords[5]] },
-            Vertex2D { position: [1.0, -1.0, -0.0], texture_coords: [coords[6], coords[7]] },
-        ];
-
-        self.mesh.update_vertices(context, bytemuck::cast_slice(&vertices));
+        self.coords = coords.get(..8).and_then(|coords| coords.try_into().ok());
+        self.write_coords(context);
     }
 
     pub fn set_coords_quad(&mut self, context: &VisContext, min: Vec2, max: Vec2) {
+        self.set_coords(context, &[min.x, max.y, max.x, min.y, min.x, min.y, max.x, max.y]);
+    }
+
+    //The rect of an animation frame, mirrored horizontally on top of the flip of the sprite.
+    pub(crate) fn set_frame(&mut self, context: &VisContext, min: Vec2, max: Vec2, mirrored: bool) {
+        self.mirrored = mirrored;
+        self.set_coords_quad(context, min, max);
+    }
+
+    pub fn coords(&self) -> Option<&[f32; 8]> {
+        self.coords.as_ref()
+    }
+
+    //Mirrors the shown texture rect, composes with animation frames and custom coords.
+    pub fn set_flip(&mut self, context: &VisContext, flip_x: bool, flip_y: bool) {
+        if self.flip != (flip_x, flip_y) {
+            self.flip = (flip_x, flip_y);
+            self.write_coords(context);
+        }
+    }
+
+    pub fn flip(&self) -> (bool, bool) {
+        self.flip
+    }
+
+    fn write_coords(&mut self, context: &VisContext) {
+        if self.nine_slice.is_some() {
+            return;
+        }
+
+        //Vertices are bottom left, top right, top left, bottom right.
+        let coords = self.coords.unwrap_or(FULL_COORDS);
+        let mut uvs = [0.0; 8];
+        let mut corners = [0, 1, 2, 3];
+
+        if self.flip.0 != self.mirrored {
+            corners = [corners[3], corners[2], corners[1], corners[0]];
+        }
+
+        if self.flip.1 {
+            corners = [corners[2], corners[3], corners[0], corners[1]];
+        }
+
+        for (vertex, corner) in corners.iter().enumerate() {
+            uvs[vertex * 2] = coords[corner * 2];
+            uvs[vertex * 2 + 1] = coords[corner * 2 + 1];
+        }
+
         let vertices = vec![
-            Vertex2D { position: [-1.0, -1.0, -0.0], texture_coords: [min.x, max.y] },
-            Vertex2D { position: [1.0, 1.0, -0.0], texture_coords: [max.x, min.y] },
-            Vertex2D { position: [-1.0, 1.0, -0.0], texture_coords: [min.x, min.y] },
-            Vertex2D { position: [1.0, -1.0, -0.0], texture_coords: [max.x, max.y] },
+            Vertex2D { position: [-1.0, -1.0, -0.0], texture_coords: [uvs[0], uvs[1]] },
+            Vertex2D { position: [1.0, 1.0, -0.0], texture_coords: [uvs[2], uvs[3]] },
+            Vertex2D { position: [-1.0, 1.0, -0.0], texture_coords: [uvs[4], uvs[5]] },
+            Vertex2D { position: [1.0, -1.0, -0.0], texture_coords: [uvs[6], uvs[7]] },
         ];
 
         self.mesh.update_vertices(context, bytemuck::cast_slice(&vertices));
@@ -107,13 +336,49 @@ impl<'a> Sprite<'a> {
         if self.texture != texture {
             self.texture = texture;
             self.waiting = true;
+            self.texture_size = None;
         }
     }
 
     pub fn set_tint(&mut self, context: &VisContext, tint: Vec4) {
         if self.tint != tint {
             self.tint = tint;
-            self.buffer.update_buffer(context, bytemuck::cast_slice(&tint.to_array()));
+            self.write_color(context);
+        }
+    }
+
+    //Hidden sprites are skipped by the renderer and their animations pause.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    //Set by the renderer from the Opacity components. Applied on top of the tint.
+    pub fn set_opacity(&mut self, context: &VisContext, opacity: f32) {
+        if self.opacity != opacity {
+            self.opacity = opacity;
+            self.write_color(context);
+        }
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    fn write_color(&mut self, context: &VisContext) {
+        let data = Self::color_data(self.tint, self.opacity, self.soft_distance);
+        self.buffer.update_buffer(context, bytemuck::bytes_of(&data));
+    }
+
+    fn color_data(tint: Vec4, opacity: f32, soft_distance: Option<f32>) -> SpriteColorUniform {
+        SpriteColorUniform {
+            tint: tint.to_array(),
+            opacity,
+            soft_distance: soft_distance.unwrap_or(0.0),
+            padding: [0.0; 2],
         }
     }
 
@@ -121,20 +386,82 @@ impl<'a> Sprite<'a> {
         &self.texture
     }
 
+    //In pixels, once the texture is bound.
+    pub fn texture_size(&self) -> Option<Vec2> {
+        self.texture_size
+    }
+
     pub fn tint(&self) -> &Vec4 {
         &self.tint
     }
 
     pub fn update(&mut self, context: &VisContext, texture: &Texture2D) {
-        if self.waiting {
+        //Streamed textures change their view when new mips arrive.
+        if self.waiting || self.generation != texture.generation() {
             self.material.update_group(
                 context,
                 &[self.buffer.group_entry(0), texture.group_entry(1), self.sampler.group_entry(2)],
+                &[self.texture.into()],
             );
             self.waiting = false;
+            self.placeholder = false;
+            self.generation = texture.generation();
+        }
+
+        let (width, height) = texture.size();
+        let texture_size = Some(Vec2::new(width as f32, height as f32));
+        self.texture_size = texture_size;
+        self.translucent_texture = texture.is_translucent();
+
+        //The texture coordinates of the slices depend on the texture size.
+        if let Some(nine_slice) = &mut self.nine_slice {
+            if nine_slice.texture_size != texture_size {
+                nine_slice.texture_size = texture_size;
+                self.write_nine_slice(context);
+            }
         }
     }
 
+    //Bind a stand-in while the real texture is loading. The sprite keeps waiting for the real one.
+    pub fn update_placeholder(&mut self, context: &VisContext, placeholder: &Texture2D) {
+        if self.waiting && !self.placeholder {
+            self.material.update_group(
+                context,
+                &[
+                    self.buffer.group_entry(0),
+                    placeholder.group_entry(1),
+                    self.sampler.group_entry(2),
+                ],
+                &[self.texture.into()],
+            );
+            self.placeholder = true;
+        }
+    }
+
+    //Anything but plain alpha blending (e.g. additive) has to be drawn in the translucent pass.
+    pub fn custom_blend(&self) -> bool {
+        self.material
+            .base_config()
+            .is_some_and(|config| config.blend != Some(wgpu::BlendState::ALPHA_BLENDING))
+    }
+
+    //Has to be drawn in the translucent pass, back to front and without writing depth.
+    pub fn is_translucent(&self) -> bool {
+        self.tint.w < 1.0
+            || self.opacity < 1.0
+            || self.translucent_texture
+            || self.custom_blend()
+            || self.soft_distance.is_some()
+    }
+
+    //Made with new_custom or as nine-slice, more than texture, tint and coords describe.
+    pub fn is_custom(&self) -> bool {
+        self.nine_slice.is_some()
+            || self.material.base_config().is_some()
+            || VertexShader::ptr(&self.material) != &*SPRITE_SHADER
+            || FragmentShader::ptr(&self.material) != &*SPRITE_SHADER
+    }
+
     pub fn material(&self) -> &GenericMaterial {
         &self.material
     }
@@ -143,3 +470,23 @@ impl<'a> Sprite<'a> {
         &self.mesh
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_sized_nine_slices_stay_finite() {
+        let slice = NineSlice {
+            borders: Vec4::splat(4.0),
+            size: Some(Vec2::new(0.0, 16.0)),
+            texture_size: Some(Vec2::splat(16.0)),
+        };
+
+        let finite = |vertex: &Vertex2D| {
+            vertex.position.iter().chain(vertex.texture_coords.iter()).all(|v| v.is_finite())
+        };
+
+        assert!(slice.vertices().iter().all(finite));
+    }
+}
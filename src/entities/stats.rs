@@ -0,0 +1,193 @@
+use std::any::TypeId;
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+use instant::{Duration, Instant};
+
+use crate::entities::animation2d::Animation2D;
+use crate::entities::skin::SkinSet;
+use crate::entities::sprite::Sprite;
+use crate::entities::tilemap::Tilemap;
+use crate::entities::transform2d::Transform2D;
+
+//Spawn/despawn rates are averaged over this window.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+//Only attached with the entity_debug feature, so shipping builds don't pay for it.
+#[cfg(feature = "entity_debug")]
+pub struct SpawnInfo {
+    pub created: Instant,
+    pub label: &'static str,
+}
+
+pub struct WorldStats {
+    names: HashMap<TypeId, &'static str>,
+    events: VecDeque<(Instant, bool)>,
+    spawned: u64,
+    despawned: u64,
+}
+
+impl Default for WorldStats {
+    fn default() -> Self {
+        let mut stats =
+            WorldStats { names: HashMap::new(), events: VecDeque::new(), spawned: 0, despawned: 0 };
+
+        stats.register_component::<Transform2D>();
+        stats.register_component::<Sprite<'static>>();
+        stats.register_component::<Animation2D>();
+        stats.register_component::<SkinSet>();
+        stats.register_component::<Tilemap>();
+        #[cfg(feature = "entity_debug")]
+        stats.register_component::<SpawnInfo>();
+
+        stats
+    }
+}
+
+impl WorldStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //hecs only knows TypeIds. Register components to see their names in the archetype list.
+    pub fn register_component<T: 'static>(&mut self) {
+        let name = std::any::type_name::<T>();
+        self.names.insert(TypeId::of::<T>(), name.rsplit("::").next().unwrap_or(name));
+    }
+
+    //Entities a world starts with count as spawned, but not into the rates.
+    pub fn record_setup(&mut self, count: u64) {
+        self.spawned += count;
+    }
+
+    pub fn record_spawn(&mut self) {
+        self.spawned += 1;
+        self.record(true);
+    }
+
+    pub fn record_despawn(&mut self) {
+        self.despawned += 1;
+        self.record(false);
+    }
+
+    fn record(&mut self, spawn: bool) {
+        let now = Instant::now();
+        self.events.push_back((now, spawn));
+
+        while let Some((time, _)) = self.events.front() {
+            if now.duration_since(*time) > RATE_WINDOW {
+                self.events.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn rate(&self, spawn: bool) -> f64 {
+        let now = Instant::now();
+        let count = self
+            .events
+            .iter()
+            .filter(|(time, s)| *s == spawn && now.duration_since(*time) <= RATE_WINDOW)
+            .count();
+
+        count as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    //Spawns per second over the last few seconds.
+    pub fn spawn_rate(&self) -> f64 {
+        self.rate(true)
+    }
+
+    pub fn despawn_rate(&self) -> f64 {
+        self.rate(false)
+    }
+
+    pub fn total_spawned(&self) -> u64 {
+        self.spawned
+    }
+
+    pub fn total_despawned(&self) -> u64 {
+        self.despawned
+    }
+
+    //Entity counts per component signature, largest first.
+    pub fn archetypes(&self, world: &hecs::World) -> Vec<(String, u32)> {
+        let mut archetypes: Vec<(String, u32)> = world
+            .archetypes()
+            .filter(|archetype| !archetype.is_empty())
+            .map(|archetype| {
+                let names: Vec<&str> = archetype
+                    .component_types()
+                    .map(|id| self.names.get(&id).copied().unwrap_or("?"))
+                    .collect();
+
+                (names.join(", "), archetype.len())
+            })
+            .collect();
+
+        archetypes.sort_by(|a, b| b.1.cmp(&a.1));
+        archetypes
+    }
+
+    #[cfg(feature = "entity_debug")]
+    pub fn oldest(
+        &self, world: &hecs::World, count: usize,
+    ) -> Vec<(hecs::Entity, &'static str, f64)> {
+        let mut entities: Vec<(hecs::Entity, &'static str, f64)> = world
+            .query::<&SpawnInfo>()
+            .iter()
+            .map(|(entity, info)| (entity, info.label, info.created.elapsed().as_secs_f64()))
+            .collect();
+
+        entities.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        entities.truncate(count);
+        entities
+    }
+
+    pub fn show(&self, ctx: &egui::Context, world: &hecs::World) {
+        egui::Window::new("World statistics").show(ctx, |ui| {
+            ui.label(format!("Alive: {}", world.len()));
+            ui.label(format!(
+                "Spawned: {} ({:.1}/s) Despawned: {} ({:.1}/s)",
+                self.spawned,
+                self.spawn_rate(),
+                self.despawned,
+                self.despawn_rate()
+            ));
+
+            ui.separator();
+
+            for (signature, count) in self.archetypes(world) {
+                ui.label(format!("{count:>6}  [{signature}]"));
+            }
+
+            #[cfg(feature = "entity_debug")]
+            {
+                ui.separator();
+                ui.label("Oldest entities:");
+
+                for (entity, label, age) in self.oldest(world, 20) {
+                    ui.label(format!("{:?} {:.1}s {}", entity, age, label));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_setup_is_not_in_the_rates() {
+        let mut stats = WorldStats::new();
+        stats.record_setup(500);
+        stats.record_spawn();
+        stats.record_despawn();
+
+        assert_eq!(stats.total_spawned(), 501);
+        assert_eq!(stats.spawn_rate(), 1.0 / RATE_WINDOW.as_secs_f64());
+        assert_eq!(stats.despawn_rate(), 1.0 / RATE_WINDOW.as_secs_f64());
+    }
+}
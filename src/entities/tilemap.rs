@@ -0,0 +1,295 @@
+use std::mem::size_of;
+
+use glam::{Vec2, Vec4};
+
+use crate::assets::assets::{Ptr, SPRITE_SHADER};
+use crate::assets::buffer::{Indices, UniformBuffer, Vertices};
+use crate::assets::texture::{Sampler, Texture2D};
+use crate::context::VisContext;
+use crate::entities::transform2d::Transform2D;
+use crate::render::material::GenericMaterial;
+use crate::render::mesh::GenericMesh;
+use crate::render::types::{BindGroupEntry, SpriteColorUniform, Vertex2D};
+
+//Tiles per chunk side. Every chunk is one draw call.
+pub const CHUNK_SIZE: u32 = 32;
+
+//coords are the rect in the tileset as uv (left, top, right, bottom). Swapped edges flip the tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub id: i64,
+    pub coords: [f32; 4],
+}
+
+struct Chunk {
+    mesh: Option<GenericMesh<'static>>,
+    dirty: bool,
+}
+
+//A grid of tiles sharing one tileset, drawn with the sprite shader.
+//Cell (x, y) covers [x, x + 1] * cell_size horizontally and [-(y + 1), -y] * cell_size vertically
+//in local space, so rows grow downwards like in ldtk. The Transform2D places the top left corner.
+pub struct Tilemap {
+    width: u32,
+    height: u32,
+    cell_size: f32,
+    tiles: Vec<Option<Tile>>,
+    chunks: Vec<Chunk>,
+    texture: Ptr<Texture2D>,
+    tint: Vec4,
+    opacity: f32,
+    buffer: UniformBuffer,
+    sampler: Sampler,
+    material: GenericMaterial,
+    waiting: bool,
+    placeholder: bool,
+    generation: u64,
+    translucent_texture: bool,
+}
+
+impl Tilemap {
+    pub fn new(
+        context: &VisContext, texture: Ptr<Texture2D>, width: u32, height: u32, cell_size: f32,
+        tint: Vec4,
+    ) -> Self {
+        let mut buffer = UniformBuffer::new(context, size_of::<SpriteColorUniform>());
+        buffer.update_buffer(context, bytemuck::bytes_of(&Self::color_data(tint, 1.0)));
+        let sampler = Sampler::new(context);
+
+        let material = GenericMaterial::new(
+            context,
+            SPRITE_SHADER.clone(),
+            SPRITE_SHADER.clone(),
+            &[UniformBuffer::layout_entry(0), Texture2D::layout_entry(1), Sampler::layout_entry(2)],
+            &[
+                buffer.group_entry(0),
+                Texture2D::error_texture(context).group_entry(1),
+                sampler.group_entry(2),
+            ],
+            &[texture.into()],
+            None,
+        );
+
+        let chunks = (0..width.div_ceil(CHUNK_SIZE) * height.div_ceil(CHUNK_SIZE))
+            .map(|_| Chunk { mesh: None, dirty: false })
+            .collect();
+
+        Tilemap {
+            width,
+            height,
+            cell_size,
+            tiles: vec![None; (width * height) as usize],
+            chunks,
+            texture,
+            tint,
+            opacity: 1.0,
+            buffer,
+            sampler,
+            material,
+            waiting: true,
+            placeholder: false,
+            generation: 0,
+            translucent_texture: false,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn texture(&self) -> &Ptr<Texture2D> {
+        &self.texture
+    }
+
+    pub fn tile(&self, x: u32, y: u32) -> Option<&Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.tiles[(y * self.width + x) as usize].as_ref()
+    }
+
+    //The chunk is rebuilt before the next draw.
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: Option<Tile>) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        self.tiles[(y * self.width + x) as usize] = tile;
+
+        let chunk = self.chunk_index(x, y);
+        self.chunks[chunk].dirty = true;
+    }
+
+    //Every set tile with its grid coordinates, e.g. to build colliders.
+    pub fn tiles(&self) -> impl Iterator<Item = (u32, u32, &Tile)> + '_ {
+        self.tiles.iter().enumerate().filter_map(|(i, tile)| {
+            tile.as_ref().map(|tile| (i as u32 % self.width, i as u32 / self.width, tile))
+        })
+    }
+
+    //The cell under a world position, the transform being the one of the tilemap entity.
+    pub fn cell_at(&self, transform: &Transform2D, position: Vec2) -> Option<(u32, u32)> {
+        let local = transform.global().inverse().transform_point3(position.extend(0.0));
+        let (x, y) = (local.x / self.cell_size, -local.y / self.cell_size);
+
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+
+        Some((x as u32, y as u32))
+    }
+
+    pub fn set_tint(&mut self, context: &VisContext, tint: Vec4) {
+        if self.tint != tint {
+            self.tint = tint;
+            self.write_color(context);
+        }
+    }
+
+    pub fn set_opacity(&mut self, context: &VisContext, opacity: f32) {
+        if self.opacity != opacity {
+            self.opacity = opacity;
+            self.write_color(context);
+        }
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        self.tint.w < 1.0 || self.opacity < 1.0 || self.translucent_texture
+    }
+
+    fn write_color(&mut self, context: &VisContext) {
+        let data = Self::color_data(self.tint, self.opacity);
+        self.buffer.update_buffer(context, bytemuck::bytes_of(&data));
+    }
+
+    fn color_data(tint: Vec4, opacity: f32) -> SpriteColorUniform {
+        SpriteColorUniform { tint: tint.to_array(), opacity, soft_distance: 0.0, padding: [0.0; 2] }
+    }
+
+    fn chunk_index(&self, x: u32, y: u32) -> usize {
+        ((y / CHUNK_SIZE) * self.width.div_ceil(CHUNK_SIZE) + x / CHUNK_SIZE) as usize
+    }
+
+    //Rebuilds the merged buffers of every changed chunk.
+    //Rebuilds the dirty chunks. Returns the vertex and index bytes uploaded for them.
+    pub(crate) fn prepare(&mut self, context: &VisContext) -> usize {
+        let columns = self.width.div_ceil(CHUNK_SIZE);
+        let mut uploaded = 0;
+
+        for index in 0..self.chunks.len() {
+            if !self.chunks[index].dirty {
+                continue;
+            }
+
+            let (chunk_x, chunk_y) = (index as u32 % columns, index as u32 / columns);
+            let built = self.build_chunk(context, chunk_x, chunk_y);
+            uploaded += built.as_ref().map_or(0, |(_, bytes)| *bytes);
+            self.chunks[index].mesh = built.map(|(mesh, _)| mesh);
+            self.chunks[index].dirty = false;
+        }
+
+        uploaded
+    }
+
+    fn build_chunk(
+        &self, context: &VisContext, chunk_x: u32, chunk_y: u32,
+    ) -> Option<(GenericMesh<'static>, usize)> {
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u16> = Vec::new();
+
+        let (x_start, y_start) = (chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+        let x_end = (x_start + CHUNK_SIZE).min(self.width);
+        let y_end = (y_start + CHUNK_SIZE).min(self.height);
+
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let Some(tile) = self.tile(x, y) else {
+                    continue;
+                };
+
+                let [left, top, right, bottom] = tile.coords;
+                let (x0, x1) = (x as f32 * self.cell_size, (x + 1) as f32 * self.cell_size);
+                let (y0, y1) = (-((y + 1) as f32) * self.cell_size, -(y as f32) * self.cell_size);
+
+                let first = vertices.len() as u16;
+
+                vertices.extend_from_slice(&[
+                    Vertex2D { position: [x0, y1, 0.0], texture_coords: [left, top] },
+                    Vertex2D { position: [x1, y1, 0.0], texture_coords: [right, top] },
+                    Vertex2D { position: [x0, y0, 0.0], texture_coords: [left, bottom] },
+                    Vertex2D { position: [x1, y0, 0.0], texture_coords: [right, bottom] },
+                ]);
+
+                indices.extend([2, 1, 0, 2, 3, 1].map(|i| first + i));
+            }
+        }
+
+        if indices.is_empty() {
+            return None;
+        }
+
+        let count = indices.len() as u32;
+        let bytes =
+            std::mem::size_of_val(vertices.as_slice()) + std::mem::size_of_val(indices.as_slice());
+        let vertices = Vertices::new(context, bytemuck::cast_slice(&vertices), Vertex2D::LAYOUT);
+        let indices =
+            Indices::new(context, bytemuck::cast_slice(&indices), wgpu::IndexFormat::Uint16);
+        Some((GenericMesh::new(vertices, indices, count), bytes))
+    }
+
+    //Non empty chunks with their local bounds.
+    pub(crate) fn chunks(&self) -> impl Iterator<Item = (&GenericMesh<'static>, Vec2, Vec2)> + '_ {
+        let columns = self.width.div_ceil(CHUNK_SIZE);
+        let chunk_size = CHUNK_SIZE as f32 * self.cell_size;
+
+        self.chunks.iter().enumerate().filter_map(move |(index, chunk)| {
+            let (x, y) = ((index as u32 % columns) as f32, (index as u32 / columns) as f32);
+            let min = Vec2::new(x * chunk_size, -(y + 1.0) * chunk_size);
+            let max = Vec2::new((x + 1.0) * chunk_size, -y * chunk_size);
+            chunk.mesh.as_ref().map(|mesh| (mesh, min, max))
+        })
+    }
+
+    pub fn material(&self) -> &GenericMaterial {
+        &self.material
+    }
+
+    pub(crate) fn update(&mut self, context: &VisContext, texture: &Texture2D) {
+        if self.waiting || self.generation != texture.generation() {
+            self.material.update_group(
+                context,
+                &[self.buffer.group_entry(0), texture.group_entry(1), self.sampler.group_entry(2)],
+                &[self.texture.into()],
+            );
+            self.waiting = false;
+            self.placeholder = false;
+            self.generation = texture.generation();
+        }
+
+        self.translucent_texture = texture.is_translucent();
+    }
+
+    pub(crate) fn update_placeholder(&mut self, context: &VisContext, placeholder: &Texture2D) {
+        if self.waiting && !self.placeholder {
+            self.material.update_group(
+                context,
+                &[
+                    self.buffer.group_entry(0),
+                    placeholder.group_entry(1),
+                    self.sampler.group_entry(2),
+                ],
+                &[self.texture.into()],
+            );
+            self.placeholder = true;
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::utils::Timestep;
+
+//Counts down in the Timers of its entity and fires a TimerFired through the TimerSystem once the
+//duration is up. Fired once timers stay on the entity, reset them to count down again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timer {
+    //In seconds.
+    pub duration: f32,
+    pub elapsed: f32,
+    //Starts over every time it fires instead of stopping.
+    pub repeating: bool,
+    pub paused: bool,
+    //Handed back with TimerFired, to tell apart the timers of one entity.
+    pub tag: u32,
+    done: bool,
+}
+
+impl Timer {
+    pub fn new(duration: f32, repeating: bool, tag: u32) -> Self {
+        Timer { duration, elapsed: 0.0, repeating, paused: false, tag, done: false }
+    }
+
+    pub fn once(seconds: f32, tag: u32) -> Self {
+        Self::new(seconds, false, tag)
+    }
+
+    pub fn every(seconds: f32, tag: u32) -> Self {
+        Self::new(seconds, true, tag)
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.done = false;
+    }
+
+    //Fired and not repeating.
+    pub fn is_finished(&self) -> bool {
+        self.done
+    }
+
+    pub fn remaining(&self) -> f32 {
+        (self.duration - self.elapsed).max(0.0)
+    }
+
+    //How often it fires in this step.
+    fn advance(&mut self, seconds: f32) -> u32 {
+        if self.paused || self.done {
+            return 0;
+        }
+
+        self.elapsed += seconds;
+
+        if self.elapsed < self.duration {
+            return 0;
+        }
+
+        if !self.repeating {
+            self.elapsed = self.duration;
+            self.done = true;
+            return 1;
+        }
+
+        //A long frame fires a repeating timer once for every period that passed. Zero length
+        //ones fire once per step instead of looping forever.
+        if self.duration <= 0.0 {
+            self.elapsed = 0.0;
+            return 1;
+        }
+
+        let fires = (self.elapsed / self.duration).floor();
+        self.elapsed -= fires * self.duration;
+        fires as u32
+    }
+}
+
+//The timers of one entity. hecs keeps a single component of each type per entity, so they are
+//grouped here and told apart by their tags.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timers(pub Vec<Timer>);
+
+impl Timers {
+    pub fn new(timers: impl IntoIterator<Item = Timer>) -> Self {
+        Timers(timers.into_iter().collect())
+    }
+
+    pub fn with(mut self, timer: Timer) -> Self {
+        self.0.push(timer);
+        self
+    }
+
+    pub fn push(&mut self, timer: Timer) {
+        self.0.push(timer);
+    }
+
+    //The first timer with that tag.
+    pub fn get(&self, tag: u32) -> Option<&Timer> {
+        self.0.iter().find(|timer| timer.tag == tag)
+    }
+
+    pub fn get_mut(&mut self, tag: u32) -> Option<&mut Timer> {
+        self.0.iter_mut().find(|timer| timer.tag == tag)
+    }
+
+    //Removes every timer with that tag.
+    pub fn remove(&mut self, tag: u32) {
+        self.0.retain(|timer| timer.tag != tag);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerFired {
+    pub entity: hecs::Entity,
+    pub tag: u32,
+}
+
+//Runs the Timers of a world. What fired is kept until the next update, hand it to Scripts::tick
+//and read it in Application::update. Schedule::default has one in its resources and runs it.
+#[derive(Default)]
+pub struct TimerSystem {
+    fired: Vec<TimerFired>,
+}
+
+impl TimerSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Forgets what fired last frame. Entities whose Timers were removed in between don't show up.
+    pub fn update(&mut self, world: &mut hecs::World, delta: &Timestep) {
+        let seconds = delta.seconds() as f32;
+        self.fired.clear();
+
+        for (entity, timers) in world.query_mut::<&mut Timers>() {
+            for timer in timers.0.iter_mut() {
+                let tag = timer.tag;
+
+                for _ in 0..timer.advance(seconds) {
+                    self.fired.push(TimerFired { entity, tag });
+                }
+            }
+        }
+    }
+
+    pub fn fired(&self) -> &[TimerFired] {
+        &self.fired
+    }
+
+    //Whether a timer of the entity with that tag fired this frame.
+    pub fn has_fired(&self, entity: hecs::Entity, tag: u32) -> bool {
+        self.fired.iter().any(|fired| fired.entity == entity && fired.tag == tag)
+    }
+}
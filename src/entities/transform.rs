@@ -1,8 +1,159 @@
-use glam::Vec3;
+use std::mem::size_of;
 
-#[derive(Debug, Clone, PartialEq)]
+use glam::{Mat4, Quat, Vec3};
+
+use crate::assets::buffer::UniformBuffer;
+use crate::context::VisContext;
+use crate::render::types::{BindGroupEntry, ModelUniform};
+use hecs_hierarchy::Hierarchy;
+
+//The 3D counterpart of Transform2D. Bound as group 1 by the Renderer, children follow their parent.
+#[derive(Debug)]
 pub struct Transform3D {
-    pub position: Vec3,
-    pub rotation: Vec3,
-    pub scale: Vec3,
+    position: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+    parent: Mat4,
+    global: Mat4,
+    uniform: UniformBuffer,
+    group: wgpu::BindGroup,
+    layout: wgpu::BindGroupLayout,
+    dirty: bool,
+}
+
+impl Transform3D {
+    pub fn new(context: &VisContext, position: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        let mut uniform = UniformBuffer::new(context, size_of::<ModelUniform>());
+
+        let global = Mat4::from_scale_rotation_translation(scale, rotation, position);
+        uniform.update_buffer(context, bytemuck::bytes_of(&Self::uniform_data(&global)));
+
+        let layout = context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[UniformBuffer::layout_entry(0)],
+        });
+
+        let group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[uniform.group_entry(0)],
+        });
+
+        Self {
+            position,
+            rotation,
+            scale,
+            parent: Mat4::IDENTITY,
+            global,
+            uniform,
+            group,
+            layout,
+            dirty: true,
+        }
+    }
+
+    pub fn update(&mut self, context: &VisContext, entity: hecs::Entity, world: &hecs::World) {
+        self.parent = if let Ok(parent) = world.parent::<Transform3D>(entity) {
+            world.get::<&Transform3D>(parent).unwrap().global
+        } else {
+            Mat4::IDENTITY
+        };
+
+        self.update_desc(context, entity, world);
+    }
+
+    fn update_desc(&mut self, context: &VisContext, entity: hecs::Entity, world: &hecs::World) {
+        if self.dirty {
+            let local =
+                Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position);
+            self.global = self.parent * local;
+
+            //Propagate to descendants
+            for child in world.children::<Transform3D>(entity) {
+                if let Ok(mut transform) = world.get::<&mut Transform3D>(child) {
+                    transform.dirty = true;
+                    transform.parent = self.global;
+                    transform.update_desc(context, child, world);
+                }
+            }
+
+            let data = Self::uniform_data(&self.global);
+            self.uniform.update_buffer(context, bytemuck::bytes_of(&data));
+            self.dirty = false;
+        }
+    }
+
+    fn uniform_data(global: &Mat4) -> ModelUniform {
+        ModelUniform {
+            model: global.to_cols_array_2d(),
+            normal: global.inverse().transpose().to_cols_array_2d(),
+        }
+    }
+
+    pub fn group(&self) -> &wgpu::BindGroup {
+        &self.group
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    //Transform including all parents. Only up to date after the renderer ran update.
+    pub fn global(&self) -> &Mat4 {
+        &self.global
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        self.rotation = rotation.normalize();
+        self.dirty = true;
+    }
+
+    //Turns it to face target, up is usually Vec3::Y.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = (target - self.position).normalize_or_zero();
+
+        if forward != Vec3::ZERO {
+            //Looking down -z, like the cameras.
+            let view = Mat4::look_to_rh(Vec3::ZERO, forward, up);
+            self.set_rotation(Quat::from_mat4(&view.inverse()));
+        }
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Vec3) {
+        self.scale = scale;
+        self.dirty = true;
+    }
+
+    pub fn add_pos(&mut self, inc: Vec3) {
+        self.position += inc;
+        self.dirty = true;
+    }
+
+    //Applied on top of the current rotation.
+    pub fn add_rot(&mut self, inc: Quat) {
+        self.rotation = (inc * self.rotation).normalize();
+        self.dirty = true;
+    }
+
+    pub fn add_scale(&mut self, inc: Vec3) {
+        self.scale += inc;
+        self.dirty = true;
+    }
 }
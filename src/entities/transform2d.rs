@@ -1,12 +1,26 @@
 use std::mem::size_of;
 
 use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::StagingBelt;
 
 use crate::assets::buffer::UniformBuffer;
 use crate::context::VisContext;
-use crate::render::types::BindGroupEntry;
+use crate::render::types::{BindGroupEntry, TransformUniform};
 use hecs_hierarchy::Hierarchy;
 
+//Stands the sprite upright on a tilted (sheared) ground plane. The whole quad gets the depth of its foot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Billboard2_5D {
+    //Local y of the foot, -1.0 is the bottom edge of the sprite.
+    pub anchor: f32,
+}
+
+impl Default for Billboard2_5D {
+    fn default() -> Self {
+        Billboard2_5D { anchor: -1.0 }
+    }
+}
+
 #[derive(Debug)]
 pub struct Transform2D {
     position: Vec3,
@@ -14,25 +28,31 @@ pub struct Transform2D {
     scale: Vec2,
     parent: Mat4,
     global: Mat4,
+    billboard: Option<Billboard2_5D>,
+    sort_band: f32,
     uniform: UniformBuffer,
     group: wgpu::BindGroup,
     layout: wgpu::BindGroupLayout,
     dirty: bool,
+    interpolate: bool,
+    //Position, rotation and scale before the last fixed step, only for interpolated transforms.
+    previous: Option<(Vec3, f32, Vec2)>,
+    alpha: f32,
 }
 
 impl Transform2D {
     pub fn new(context: &VisContext, position: Vec3, rotation: f32, scale: Vec2) -> Self {
-        let mut uniform = UniformBuffer::new(context, size_of::<[[f32; 4]; 4]>());
+        let mut uniform = UniformBuffer::new(context, size_of::<TransformUniform>());
 
         let global = glam::Mat4::from_scale_rotation_translation(
             glam::Vec3::new(scale.x, scale.y, 1.0),
             glam::Quat::from_rotation_z(rotation),
-            glam::Vec3::new(position.x, position.y, 0.0),
+            position,
         );
 
         let parent = Mat4::IDENTITY;
 
-        uniform.update_buffer(context, bytemuck::cast_slice(&global.to_cols_array_2d()));
+        uniform.update_buffer(context, bytemuck::bytes_of(&Self::uniform_data(&global, None, 0.0)));
 
         let layout = context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
@@ -45,46 +65,195 @@ impl Transform2D {
             entries: &[uniform.group_entry(0)],
         });
 
-        Self { position, rotation, scale, parent, global, uniform, group, layout, dirty: true }
+        Self {
+            position,
+            rotation,
+            scale,
+            parent,
+            global,
+            billboard: None,
+            sort_band: 0.0,
+            uniform,
+            group,
+            layout,
+            dirty: true,
+            interpolate: false,
+            previous: None,
+            alpha: 1.0,
+        }
     }
 
     pub fn update(&mut self, context: &VisContext, entity: hecs::Entity, world: &hecs::World) {
+        self.update_parent(entity, world);
+        self.update_desc(context, &mut None, entity, world);
+    }
+
+    //Same as update, but the uniform writes are recorded into the encoder, see UniformBuffer::update_buffer_staged.
+    pub fn update_staged(
+        &mut self, context: &VisContext, encoder: &mut wgpu::CommandEncoder,
+        belt: &mut StagingBelt, entity: hecs::Entity, world: &hecs::World,
+    ) {
+        self.update_parent(entity, world);
+        self.update_desc(context, &mut Some((encoder, belt)), entity, world);
+    }
+
+    fn update_parent(&mut self, entity: hecs::Entity, world: &hecs::World) {
         self.parent = if let Ok(parent) = world.parent::<Transform2D>(entity) {
             world.get::<&Transform2D>(parent).unwrap().global
         } else {
             Mat4::IDENTITY
         };
-
-        self.update_desc(context, entity, world);
     }
 
-    fn update_desc(&mut self, context: &VisContext, entity: hecs::Entity, world: &hecs::World) {
+    //For transforms that live outside of a world, e.g. overlay quads. They have no parent.
+    pub(crate) fn update_detached(&mut self, context: &VisContext) {
         if self.dirty {
-            //Get local transform
-            let local = glam::Mat4::from_scale_rotation_translation(
-                glam::Vec3::new(self.scale.x, self.scale.y, 1.0),
-                glam::Quat::from_rotation_z(self.rotation),
-                glam::Vec3::new(self.position.x, self.position.y, 0.0),
-            );
+            self.parent = Mat4::IDENTITY;
+            self.global = self.local();
 
+            let data = Self::uniform_data(&self.global, self.billboard, self.sort_band);
+            self.uniform.update_buffer(context, bytemuck::bytes_of(&data));
+            self.dirty = false;
+        }
+    }
+
+    fn update_desc(
+        &mut self, context: &VisContext,
+        staging: &mut Option<(&mut wgpu::CommandEncoder, &mut StagingBelt)>, entity: hecs::Entity,
+        world: &hecs::World,
+    ) {
+        if self.dirty {
             //Calculate global transform
-            self.global = self.parent * local;
+            self.global = self.parent * self.local();
 
             //Propagate to descendants
             for child in world.children::<Transform2D>(entity) {
                 if let Ok(mut transform) = world.get::<&mut Transform2D>(child) {
                     transform.dirty = true;
                     transform.parent = self.global;
-                    transform.update_desc(context, child, world);
+                    transform.update_desc(context, staging, child, world);
                 }
             }
 
-            self.uniform
-                .update_buffer(context, bytemuck::cast_slice(&self.global.to_cols_array_2d()));
+            let data = Self::uniform_data(&self.global, self.billboard, self.sort_band);
+            let data = bytemuck::bytes_of(&data);
+
+            match staging {
+                Some((encoder, belt)) => {
+                    self.uniform.update_buffer_staged(context, encoder, belt, data)
+                }
+                None => self.uniform.update_buffer(context, data),
+            }
+
             self.dirty = false;
         }
     }
 
+    //Interpolated transforms are blended from the previous state by alpha.
+    fn local(&self) -> Mat4 {
+        let (position, rotation, scale) = match self.previous.filter(|_| self.interpolate) {
+            Some((position, rotation, scale)) => (
+                position.lerp(self.position, self.alpha),
+                rotation + (self.rotation - rotation) * self.alpha,
+                scale.lerp(self.scale, self.alpha),
+            ),
+            None => (self.position, self.rotation, self.scale),
+        };
+
+        Self::compose(position, rotation, scale)
+    }
+
+    fn compose(position: Vec3, rotation: f32, scale: Vec2) -> Mat4 {
+        glam::Mat4::from_scale_rotation_translation(
+            glam::Vec3::new(scale.x, scale.y, 1.0),
+            glam::Quat::from_rotation_z(rotation),
+            position,
+        )
+    }
+
+    //Like global, but from the current (not interpolated) state, so it is right before the renderer
+    //ran. The parent matrix is the one of the last update.
+    fn current_global(&self) -> Mat4 {
+        self.parent * Self::compose(self.position, self.rotation, self.scale)
+    }
+
+    //Drawn in between the state before the last fixed step and the current one, see
+    //Context::fixed_alpha. Only for transforms that are moved in fixed steps.
+    pub fn set_interpolate(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
+        self.previous = None;
+        self.dirty = true;
+    }
+
+    pub fn interpolate(&self) -> bool {
+        self.interpolate
+    }
+
+    //Remembers the current state as the one before the next fixed step. Call it again right
+    //after a teleport to not glide there.
+    pub fn snapshot(&mut self) {
+        if !self.interpolate {
+            return;
+        }
+
+        let current = Some((self.position, self.rotation, self.scale));
+
+        //The last upload may still be blended from the old state.
+        if self.previous != current {
+            self.previous = current;
+            self.dirty = true;
+        }
+    }
+
+    //Set by the renderer every frame.
+    pub(crate) fn set_alpha(&mut self, alpha: f32) {
+        if !self.interpolate || self.alpha == alpha {
+            return;
+        }
+
+        self.alpha = alpha;
+
+        if self
+            .previous
+            .is_some_and(|previous| previous != (self.position, self.rotation, self.scale))
+        {
+            self.dirty = true;
+        }
+    }
+
+    fn uniform_data(
+        global: &Mat4, billboard: Option<Billboard2_5D>, sort_band: f32,
+    ) -> TransformUniform {
+        TransformUniform {
+            transform: global.to_cols_array_2d(),
+            billboard: match billboard {
+                Some(billboard) => [1.0, billboard.anchor, sort_band, 0.0],
+                None => [0.0, 0.0, sort_band, 0.0],
+            },
+        }
+    }
+
+    pub fn billboard(&self) -> Option<Billboard2_5D> {
+        self.billboard
+    }
+
+    pub fn set_billboard(&mut self, billboard: Option<Billboard2_5D>) {
+        self.billboard = billboard;
+        self.dirty = true;
+    }
+
+    //Set by the renderer from RenderLayer and OrderInLayer.
+    pub(crate) fn set_sort_band(&mut self, band: usize) {
+        if self.sort_band != band as f32 {
+            self.sort_band = band as f32;
+            self.dirty = true;
+        }
+    }
+
+    pub fn sort_band(&self) -> f32 {
+        self.sort_band
+    }
+
     pub fn group(&self) -> &wgpu::BindGroup {
         &self.group
     }
@@ -93,6 +262,57 @@ impl Transform2D {
         &self.layout
     }
 
+    //Transform including all parents. Only up to date after the renderer ran update.
+    pub fn global(&self) -> &Mat4 {
+        &self.global
+    }
+
+    pub fn global_position(&self) -> Vec3 {
+        self.current_global().w_axis.truncate()
+    }
+
+    //In radians.
+    pub fn global_rotation(&self) -> f32 {
+        let x_axis = self.current_global().x_axis;
+        x_axis.y.atan2(x_axis.x)
+    }
+
+    //Always positive, a mirroring scale shows up in the rotation instead.
+    pub fn global_scale(&self) -> Vec2 {
+        let global = self.current_global();
+        Vec2::new(global.x_axis.truncate().length(), global.y_axis.truncate().length())
+    }
+
+    //World point into the space of this transform, e.g. where a click hit a sprite (-1..1 on it).
+    pub fn world_to_local(&self, point: Vec2) -> Vec2 {
+        self.current_global().inverse().transform_point3(point.extend(0.0)).truncate()
+    }
+
+    pub fn local_to_world(&self, point: Vec2) -> Vec2 {
+        self.current_global().transform_point3(point.extend(0.0)).truncate()
+    }
+
+    //Moves the transform so it ends up there in world space, the world z stays.
+    pub fn set_global_position(&mut self, position: Vec2) {
+        let target = position.extend(self.global_position().z);
+        self.set_position(self.parent.inverse().transform_point3(target));
+    }
+
+    //World space bounds of the unit sprite quad under this transform.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        self.transform_bounds(Vec2::new(-1.0, -1.0), Vec2::ONE)
+    }
+
+    //World space bounds of a local rectangle under this transform.
+    pub fn transform_bounds(&self, min: Vec2, max: Vec2) -> (Vec2, Vec2) {
+        let corners = [min, Vec2::new(max.x, min.y), Vec2::new(min.x, max.y), max]
+            .map(|corner| self.global.transform_point3(corner.extend(0.0)).truncate());
+
+        let min = corners.iter().fold(Vec2::splat(f32::MAX), |acc, c| acc.min(*c));
+        let max = corners.iter().fold(Vec2::splat(f32::MIN), |acc, c| acc.max(*c));
+        (min, max)
+    }
+
     pub fn position(&self) -> Vec3 {
         self.position
     }
@@ -135,3 +355,11 @@ impl Transform2D {
         self.dirty = true;
     }
 }
+
+//Call before every fixed step that moves interpolated transforms, the engine does it for the
+//current world of apps that hand out systems.
+pub fn snapshot_interpolated(world: &mut hecs::World) {
+    for (_, transform) in world.query_mut::<&mut Transform2D>() {
+        transform.snapshot();
+    }
+}
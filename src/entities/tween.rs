@@ -0,0 +1,329 @@
+use std::f32::consts::PI;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::context::VisContext;
+use crate::entities::sprite::Sprite;
+use crate::entities::transform2d::Transform2D;
+use crate::utils::Timestep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ease {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    //Overshoots a bit before settling.
+    BackIn,
+    BackOut,
+    BackInOut,
+    //Springs around the target.
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+}
+
+impl Ease {
+    //Maps the progress (0..1) of a tween to how far it has moved. Back and elastic leave 0..1 on the way.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        let curve: fn(f32) -> f32 = match self {
+            Ease::Linear => return t,
+            Ease::QuadIn | Ease::QuadOut | Ease::QuadInOut => |t| t * t,
+            Ease::CubicIn | Ease::CubicOut | Ease::CubicInOut => |t| t * t * t,
+            Ease::BackIn | Ease::BackOut | Ease::BackInOut => {
+                |t| 2.70158 * t * t * t - 1.70158 * t * t
+            }
+            Ease::ElasticIn | Ease::ElasticOut | Ease::ElasticInOut => |t| {
+                if t <= 0.0 || t >= 1.0 {
+                    t
+                } else {
+                    -(2.0f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+                }
+            },
+        };
+
+        //Every curve is written as ease in, out and in-out are mirrored from it.
+        match self {
+            Ease::QuadOut | Ease::CubicOut | Ease::BackOut | Ease::ElasticOut => {
+                1.0 - curve(1.0 - t)
+            }
+            Ease::QuadInOut | Ease::CubicInOut | Ease::BackInOut | Ease::ElasticInOut => {
+                if t < 0.5 {
+                    curve(2.0 * t) / 2.0
+                } else {
+                    1.0 - curve(2.0 - 2.0 * t) / 2.0
+                }
+            }
+            _ => curve(t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TweenMode {
+    //Removed from the entity when done.
+    #[default]
+    Once,
+    //Starts over from the first step.
+    Loop,
+    //Plays the steps forwards, then backwards and so on.
+    PingPong,
+}
+
+//A value a tween can drive. The type picks the component and setter: Vec3 is the position and
+//Vec2 the scale of the Transform2D, f32 its rotation and Vec4 the tint of the Sprite.
+pub trait Tweenable: Copy + Send + Sync + 'static {
+    type Target: hecs::Component;
+
+    fn get(target: &Self::Target) -> Self;
+    fn set(target: &mut Self::Target, context: &VisContext, value: Self);
+    fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Tweenable for Vec3 {
+    type Target = Transform2D;
+
+    fn get(target: &Transform2D) -> Self {
+        target.position()
+    }
+
+    fn set(target: &mut Transform2D, _context: &VisContext, value: Self) {
+        target.set_position(value);
+    }
+
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for Vec2 {
+    type Target = Transform2D;
+
+    fn get(target: &Transform2D) -> Self {
+        target.scale()
+    }
+
+    fn set(target: &mut Transform2D, _context: &VisContext, value: Self) {
+        target.set_scale(value);
+    }
+
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for f32 {
+    type Target = Transform2D;
+
+    fn get(target: &Transform2D) -> Self {
+        target.rotation()
+    }
+
+    fn set(target: &mut Transform2D, _context: &VisContext, value: Self) {
+        target.set_rotation(value);
+    }
+
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec4 {
+    type Target = Sprite<'static>;
+
+    fn get(target: &Sprite<'static>) -> Self {
+        *target.tint()
+    }
+
+    fn set(target: &mut Sprite<'static>, context: &VisContext, value: Self) {
+        target.set_tint(context, value);
+    }
+
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+pub type TweenCallback = Box<dyn FnOnce(&mut hecs::World, hecs::Entity) + Send + Sync>;
+
+struct TweenStep<T> {
+    //Whatever the value was when the step started the first time.
+    from: Option<T>,
+    to: T,
+    //In seconds.
+    duration: f32,
+    ease: Ease,
+}
+
+//Moves a value of its entity to one or more targets, one after the other. Built like
+//Tween::position(to).duration(0.5).ease(Ease::QuadOut).then(Tween::position(back)).
+pub struct Tween<T: Tweenable> {
+    steps: Vec<TweenStep<T>>,
+    current: usize,
+    elapsed: f32,
+    mode: TweenMode,
+    reverse: bool,
+    on_complete: Option<TweenCallback>,
+}
+
+impl<T: Tweenable> Tween<T> {
+    //One second, linear.
+    pub fn to(to: T) -> Self {
+        Tween {
+            steps: vec![TweenStep { from: None, to, duration: 1.0, ease: Ease::Linear }],
+            current: 0,
+            elapsed: 0.0,
+            mode: TweenMode::Once,
+            reverse: false,
+            on_complete: None,
+        }
+    }
+
+    //Of the last step, in seconds.
+    pub fn duration(mut self, duration: f32) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.duration = duration.max(0.0);
+        }
+        self
+    }
+
+    //Of the last step.
+    pub fn ease(mut self, ease: Ease) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.ease = ease;
+        }
+        self
+    }
+
+    //Plays the steps of next once this one is there. Mode and callback of next are dropped.
+    pub fn then(mut self, next: Tween<T>) -> Self {
+        self.steps.extend(next.steps);
+        self
+    }
+
+    pub fn mode(mut self, mode: TweenMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    //Runs once the last step is done, never for looping tweens. The tween is already removed then.
+    pub fn on_complete(
+        mut self, callback: impl FnOnce(&mut hecs::World, hecs::Entity) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    //Returns true once the tween is done.
+    fn advance(&mut self, context: &VisContext, target: &mut T::Target, delta: f32) -> bool {
+        let mut delta = delta;
+
+        while !self.steps.is_empty() {
+            let index = match self.reverse {
+                true => self.steps.len() - 1 - self.current,
+                false => self.current,
+            };
+
+            let step = &mut self.steps[index];
+            let from = *step.from.get_or_insert_with(|| T::get(target));
+
+            self.elapsed += delta;
+            let t = match step.duration > 0.0 {
+                true => (self.elapsed / step.duration).min(1.0),
+                false => 1.0,
+            };
+
+            let (from, to) = if self.reverse { (step.to, from) } else { (from, step.to) };
+            T::set(target, context, T::lerp(from, to, step.ease.apply(t)));
+
+            if self.elapsed < step.duration {
+                return false;
+            }
+
+            //The time left over goes to the next step.
+            delta = self.elapsed - step.duration;
+            self.elapsed = 0.0;
+            self.current += 1;
+
+            if self.current == self.steps.len() {
+                self.current = 0;
+
+                match self.mode {
+                    TweenMode::Once => return true,
+                    TweenMode::Loop => {}
+                    TweenMode::PingPong => self.reverse = !self.reverse,
+                }
+            }
+
+            //Steps without duration would spin forever in a loop otherwise.
+            if delta <= 0.0 {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Tween<Vec3> {
+    pub fn position(to: Vec3) -> Self {
+        Self::to(to)
+    }
+}
+
+impl Tween<Vec2> {
+    pub fn scale(to: Vec2) -> Self {
+        Self::to(to)
+    }
+}
+
+impl Tween<f32> {
+    //In radians.
+    pub fn rotation(to: f32) -> Self {
+        Self::to(to)
+    }
+}
+
+impl Tween<Vec4> {
+    pub fn tint(to: Vec4) -> Self {
+        Self::to(to)
+    }
+}
+
+pub struct TweenSystem;
+
+impl TweenSystem {
+    //Values are written through the setters of the components, so the renderer picks them up.
+    pub fn update(context: &VisContext, world: &mut hecs::World, delta: &Timestep) {
+        let delta = delta.seconds() as f32;
+
+        Self::update_tweens::<Vec3>(context, world, delta);
+        Self::update_tweens::<Vec2>(context, world, delta);
+        Self::update_tweens::<f32>(context, world, delta);
+        Self::update_tweens::<Vec4>(context, world, delta);
+    }
+
+    fn update_tweens<T: Tweenable>(context: &VisContext, world: &mut hecs::World, delta: f32) {
+        let mut finished = Vec::new();
+
+        for (entity, (tween, target)) in world.query_mut::<(&mut Tween<T>, &mut T::Target)>() {
+            if tween.advance(context, target, delta) {
+                finished.push(entity);
+            }
+        }
+
+        for entity in finished {
+            if let Ok(tween) = world.remove_one::<Tween<T>>(entity) {
+                if let Some(callback) = tween.on_complete {
+                    callback(world, entity);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,20 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::Path;
 use std::{io::BufReader, path::PathBuf};
 
+use crate::input::Binding;
 use crate::utils::FileUtils;
+use crate::window::WindowConfig;
 use serde::{Deserialize, Serialize};
 
 use super::error::ConfigError;
 
+//Relative to the working directory, holds the engine wide files like config.json.
+const CONFIG_FOLDER: &str = "config";
+const WINDOW_FILE: &str = "window.json";
+const AUDIO_FILE: &str = "audio.json";
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ProjectConfiguration {
     pub project_name: String,
@@ -18,6 +27,17 @@ pub struct ProjectConfiguration {
 
     pub data_folder: Option<PathBuf>,
     pub code_folder: Option<PathBuf>,
+
+    //Fixed seed for the engine Rng. Random if not set.
+    pub random_seed: Option<u64>,
+
+    //Named input actions, see ActionMap.
+    #[serde(default)]
+    pub actions: BTreeMap<String, Vec<Binding>>,
+
+    //Replaces the global theme as a whole, see Config::theme_config.
+    #[serde(default)]
+    pub theme: Option<ThemeConfiguration>,
 }
 
 impl ProjectConfiguration {
@@ -29,6 +49,9 @@ impl ProjectConfiguration {
             location: path,
             data_folder: None,
             code_folder: None,
+            random_seed: None,
+            actions: BTreeMap::new(),
+            theme: None,
         }
     }
 
@@ -52,6 +75,13 @@ impl ProjectConfiguration {
         self
     }
 
+    //The data folder joined to the location. The location may also be the project file itself.
+    pub fn data_path(&self) -> Option<PathBuf> {
+        let location = self.location.as_ref()?;
+        let root = if location.is_file() { location.parent()? } else { location.as_path() };
+        Some(root.join(self.data_folder.as_ref()?))
+    }
+
     pub fn with_code(mut self, path: PathBuf) -> ProjectConfiguration {
         self.code_folder = Some(path);
         self
@@ -61,38 +91,442 @@ impl ProjectConfiguration {
 #[derive(Serialize, Deserialize)]
 pub struct EngineConfiguration {
     pub project_file_extension: String,
+    #[serde(default)]
+    pub debug_bindings: DebugBindings,
+    //Lets the screenshot binding write pngs to the screenshot folder.
+    #[serde(default)]
+    pub screenshots: bool,
+    #[serde(default = "EngineConfiguration::default_screenshot_folder")]
+    pub screenshot_folder: String,
+    //Frame deltas handed to update are clamped to this many ms.
+    #[serde(default = "EngineConfiguration::default_max_delta")]
+    pub max_delta: f64,
+    //Application::fixed_update runs this many times per second.
+    #[serde(default = "EngineConfiguration::default_fixed_rate")]
+    pub fixed_rate: f64,
+    #[serde(default)]
+    pub graphics: GraphicsConfiguration,
+    //Loads files dropped onto the window into the assets and worlds of Application::systems and
+    //sends Event::AssetDropped or Event::WorldDropped. Images, .fur files of the project and .ldtk files.
+    #[serde(default)]
+    pub load_dropped_files: bool,
+    //Touchpad pixels per mouse wheel line in InputState::scroll_delta.
+    #[serde(default = "EngineConfiguration::default_scroll_pixels_per_line")]
+    pub scroll_pixels_per_line: f32,
+    //Also sends the first finger on a touchscreen as cursor moves and left mouse button events.
+    #[serde(default)]
+    pub touch_emulates_mouse: bool,
+    //Radial deadzone of gamepad sticks, 0.15 ignores the innermost 15% of the way.
+    #[serde(default = "EngineConfiguration::default_gamepad_deadzone")]
+    pub gamepad_deadzone: f32,
+    //Lets go of the mouse buttons when the cursor leaves the window, instead of dragging on.
+    #[serde(default)]
+    pub release_mouse_on_cursor_left: bool,
 }
 
 impl Default for EngineConfiguration {
     fn default() -> Self {
-        EngineConfiguration { project_file_extension: "rbe".to_string() }
+        EngineConfiguration {
+            project_file_extension: "rbe".to_string(),
+            debug_bindings: DebugBindings::default(),
+            screenshots: false,
+            screenshot_folder: Self::default_screenshot_folder(),
+            max_delta: Self::default_max_delta(),
+            fixed_rate: Self::default_fixed_rate(),
+            load_dropped_files: false,
+            scroll_pixels_per_line: Self::default_scroll_pixels_per_line(),
+            touch_emulates_mouse: false,
+            gamepad_deadzone: Self::default_gamepad_deadzone(),
+            release_mouse_on_cursor_left: false,
+            graphics: GraphicsConfiguration::default(),
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+impl EngineConfiguration {
+    fn default_screenshot_folder() -> String {
+        "screenshots".to_string()
+    }
+
+    fn default_fixed_rate() -> f64 {
+        60.0
+    }
+
+    fn default_max_delta() -> f64 {
+        crate::utils::MAX_DELTA
+    }
+
+    fn default_scroll_pixels_per_line() -> f32 {
+        40.0
+    }
+
+    fn default_gamepad_deadzone() -> f32 {
+        0.15
+    }
+}
+
+//Which GPU the engine runs on and how it presents. Names are matched case insensitive. Changed
+//through Context and the renderers at runtime, kept with Config::save_engine_config.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphicsConfiguration {
+    //Any of "Vulkan", "Metal", "Dx12", "Gl" and "BrowserWebGpu". Empty allows all of them.
+    #[serde(default)]
+    pub backends: Vec<String>,
+    //"LowPower" prefers the integrated GPU of a laptop, "HighPerformance" the discrete one.
+    #[serde(default = "GraphicsConfiguration::default_power_preference")]
+    pub power_preference: String,
+    //Picks the first adapter whose name contains this, over the power preference.
+    #[serde(default)]
+    pub adapter: Option<String>,
+    //A wgpu::PresentMode, e.g. "AutoVsync", "AutoNoVsync" or "Mailbox".
+    #[serde(default = "GraphicsConfiguration::default_present_mode")]
+    pub present_mode: String,
+    //Samples per pixel, 1 turns msaa off. Counts the adapter doesn't support fall back to 1, the
+    //compatibility list may turn it off as well. true and false are read as 4 and 1.
+    #[serde(default = "GraphicsConfiguration::default_msaa", deserialize_with = "msaa_samples")]
+    pub msaa: u32,
+    //Frames are waited for to stay below this rate. None draws as fast as the present mode lets.
+    #[serde(default)]
+    pub target_fps: Option<u32>,
+    //Width over height of the 2D cameras, the window is letterboxed to it. None fills the window.
+    #[serde(default = "GraphicsConfiguration::default_fixed_aspect")]
+    pub fixed_aspect: Option<f32>,
+    //Of the Renderer2D, where neither background nor world is drawn.
+    #[serde(default = "GraphicsConfiguration::default_clear_color")]
+    pub clear_color: [f32; 4],
+}
+
+impl Default for GraphicsConfiguration {
+    fn default() -> Self {
+        GraphicsConfiguration {
+            backends: Vec::new(),
+            power_preference: Self::default_power_preference(),
+            adapter: None,
+            present_mode: Self::default_present_mode(),
+            msaa: Self::default_msaa(),
+            target_fps: None,
+            fixed_aspect: Self::default_fixed_aspect(),
+            clear_color: Self::default_clear_color(),
+        }
+    }
+}
+
+fn msaa_samples<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Samples {
+        Enabled(bool),
+        Count(u32),
+    }
+
+    Ok(match Samples::deserialize(deserializer)? {
+        Samples::Enabled(true) => GraphicsConfiguration::default_msaa(),
+        Samples::Enabled(false) => 1,
+        Samples::Count(samples) => samples.max(1),
+    })
+}
+
+impl GraphicsConfiguration {
+    fn default_power_preference() -> String {
+        "HighPerformance".to_string()
+    }
+
+    fn default_present_mode() -> String {
+        "AutoVsync".to_string()
+    }
+
+    fn default_msaa() -> u32 {
+        4
+    }
+
+    fn default_fixed_aspect() -> Option<f32> {
+        Some(16.0 / 9.0)
+    }
+
+    fn default_clear_color() -> [f32; 4] {
+        [0.0, 0.0, 0.0, 1.0]
+    }
+
+    pub fn backends(&self) -> wgpu::Backends {
+        let mut backends = wgpu::Backends::empty();
+
+        for name in self.backends.iter() {
+            match name.to_lowercase().as_str() {
+                "vulkan" => backends |= wgpu::Backends::VULKAN,
+                "metal" => backends |= wgpu::Backends::METAL,
+                "dx12" => backends |= wgpu::Backends::DX12,
+                "gl" => backends |= wgpu::Backends::GL,
+                "browserwebgpu" => backends |= wgpu::Backends::BROWSER_WEBGPU,
+                _ => log::warn!("Unknown graphics backend {} in the config.", name),
+            }
+        }
+
+        if backends.is_empty() {
+            wgpu::Backends::all()
+        } else {
+            backends
+        }
+    }
+
+    pub fn power_preference(&self) -> wgpu::PowerPreference {
+        match self.power_preference.to_lowercase().as_str() {
+            "lowpower" => wgpu::PowerPreference::LowPower,
+            "highperformance" => wgpu::PowerPreference::HighPerformance,
+            "none" => wgpu::PowerPreference::None,
+            _ => {
+                log::warn!("Unknown power preference {} in the config.", self.power_preference);
+                wgpu::PowerPreference::HighPerformance
+            }
+        }
+    }
+
+    //None for a name that is no present mode.
+    pub fn present_mode(&self) -> Option<wgpu::PresentMode> {
+        [
+            wgpu::PresentMode::AutoVsync,
+            wgpu::PresentMode::AutoNoVsync,
+            wgpu::PresentMode::Fifo,
+            wgpu::PresentMode::FifoRelaxed,
+            wgpu::PresentMode::Immediate,
+            wgpu::PresentMode::Mailbox,
+        ]
+        .into_iter()
+        .find(|mode| format!("{:?}", mode).eq_ignore_ascii_case(&self.present_mode))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum DebugAction {
+    Exit,
+    ToggleVsync,
+    ToggleFullscreen,
+    DebugOverlay,
+    Console,
+    FrameStep,
+    Screenshot,
+    GpuCapture,
+}
+
+impl fmt::Display for DebugAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+//Engine shortcuts. Keys are winit KeyCode names (e.g. "F1", "KeyV", "Escape"), null disables a single binding.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DebugBindings {
+    #[serde(default = "DebugBindings::default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "DebugBindings::default_bindings")]
+    pub bindings: BTreeMap<DebugAction, Option<String>>,
+}
+
+impl Default for DebugBindings {
+    fn default() -> Self {
+        DebugBindings { enabled: true, bindings: Self::default_bindings() }
+    }
+}
+
+impl DebugBindings {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    //Exit is unbound so games can use Escape for their menus. The engine config can bind it.
+    fn default_bindings() -> BTreeMap<DebugAction, Option<String>> {
+        let mut bindings: BTreeMap<_, _> = [
+            (DebugAction::DebugOverlay, "F1"),
+            (DebugAction::ToggleVsync, "F2"),
+            (DebugAction::FrameStep, "F6"),
+            (DebugAction::GpuCapture, "F10"),
+            (DebugAction::ToggleFullscreen, "F11"),
+            (DebugAction::Screenshot, "F12"),
+            (DebugAction::Console, "Backquote"),
+        ]
+        .into_iter()
+        .map(|(action, key)| (action, Some(key.to_string())))
+        .collect();
+
+        bindings.insert(DebugAction::Exit, None);
+        bindings
+    }
+
+    //Shipped games can turn off all engine shortcuts at once.
+    pub fn disabled() -> Self {
+        DebugBindings { enabled: false, bindings: BTreeMap::new() }
+    }
+
+    pub fn action_for(&self, keycode: &winit::keyboard::KeyCode) -> Option<DebugAction> {
+        if !self.enabled {
+            return None;
+        }
+
+        let name = format!("{:?}", keycode);
+
+        self.bindings
+            .iter()
+            .find(|(_, key)| key.as_deref() == Some(name.as_str()))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn active(&self) -> Vec<(DebugAction, &str)> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        self.bindings
+            .iter()
+            .filter_map(|(action, key)| key.as_deref().map(|key| (*action, key)))
+            .collect()
+    }
+}
+
+//Files are looked up in the data folder of the project first, then in the themes folder. See
+//Config::theme_folders.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ThemeConfiguration {
-    pub background_music: String,
+    //Background music, played one after the other by AudioEngine::play_background. A single file
+    //name is read as a playlist of one, like the old background_music.
+    #[serde(default, alias = "background_music", deserialize_with = "one_or_many")]
+    pub playlist: Vec<String>,
+    //Plays the playlist in a new random order every time around.
+    #[serde(default)]
+    pub shuffle: bool,
+    //Starts over after the last track instead of going silent.
+    #[serde(default = "ThemeConfiguration::default_repeat")]
+    pub repeat: bool,
+    //Played by AudioEngine::play_click.
+    #[serde(default)]
+    pub click_sound: Option<String>,
 }
 
 impl Default for ThemeConfiguration {
     fn default() -> Self {
-        ThemeConfiguration { background_music: "default.mp3".to_string() }
+        ThemeConfiguration {
+            playlist: vec!["default.mp3".to_string()],
+            shuffle: false,
+            repeat: Self::default_repeat(),
+            click_sound: None,
+        }
     }
 }
 
+impl ThemeConfiguration {
+    fn default_repeat() -> bool {
+        true
+    }
+}
+
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(file) => vec![file],
+        OneOrMany::Many(files) => files,
+    })
+}
+
+//Volumes of the AudioEngine buses, from 0 (silent) to 1. Music and sound effects are multiplied
+//with the master volume.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct AudioConfiguration {
+    #[serde(default = "AudioConfiguration::default_volume")]
+    pub master_volume: f32,
+    #[serde(default = "AudioConfiguration::default_music_volume")]
+    pub music_volume: f32,
+    #[serde(default = "AudioConfiguration::default_volume")]
+    pub sfx_volume: f32,
+}
+
+impl Default for AudioConfiguration {
+    fn default() -> Self {
+        AudioConfiguration {
+            master_volume: Self::default_volume(),
+            music_volume: Self::default_music_volume(),
+            sfx_volume: Self::default_volume(),
+        }
+    }
+}
+
+impl AudioConfiguration {
+    fn default_volume() -> f32 {
+        1.0
+    }
+
+    //About -20 dB, the background music is meant to stay in the background.
+    fn default_music_volume() -> f32 {
+        0.67
+    }
+}
+
+//An entry of the compatibility list. Matches adapters whose name or driver contains the given string.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CompatibilityEntry {
+    pub adapter: String,
+    #[serde(default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub warning: Option<String>,
+    #[serde(default)]
+    pub disable_msaa: bool,
+    #[serde(default)]
+    pub disable_compressed_textures: bool,
+}
+
+impl CompatibilityEntry {
+    pub fn matches(&self, info: &wgpu::AdapterInfo) -> bool {
+        let pattern = self.adapter.to_lowercase();
+
+        let adapter_matches = info.name.to_lowercase().contains(&pattern)
+            || info.driver.to_lowercase().contains(&pattern)
+            || info.driver_info.to_lowercase().contains(&pattern);
+
+        let backend_matches = self
+            .backend
+            .as_ref()
+            .map_or(true, |backend| format!("{:?}", info.backend).eq_ignore_ascii_case(backend));
+
+        adapter_matches && backend_matches
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct CompatibilityConfiguration {
+    pub entries: Vec<CompatibilityEntry>,
+}
+
 pub struct Config {
     engine_config: EngineConfiguration,
     theme_config: ThemeConfiguration,
+    audio_config: AudioConfiguration,
     project_config: ProjectConfiguration,
+    //The .rbe file the project was loaded from or created as, written by save_project.
+    project_file: Option<PathBuf>,
+    compat_config: CompatibilityConfiguration,
 }
 
 impl Config {
     pub fn new(project_config: Option<ProjectConfiguration>) -> Self {
         let engine_config = Config::load_engine_config();
         let theme_config = Config::load_theme_config(&engine_config);
+        let audio_config = Config::load_audio_config();
         let project_config = project_config.unwrap_or(ProjectConfiguration::new(None));
-
-        Config { engine_config, theme_config, project_config }
+        let compat_config = Config::load_compat_config();
+
+        Config {
+            engine_config,
+            theme_config,
+            audio_config,
+            project_config,
+            project_file: None,
+            compat_config,
+        }
     }
 
     pub fn exist_project(&self, path: &Path) -> bool {
@@ -132,6 +566,7 @@ impl Config {
                         Ok(configuration) => {
                             self.project_config = configuration;
                             self.project_config.location = Some(path.to_path_buf());
+                            self.project_file = Some(file_path.to_path_buf());
                             return Ok(());
                         }
                     }
@@ -142,37 +577,18 @@ impl Config {
         Err(ConfigError::NotFound)
     }
 
-    pub fn create_project(&mut self, config: ProjectConfiguration) {
-        let path = if let Some(path) = &config.location {
-            if self.exist_project(path.as_path()) {
-                log::error!("Project {} already exists.", path.display());
-                return;
-            }
+    pub fn create_project(&mut self, config: ProjectConfiguration) -> Result<(), ConfigError> {
+        let path = config.location.as_deref().ok_or(ConfigError::NoLocation)?;
 
-            path.as_path()
-        } else {
-            log::error!(
-                "Cannot create Project without location. Maybe you want to load a project instead?"
-            );
-            return;
-        };
-
-        if path.is_file() {
-            log::error!("Invalid path {}. Project path must be a folder.", path.display());
-            return;
+        if self.exist_project(path) {
+            return Err(ConfigError::AlreadyExists(path.to_path_buf()));
         }
 
-        if let Err(e) = std::fs::create_dir_all(path) {
-            log::error!("Could not create project directory. Message: {}.", e);
-            return;
+        if path.is_file() {
+            return Err(ConfigError::InvalidPath(path.to_path_buf()));
         }
 
-        if FileUtils::find_ext_in_dir(path, self.engine_config.project_file_extension.as_str())
-            .is_some()
-        {
-            log::error!("Project {} already exists.", path.display());
-            return;
-        }
+        std::fs::create_dir_all(path).map_err(ConfigError::Io)?;
 
         let mut file_name = config.project_name.clone();
         file_name.push('.');
@@ -180,34 +596,168 @@ impl Config {
 
         let file_path = path.join(file_name);
 
-        let file = std::fs::File::create(file_path.clone());
+        let contents = serde_json::to_string_pretty(&config).map_err(ConfigError::JsonError)?;
+        Self::write_atomic(&file_path, &contents)?;
 
-        if file.is_ok() {
-            self.project_config = config;
+        self.project_config = config;
+        self.project_file = Some(file_path);
+        Ok(())
+    }
 
-            if let Err(e) = std::fs::write(
-                file_path.clone(),
-                serde_json::to_string(&self.project_config).unwrap_or("{}".to_string()),
-            ) {
-                log::error!("Could not create {}. {}", file_path.display(), e);
+    //Writes the project back to the file it was loaded from or created as. Fields of the file the
+    //engine doesn't know, e.g. added by other tools, are kept.
+    pub fn save_project(&self) -> Result<(), ConfigError> {
+        let file_path = self.project_file.as_deref().ok_or(ConfigError::NotFound)?;
+
+        let known = serde_json::to_value(&self.project_config).map_err(ConfigError::JsonError)?;
+        let existing = std::fs::read_to_string(file_path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+
+        let value = match (existing, known) {
+            (Some(serde_json::Value::Object(mut existing)), serde_json::Value::Object(known)) => {
+                existing.extend(known);
+                serde_json::Value::Object(existing)
             }
-        }
+            (_, known) => known,
+        };
+
+        let contents = serde_json::to_string_pretty(&value).map_err(ConfigError::JsonError)?;
+        Self::write_atomic(file_path, &contents)
+    }
+
+    //Goes through a temporary file next to it, so a crash while writing can't leave half a
+    //project file behind.
+    fn write_atomic(path: &Path, contents: &str) -> Result<(), ConfigError> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        std::fs::write(&temp, contents)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(ConfigError::Io)
     }
 
     pub fn engine_config(&self) -> &EngineConfiguration {
         &self.engine_config
     }
 
+    //Changes are kept in memory until save_engine_config.
+    pub fn engine_config_mut(&mut self) -> &mut EngineConfiguration {
+        &mut self.engine_config
+    }
+
+    //Writes the engine config back to config/config.json, e.g. after the graphics settings changed.
+    //Also done on exit.
+    pub fn save_engine_config(&self) -> Result<(), ConfigError> {
+        Self::save_json(Self::config_folder(), "config.json", &self.engine_config)
+    }
+
+    //Done on exit, see saved_window_config.
+    pub fn save_window_config(window: &WindowConfig) -> Result<(), ConfigError> {
+        Self::save_json(Self::config_folder(), WINDOW_FILE, window)
+    }
+
+    //The window state saved on the last exit, or "{}" if there is none. Can be passed to Window::new.
+    pub fn saved_window_config() -> String {
+        std::fs::read_to_string(Self::config_folder().join(WINDOW_FILE)).unwrap_or("{}".to_string())
+    }
+
+    fn save_json(folder: &Path, file: &str, value: &impl Serialize) -> Result<(), ConfigError> {
+        let contents = serde_json::to_string_pretty(value).map_err(ConfigError::JsonError)?;
+
+        std::fs::create_dir_all(folder).map_err(ConfigError::Io)?;
+        Self::write_atomic(&folder.join(file), &contents)
+    }
+
+    //The theme of the project if it has one, the global one otherwise.
     pub fn theme_config(&self) -> &ThemeConfiguration {
-        &self.theme_config
+        self.project_config.theme.as_ref().unwrap_or(&self.theme_config)
+    }
+
+    //Where the files of the theme are looked up, in this order.
+    pub fn theme_folders(&self) -> Vec<PathBuf> {
+        self.project_config.data_path().into_iter().chain([PathBuf::from("themes")]).collect()
+    }
+
+    pub fn audio_config(&self) -> &AudioConfiguration {
+        &self.audio_config
+    }
+
+    //Keeps the volumes for the next start, e.g. AudioEngine::configuration from a settings menu.
+    //They are used for this run even if saving fails.
+    pub fn set_audio_config(&mut self, config: AudioConfiguration) -> Result<(), ConfigError> {
+        self.audio_config = config;
+        Self::save_json(Self::config_folder(), AUDIO_FILE, &self.audio_config)
     }
 
     pub fn project_config(&self) -> &ProjectConfiguration {
         &self.project_config
     }
 
+    //Changes are kept in memory until save_project.
+    pub fn project_config_mut(&mut self) -> &mut ProjectConfiguration {
+        &mut self.project_config
+    }
+
+    pub fn project_file(&self) -> Option<&Path> {
+        self.project_file.as_deref()
+    }
+
+    pub fn compat_config(&self) -> &CompatibilityConfiguration {
+        &self.compat_config
+    }
+
+    //Where the engine keeps its own files, e.g. the console history.
+    pub fn config_folder() -> &'static Path {
+        Path::new(CONFIG_FOLDER)
+    }
+
+    //Written by set_audio_config. Until then the defaults are used.
+    fn load_audio_config() -> AudioConfiguration {
+        let config = Self::config_folder().join(AUDIO_FILE);
+
+        let file = match std::fs::File::open(config.clone()) {
+            Ok(file) => file,
+            Err(_) => return AudioConfiguration::default(),
+        };
+
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(conf) => conf,
+            Err(e) => {
+                log::error!(
+                    "Failed to parse {}. Message: {}. Defaulting...",
+                    config.to_str().unwrap_or("ERR_NON_UTF8_PATH"),
+                    e
+                );
+                AudioConfiguration::default()
+            }
+        }
+    }
+
+    //The compatibility list is shipped with the game. If there is none, nothing gets overridden.
+    fn load_compat_config() -> CompatibilityConfiguration {
+        let config = Self::config_folder().join("compat.json");
+
+        let file = match std::fs::File::open(config.clone()) {
+            Ok(file) => file,
+            Err(_) => return CompatibilityConfiguration::default(),
+        };
+
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(conf) => conf,
+            Err(e) => {
+                log::error!(
+                    "Failed to parse {}. Message: {}. Ignoring compatibility list...",
+                    config.to_str().unwrap_or("ERR_NON_UTF8_PATH"),
+                    e
+                );
+                CompatibilityConfiguration::default()
+            }
+        }
+    }
+
     fn load_engine_config() -> EngineConfiguration {
-        let config_folder = Path::new("config");
+        let config_folder = Self::config_folder();
         let config = config_folder.join("config.json");
 
         if let Err(e) = std::fs::create_dir_all(config_folder) {
@@ -302,3 +852,76 @@ impl Config {
         conf.unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustybear_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn runtime_graphics_settings_survive_a_restart() {
+        let folder = temp_folder("engine_config");
+        let mut config = EngineConfiguration::default();
+        config.graphics.msaa = 1;
+        config.graphics.present_mode = "AutoNoVsync".to_string();
+
+        Config::save_json(&folder, "config.json", &config).unwrap();
+        //A second save replaces the file instead of failing on the leftover.
+        config.graphics.msaa = 8;
+        Config::save_json(&folder, "config.json", &config).unwrap();
+
+        let contents = std::fs::read_to_string(folder.join("config.json")).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        let loaded: EngineConfiguration = serde_json::from_str(&contents).unwrap();
+        assert_eq!(loaded.graphics.msaa, 8);
+        assert_eq!(loaded.graphics.present_mode(), Some(wgpu::PresentMode::AutoNoVsync));
+    }
+
+    #[test]
+    fn debug_bindings_without_enabled_still_load() {
+        let bindings: DebugBindings =
+            serde_json::from_str(r#"{"bindings":{"Exit":null,"Console":"F3"}}"#).unwrap();
+
+        assert!(bindings.enabled);
+        assert_eq!(bindings.action_for(&winit::keyboard::KeyCode::F3), Some(DebugAction::Console));
+        assert_eq!(bindings.action_for(&winit::keyboard::KeyCode::Escape), None);
+
+        let bindings: DebugBindings = serde_json::from_str("{}").unwrap();
+        assert_eq!(bindings.active(), DebugBindings::default().active());
+    }
+
+    #[test]
+    fn escape_does_not_exit_by_default() {
+        let bindings = DebugBindings::default();
+
+        assert_eq!(bindings.action_for(&winit::keyboard::KeyCode::Escape), None);
+        assert!(bindings.active().iter().all(|(action, _)| *action != DebugAction::Exit));
+    }
+
+    #[test]
+    fn window_state_round_trips() {
+        let folder = temp_folder("window_config");
+        let window = WindowConfig {
+            size: (1280, 720),
+            title: "Test".to_string(),
+            position: (10.0, 20.0),
+            resizeable: false,
+            fullscreen: false,
+            visible: true,
+            border: true,
+        };
+
+        Config::save_json(&folder, WINDOW_FILE, &window).unwrap();
+        let contents = std::fs::read_to_string(folder.join(WINDOW_FILE)).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        let loaded: WindowConfig = serde_json::from_str(&contents).unwrap();
+        assert_eq!(loaded.size, (1280, 720));
+        assert_eq!(loaded.position, (10.0, 20.0));
+        assert!(!loaded.resizeable);
+    }
+}
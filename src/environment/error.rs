@@ -1,6 +1,70 @@
+use std::fmt;
+use std::path::PathBuf;
+
 #[derive(Debug)]
 pub enum ConfigError {
     Io(std::io::Error),
     JsonError(serde_json::Error),
     NotFound,
+    //There already is a project file at or in the path.
+    AlreadyExists(PathBuf),
+    //The project has no location to be created at.
+    NoLocation,
+    //Projects are created in a folder, not at a file.
+    InvalidPath(PathBuf),
 }
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Could not access the config file: {}", error),
+            ConfigError::JsonError(error) => write!(f, "Failed to parse the config: {}", error),
+            ConfigError::NotFound => write!(f, "No project file found."),
+            ConfigError::AlreadyExists(path) => {
+                write!(f, "Project {} already exists.", path.display())
+            }
+            ConfigError::NoLocation => write!(
+                f,
+                "Cannot create Project without location. Maybe you want to load a project instead?"
+            ),
+            ConfigError::InvalidPath(path) => {
+                write!(f, "Invalid path {}. Project path must be a folder.", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+//Why Context::new could not set up the graphics.
+#[derive(Debug)]
+pub enum ContextError {
+    SurfaceCreation(wgpu::CreateSurfaceError),
+    //No adapter of the configured backends can draw to the window.
+    NoAdapter,
+    DeviceRequest(wgpu::RequestDeviceError),
+    //The adapter was found but can't be used, e.g. the surface has no texture format.
+    Unsupported(String),
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextError::SurfaceCreation(error) => {
+                write!(f, "Failed to create a surface for the window: {}", error)
+            }
+            ContextError::NoAdapter => write!(
+                f,
+                "No graphics adapter can draw to the window. Are the GPU drivers installed?"
+            ),
+            ContextError::DeviceRequest(error) => {
+                write!(f, "Failed to request a graphics device: {}", error)
+            }
+            ContextError::Unsupported(reason) => {
+                write!(f, "The graphics adapter is not supported: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
@@ -1,12 +1,26 @@
+use std::any::Any;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use gilrs::GamepadId;
 use winit::{
     event::{MouseScrollDelta, WindowEvent},
-    keyboard::PhysicalKey,
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
 
+use crate::assets::assets::GenPtr;
 use crate::context::Context;
+use crate::environment::config::DebugAction;
+use crate::utils::Guid;
+
+//A gamepad as the engine sees it, the index gilrs gave it. Stays the same while it is connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadId(pub usize);
+
+impl From<gilrs::GamepadId> for GamepadId {
+    fn from(id: gilrs::GamepadId) -> Self {
+        GamepadId(id.into())
+    }
+}
 
 #[derive(Clone, PartialEq)]
 pub enum GamepadButtonState {
@@ -19,6 +33,8 @@ pub enum GamepadButtonState {
 pub enum Event {
     //Winit Events
     Resized { width: u32, height: u32 },
+    //The window moved to a monitor with another DPI. A Resized with the new size follows.
+    ScaleFactorChanged { scale: f64 },
     Moved { x: i32, y: i32 },
     CloseRequested,
     Destroyed,
@@ -26,7 +42,8 @@ pub enum Event {
     HoveredFile(PathBuf),
     HoveredFileCancelled,
     Focused(bool),
-    KeyboardInput { keycode: winit::keyboard::KeyCode, state: winit::event::ElementState },
+    //Modifiers are the ones held when the key changed, e.g. ctrl for Ctrl+S.
+    KeyboardInput { keycode: KeyCode, state: winit::event::ElementState, modifiers: ModifiersState },
     ModifiersChanged(winit::event::Modifiers),
     CursorMoved { x: f64, y: f64 },
     CursorEntered,
@@ -34,8 +51,30 @@ pub enum Event {
     MouseWheel { delta_x: f64, delta_y: f64, state: winit::event::TouchPhase },
     MouseScroll { delta_x: f32, delta_y: f32, state: winit::event::TouchPhase },
     MouseInput { mousecode: winit::event::MouseButton, state: winit::event::ElementState },
+    //Raw movement of the mouse, unaffected by the window edges and cursor grabs. Not in pixels,
+    //the unit depends on the platform and mouse.
+    MouseMotion { delta_x: f64, delta_y: f64 },
+    //A finger on a touchscreen, in physical pixels. The id stays the same until it is lifted.
+    Touch { id: u64, phase: winit::event::TouchPhase, x: f64, y: f64 },
     Unknown,
 
+    //Engine Events
+    DebugAction(DebugAction),
+    //Sent by the camera.pos console command.
+    CameraMoveTo { x: f32, y: f32 },
+    //The gpu was reset. Nothing created on the old device can be used anymore.
+    DeviceLost,
+    //Sent after Context::rebuild_graphics, recreate pipelines, buffers and textures on the new device.
+    DeviceRestored,
+    //A file dropped onto the window finished loading, see EngineConfiguration::load_dropped_files.
+    //Images are a Texture2D.
+    AssetDropped { ptr: GenPtr, path: PathBuf },
+    //The levels of a dropped ldtk file were added to the worlds, this is the first one. It is not
+    //started, switch to it to show it.
+    WorldDropped { world: Guid, path: PathBuf },
+    //Sent by the game through Context::emit, read it with Event::custom.
+    Custom(Arc<dyn Any + Send + Sync>),
+
     //gilrs Events todo
     GamepadInput { id: GamepadId, buttoncode: gilrs::Button, state: GamepadButtonState },
     GamepadInputChanged { id: GamepadId, scancode: u32, value: f32 },
@@ -45,6 +84,16 @@ pub enum Event {
     GamepadDropped { id: GamepadId },
 }
 
+impl Event {
+    //The event passed to Context::emit, if it is a T.
+    pub fn custom<T: Any>(&self) -> Option<&T> {
+        match self {
+            Event::Custom(event) => event.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum EventType {
     App,
@@ -55,107 +104,185 @@ pub trait EventSubscriber {
     fn on_event(&mut self, event: &Event, context: &mut Context) -> bool;
 }
 
-type EventCallback<'a> = Box<dyn FnMut(&Event, &mut Context) -> bool + 'a>;
+//None once the subscriber is gone, it is removed then.
+type EventCallback<'a> = Box<dyn FnMut(&Event, &mut Context) -> Option<bool> + 'a>;
+
+//Returned by EventStack::push and ModuleStack::subscribe, stays valid until unsubscribed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+struct Subscription<'a> {
+    id: SubscriptionId,
+    callback: EventCallback<'a>,
+    dropped: bool,
+}
 
 #[derive(Default)]
 pub struct EventStack<'a> {
-    input_stack: Vec<EventCallback<'a>>,
-    app_stack: Vec<EventCallback<'a>>,
+    input_stack: Vec<Subscription<'a>>,
+    app_stack: Vec<Subscription<'a>>,
+    next_id: u64,
 }
 
 impl<'a> EventStack<'a> {
     pub fn new() -> EventStack<'a> {
-        EventStack { input_stack: Vec::new(), app_stack: Vec::new() }
+        EventStack::default()
     }
 
     pub fn push(
-        &mut self, event_type: EventType, callback: impl FnMut(&Event, &mut Context) -> bool + 'a,
-    ) -> usize {
-        match event_type {
-            EventType::App => {
-                self.app_stack.push(Box::new(callback));
+        &mut self, event_type: EventType,
+        mut callback: impl FnMut(&Event, &mut Context) -> bool + 'a,
+    ) -> SubscriptionId {
+        self.push_dropping(event_type, move |event, context| Some(callback(event, context)))
+    }
 
-                self.app_stack.len() - 1
-            }
+    //Like push, the callback returns None when its subscriber is gone.
+    pub(crate) fn push_dropping(
+        &mut self, event_type: EventType,
+        callback: impl FnMut(&Event, &mut Context) -> Option<bool> + 'a,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
 
-            EventType::Layer => {
-                self.input_stack.push(Box::new(callback));
+        let subscription = Subscription { id, callback: Box::new(callback), dropped: false };
 
-                self.input_stack.len() - 1
-            }
+        match event_type {
+            EventType::App => self.app_stack.push(subscription),
+            EventType::Layer => self.input_stack.push(subscription),
         }
+
+        id
     }
 
-    pub fn swap(&mut self, lhs: usize, rhs: usize) {
-        self.input_stack.swap(lhs, rhs);
+    //Swaps the order of two layers. False if one of them isn't a layer.
+    pub fn swap(&mut self, lhs: SubscriptionId, rhs: SubscriptionId) -> bool {
+        let position = |id| self.input_stack.iter().position(|subscription| subscription.id == id);
+
+        match (position(lhs), position(rhs)) {
+            (Some(lhs), Some(rhs)) => {
+                self.input_stack.swap(lhs, rhs);
+                true
+            }
+            _ => false,
+        }
     }
 
     pub fn propagate_event(&mut self, event: &Event, context: &mut Context) -> bool {
         self.propagate_app_event(event, context);
 
-        for callback in self.input_stack.iter_mut().rev() {
-            if (callback)(event, context) {
-                return true;
+        let mut handled = false;
+
+        for subscription in self.input_stack.iter_mut().rev() {
+            match (subscription.callback)(event, context) {
+                Some(true) => {
+                    handled = true;
+                    break;
+                }
+                Some(false) => {}
+                None => subscription.dropped = true,
             }
         }
 
-        false
+        self.input_stack.retain(|subscription| !subscription.dropped);
+        handled
     }
 
     pub fn propagate_app_event(&mut self, event: &Event, context: &mut Context) -> bool {
-        for callback in self.app_stack.iter_mut() {
-            if !(callback)(event, context) {
-                log::error!("Error while processing event. Application layer returned false.");
-                return false;
+        let mut success = true;
+
+        for subscription in self.app_stack.iter_mut() {
+            match (subscription.callback)(event, context) {
+                Some(true) => {}
+                Some(false) => {
+                    log::error!("Error while processing event. Application layer returned false.");
+                    success = false;
+                    break;
+                }
+                None => subscription.dropped = true,
             }
         }
 
-        true
+        self.app_stack.retain(|subscription| !subscription.dropped);
+        success
+    }
+
+    //Removes the topmost subscription of that type.
+    pub fn pop(&mut self, event_type: EventType) -> Option<SubscriptionId> {
+        let stack = match event_type {
+            EventType::App => &mut self.app_stack,
+            EventType::Layer => &mut self.input_stack,
+        };
+
+        stack.pop().map(|subscription| subscription.id)
     }
 
-    pub fn pop(&mut self) -> usize {
-        self.input_stack.pop();
+    //False if it was already removed. Other ids stay valid.
+    pub fn remove(&mut self, id: SubscriptionId) -> bool {
+        for stack in [&mut self.app_stack, &mut self.input_stack] {
+            if let Some(position) = stack.iter().position(|subscription| subscription.id == id) {
+                let _dying_closure = stack.remove(position);
+                return true;
+            }
+        }
 
-        self.input_stack.len() - 1
+        false
     }
+}
 
-    pub fn remove(&mut self, index: usize) {
-        let _dying_closure = self.app_stack.remove(index);
+//Where the engine gets gamepad events from. gilrs, or anything else producing the Gamepad events
+//of the engine, e.g. a replay.
+pub trait GamepadEventSource {
+    fn next_event(&mut self) -> Option<Event>;
+}
+
+//Everything that came in since the last frame, so a tap and release in one frame both arrive.
+//The engine delivers them to the layers, which includes the InputState.
+pub(crate) fn pump_gamepad_events(
+    source: &mut impl GamepadEventSource, mut deliver: impl FnMut(&Event),
+) {
+    while let Some(event) = source.next_event() {
+        deliver(&event);
+    }
+}
+
+impl GamepadEventSource for gilrs::Gilrs {
+    fn next_event(&mut self) -> Option<Event> {
+        gilrs::Gilrs::next_event(self).map(|event| to_gamepad_event(&event))
     }
 }
 
 pub fn to_gamepad_event(event: &gilrs::Event) -> Event {
+    let id = GamepadId::from(event.id);
+
     match event.event {
-        gilrs::EventType::Connected => Event::GamepadConnected { id: event.id },
-        gilrs::EventType::Disconnected => Event::GamepadDisconnected { id: event.id },
-        gilrs::EventType::ButtonPressed(button, ..) => Event::GamepadInput {
-            id: event.id,
-            buttoncode: button,
-            state: GamepadButtonState::Pressed,
-        },
-        gilrs::EventType::ButtonReleased(button, ..) => Event::GamepadInput {
-            id: event.id,
-            buttoncode: button,
-            state: GamepadButtonState::Released,
-        },
-        gilrs::EventType::ButtonRepeated(button, ..) => Event::GamepadInput {
-            id: event.id,
-            buttoncode: button,
-            state: GamepadButtonState::Repeated,
-        },
+        gilrs::EventType::Connected => Event::GamepadConnected { id },
+        gilrs::EventType::Disconnected => Event::GamepadDisconnected { id },
+        gilrs::EventType::ButtonPressed(button, ..) => {
+            Event::GamepadInput { id, buttoncode: button, state: GamepadButtonState::Pressed }
+        }
+        gilrs::EventType::ButtonReleased(button, ..) => {
+            Event::GamepadInput { id, buttoncode: button, state: GamepadButtonState::Released }
+        }
+        gilrs::EventType::ButtonRepeated(button, ..) => {
+            Event::GamepadInput { id, buttoncode: button, state: GamepadButtonState::Repeated }
+        }
         gilrs::EventType::ButtonChanged(button, value, ..) => {
-            Event::GamepadInputChanged { id: event.id, scancode: button as u32, value }
+            Event::GamepadInputChanged { id, scancode: button as u32, value }
         }
         gilrs::EventType::AxisChanged(axis, value, ..) => {
-            Event::GamepadAxis { id: event.id, axiscode: axis, value }
+            Event::GamepadAxis { id, axiscode: axis, value }
         }
         _ => Event::Unknown,
     }
 }
 
-pub fn to_event(event: &WindowEvent) -> Event {
+//Modifiers are the ones currently held, the window events don't carry them.
+pub fn to_event(event: &WindowEvent, modifiers: ModifiersState) -> Event {
     match event {
         WindowEvent::Resized(size) => Event::Resized { width: size.width, height: size.height },
+        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            Event::ScaleFactorChanged { scale: *scale_factor }
+        }
         WindowEvent::Moved(pos) => Event::Moved { x: pos.x, y: pos.y },
         WindowEvent::CloseRequested => Event::CloseRequested,
         WindowEvent::Destroyed => Event::Destroyed,
@@ -165,7 +292,7 @@ pub fn to_event(event: &WindowEvent) -> Event {
         WindowEvent::Focused(focused) => Event::Focused(*focused),
         WindowEvent::KeyboardInput { event, .. } => {
             if let PhysicalKey::Code(code) = event.physical_key {
-                Event::KeyboardInput { keycode: code, state: event.state }
+                Event::KeyboardInput { keycode: code, state: event.state, modifiers }
             } else {
                 //TODO support non standard keys.
                 Event::Unknown
@@ -188,6 +315,12 @@ pub fn to_event(event: &WindowEvent) -> Event {
         WindowEvent::MouseInput { state, button, .. } => {
             Event::MouseInput { mousecode: *button, state: *state }
         }
+        WindowEvent::Touch(touch) => Event::Touch {
+            id: touch.id,
+            phase: touch.phase,
+            x: touch.location.x,
+            y: touch.location.y,
+        },
 
         _ => Event::Unknown,
     }
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use hashbrown::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+//A channel of the event log. Topics are plain names, so the console can refer to them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Topic(pub &'static str);
+
+impl Topic {
+    //Emitted by the engine, for applications that hand out their worlds and assets through Application::systems.
+    pub const WORLD_SWITCHED: Topic = Topic("world.switched");
+    pub const ASSET_FAILED: Topic = Topic("asset.failed");
+    pub const ENTITY_DESPAWNED: Topic = Topic("entity.despawned");
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Record {
+    pub topic: &'static str,
+    pub frame: u64,
+    pub payload: serde_json::Value,
+}
+
+struct Channel {
+    records: VecDeque<Record>,
+    capacity: usize,
+    //Records pushed out because nobody drained them in time.
+    overflowed: u64,
+}
+
+impl Channel {
+    fn new(capacity: usize) -> Self {
+        Channel { records: VecDeque::new(), capacity, overflowed: 0 }
+    }
+}
+
+//Things that happened in the game, e.g. for achievements or analytics, without the systems knowing each other.
+//Every topic is a bounded ring. Consumers drain the topics they care about once per frame.
+pub struct EventLog {
+    channels: HashMap<Topic, Channel>,
+    capacity: usize,
+    frame: u64,
+    //Every record also goes here as one json object per line.
+    sink: Option<BufWriter<File>>,
+    unflushed: bool,
+    tail: Option<String>,
+    tailed: Vec<String>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Capacity of every topic that has no capacity of its own.
+    pub fn with_capacity(capacity: usize) -> Self {
+        EventLog {
+            channels: HashMap::new(),
+            capacity: capacity.max(1),
+            frame: 0,
+            sink: None,
+            unflushed: false,
+            tail: None,
+            tailed: Vec::new(),
+        }
+    }
+
+    pub fn set_capacity(&mut self, topic: Topic, capacity: usize) {
+        let channel = self.channels.entry(topic).or_insert_with(|| Channel::new(capacity));
+        channel.capacity = capacity.max(1);
+
+        while channel.records.len() > channel.capacity {
+            channel.records.pop_front();
+            channel.overflowed += 1;
+        }
+    }
+
+    pub fn emit(&mut self, topic: Topic, payload: impl Serialize) {
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::warn!("Dropped event on {}, the payload does not serialize. {}", topic.0, e);
+                return;
+            }
+        };
+
+        let record = Record { topic: topic.0, frame: self.frame, payload };
+
+        if let Some(sink) = &mut self.sink {
+            let written = serde_json::to_writer(&mut *sink, &record)
+                .map_err(io::Error::from)
+                .and_then(|_| sink.write_all(b"\n"));
+
+            if let Err(e) = written {
+                log::error!("Could not write to the event log file, closing it. {}", e);
+                self.sink = None;
+            } else {
+                self.unflushed = true;
+            }
+        }
+
+        if self.tail.as_deref() == Some(topic.0) {
+            self.tailed.push(format!("[{}] {} {}", record.frame, topic.0, record.payload));
+        }
+
+        let capacity = self.capacity;
+        let channel = self.channels.entry(topic).or_insert_with(|| Channel::new(capacity));
+
+        if channel.records.len() >= channel.capacity {
+            channel.records.pop_front();
+            channel.overflowed += 1;
+        }
+
+        channel.records.push_back(record);
+    }
+
+    //Oldest first.
+    pub fn drain(&mut self, topic: Topic) -> impl Iterator<Item = Record> + '_ {
+        self.channels.get_mut(&topic).into_iter().flat_map(|channel| channel.records.drain(..))
+    }
+
+    //Drains the payloads as T. Payloads of another shape are skipped.
+    pub fn drain_as<T: DeserializeOwned>(&mut self, topic: Topic) -> impl Iterator<Item = T> + '_ {
+        self.drain(topic).filter_map(|record| serde_json::from_value(record.payload).ok())
+    }
+
+    //Without removing them.
+    pub fn peek(&self, topic: Topic) -> impl Iterator<Item = &Record> + '_ {
+        self.channels.get(&topic).into_iter().flat_map(|channel| channel.records.iter())
+    }
+
+    pub fn len(&self, topic: Topic) -> usize {
+        self.channels.get(&topic).map_or(0, |channel| channel.records.len())
+    }
+
+    pub fn is_empty(&self, topic: Topic) -> bool {
+        self.len(topic) == 0
+    }
+
+    pub fn overflowed(&self, topic: Topic) -> u64 {
+        self.channels.get(&topic).map_or(0, |channel| channel.overflowed)
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = Topic> + '_ {
+        self.channels.keys().copied()
+    }
+
+    //Frames counted by the engine, stored with every record.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    //Appends every record to a jsonl file, e.g. to capture a playtest.
+    pub fn open_sink<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        self.close_sink();
+        self.sink = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    pub fn close_sink(&mut self) {
+        if let Some(mut sink) = self.sink.take() {
+            if let Err(e) = sink.flush() {
+                log::error!("Could not write to the event log file. {}", e);
+            }
+        }
+    }
+
+    //Copies every record of the topic to the console as it comes in. None stops.
+    pub fn set_tail(&mut self, topic: Option<&str>) {
+        self.tail = topic.map(str::to_string);
+    }
+
+    pub fn tail(&self) -> Option<&str> {
+        self.tail.as_deref()
+    }
+
+    pub(crate) fn take_tailed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.tailed)
+    }
+
+    pub(crate) fn next_frame(&mut self) {
+        self.frame += 1;
+
+        if std::mem::take(&mut self.unflushed) {
+            if let Some(sink) = &mut self.sink {
+                let _ = sink.flush();
+            }
+        }
+    }
+}
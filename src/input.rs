@@ -1,20 +1,189 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use winit::event::ElementState;
+use glam::Vec2;
+use rccell::RcCell;
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, TouchPhase};
+use winit::keyboard::ModifiersState;
 
 use crate::{
     context::Context,
-    event::{Event, EventSubscriber, GamepadButtonState},
+    event::{Event, EventSubscriber, GamepadButtonState, GamepadId},
 };
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindingKind {
+    Key,
+    Mouse,
+    GamepadButton,
+    GamepadAxis,
+}
+
+//One input of an action. Names are the winit KeyCode and MouseButton or gilrs Button and Axis names,
+//e.g. "KeyW", "Left", "South" or "LeftStickX".
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub kind: BindingKind,
+    pub name: String,
+    //Counts as -1 in action_axis, e.g. KeyA for "move_x". Flips gamepad axes, so a flipped
+    //LeftStickX is down when pushed to the left.
+    #[serde(default)]
+    pub negative: bool,
+    //Gamepad axes only. Values closer to 0 are ignored, past it the action is down.
+    #[serde(default = "Binding::default_threshold")]
+    pub threshold: f32,
+}
+
+impl Binding {
+    fn default_threshold() -> f32 {
+        0.2
+    }
+
+    fn new(kind: BindingKind, name: String) -> Self {
+        Binding { kind, name, negative: false, threshold: Self::default_threshold() }
+    }
+
+    pub fn key(keycode: winit::keyboard::KeyCode) -> Self {
+        Self::new(BindingKind::Key, format!("{:?}", keycode))
+    }
+
+    pub fn mouse(button: winit::event::MouseButton) -> Self {
+        Self::new(BindingKind::Mouse, format!("{:?}", button))
+    }
+
+    pub fn gamepad_button(button: gilrs::Button) -> Self {
+        Self::new(BindingKind::GamepadButton, format!("{:?}", button))
+    }
+
+    pub fn gamepad_axis(axis: gilrs::Axis, threshold: f32) -> Self {
+        Binding { threshold, ..Self::new(BindingKind::GamepadAxis, format!("{:?}", axis)) }
+    }
+
+    pub fn negative(mut self) -> Self {
+        self.negative = true;
+        self
+    }
+}
+
+//Named actions and the inputs bound to them, read through InputState::is_action_down and friends.
+//Actions are strings, user enums work through AsRef<str>. Loaded from the actions of the project
+//config, change it at runtime through Context::actions.
+#[derive(Default, Clone)]
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl ActionMap {
+    pub fn new(actions: &BTreeMap<String, Vec<Binding>>) -> Self {
+        ActionMap {
+            actions: actions
+                .iter()
+                .map(|(name, bindings)| (name.clone(), bindings.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn bind(&mut self, action: impl AsRef<str>, binding: Binding) {
+        let bindings = self.actions.entry(action.as_ref().to_string()).or_default();
+
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: impl AsRef<str>, binding: &Binding) {
+        if let Some(bindings) = self.actions.get_mut(action.as_ref()) {
+            bindings.retain(|bound| bound != binding);
+        }
+    }
+
+    //Replaces all bindings of the action, e.g. from a controls menu.
+    pub fn rebind(&mut self, action: impl AsRef<str>, bindings: Vec<Binding>) {
+        self.actions.insert(action.as_ref().to_string(), bindings);
+    }
+
+    pub fn remove(&mut self, action: impl AsRef<str>) {
+        self.actions.remove(action.as_ref());
+    }
+
+    pub fn bindings(&self, action: impl AsRef<str>) -> &[Binding] {
+        self.actions.get(action.as_ref()).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(String::as_str)
+    }
+
+    //For the actions section of the project config.
+    pub fn to_config(&self) -> BTreeMap<String, Vec<Binding>> {
+        self.actions.iter().map(|(name, bindings)| (name.clone(), bindings.clone())).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadStick {
+    Left,
+    Right,
+}
+
+impl GamepadStick {
+    fn axes(&self) -> (gilrs::Axis, gilrs::Axis) {
+        match self {
+            GamepadStick::Left => (gilrs::Axis::LeftStickX, gilrs::Axis::LeftStickY),
+            GamepadStick::Right => (gilrs::Axis::RightStickX, gilrs::Axis::RightStickY),
+        }
+    }
+}
+
 #[derive(Default)]
+struct GamepadState {
+    buttons: HashMap<gilrs::Button, bool>,
+    axes: HashMap<gilrs::Axis, f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+    pub id: u64,
+    //In physical pixels, like the mouse position.
+    pub position: (f64, f64),
+    //Where the finger went down.
+    pub start: (f64, f64),
+}
+
 pub struct InputState {
     keyboard: HashMap<winit::keyboard::KeyCode, bool>,
+    modifiers: ModifiersState,
     mouse_button: HashMap<winit::event::MouseButton, bool>,
-    gamepad_button: HashMap<gilrs::Button, bool>,
-    gamepad_axis: HashMap<gilrs::Axis, f32>,
+    gamepads: HashMap<GamepadId, GamepadState>,
+    //Sticks closer to the center than this read as centered, see gamepad_stick.
+    deadzone: f32,
     mouse_position: (f64, f64),
     last_mouse_position: (f64, f64),
+    //Raw mouse movement since the end of the last frame.
+    mouse_motion: (f64, f64),
+    actions: RcCell<ActionMap>,
+    //Inputs by the names bindings use, 1 for pressed buttons.
+    named: HashMap<BindingKind, HashMap<String, f32>>,
+    down_actions: HashSet<String>,
+    //Went down since the end of the last frame.
+    pressed_actions: HashSet<String>,
+    released_actions: HashSet<String>,
+    //Lets go of the mouse buttons when the cursor leaves the window.
+    release_on_cursor_left: bool,
+    //Scrolled since the end of the last frame. Wheels report lines, touchpads pixels.
+    scroll_lines: Vec2,
+    scroll_pixels: Vec2,
+    pixels_per_line: f32,
+    //Oldest first.
+    touches: Vec<TouchPoint>,
+    //Distance of the first two touches at the end of the last frame.
+    last_pinch: Option<f64>,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState::with_actions(RcCell::new(ActionMap::default()))
+    }
 }
 
 impl InputState {
@@ -22,20 +191,124 @@ impl InputState {
         InputState::default()
     }
 
+    pub fn with_actions(actions: RcCell<ActionMap>) -> InputState {
+        InputState {
+            keyboard: HashMap::new(),
+            modifiers: ModifiersState::empty(),
+            mouse_button: HashMap::new(),
+            gamepads: HashMap::new(),
+            deadzone: 0.15,
+            mouse_position: (0.0, 0.0),
+            last_mouse_position: (0.0, 0.0),
+            mouse_motion: (0.0, 0.0),
+            actions,
+            named: HashMap::new(),
+            down_actions: HashSet::new(),
+            pressed_actions: HashSet::new(),
+            released_actions: HashSet::new(),
+            release_on_cursor_left: false,
+            scroll_lines: Vec2::ZERO,
+            scroll_pixels: Vec2::ZERO,
+            pixels_per_line: 40.0,
+            touches: Vec::new(),
+            last_pinch: None,
+        }
+    }
+
+    //How many pixels of touchpad scrolling make up one line of a mouse wheel.
+    pub fn with_pixels_per_line(mut self, pixels: f32) -> InputState {
+        self.pixels_per_line = pixels.max(f32::EPSILON);
+        self
+    }
+
+    pub fn with_deadzone(mut self, deadzone: f32) -> InputState {
+        self.deadzone = deadzone.clamp(0.0, 0.99);
+        self
+    }
+
+    //Off by default, so dragging outside of the window keeps the button held.
+    pub fn with_release_on_cursor_left(mut self, release: bool) -> InputState {
+        self.release_on_cursor_left = release;
+        self
+    }
+
     pub fn is_key_down(&self, keycode: &winit::keyboard::KeyCode) -> bool {
         *self.keyboard.get(keycode).unwrap_or(&false)
     }
 
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.modifiers.control_key()
+    }
+
+    pub fn shift(&self) -> bool {
+        self.modifiers.shift_key()
+    }
+
+    pub fn alt(&self) -> bool {
+        self.modifiers.alt_key()
+    }
+
+    //The Windows or Command key.
+    pub fn logo(&self) -> bool {
+        self.modifiers.super_key()
+    }
+
     pub fn is_mouse_down(&self, keycode: &winit::event::MouseButton) -> bool {
         *self.mouse_button.get(keycode).unwrap_or(&false)
     }
 
+    pub fn is_gamepad_button_down(&self, id: GamepadId, button: gilrs::Button) -> bool {
+        self.gamepads
+            .get(&id)
+            .and_then(|gamepad| gamepad.buttons.get(&button))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    //Raw value without deadzone.
+    pub fn gamepad_axis(&self, id: GamepadId, axis: gilrs::Axis) -> f32 {
+        self.gamepads.get(&id).and_then(|gamepad| gamepad.axes.get(&axis)).copied().unwrap_or(0.0)
+    }
+
+    //Both axes of the stick with the deadzone applied to their length, so diagonals aren't cut
+    //off. Rescaled to start at 0 right outside of the deadzone.
+    pub fn gamepad_stick(&self, id: GamepadId, stick: GamepadStick) -> Vec2 {
+        let (x, y) = stick.axes();
+        let value = Vec2::new(self.gamepad_axis(id, x), self.gamepad_axis(id, y));
+        let length = value.length();
+
+        if length <= self.deadzone {
+            return Vec2::ZERO;
+        }
+
+        value / length * ((length - self.deadzone) / (1.0 - self.deadzone)).min(1.0)
+    }
+
+    //Gamepads that were connected or sent input, in no particular order.
+    pub fn connected_gamepads(&self) -> Vec<GamepadId> {
+        self.gamepads.keys().copied().collect()
+    }
+
+    //Down on any gamepad.
+    pub fn is_any_gamepad_button_down(&self, button: gilrs::Button) -> bool {
+        self.gamepads.keys().any(|id| self.is_gamepad_button_down(*id, button))
+    }
+
+    //Down on any gamepad. Kept for older code, see is_gamepad_button_down.
     pub fn is_gamepad_butto_down(&self, keycode: &gilrs::Button) -> bool {
-        *self.gamepad_button.get(keycode).unwrap_or(&false)
+        self.is_any_gamepad_button_down(*keycode)
     }
 
+    //The value furthest from the center of all gamepads, without deadzone.
     pub fn get_gamepad_axis(&self, axiscode: &gilrs::Axis) -> f32 {
-        *self.gamepad_axis.get(axiscode).unwrap_or(&0.0)
+        self.gamepads.keys().map(|id| self.gamepad_axis(*id, *axiscode)).fold(
+            0.0f32,
+            |furthest, value| if value.abs() > furthest.abs() { value } else { furthest },
+        )
     }
 
     pub fn get_mouse_pos(&self) -> (f64, f64) {
@@ -52,17 +325,252 @@ impl InputState {
             self.mouse_position.1 - self.last_mouse_position.1,
         )
     }
+
+    //Moved since the last frame, keeps counting at the window edges and while the cursor is
+    //grabbed. Better for looking around than the cursor position.
+    pub fn mouse_motion(&self) -> (f64, f64) {
+        self.mouse_motion
+    }
+
+    //Scrolled since the last frame in lines, pixel deltas are converted. Positive y scrolls up.
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.scroll_lines + self.scroll_pixels / self.pixels_per_line
+    }
+
+    pub fn pixels_per_line(&self) -> f32 {
+        self.pixels_per_line
+    }
+
+    pub fn touches(&self) -> &[TouchPoint] {
+        &self.touches
+    }
+
+    //The oldest touch still down.
+    pub fn primary_touch(&self) -> Option<&TouchPoint> {
+        self.touches.first()
+    }
+
+    //How much the first two touches moved apart since the last frame, in pixels. Negative when
+    //pinching together, 0 with less than two touches or when the second one just went down.
+    pub fn pinch_delta(&self) -> f64 {
+        match (self.pinch_distance(), self.last_pinch) {
+            (Some(distance), Some(last)) => distance - last,
+            _ => 0.0,
+        }
+    }
+
+    fn pinch_distance(&self) -> Option<f64> {
+        let [first, second, ..] = self.touches.as_slice() else {
+            return None;
+        };
+
+        let (x, y) = (first.position.0 - second.position.0, first.position.1 - second.position.1);
+        Some((x * x + y * y).sqrt())
+    }
+
+    fn on_touch(&mut self, id: u64, phase: TouchPhase, position: (f64, f64)) {
+        match phase {
+            TouchPhase::Started => {
+                self.touches.push(TouchPoint { id, position, start: position });
+            }
+            TouchPhase::Moved => {
+                if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                    touch.position = position;
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.retain(|touch| touch.id != id);
+            }
+        }
+
+        //Another pair of fingers, measure again from here instead of jumping.
+        if phase != TouchPhase::Moved {
+            self.last_pinch = self.pinch_distance();
+        }
+    }
+
+    pub fn is_action_down(&self, action: impl AsRef<str>) -> bool {
+        self.down_actions.contains(action.as_ref())
+    }
+
+    //Went down since the last frame. Quick taps within one frame count as well.
+    pub fn just_pressed_action(&self, action: impl AsRef<str>) -> bool {
+        self.pressed_actions.contains(action.as_ref())
+    }
+
+    //Went up since the last frame, also when the window lost focus while it was held.
+    pub fn just_released_action(&self, action: impl AsRef<str>) -> bool {
+        self.released_actions.contains(action.as_ref())
+    }
+
+    //Sum of the bindings in -1..1. Buttons add 1, negative ones -1, gamepad axes their value.
+    pub fn action_axis(&self, action: impl AsRef<str>) -> f32 {
+        let actions = self.actions.borrow();
+
+        actions
+            .bindings(action)
+            .iter()
+            .map(|binding| self.binding_value(binding))
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    //Negative bindings give negative values. Gamepad axes are 0 within their threshold.
+    fn binding_value(&self, binding: &Binding) -> f32 {
+        let value = self
+            .named
+            .get(&binding.kind)
+            .and_then(|inputs| inputs.get(&binding.name))
+            .copied()
+            .unwrap_or(0.0);
+
+        let value = if binding.kind == BindingKind::GamepadAxis && value.abs() < binding.threshold {
+            0.0
+        } else {
+            value
+        };
+
+        if binding.negative {
+            -value
+        } else {
+            value
+        }
+    }
+
+    //Axes only count as down when pushed past the threshold to the positive side, after flipping.
+    fn is_binding_down(&self, binding: &Binding) -> bool {
+        let value = self.binding_value(binding);
+
+        if binding.kind == BindingKind::GamepadAxis {
+            value > 0.0
+        } else {
+            value != 0.0
+        }
+    }
+
+    fn set_named(&mut self, kind: BindingKind, name: String, value: f32) {
+        self.named.entry(kind).or_default().insert(name, value);
+
+        let actions = self.actions.borrow();
+
+        for action in actions.actions() {
+            let down = actions.bindings(action).iter().any(|binding| self.is_binding_down(binding));
+
+            if !down {
+                if self.down_actions.remove(action) {
+                    self.released_actions.insert(action.to_string());
+                }
+            } else if self.down_actions.insert(action.to_string()) {
+                self.pressed_actions.insert(action.to_string());
+            }
+        }
+    }
+
+    //The releases of keys and buttons held while the window lost focus go to the other window.
+    //Let go of everything, as if they were released.
+    fn release_all(&mut self) {
+        let keys: Vec<_> =
+            self.keyboard.iter().filter(|(_, down)| **down).map(|(key, _)| *key).collect();
+
+        for key in keys {
+            self.keyboard.insert(key, false);
+            self.set_named(BindingKind::Key, format!("{:?}", key), 0.0);
+        }
+
+        self.release_mouse();
+
+        let mut buttons = Vec::new();
+        let mut axes = Vec::new();
+
+        for gamepad in self.gamepads.values_mut() {
+            for (button, down) in gamepad.buttons.iter_mut() {
+                *down = false;
+                buttons.push(*button);
+            }
+
+            for (axis, value) in gamepad.axes.iter_mut() {
+                *value = 0.0;
+                axes.push(*axis);
+            }
+        }
+
+        for button in buttons {
+            self.update_gamepad_button(button);
+        }
+
+        for axis in axes {
+            self.update_gamepad_axis(axis);
+        }
+    }
+
+    fn release_mouse(&mut self) {
+        let buttons: Vec<_> = self
+            .mouse_button
+            .iter()
+            .filter(|(_, down)| **down)
+            .map(|(button, _)| *button)
+            .collect();
+
+        for button in buttons {
+            self.mouse_button.insert(button, false);
+            self.set_named(BindingKind::Mouse, format!("{:?}", button), 0.0);
+        }
+    }
+
+    //Actions are bound to the buttons and axes of all gamepads at once.
+    fn update_gamepad_button(&mut self, button: gilrs::Button) {
+        let value = if self.is_any_gamepad_button_down(button) { 1.0 } else { 0.0 };
+        self.set_named(BindingKind::GamepadButton, format!("{:?}", button), value);
+    }
+
+    fn update_gamepad_axis(&mut self, axis: gilrs::Axis) {
+        let value = self.get_gamepad_axis(&axis);
+        self.set_named(BindingKind::GamepadAxis, format!("{:?}", axis), value);
+    }
+
+    //Called by the engine once the frame is updated.
+    pub(crate) fn end_frame(&mut self) {
+        self.pressed_actions.clear();
+        self.released_actions.clear();
+        self.mouse_motion = (0.0, 0.0);
+        self.scroll_lines = Vec2::ZERO;
+        self.scroll_pixels = Vec2::ZERO;
+        self.last_pinch = self.pinch_distance();
+    }
 }
 
 impl EventSubscriber for InputState {
     fn on_event(&mut self, event: &Event, _context: &mut Context) -> bool {
+        self.handle_event(event);
+        true
+    }
+}
+
+impl InputState {
+    //What on_event does, for input fed by hand, e.g. from a GamepadEventSource.
+    pub fn handle_event(&mut self, event: &Event) {
         match event {
-            Event::KeyboardInput { keycode, state } => {
+            Event::Focused(false) => {
+                self.release_all();
+                self.modifiers = ModifiersState::empty();
+            }
+            Event::CursorLeft if self.release_on_cursor_left => {
+                self.release_mouse();
+            }
+            Event::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            Event::KeyboardInput { keycode, state, modifiers } => {
+                self.modifiers = *modifiers;
+
                 if *state == ElementState::Pressed {
                     self.keyboard.insert(*keycode, true);
                 } else {
                     self.keyboard.insert(*keycode, false);
                 }
+
+                let value = if self.is_key_down(keycode) { 1.0 } else { 0.0 };
+                self.set_named(BindingKind::Key, format!("{:?}", keycode), value);
             }
             Event::MouseInput { mousecode, state } => {
                 if *state == ElementState::Pressed {
@@ -70,24 +578,151 @@ impl EventSubscriber for InputState {
                 } else {
                     self.mouse_button.insert(*mousecode, false);
                 }
+
+                let value = if self.is_mouse_down(mousecode) { 1.0 } else { 0.0 };
+                self.set_named(BindingKind::Mouse, format!("{:?}", mousecode), value);
+            }
+            Event::MouseScroll { delta_x, delta_y, .. } => {
+                self.scroll_lines += Vec2::new(*delta_x, *delta_y);
+            }
+            Event::MouseWheel { delta_x, delta_y, .. } => {
+                self.scroll_pixels += Vec2::new(*delta_x as f32, *delta_y as f32);
+            }
+            Event::MouseMotion { delta_x, delta_y } => {
+                self.mouse_motion.0 += delta_x;
+                self.mouse_motion.1 += delta_y;
+            }
+            Event::Touch { id, phase, x, y } => {
+                self.on_touch(*id, *phase, (*x, *y));
             }
             Event::CursorMoved { x, y } => {
                 self.last_mouse_position = self.mouse_position;
                 self.mouse_position = (*x, *y);
             }
-            Event::GamepadInput { buttoncode, state, .. } => {
-                if *state == GamepadButtonState::Pressed {
-                    self.gamepad_button.insert(*buttoncode, true);
-                } else {
-                    self.gamepad_button.insert(*buttoncode, false);
-                }
+            Event::GamepadInput { id, buttoncode, state } => {
+                //Repeats come while the button is held.
+                let down = *state != GamepadButtonState::Released;
+                self.gamepads.entry(*id).or_default().buttons.insert(*buttoncode, down);
+                self.update_gamepad_button(*buttoncode);
+            }
+            Event::GamepadAxis { id, axiscode, value } => {
+                self.gamepads.entry(*id).or_default().axes.insert(*axiscode, *value);
+                self.update_gamepad_axis(*axiscode);
+            }
+            Event::GamepadConnected { id } => {
+                self.gamepads.entry(*id).or_default();
             }
-            Event::GamepadAxis { axiscode, value, .. } => {
-                self.gamepad_axis.insert(*axiscode, *value);
+            Event::GamepadDisconnected { id } | Event::GamepadDropped { id } => {
+                //Let go of everything it held down.
+                if let Some(gamepad) = self.gamepads.remove(id) {
+                    for button in gamepad.buttons.into_keys() {
+                        self.update_gamepad_button(button);
+                    }
+
+                    for axis in gamepad.axes.into_keys() {
+                        self.update_gamepad_axis(axis);
+                    }
+                }
             }
             _ => {}
         }
+    }
+}
 
-        true
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::event::{self, GamepadEventSource};
+
+    //Plays back a fixed list of events, like a recorded session.
+    struct ScriptedGamepads(VecDeque<Event>);
+
+    impl GamepadEventSource for ScriptedGamepads {
+        fn next_event(&mut self) -> Option<Event> {
+            self.0.pop_front()
+        }
+    }
+
+    //What Context::pump_gamepad_events does, with the InputState as the only layer.
+    fn pump(source: &mut impl GamepadEventSource, input: &mut InputState) {
+        event::pump_gamepad_events(source, |event| input.handle_event(event));
+    }
+
+    #[test]
+    fn tap_within_one_pump_is_pressed_and_released() {
+        let id = GamepadId(0);
+        let button = |state| Event::GamepadInput { id, buttoncode: gilrs::Button::South, state };
+
+        let mut actions = ActionMap::default();
+        actions.bind("jump", Binding::gamepad_button(gilrs::Button::South));
+        let mut input = InputState::with_actions(RcCell::new(actions));
+
+        let mut source = ScriptedGamepads(VecDeque::from([
+            button(GamepadButtonState::Pressed),
+            button(GamepadButtonState::Released),
+        ]));
+        pump(&mut source, &mut input);
+
+        assert!(input.just_pressed_action("jump"));
+        assert!(input.just_released_action("jump"));
+        assert!(!input.is_action_down("jump"));
+
+        input.end_frame();
+
+        assert!(!input.just_pressed_action("jump"));
+        assert!(!input.just_released_action("jump"));
+    }
+
+    #[test]
+    fn scripted_events_are_kept_per_gamepad() {
+        let (first, second) = (GamepadId(0), GamepadId(1));
+
+        let mut source = ScriptedGamepads(VecDeque::from([
+            Event::GamepadConnected { id: first },
+            Event::GamepadConnected { id: second },
+            Event::GamepadInput {
+                id: first,
+                buttoncode: gilrs::Button::South,
+                state: GamepadButtonState::Pressed,
+            },
+            Event::GamepadAxis { id: second, axiscode: gilrs::Axis::LeftStickX, value: 0.8 },
+        ]));
+
+        let mut input = InputState::new();
+        pump(&mut source, &mut input);
+
+        let mut connected = input.connected_gamepads();
+        connected.sort();
+        assert_eq!(connected, vec![first, second]);
+
+        assert!(input.is_gamepad_button_down(first, gilrs::Button::South));
+        assert!(!input.is_gamepad_button_down(second, gilrs::Button::South));
+        assert_eq!(input.gamepad_axis(second, gilrs::Axis::LeftStickX), 0.8);
+        assert_eq!(input.gamepad_axis(first, gilrs::Axis::LeftStickX), 0.0);
+    }
+
+    #[test]
+    fn disconnecting_releases_only_that_gamepad() {
+        let (first, second) = (GamepadId(0), GamepadId(1));
+        let press = |id| Event::GamepadInput {
+            id,
+            buttoncode: gilrs::Button::East,
+            state: GamepadButtonState::Pressed,
+        };
+
+        let mut source = ScriptedGamepads(VecDeque::from([
+            press(first),
+            press(second),
+            Event::GamepadDisconnected { id: first },
+        ]));
+
+        let mut input = InputState::new();
+        pump(&mut source, &mut input);
+
+        assert_eq!(input.connected_gamepads(), vec![second]);
+        assert!(!input.is_gamepad_button_down(first, gilrs::Button::East));
+        assert!(input.is_any_gamepad_button_down(gilrs::Button::East));
     }
 }
@@ -4,14 +4,18 @@ pub mod utils;
 #[macro_use]
 pub mod core;
 pub mod assets;
+pub mod console;
 pub mod context;
 pub mod entities;
 pub mod entry;
 pub mod environment;
 pub mod event;
+pub mod event_log;
 pub mod input;
 pub mod logging;
+pub mod random;
 pub mod render;
+pub mod schedule;
 pub mod sound;
 pub mod window;
 
@@ -30,12 +34,13 @@ pub use what;
 pub use winit;
 
 use assets::assets::Assets;
-use egui::lerp;
+use entities::entities::Worlds;
 use glam::Vec3;
 use input::InputState;
 
 use rccell::RcCell;
 use render::{camera::PerspectiveCamera, renderer::Renderer};
+use schedule::Schedule;
 
 use crate::{context::Context, core::Application, sound::AudioEngine};
 
@@ -43,6 +48,7 @@ use event::{Event, EventSubscriber};
 use winit::{
     event::{ElementState, MouseButton},
     keyboard::KeyCode,
+    window::CursorGrabMode,
 };
 
 use crate::core::ModuleStack;
@@ -52,7 +58,9 @@ struct MyHandler {
 }
 
 impl EventSubscriber for MyHandler {
-    fn on_event(&mut self, event: &event::Event, _context: &mut Context) -> bool {
+    fn on_event(&mut self, event: &event::Event, context: &mut Context) -> bool {
+        self.audio.on_event(event, context);
+
         if let event::Event::MouseInput { mousecode, state } = event {
             match mousecode {
                 MouseButton::Left => {
@@ -74,38 +82,40 @@ impl EventSubscriber for MyHandler {
 
 impl MyHandler {
     pub fn new(context: &Context) -> MyHandler {
-        let mut audio = AudioEngine::new(context.config.theme_config());
+        let mut audio = AudioEngine::from_config(&context.config, &context.rng);
+        audio.set_pause_on_focus_loss(true);
         audio.play_background();
 
         MyHandler { audio }
     }
 }
 
+//Degrees per unit of mouse motion.
+const MOUSE_SENSITIVITY: f32 = 0.1;
+
 pub struct RustyRuntime<'a> {
     stack: ModuleStack<'a>,
+    handler: RcCell<MyHandler>,
     renderer: RcCell<Renderer>,
     camera: RcCell<PerspectiveCamera>,
+    assets: Assets,
+    worlds: Worlds,
+    schedule: Schedule,
     demo_window: egui_demo_lib::DemoWindows,
+    looking: bool,
 }
 
 impl<'a> Application<'a> for RustyRuntime<'a> {
     fn on_event(&mut self, event: &Event, context: &mut Context) -> bool {
-        match event {
-            event::Event::KeyboardInput { keycode, state } => match keycode {
-                KeyCode::KeyV => {
-                    if *state == ElementState::Pressed {
-                        context.set_vsync(!context.vsync());
-                    }
-                    false
-                }
-                _ => false,
-            },
-            _ => false,
+        if let Event::DeviceRestored = event {
+            self.renderer.borrow_mut().recreate(context, &mut self.assets);
         }
+
+        false
     }
 
     fn render(
-        &mut self, view: &wgpu::TextureView, context: &mut Context, window: &winit::window::Window,
+        &mut self, view: &wgpu::TextureView, context: &mut Context, _window: &winit::window::Window,
     ) {
         {
             let mut renderer = self.renderer.borrow_mut();
@@ -120,7 +130,7 @@ impl<'a> Application<'a> for RustyRuntime<'a> {
 
             renderer.update_skybox_buffer(&context.graphics, view_matrix, projection);
 
-            renderer.render(context, view, window);
+            renderer.render(context, &mut self.assets, &mut self.worlds, view);
         }
     }
 
@@ -131,25 +141,32 @@ impl<'a> Application<'a> for RustyRuntime<'a> {
     fn update(
         &mut self, delta: &utils::Timestep, input_state: Ref<InputState>, context: &mut Context,
     ) {
-        let mut cam = self.camera.borrow_mut();
-
-        let (x, y) = input_state.get_mouse_pos();
-        let (last_x, last_y) = input_state.get_last_mouse_pos();
+        self.handler.borrow_mut().audio.update(delta);
 
-        let (width, height) = (context.surface_config.width, context.surface_config.height);
-
-        //Convert x and y to degrees using the window with and height.
-        let (x, y) = ((x / width as f64) * 180.0 - 90.0, (y / height as f64) * 180.0 - 90.0);
+        let mut cam = self.camera.borrow_mut();
 
-        let (last_x, last_y) =
-            ((last_x / width as f64) * 180.0 - 90.0, (last_y / height as f64) * 180.0 - 90.0);
+        //Look around while the right mouse button is held, the cursor stays where it is meanwhile.
+        let looking = input_state.is_mouse_down(&MouseButton::Right);
 
-        let newX = lerp(last_x..=x, 0.6 * delta.norm() as f64);
-        let newY = lerp(last_y..=y, 0.6 * delta.norm() as f64);
+        if looking != self.looking {
+            context.set_cursor_grab(if looking {
+                CursorGrabMode::Locked
+            } else {
+                CursorGrabMode::None
+            });
+            self.looking = looking;
+        }
 
-        let rot = cam.rotation();
+        if looking {
+            let (x, y) = input_state.mouse_motion();
+            let rot = cam.rotation();
 
-        cam.set_rotation(Vec3::new(-newY.clamp(-90.0, 90.0) as f32, -newX as f32, rot.z));
+            cam.set_rotation(Vec3::new(
+                (rot.x - y as f32 * MOUSE_SENSITIVITY).clamp(-90.0, 90.0),
+                rot.y - x as f32 * MOUSE_SENSITIVITY,
+                rot.z,
+            ));
+        }
 
         if input_state.is_key_down(&KeyCode::KeyW) {
             cam.inc_pos(glam::Vec3::new(0.0, 0.0, -(0.1 * delta.norm())));
@@ -178,6 +195,10 @@ impl<'a> Application<'a> for RustyRuntime<'a> {
 
     fn quit(&mut self) {}
 
+    fn systems(&mut self) -> Option<(&mut Schedule, &mut Worlds, &mut Assets)> {
+        Some((&mut self.schedule, &mut self.worlds, &mut self.assets))
+    }
+
     fn get_stack(&mut self) -> &mut ModuleStack<'a> {
         &mut self.stack
     }
@@ -195,13 +216,17 @@ impl<'a> RustyRuntime<'a> {
             log::warn!("Project: {:?}", path);
         }
 
-        let assets =
-            Assets::new(context.graphics.clone(), loc, (context.free_memory() / 2) as usize);
+        let mut assets = Assets::new(
+            context.graphics.clone(),
+            &context.rng,
+            loc,
+            (context.free_memory() / 2) as usize,
+        );
 
         let handler = RcCell::new(MyHandler::new(context));
-        stack.subscribe(event::EventType::Layer, handler);
+        stack.subscribe(event::EventType::Layer, handler.clone());
 
-        let renderer = RcCell::new(Renderer::new(context, assets));
+        let renderer = RcCell::new(Renderer::new(context, &mut assets));
         stack.subscribe(event::EventType::Layer, renderer.clone());
 
         let camera = RcCell::new(PerspectiveCamera::default());
@@ -214,6 +239,16 @@ impl<'a> RustyRuntime<'a> {
 
         camera.borrow_mut().set_centered(true);
 
-        RustyRuntime { stack, renderer, camera, demo_window: egui_demo_lib::DemoWindows::default() }
+        RustyRuntime {
+            stack,
+            handler,
+            renderer,
+            camera,
+            assets,
+            worlds: Worlds::new(&context.rng),
+            schedule: Schedule::new(),
+            demo_window: egui_demo_lib::DemoWindows::default(),
+            looking: false,
+        }
     }
 }
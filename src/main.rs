@@ -14,9 +14,15 @@ fn main() {
 
     let config = Config::new(None);
 
-    //Create the window from the config and create the context.
-    let window = Window::new("{}".to_string());
-    let context = pollster::block_on(Context::new(window.native.clone(), config));
+    //Create the window from the state saved on the last exit and create the context.
+    let window = Window::new(Config::saved_window_config());
+    let context = match pollster::block_on(Context::new(window.native.clone(), config)) {
+        Ok(context) => context,
+        Err(error) => {
+            log::error!("Could not start: {}", error);
+            return;
+        }
+    };
 
     //Create and init the application
     let myapp = RustyRuntime::new(&context);
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+//A single PCG32 sequence. Systems own one each, so they don't perturb each other.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RandomStream {
+    state: u64,
+    inc: u64,
+}
+
+impl RandomStream {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = RandomStream { state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random::<u64>(), rand::random::<u64>())
+    }
+
+    //A new sequence seeded from this one, e.g. one per particle emitter. Splitting in the same order
+    //gives the same streams.
+    pub fn split(&mut self) -> RandomStream {
+        RandomStream::new(self.next_u64(), self.next_u64())
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    //Uniform in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, range: Range<f32>) -> f32 {
+        range.start + (range.end - range.start) * self.next_f32()
+    }
+
+    pub fn range_i32(&mut self, range: Range<i32>) -> i32 {
+        if range.is_empty() {
+            return range.start;
+        }
+
+        let span = (range.end as i64 - range.start as i64) as u64;
+        (range.start as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+
+        items.get((self.next_u64() % items.len() as u64) as usize)
+    }
+
+    //Fisher-Yates, every order is equally likely.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, (self.next_u64() % (i as u64 + 1)) as usize);
+        }
+    }
+
+    pub fn unit_vec2(&mut self) -> Vec2 {
+        let angle = self.range_f32(0.0..std::f32::consts::TAU);
+        Vec2::new(angle.cos(), angle.sin())
+    }
+}
+
+//The engine random service. Named streams are derived from the seed and the name only.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rng {
+    seed: u64,
+    streams: BTreeMap<String, RandomStream>,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(rand::random::<u64>())
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { seed, streams: BTreeMap::new() }
+    }
+
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        seed.map(Rng::new).unwrap_or_default()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    //For systems that are created once and keep their stream, e.g. the GuidGenerator. Needs no
+    //mutable access, the same seed and name always give the same stream.
+    pub fn derive(&self, name: &str) -> RandomStream {
+        RandomStream::new(self.seed, Rng::stream_id(name))
+    }
+
+    pub fn stream(&mut self, name: &str) -> &mut RandomStream {
+        let seed = self.seed;
+        self.streams
+            .entry(name.to_string())
+            .or_insert_with(|| RandomStream::new(seed, Rng::stream_id(name)))
+    }
+
+    //FNV-1a, so stream ids are stable across runs and platforms.
+    fn stream_id(name: &str) -> u64 {
+        name.bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    pub fn save(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn load(data: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(data)
+    }
+
+    //Replays must end with bitwise identical stream states.
+    pub fn assert_same_state(&self, other: &Rng) {
+        assert_eq!(self.seed, other.seed, "Replay diverged: different seeds.");
+
+        for (name, stream) in self.streams.iter() {
+            assert_eq!(
+                Some(stream),
+                other.streams.get(name),
+                "Replay diverged: random stream '{}' differs.",
+                name
+            );
+        }
+
+        assert_eq!(self.streams.len(), other.streams.len(), "Replay diverged: stream count.");
+    }
+}
@@ -1,8 +1,7 @@
 use std::f32::consts::PI;
 
 use glam::{Mat4, Vec2, Vec3, Vec4};
-use once_cell::sync::OnceCell;
-use wgpu::util::DeviceExt;
+use wgpu::util::{DeviceExt, StagingBelt};
 
 use crate::{
     context::{Context, VisContext},
@@ -20,15 +19,21 @@ pub const OPENGL_TO_WGPU: glam::Mat4 = glam::mat4
     Vec4::new(0.0, 0.0, 0.0, 1.5),
 );
 
+//Past this the ground is squashed too flat to read, and billboards would stretch without bound.
+pub const MAX_SHEAR: f32 = 0.9;
+
+#[derive(Clone)]
 struct AspectMgr {
     width: f32,
     height: f32,
     fixed_aspect_ratio: Option<f32>,
+    //Round the viewport to whole pixels.
+    snap: bool,
 }
 
 impl AspectMgr {
     pub fn new(width: f32, height: f32, fixed_aspect_ratio: Option<f32>) -> Self {
-        AspectMgr { width, height, fixed_aspect_ratio }
+        AspectMgr { width, height, fixed_aspect_ratio, snap: false }
     }
 
     pub fn viewport(&self) -> (f32, f32, f32, f32) {
@@ -54,6 +59,11 @@ impl AspectMgr {
 
         let x = (width - self.width).abs() / 2.0;
         let y = (height - self.height).abs() / 2.0;
+
+        if self.snap {
+            return (x.floor(), y.floor(), width.floor(), height.floor());
+        }
+
         (x, y, width, height)
     }
 
@@ -85,6 +95,11 @@ pub struct OrthographicCamera {
     zoom_level: f32,
     near: f32,
     far: f32,
+    shear: f32,
+    //Pixels per world unit of the art, if the camera snaps to whole texels.
+    pixel_perfect: Option<f32>,
+    //Of the window, from Event::ScaleFactorChanged.
+    scale_factor: f32,
     view: Mat4,
     projection: Mat4,
     dirty: bool,
@@ -101,6 +116,9 @@ impl Default for OrthographicCamera {
             zoom_level: 1.0,
             near: 0.1,
             far: 100.0,
+            shear: 0.0,
+            pixel_perfect: None,
+            scale_factor: 1.0,
             view: glam::Mat4::IDENTITY,
             projection: glam::Mat4::IDENTITY,
             dirty: true,
@@ -116,12 +134,32 @@ impl EventSubscriber for OrthographicCamera {
                 self.dirty = true;
                 false
             }
+            event::Event::ScaleFactorChanged { scale } => {
+                self.scale_factor = *scale as f32;
+                false
+            }
+            event::Event::CameraMoveTo { x, y } => {
+                self.set_position(Vec2::new(*x, *y));
+                false
+            }
             _ => false,
         }
     }
 }
 
 impl OrthographicCamera {
+    //Sized like the window, with the fixed aspect ratio of the graphics config.
+    pub fn new(context: &Context) -> Self {
+        let (width, height) = context.window_size();
+        let fixed_aspect = context.config.engine_config().graphics.fixed_aspect;
+
+        OrthographicCamera {
+            aspect_mgr: AspectMgr::new(width as f32, height as f32, fixed_aspect),
+            scale_factor: context.scale_factor() as f32,
+            ..Default::default()
+        }
+    }
+
     pub fn view_projection(&mut self) -> Mat4 {
         if self.dirty {
             self.calc_view_projection();
@@ -147,13 +185,54 @@ impl OrthographicCamera {
     }
 
     fn calc_view_projection(&mut self) {
-        self.set_projection(self.aspect_mgr.aspect_ratio(), self.zoom_level, self.near, self.far);
-        self.set_view(self.position, self.rotation);
+        (self.projection, self.view) = self.matrices(&self.aspect_mgr);
         self.dirty = false;
     }
 
+    //Projection and view for a surface as described by aspect_mgr.
+    fn matrices(&self, aspect_mgr: &AspectMgr) -> (Mat4, Mat4) {
+        let (zoom, position, aspect_ratio) = match self.pixel_perfect {
+            Some(pixels_per_unit) => self.snap_to_texels(aspect_mgr, pixels_per_unit),
+            None => (self.zoom_level, self.position, aspect_mgr.aspect_ratio()),
+        };
+
+        //The tilted ground plane is squashed towards the center of the view.
+        let tilt = Mat4::from_scale(Vec3::new(1.0, 1.0 - self.shear, 1.0));
+
+        (
+            Self::orthographic(aspect_ratio, zoom, self.near, self.far) * tilt,
+            Self::view_matrix(position, self.rotation),
+        )
+    }
+
+    //Zoom, position and aspect ratio so that a texel covers a whole number of screen pixels
+    //and texel edges fall onto pixel edges.
+    fn snap_to_texels(&self, aspect_mgr: &AspectMgr, pixels_per_unit: f32) -> (f32, Vec2, f32) {
+        let (_, _, width, height) = aspect_mgr.viewport();
+        let (width, height) = (width.max(1.0), height.max(1.0));
+
+        //The view is 2 * zoom units high.
+        let scale = (height / (2.0 * self.zoom_level * pixels_per_unit)).round().max(1.0);
+        let zoom = height / (2.0 * pixels_per_unit * scale);
+
+        //With an odd pixel count the center sits in the middle of a pixel, shift it onto an edge.
+        let pixel = 1.0 / (pixels_per_unit * scale);
+        let odd = Vec2::new(width % 2.0, height % 2.0) * pixel / 2.0;
+        let position = (self.position * pixels_per_unit).round() / pixels_per_unit + odd;
+
+        (zoom, position, width / height)
+    }
+
     pub fn set_projection(&mut self, aspect_ratio: f32, zoom_level: f32, near: f32, far: f32) {
-        self.projection = glam::Mat4::orthographic_rh(
+        self.projection = Self::orthographic(aspect_ratio, zoom_level, near, far);
+    }
+
+    pub fn set_view(&mut self, position: Vec2, rotation: f32) {
+        self.view = Self::view_matrix(position, rotation);
+    }
+
+    fn orthographic(aspect_ratio: f32, zoom_level: f32, near: f32, far: f32) -> Mat4 {
+        glam::Mat4::orthographic_rh(
             -aspect_ratio * zoom_level,
             aspect_ratio * zoom_level,
             -zoom_level,
@@ -163,10 +242,31 @@ impl OrthographicCamera {
         )
     }
 
-    pub fn set_view(&mut self, position: Vec2, rotation: f32) {
+    fn view_matrix(position: Vec2, rotation: f32) -> Mat4 {
         let rotation = glam::Mat4::from_rotation_z(rotation * PI / 180.0);
-        self.view = rotation * glam::Mat4::from_translation(Vec3::new(position.x, position.y, 1.0));
-        self.view = self.view.inverse();
+        let view = rotation * glam::Mat4::from_translation(Vec3::new(position.x, position.y, 1.0));
+        view.inverse()
+    }
+
+    //The point on the z = 0 plane under a pixel of the surface, e.g. the cursor position. Includes
+    //the letterbox of a fixed aspect ratio, zoom, rotation and the shear of the ground.
+    pub fn screen_to_world(&self, screen_pos: Vec2, surface_size: (u32, u32)) -> Vec2 {
+        let mut aspect_mgr = self.aspect_mgr.clone();
+        aspect_mgr.set_dims(surface_size.0 as f32, surface_size.1 as f32);
+
+        let (projection, view) = self.matrices(&aspect_mgr);
+        let ndc = viewport_to_ndc(screen_pos, aspect_mgr.viewport());
+        let (origin, direction) = unproject(OPENGL_TO_WGPU * projection * view, ndc);
+
+        match direction.z.abs() > f32::EPSILON {
+            true => (origin - direction * (origin.z / direction.z)).truncate(),
+            false => origin.truncate(),
+        }
+    }
+
+    //Like screen_to_world, for positions in logical pixels, e.g. those of egui.
+    pub fn logical_to_world(&self, logical_pos: Vec2, surface_size: (u32, u32)) -> Vec2 {
+        self.screen_to_world(logical_pos * self.scale_factor, surface_size)
     }
 
     pub fn viewport(&self) -> (f32, f32, f32, f32) {
@@ -218,6 +318,12 @@ impl OrthographicCamera {
         self.dirty = true;
     }
 
+    //None fills the window, otherwise it is letterboxed to the ratio of width over height.
+    pub fn set_fixed_aspect_ratio(&mut self, aspect_ratio: Option<f32>) {
+        self.aspect_mgr.fixed_aspect_ratio = aspect_ratio;
+        self.dirty = true;
+    }
+
     pub fn zoom_level(&self) -> f32 {
         self.zoom_level
     }
@@ -231,6 +337,41 @@ impl OrthographicCamera {
         self.position += size;
         self.dirty = true;
     }
+
+    //Tilts the ground plane away from the viewer: its y is squashed by 1 - shear on screen and
+    //things further up are further back. Billboards keep their height. Zero is a plain top
+    //down/side view. Pass it to the renderer with update_camera_shear.
+    pub fn shear(&self) -> f32 {
+        self.shear
+    }
+
+    pub fn set_shear(&mut self, shear: f32) {
+        self.shear = shear.clamp(0.0, MAX_SHEAR);
+        self.dirty = true;
+    }
+
+    //Snaps zoom and position so one texel covers a whole number of screen pixels. Meant for pixel art
+    //with nearest sampling. pixels_per_unit is how many texels of the art make up one world unit.
+    pub fn set_pixel_perfect(&mut self, pixels_per_unit: f32, enabled: bool) {
+        self.pixel_perfect = (enabled && pixels_per_unit > 0.0).then_some(pixels_per_unit);
+        self.aspect_mgr.snap = self.pixel_perfect.is_some();
+        self.dirty = true;
+    }
+
+    pub fn pixel_perfect(&self) -> Option<f32> {
+        self.pixel_perfect
+    }
+
+    //Physical pixels per logical pixel. The viewport and screen_to_world work in physical ones,
+    //logical_to_world takes logical ones.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    //Cameras created after the window start with 1, hand them Context::scale_factor.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+    }
 }
 
 pub struct PerspectiveCamera {
@@ -240,6 +381,8 @@ pub struct PerspectiveCamera {
     aspect_ratio: f32,
     near: f32,
     far: f32,
+    //Of the window, from Event::ScaleFactorChanged.
+    scale_factor: f32,
     view: Mat4,
     projection: Mat4,
     dirty: bool,
@@ -255,6 +398,10 @@ impl EventSubscriber for PerspectiveCamera {
                 self.dirty = true;
                 false
             }
+            event::Event::ScaleFactorChanged { scale } => {
+                self.scale_factor = *scale as f32;
+                false
+            }
             _ => false,
         }
     }
@@ -269,6 +416,7 @@ impl Default for PerspectiveCamera {
             aspect_ratio: 1280.0 / 720.0,
             near: 0.1,
             far: 100.0,
+            scale_factor: 1.0,
             view: glam::Mat4::IDENTITY,
             projection: glam::Mat4::IDENTITY,
             dirty: true,
@@ -380,6 +528,15 @@ impl PerspectiveCamera {
         self.aspect_ratio
     }
 
+    //Physical pixels per logical pixel, see OrthographicCamera::scale_factor.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+    }
+
     pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
         self.aspect_ratio = aspect_ratio;
         self.dirty = true;
@@ -394,6 +551,40 @@ impl PerspectiveCamera {
         self.rotation += size;
         self.dirty = true;
     }
+
+    //Origin and direction (normalized) of the ray through a pixel of the surface, e.g. the cursor
+    //position. The origin is the eye of the camera.
+    pub fn screen_ray(&self, screen_pos: Vec2, surface_size: (u32, u32)) -> (Vec3, Vec3) {
+        let (width, height) = (surface_size.0 as f32, surface_size.1 as f32);
+
+        let mut camera = PerspectiveCamera { aspect_ratio: width / height.max(1.0), ..*self };
+        camera.calc_view_projection();
+
+        let ndc = viewport_to_ndc(screen_pos, (0.0, 0.0, width, height));
+        let (point, _) = unproject(OPENGL_TO_WGPU * camera.projection * camera.view, ndc);
+        let eye = camera.view.inverse().transform_point3(Vec3::ZERO);
+
+        (eye, (point - eye).normalize_or_zero())
+    }
+}
+
+//Pixel position into normalized device coordinates of the viewport (x, y, width, height), y up.
+fn viewport_to_ndc(screen_pos: Vec2, viewport: (f32, f32, f32, f32)) -> Vec2 {
+    let (x, y, width, height) = viewport;
+
+    Vec2::new(
+        (screen_pos.x - x) / width.max(1.0) * 2.0 - 1.0,
+        1.0 - (screen_pos.y - y) / height.max(1.0) * 2.0,
+    )
+}
+
+//The line of world points that end up at ndc, as a point on it and its normalized direction.
+fn unproject(view_projection: Mat4, ndc: Vec2) -> (Vec3, Vec3) {
+    let inverse = view_projection.inverse();
+    let near = inverse.project_point3(ndc.extend(0.0));
+    let far = inverse.project_point3(ndc.extend(1.0));
+
+    (near, (far - near).normalize_or_zero())
 }
 
 pub struct CameraBuffer {
@@ -429,12 +620,56 @@ impl CameraBuffer {
         CameraBuffer { name: String::from(name), bind_group, camera_buffer, uniform, viewport }
     }
 
-    //TODO: Use some kind of staging buffer, for performance
+    //Writes right away. Renderer2D sets its cameras and writes them with update_buffer_staged instead.
     pub fn update_buffer(&mut self, context: &VisContext, camera: [[f32; 4]; 4]) {
         self.uniform.view_projection = camera;
         context.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
 
+    pub fn update_shear(&mut self, context: &VisContext, shear: f32) {
+        self.uniform.params[0] = shear;
+        context.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    //The depth range is split into this many bands, one per (layer, order) pair.
+    pub fn update_bands(&mut self, context: &VisContext, bands: usize) {
+        let bands = bands.max(1) as f32;
+
+        if self.uniform.params[1] != bands {
+            self.uniform.params[1] = bands;
+            context.queue.write_buffer(
+                &self.camera_buffer,
+                0,
+                bytemuck::cast_slice(&[self.uniform]),
+            );
+        }
+    }
+
+    //The setters only change the uniform, it is written by the next update_buffer_staged.
+    pub fn set_view_projection(&mut self, camera: [[f32; 4]; 4]) {
+        self.uniform.view_projection = camera;
+    }
+
+    pub fn set_shear(&mut self, shear: f32) {
+        self.uniform.params[0] = shear;
+    }
+
+    pub fn set_bands(&mut self, bands: usize) {
+        self.uniform.params[1] = bands.max(1) as f32;
+    }
+
+    //Records the write of the whole uniform into the encoder. Finish the belt before submitting it.
+    pub fn update_buffer_staged(
+        &mut self, context: &VisContext, encoder: &mut wgpu::CommandEncoder, belt: &mut StagingBelt,
+    ) {
+        let data = bytemuck::bytes_of(&self.uniform);
+
+        if let Some(size) = wgpu::BufferSize::new(data.len() as u64) {
+            belt.write_buffer(encoder, &self.camera_buffer, 0, size, &context.device)
+                .copy_from_slice(data);
+        }
+    }
+
     pub fn update_viewport(&mut self, viewport: (f32, f32, f32, f32)) {
         self.viewport = viewport;
     }
@@ -443,6 +678,10 @@ impl CameraBuffer {
         &self.name
     }
 
+    pub fn view_projection(&self) -> Mat4 {
+        Mat4::from_cols_array_2d(&self.uniform.view_projection)
+    }
+
     pub fn viewport(&self) -> (f32, f32, f32, f32) {
         self.viewport
     }
@@ -451,10 +690,8 @@ impl CameraBuffer {
         &self.bind_group
     }
 
-    pub fn layout(context: &VisContext) -> &'static wgpu::BindGroupLayout {
-        static LAYOUT: OnceCell<wgpu::BindGroupLayout> = OnceCell::new();
-
-        LAYOUT.get_or_init(|| {
+    pub fn layout(context: &VisContext) -> &wgpu::BindGroupLayout {
+        context.camera_layout.get_or_init(|| {
             context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Camera Buffer Layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -471,3 +708,41 @@ impl CameraBuffer {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shear_squashes_the_ground_on_screen() {
+        let mut camera = OrthographicCamera::default();
+        let flat = camera.view_projection() * Vec4::new(1.0, 1.0, 0.0, 1.0);
+
+        camera.set_shear(0.5);
+        let tilted = camera.view_projection() * Vec4::new(1.0, 1.0, 0.0, 1.0);
+
+        assert_eq!(tilted.x, flat.x);
+        assert!((tilted.y - flat.y * 0.5).abs() < 1e-5);
+
+        //The cursor still finds the ground point under it.
+        let surface = (1280, 720);
+        let screen = camera.viewport();
+        let center = Vec2::new(screen.0 + screen.2 / 2.0, screen.1 + screen.3 / 2.0);
+        let above = camera.screen_to_world(center - Vec2::new(0.0, 90.0), surface);
+        camera.set_shear(0.0);
+        let flat_above = camera.screen_to_world(center - Vec2::new(0.0, 90.0), surface);
+
+        assert!((above.y - flat_above.y * 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn logical_positions_are_scaled_to_physical_ones() {
+        let mut camera = OrthographicCamera::default();
+        camera.set_scale_factor(2.0);
+
+        let surface = (1280, 720);
+        let logical = camera.logical_to_world(Vec2::new(100.0, 50.0), surface);
+
+        assert_eq!(logical, camera.screen_to_world(Vec2::new(200.0, 100.0), surface));
+    }
+}
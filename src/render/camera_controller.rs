@@ -0,0 +1,328 @@
+use glam::Vec2;
+use rccell::RcCell;
+use winit::keyboard::KeyCode;
+
+use crate::context::Context;
+use crate::entities::entities::Worlds;
+use crate::entities::transform2d::Transform2D;
+use crate::event::{Event, EventSubscriber};
+use crate::input::InputState;
+use crate::utils::Timestep;
+
+use super::camera::OrthographicCamera;
+
+#[derive(Clone, Debug)]
+pub struct CameraBindings {
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+}
+
+impl Default for CameraBindings {
+    fn default() -> Self {
+        CameraBindings {
+            up: vec![KeyCode::KeyW, KeyCode::ArrowUp],
+            down: vec![KeyCode::KeyS, KeyCode::ArrowDown],
+            left: vec![KeyCode::KeyA, KeyCode::ArrowLeft],
+            right: vec![KeyCode::KeyD, KeyCode::ArrowRight],
+        }
+    }
+}
+
+//Pans an orthographic camera with the keyboard and zooms it with the mouse wheel, touchpad or pinching.
+//Input moves a target, the camera follows it smoothly. Subscribe it to the Layer events for the
+//camera.pos console command.
+pub struct CameraController2D {
+    camera: RcCell<OrthographicCamera>,
+    bindings: CameraBindings,
+    //Horizontal and vertical action axes, used instead of the bindings when set.
+    actions: Option<(String, String)>,
+    target: Vec2,
+    target_zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    //Zoom factor per wheel line.
+    zoom_step: f32,
+    //Half screen heights per second, so panning feels the same at every zoom level.
+    pan_speed: f32,
+    //How fast the camera catches up with the target, 0 snaps instantly.
+    smoothing: f32,
+}
+
+impl CameraController2D {
+    pub fn new(camera: RcCell<OrthographicCamera>) -> Self {
+        let (target, target_zoom) = {
+            let camera = camera.borrow();
+            (camera.position(), camera.zoom_level())
+        };
+
+        CameraController2D {
+            camera,
+            bindings: CameraBindings::default(),
+            actions: None,
+            target,
+            target_zoom,
+            min_zoom: 0.1,
+            max_zoom: 100.0,
+            zoom_step: 1.1,
+            pan_speed: 1.5,
+            smoothing: 12.0,
+        }
+    }
+
+    pub fn with_bindings(mut self, bindings: CameraBindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    //Pans with InputState::action_axis, so the controls can be rebound and gamepad sticks work.
+    pub fn with_actions<S: Into<String>>(mut self, horizontal: S, vertical: S) -> Self {
+        self.actions = Some((horizontal.into(), vertical.into()));
+        self
+    }
+
+    pub fn with_zoom_limits(mut self, min: f32, max: f32) -> Self {
+        self.min_zoom = min.max(f32::EPSILON);
+        self.max_zoom = max.max(self.min_zoom);
+        self.target_zoom = self.target_zoom.clamp(self.min_zoom, self.max_zoom);
+        self
+    }
+
+    pub fn with_zoom_step(mut self, step: f32) -> Self {
+        self.zoom_step = step.max(1.0);
+        self
+    }
+
+    pub fn with_pan_speed(mut self, speed: f32) -> Self {
+        self.pan_speed = speed;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.max(0.0);
+        self
+    }
+
+    pub fn camera(&self) -> &RcCell<OrthographicCamera> {
+        &self.camera
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut CameraBindings {
+        &mut self.bindings
+    }
+
+    pub fn target(&self) -> Vec2 {
+        self.target
+    }
+
+    //The camera glides there, use jump_to to skip the smoothing.
+    pub fn set_target(&mut self, target: Vec2) {
+        self.target = target;
+    }
+
+    pub fn jump_to(&mut self, position: Vec2) {
+        self.target = position;
+        self.camera.borrow_mut().set_position(position);
+    }
+
+    pub fn target_zoom(&self) -> f32 {
+        self.target_zoom
+    }
+
+    pub fn set_target_zoom(&mut self, zoom: f32) {
+        self.target_zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+    }
+
+    //Positive lines zoom in.
+    pub fn zoom(&mut self, lines: f32) {
+        self.set_target_zoom(self.target_zoom * self.zoom_step.powf(-lines));
+    }
+
+    pub fn update(&mut self, delta: &Timestep, input_state: &InputState) {
+        let seconds = delta.seconds() as f32;
+        let direction = self.direction(input_state);
+
+        //Spreading two fingers zooms in like scrolling up.
+        let pinch = input_state.pinch_delta() as f32 / input_state.pixels_per_line();
+        let scroll = input_state.scroll_delta().y + pinch;
+        if scroll != 0.0 {
+            self.zoom(scroll);
+        }
+
+        let mut camera = self.camera.borrow_mut();
+
+        //Clamped instead of normalized, a stick pushed halfway pans at half speed.
+        self.target +=
+            direction.clamp_length_max(1.0) * self.pan_speed * camera.zoom_level() * seconds;
+
+        //Frame rate independent lerp factor.
+        let t = if self.smoothing > 0.0 { 1.0 - (-self.smoothing * seconds).exp() } else { 1.0 };
+
+        let position = camera.position();
+        if position != self.target {
+            camera.set_position(position.lerp(self.target, t));
+        }
+
+        let zoom = camera.zoom_level();
+        if zoom != self.target_zoom {
+            camera.set_zoom_level(zoom + (self.target_zoom - zoom) * t);
+        }
+    }
+}
+
+impl CameraController2D {
+    fn direction(&self, input_state: &InputState) -> Vec2 {
+        if let Some((horizontal, vertical)) = &self.actions {
+            return Vec2::new(
+                input_state.action_axis(horizontal),
+                input_state.action_axis(vertical),
+            );
+        }
+
+        let down = |keys: &[KeyCode]| keys.iter().any(|key| input_state.is_key_down(key));
+
+        let mut direction = Vec2::ZERO;
+
+        if down(&self.bindings.up) {
+            direction.y += 1.0;
+        }
+
+        if down(&self.bindings.down) {
+            direction.y -= 1.0;
+        }
+
+        if down(&self.bindings.left) {
+            direction.x -= 1.0;
+        }
+
+        if down(&self.bindings.right) {
+            direction.x += 1.0;
+        }
+
+        direction
+    }
+}
+
+impl EventSubscriber for CameraController2D {
+    fn on_event(&mut self, event: &Event, _context: &mut Context) -> bool {
+        match event {
+            //The camera jumps by itself, keep it from gliding back.
+            Event::CameraMoveTo { x, y } => {
+                self.target = Vec2::new(*x, *y);
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+//Keeps an entity of the active world in view. The camera only moves once the entity leaves the
+//deadzone around the center, and never shows anything outside of the bounds. Runs after
+//Application::update when put into the resources of a Schedule.
+pub struct CameraFollow {
+    camera: RcCell<OrthographicCamera>,
+    target: hecs::Entity,
+    //Width and height in world units.
+    deadzone: Vec2,
+    //How fast the camera catches up, 0 snaps instantly.
+    smoothing: f32,
+    bounds: Option<(Vec2, Vec2)>,
+    focus: Vec2,
+    lost: bool,
+}
+
+impl CameraFollow {
+    pub fn new(
+        camera: RcCell<OrthographicCamera>, target: hecs::Entity, deadzone: Vec2, smoothing: f32,
+        bounds: Option<(Vec2, Vec2)>,
+    ) -> Self {
+        let focus = camera.borrow().position();
+
+        CameraFollow {
+            camera,
+            target,
+            deadzone: deadzone.max(Vec2::ZERO),
+            smoothing: smoothing.max(0.0),
+            bounds,
+            focus,
+            lost: false,
+        }
+    }
+
+    pub fn camera(&self) -> &RcCell<OrthographicCamera> {
+        &self.camera
+    }
+
+    pub fn target(&self) -> hecs::Entity {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: hecs::Entity) {
+        self.target = target;
+        self.lost = false;
+    }
+
+    //True once the target is gone from the active world. The camera stays where it is then.
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: Vec2) {
+        self.deadzone = deadzone.max(Vec2::ZERO);
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.max(0.0);
+    }
+
+    pub fn set_bounds(&mut self, bounds: Option<(Vec2, Vec2)>) {
+        self.bounds = bounds;
+    }
+
+    pub fn update(&mut self, delta: &Timestep, worlds: &mut Worlds) {
+        let position = worlds.get().and_then(|world| {
+            let transform = world.get::<&Transform2D>(self.target).ok()?;
+            Some(transform.global().w_axis.truncate().truncate())
+        });
+
+        self.lost = position.is_none();
+
+        if let Some(position) = position {
+            //Push the focus just far enough to have the target on the edge of the deadzone.
+            let half = self.deadzone / 2.0;
+            let offset = position - self.focus;
+            self.focus += offset - offset.clamp(-half, half);
+        }
+
+        let mut camera = self.camera.borrow_mut();
+        self.focus = self.clamp(&camera, self.focus);
+
+        let seconds = delta.seconds() as f32;
+        let t = if self.smoothing > 0.0 { 1.0 - (-self.smoothing * seconds).exp() } else { 1.0 };
+
+        let current = camera.position();
+        let next = self.clamp(&camera, current.lerp(self.focus, t));
+
+        if next != current {
+            camera.set_position(next);
+        }
+    }
+
+    //Keeps the visible area inside the bounds. If they are smaller than the view, it is centered on them.
+    fn clamp(&self, camera: &OrthographicCamera, position: Vec2) -> Vec2 {
+        let Some((min, max)) = self.bounds else {
+            return position;
+        };
+
+        let zoom = camera.zoom_level();
+        let half = Vec2::new(zoom * camera.aspect_ratio(), zoom);
+        let (low, high) = (min + half, max - half);
+        let center = (min + max) / 2.0;
+
+        Vec2::new(
+            if low.x > high.x { center.x } else { position.x.clamp(low.x, high.x) },
+            if low.y > high.y { center.y } else { position.y.clamp(low.y, high.y) },
+        )
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::context::VisContext;
+
+use super::frame::FrameEncoder;
+
+type CaptureCallback = Box<dyn FnOnce(image::RgbaImage) + Send>;
+
+struct PendingCapture {
+    buffer: wgpu::Buffer,
+    ready: Arc<AtomicBool>,
+    width: u32,
+    height: u32,
+    padded_row: u32,
+    bgra: bool,
+    callbacks: Vec<CaptureCallback>,
+}
+
+//Copies presented frames back to the cpu. Requests are served with the next frame and delivered
+//a frame or two later, once the copy is mapped.
+#[derive(Default)]
+pub struct FrameCapture {
+    requests: Vec<CaptureCallback>,
+    pending: Vec<PendingCapture>,
+}
+
+impl FrameCapture {
+    pub fn request(&mut self, callback: impl FnOnce(image::RgbaImage) + Send + 'static) {
+        self.requests.push(Box::new(callback));
+    }
+
+    //Encode and save as png on a worker thread, so the frame doesn't stall.
+    pub fn request_save(&mut self, path: PathBuf) {
+        self.request(move |image| {
+            rayon::spawn(move || {
+                if let Some(parent) = path.parent() {
+                    let _ = std::fs::create_dir_all(parent);
+                }
+
+                match image.save(&path) {
+                    Ok(_) => log::info!("Saved screenshot to {}", path.display()),
+                    Err(e) => log::error!("Could not save screenshot {}. {}", path.display(), e),
+                }
+            });
+        });
+    }
+
+    pub fn has_requests(&self) -> bool {
+        !self.requests.is_empty()
+    }
+
+    //The texture needs COPY_SRC usage. Called right before the frame is presented.
+    //The copy has to be submitted before it can be mapped, so this flushes the frame.
+    pub fn copy_frame(
+        &mut self, context: &VisContext, frame: &mut FrameEncoder, texture: &wgpu::Texture,
+    ) {
+        if self.requests.is_empty() {
+            return;
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (width * 4 + align - 1) / align * align;
+
+        let bgra = match texture.format() {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            format => {
+                log::error!("Screenshots are not supported for surface format {:?}.", format);
+                self.requests.clear();
+                return;
+            }
+        };
+
+        let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Capture Buffer"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        frame.encoder().copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+
+        frame.flush();
+
+        let ready = Arc::new(AtomicBool::new(false));
+        let flag = ready.clone();
+
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| match result {
+            Ok(_) => flag.store(true, Ordering::Release),
+            Err(e) => log::error!("Could not map frame capture. {}", e),
+        });
+
+        self.pending.push(PendingCapture {
+            buffer,
+            ready,
+            width,
+            height,
+            padded_row,
+            bgra,
+            callbacks: std::mem::take(&mut self.requests),
+        });
+    }
+
+    //Hands finished captures to their callbacks. Call once per frame.
+    pub fn poll(&mut self, context: &VisContext) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        context.device.poll(wgpu::Maintain::Poll);
+
+        let (ready, pending): (Vec<_>, Vec<_>) =
+            self.pending.drain(..).partition(|capture| capture.ready.load(Ordering::Acquire));
+        self.pending = pending;
+
+        for capture in ready {
+            let image = capture.to_image();
+
+            for callback in capture.callbacks {
+                callback(image.clone());
+            }
+        }
+    }
+}
+
+impl PendingCapture {
+    fn to_image(&self) -> image::RgbaImage {
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+
+            //Strip the row padding.
+            for row in data.chunks(self.padded_row as usize) {
+                pixels.extend_from_slice(&row[..(self.width * 4) as usize]);
+            }
+        }
+
+        self.buffer.unmap();
+
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("Frame capture has the wrong size.")
+    }
+}
@@ -0,0 +1,154 @@
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::assets::assets::{Assets, Ptr, LOADING_TEXTURE};
+use crate::assets::texture::Texture2D;
+use crate::context::VisContext;
+use crate::entities::sprite::Sprite;
+use crate::entities::transform2d::Transform2D;
+use crate::event::Event;
+
+use super::camera::CameraBuffer;
+
+//A cursor image drawn by the renderer instead of the OS. The hotspot is the pixel of the image that points at the cursor position.
+#[derive(Clone)]
+pub struct SoftwareCursor {
+    texture: Ptr<Texture2D>,
+    hotspot: Vec2,
+    size: Option<Vec2>,
+}
+
+impl SoftwareCursor {
+    pub fn new(texture: Ptr<Texture2D>, hotspot: Vec2) -> Self {
+        SoftwareCursor { texture, hotspot, size: None }
+    }
+
+    //Draw the image with a fixed size (in pixels) instead of the size of the texture.
+    pub fn with_size(mut self, size: Vec2) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn texture(&self) -> &Ptr<Texture2D> {
+        &self.texture
+    }
+
+    pub fn hotspot(&self) -> Vec2 {
+        self.hotspot
+    }
+
+    pub fn set_hotspot(&mut self, hotspot: Vec2) {
+        self.hotspot = hotspot;
+    }
+}
+
+//Renderer side of the software cursor. Lives in screen space, so it has its own camera and an identity transform.
+pub(crate) struct CursorOverlay {
+    cursor: Option<SoftwareCursor>,
+    sprite: Sprite<'static>,
+    transform: Transform2D,
+    camera: CameraBuffer,
+    position: Vec2,
+    focused: bool,
+    inside: bool,
+    os_visible: Option<bool>,
+}
+
+impl CursorOverlay {
+    pub fn new(context: &VisContext) -> Self {
+        CursorOverlay {
+            cursor: None,
+            sprite: Sprite::new(context, *LOADING_TEXTURE, Vec4::ONE, None, None),
+            transform: Transform2D::new(context, Vec3::ZERO, 0.0, Vec2::ONE),
+            camera: CameraBuffer::new(context, "Cursor Camera"),
+            position: Vec2::ZERO,
+            focused: true,
+            inside: true,
+            os_visible: None,
+        }
+    }
+
+    pub fn set_cursor(&mut self, cursor: Option<SoftwareCursor>) {
+        if let Some(cursor) = &cursor {
+            self.sprite.set_texture(*cursor.texture());
+        }
+
+        self.cursor = cursor;
+    }
+
+    pub fn cursor(&self) -> Option<&SoftwareCursor> {
+        self.cursor.as_ref()
+    }
+
+    pub fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::CursorMoved { x, y } => self.position = Vec2::new(*x as f32, *y as f32),
+            Event::CursorEntered => self.inside = true,
+            Event::CursorLeft => self.inside = false,
+            Event::Focused(focused) => self.focused = *focused,
+            //Other code toggles the OS cursor on mouse input, so reapply our state next frame.
+            Event::MouseInput { .. } => self.os_visible = None,
+            _ => {}
+        }
+    }
+
+    //Only draw while we own the pointer. Without focus the OS cursor takes over immediately.
+    pub fn active(&self) -> bool {
+        self.cursor.is_some() && self.focused && self.inside
+    }
+
+    pub fn sync_os_cursor(&mut self, window: &winit::window::Window) {
+        let visible = !self.active();
+
+        if self.os_visible != Some(visible) {
+            window.set_cursor_visible(visible);
+            self.os_visible = Some(visible);
+        }
+    }
+
+    //Returns false if there is nothing to draw this frame.
+    pub fn prepare(
+        &mut self, context: &VisContext, assets: &Assets, width: u32, height: u32,
+    ) -> bool {
+        let Some(cursor) = &self.cursor else {
+            return false;
+        };
+
+        if !self.active() {
+            return false;
+        }
+
+        let Some(texture) = assets.try_get(cursor.texture()) else {
+            return false;
+        };
+
+        self.sprite.update(context, texture);
+
+        let extent = texture.texture().size();
+        let size = cursor.size.unwrap_or(Vec2::new(extent.width as f32, extent.height as f32));
+        let center = self.position - cursor.hotspot + size / 2.0;
+
+        //The sprite quad spans -1..1, so scale by half the size.
+        let projection = Mat4::orthographic_rh(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x / 2.0, size.y / 2.0, 1.0),
+            glam::Quat::IDENTITY,
+            Vec3::new(center.x, center.y, 0.0),
+        );
+
+        self.camera.update_buffer(context, (projection * model).to_cols_array_2d());
+        self.camera.update_viewport((0.0, 0.0, width as f32, height as f32));
+        true
+    }
+
+    pub fn sprite(&self) -> &Sprite<'static> {
+        &self.sprite
+    }
+
+    pub fn transform(&self) -> &Transform2D {
+        &self.transform
+    }
+
+    pub fn camera(&self) -> &CameraBuffer {
+        &self.camera
+    }
+}
@@ -4,9 +4,10 @@ use std::hash::Hasher;
 
 use crate::assets::assets::Assets;
 use crate::assets::assets::GenPtr;
-use crate::assets::shader::ShaderVariant;
+use crate::assets::shader::{EntryPoint, ShaderVariant};
 use crate::context::VisContext;
 use crate::utils::Guid;
+use hashbrown::hash_map::Entry;
 use hashbrown::HashMap;
 use smallvec::SmallVec;
 
@@ -14,16 +15,21 @@ use super::types::BindLayout;
 use super::types::PipelineBaseConfig;
 use super::types::VertexLayout;
 
-#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Default, Clone, PartialEq, Eq, Hash)]
 pub struct PipelineConfigKey {
     vertex: Guid,
     fragment: Guid,
+    //Part of the key, a module can have several entry points of the same stage.
+    vertex_entry: EntryPoint,
+    fragment_entry: EntryPoint,
     base_config: PipelineBaseConfig,
 }
 
 pub struct RenderPipelineConfig<'a> {
     pub vertex_shader: &'a wgpu::ShaderModule,
     pub fragment_shader: &'a wgpu::ShaderModule,
+    pub vertex_entry: &'a str,
+    pub fragment_entry: &'a str,
     pub vertex_layout: &'a [wgpu::VertexBufferLayout<'a>],
     pub bind_layouts: SmallVec<[&'a wgpu::BindGroupLayout; 16]>,
     key: PipelineConfigKey,
@@ -46,11 +52,15 @@ impl<'a> RenderPipelineConfig<'a> {
         Self {
             vertex_shader: shader.vertex().module(),
             fragment_shader: shader.fragment().module(),
+            vertex_entry: shader.vertex().vertex_entry(),
+            fragment_entry: shader.fragment().fragment_entry(),
             vertex_layout,
             bind_layouts,
             key: PipelineConfigKey {
                 vertex: shader.vertex_id().inner(),
                 fragment: shader.fragment_id().inner(),
+                vertex_entry: shader.vertex().entry_ids().0,
+                fragment_entry: shader.fragment().entry_ids().1,
                 base_config: PipelineBaseConfig::default(),
             },
         }
@@ -61,7 +71,7 @@ impl<'a> RenderPipelineConfig<'a> {
     }
 
     pub fn key(&self) -> PipelineConfigKey {
-        self.key
+        self.key.clone()
     }
 }
 
@@ -101,19 +111,31 @@ impl<'a> RenderPipelineBuilder<'a> {
         RenderPipelineConfig {
             vertex_shader: self.shader.vertex().module(),
             fragment_shader: self.shader.fragment().module(),
+            vertex_entry: self.shader.vertex().vertex_entry(),
+            fragment_entry: self.shader.fragment().fragment_entry(),
             vertex_layout: self.vertex_layout,
             bind_layouts: self.bind_layouts,
             key: PipelineConfigKey {
                 vertex: self.shader.vertex_id().inner(),
                 fragment: self.shader.fragment_id().inner(),
+                vertex_entry: self.shader.vertex().entry_ids().0,
+                fragment_entry: self.shader.fragment().entry_ids().1,
                 base_config: self.base_config,
             },
         }
     }
 }
 
+//Lookups through get_or_create since the last reset.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
 pub struct PipelineFactory {
     cache: HashMap<PipelineConfigKey, wgpu::RenderPipeline>,
+    stats: PipelineCacheStats,
 }
 
 impl Default for PipelineFactory {
@@ -124,7 +146,7 @@ impl Default for PipelineFactory {
 
 impl PipelineFactory {
     pub fn new() -> Self {
-        Self { cache: HashMap::new() }
+        Self { cache: HashMap::new(), stats: PipelineCacheStats::default() }
     }
 
     pub fn get(&self, config: &RenderPipelineConfig) -> Option<&wgpu::RenderPipeline> {
@@ -142,7 +164,24 @@ impl PipelineFactory {
     pub fn get_or_create(
         &mut self, context: &VisContext, config: &RenderPipelineConfig,
     ) -> &wgpu::RenderPipeline {
-        self.cache.entry(config.key).or_insert_with(|| PipelineFactory::create(context, config))
+        match self.cache.entry(config.key.clone()) {
+            Entry::Occupied(entry) => {
+                self.stats.hits += 1;
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                self.stats.misses += 1;
+                entry.insert(PipelineFactory::create(context, config))
+            }
+        }
+    }
+
+    pub fn stats(&self) -> PipelineCacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = PipelineCacheStats::default();
     }
 
     fn create(context: &VisContext, config: &RenderPipelineConfig) -> wgpu::RenderPipeline {
@@ -173,15 +212,15 @@ impl PipelineFactory {
             },
             vertex: wgpu::VertexState {
                 module: config.vertex_shader,
-                entry_point: "vertex_main",
+                entry_point: config.vertex_entry,
                 buffers: config.vertex_layout,
             },
             fragment: Some(wgpu::FragmentState {
                 module: config.fragment_shader,
-                entry_point: "fragment_main",
+                entry_point: config.fragment_entry,
                 targets: color_state,
             }),
-            depth_stencil: None,
+            depth_stencil: config.key.base_config.depth.clone(),
             multisample: wgpu::MultisampleState {
                 count: config.key.base_config.samples,
                 mask: !0,
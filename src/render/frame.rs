@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use crate::context::VisContext;
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub submissions: u32,
+    pub command_buffers: u32,
+}
+
+//Collects the commands of a frame, so everything goes to the queue in one submit at the end of it.
+//Renderers and custom passes record into the shared encoder. Work recorded elsewhere, e.g. on
+//another thread, is handed back with push and runs before the shared encoder, in the order pushed.
+pub struct FrameEncoder {
+    graphics: Arc<VisContext>,
+    encoder: Option<wgpu::CommandEncoder>,
+    pushed: Vec<wgpu::CommandBuffer>,
+    current: FrameStats,
+    last: FrameStats,
+}
+
+impl FrameEncoder {
+    pub(crate) fn new(graphics: Arc<VisContext>) -> Self {
+        FrameEncoder {
+            graphics,
+            encoder: None,
+            pushed: Vec::new(),
+            current: FrameStats::default(),
+            last: FrameStats::default(),
+        }
+    }
+
+    //The shared encoder of this frame, created on first use.
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        self.encoder.get_or_insert_with(|| {
+            self.graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame Encoder"),
+            })
+        })
+    }
+
+    //An encoder of its own. Finish it and push the result to have it submitted with the frame.
+    pub fn secondary(&self, label: &str) -> wgpu::CommandEncoder {
+        self.graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) })
+    }
+
+    pub fn push(&mut self, commands: wgpu::CommandBuffer) {
+        self.pushed.push(commands);
+    }
+
+    //Submits everything recorded so far right away. Only for work whose results are needed before
+    //the frame ends, e.g. a readback that has to be mapped. Every flush is one more submission.
+    pub fn flush(&mut self) -> Option<wgpu::SubmissionIndex> {
+        let mut buffers = std::mem::take(&mut self.pushed);
+        buffers.extend(self.encoder.take().map(|encoder| encoder.finish()));
+
+        if buffers.is_empty() {
+            return None;
+        }
+
+        self.current.submissions += 1;
+        self.current.command_buffers += buffers.len() as u32;
+        Some(self.graphics.queue.submit(buffers))
+    }
+
+    //Submits the rest, called by the engine before the frame is presented.
+    pub(crate) fn end_frame(&mut self) {
+        self.flush();
+        self.last = std::mem::take(&mut self.current);
+    }
+
+    //Of the last finished frame.
+    pub fn stats(&self) -> FrameStats {
+        self.last
+    }
+}
@@ -1,7 +1,15 @@
 use crate::context::Context;
 
+use super::types::PipelineBaseConfig;
+
+//Has a stencil aspect, so materials can use stencil masks without a separate attachment.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
 pub struct Framebuffer {
     texture: wgpu::Texture,
+    depth: wgpu::Texture,
+    //Scene depth as a texture for the translucent pass, None if it can't be sampled.
+    depth_binding: Option<(wgpu::BindGroupLayout, wgpu::BindGroup)>,
     sample_count: u32,
     width: f32,
     height: f32,
@@ -21,7 +29,17 @@ impl Framebuffer {
             view_formats: &context.surface_config.view_formats,
         });
 
-        Framebuffer { texture, sample_count, width: width as f32, height: height as f32 }
+        let depth = Framebuffer::create_depth(context, sample_count, width, height);
+        let depth_binding = Framebuffer::create_depth_binding(context, &depth, sample_count, None);
+
+        Framebuffer {
+            texture,
+            depth,
+            depth_binding,
+            sample_count,
+            width: width as f32,
+            height: height as f32,
+        }
     }
 
     pub fn resize(&mut self, context: &Context, width: u32, height: u32) {
@@ -34,7 +52,7 @@ impl Framebuffer {
     }
 
     pub fn change_sample_count(&mut self, context: &Context, sample_count: u32) -> bool {
-        if context.features.texture_features.sample_count_supported(sample_count) {
+        if context.features.supports_samples(sample_count) {
             let width = self.texture.width();
             let height = self.texture.height();
             self.sample_count = sample_count;
@@ -49,7 +67,111 @@ impl Framebuffer {
         self.sample_count
     }
 
+    pub fn depth_view(&self) -> wgpu::TextureView {
+        self.depth.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    //Only valid while the depth attachment is read only, i.e. after the opaque pass.
+    pub fn depth_binding(&self) -> Option<(&wgpu::BindGroupLayout, &wgpu::BindGroup)> {
+        self.depth_binding.as_ref().map(|(layout, group)| (layout, group))
+    }
+
+    //Depth state for pipelines drawing into this framebuffer. Larger z ends up in front.
+    pub fn depth_state(write: bool) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: write,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    //Depth state of a material drawn into this framebuffer. Its own one if it brings one, which has
+    //to fit the attachment, the default otherwise. Translucent draws never write depth.
+    pub fn material_depth(
+        base: &PipelineBaseConfig, write: bool, pass: &str,
+    ) -> wgpu::DepthStencilState {
+        match &base.depth {
+            Some(depth) => {
+                base.check_depth_attachment(Some(DEPTH_FORMAT), pass);
+                wgpu::DepthStencilState {
+                    depth_write_enabled: write && depth.depth_write_enabled,
+                    ..depth.clone()
+                }
+            }
+            None => Framebuffer::depth_state(write),
+        }
+    }
+
+    fn create_depth(
+        context: &Context, sample_count: u32, width: u32, height: u32,
+    ) -> wgpu::Texture {
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+
+        if context.features.depth_sampling {
+            usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+        }
+
+        context.graphics.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage,
+            view_formats: &[],
+        })
+    }
+
+    //Keeps the layout if one is passed in, so pipelines stay compatible across resizes.
+    fn create_depth_binding(
+        context: &Context, depth: &wgpu::Texture, sample_count: u32,
+        layout: Option<wgpu::BindGroupLayout>,
+    ) -> Option<(wgpu::BindGroupLayout, wgpu::BindGroup)> {
+        if !context.features.depth_sampling {
+            return None;
+        }
+
+        let device = &context.graphics.device;
+
+        let layout = layout.unwrap_or_else(|| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Scene Depth Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: sample_count > 1,
+                    },
+                    count: None,
+                }],
+            })
+        });
+
+        let view = depth.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+
+        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Scene Depth"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        Some((layout, group))
+    }
+
     fn create_buffer(&mut self, context: &Context, sample_count: u32, width: u32, height: u32) {
+        let previous_samples = self.depth.sample_count();
+
         self.texture = context.graphics.device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Texture"),
             size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
@@ -60,6 +182,17 @@ impl Framebuffer {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &context.surface_config.view_formats,
         });
+
+        self.depth = Framebuffer::create_depth(context, sample_count, width, height);
+
+        //A different sample count needs a different layout.
+        let layout = self
+            .depth_binding
+            .take()
+            .filter(|_| sample_count == previous_samples)
+            .map(|(layout, _)| layout);
+        self.depth_binding =
+            Framebuffer::create_depth_binding(context, &self.depth, sample_count, layout);
     }
 }
 
@@ -0,0 +1,149 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::assets::assets::{Ptr, LIGHTING_SHADER};
+use crate::assets::buffer::UniformBuffer;
+use crate::assets::shader::Shader;
+use crate::context::VisContext;
+use crate::entities::light2d::PointLight2D;
+use crate::entities::transform2d::Transform2D;
+use crate::render::types::BindGroupEntry;
+
+use super::types::{
+    BindGroup, BindLayout, FragmentShader, LightingUniform, Material, MaterialLayout,
+    PipelineBaseConfig, PointLightUniform, VertexShader, MAX_LIGHTS,
+};
+
+//The point lights of a world and the ambient light. Drawn as one pass over the finished world,
+//multiplying it with the light that reaches each pixel.
+pub(crate) struct Lighting {
+    ambient: Vec3,
+    uniforms: UniformBuffer,
+    bind_layout: [wgpu::BindGroupLayout; 1],
+    bind_group: [wgpu::BindGroup; 1],
+}
+
+impl Lighting {
+    pub fn new(context: &VisContext) -> Self {
+        let uniforms = UniformBuffer::new(context, std::mem::size_of::<LightingUniform>());
+
+        let bind_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Lighting Layout"),
+                entries: &[UniformBuffer::layout_entry(0)],
+            });
+
+        let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lighting Group"),
+            layout: &bind_layout,
+            entries: &[uniforms.group_entry(0)],
+        });
+
+        Lighting {
+            ambient: Vec3::ONE,
+            uniforms,
+            bind_layout: [bind_layout],
+            bind_group: [bind_group],
+        }
+    }
+
+    pub fn ambient(&self) -> Vec3 {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: Vec3) {
+        self.ambient = ambient.max(Vec3::ZERO);
+    }
+
+    //Gathers the lights of the world that reach into the view. If there are too many, the ones
+    //closest to its center win. Returns false if there is nothing to light, the pass is skipped then.
+    pub fn prepare(
+        &mut self, context: &VisContext, encoder: &mut wgpu::CommandEncoder,
+        belt: &mut wgpu::util::StagingBelt, world: &hecs::World, view_projection: Mat4,
+        bounds: Option<(Vec2, Vec2)>,
+    ) -> bool {
+        let center = bounds.map(|(min, max)| (min + max) / 2.0).unwrap_or_default();
+
+        let mut lights: Vec<(Vec2, PointLight2D)> = world
+            .query::<(&Transform2D, &PointLight2D)>()
+            .iter()
+            .map(|(_, (transform, light))| {
+                (transform.global().w_axis.truncate().truncate(), *light)
+            })
+            .filter(|(position, light)| {
+                light.radius > 0.0
+                    && bounds.map_or(true, |(min, max)| {
+                        position.cmpge(min - light.radius).all()
+                            && position.cmple(max + light.radius).all()
+                    })
+            })
+            .collect();
+
+        if lights.is_empty() && self.ambient == Vec3::ONE {
+            return false;
+        }
+
+        if lights.len() > MAX_LIGHTS {
+            lights.sort_by(|(a, _), (b, _)| {
+                a.distance_squared(center).total_cmp(&b.distance_squared(center))
+            });
+            lights.truncate(MAX_LIGHTS);
+        }
+
+        let mut uniform = LightingUniform {
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            ambient: self.ambient.extend(1.0).to_array(),
+            count: lights.len() as u32,
+            padding: [0; 3],
+            lights: [PointLightUniform::default(); MAX_LIGHTS],
+        };
+
+        for (slot, (position, light)) in uniform.lights.iter_mut().zip(lights) {
+            *slot = PointLightUniform {
+                position: position.to_array(),
+                radius: light.radius,
+                falloff: light.falloff,
+                color: (light.color * light.intensity).extend(1.0).to_array(),
+            };
+        }
+
+        self.uniforms.update_buffer_staged(
+            context,
+            encoder,
+            belt,
+            bytemuck::cast_slice(&[uniform]),
+        );
+        true
+    }
+}
+
+impl MaterialLayout for Lighting {
+    fn base_config(&self) -> Option<PipelineBaseConfig> {
+        Some(PipelineBaseConfig::multiply())
+    }
+}
+
+impl Material for Lighting {}
+
+impl BindLayout for Lighting {
+    fn layouts(&self) -> &[wgpu::BindGroupLayout] {
+        &self.bind_layout
+    }
+}
+
+impl BindGroup for Lighting {
+    fn groups(&self) -> &[wgpu::BindGroup] {
+        &self.bind_group
+    }
+}
+
+impl FragmentShader for Lighting {
+    fn ptr(&self) -> &Ptr<Shader> {
+        &LIGHTING_SHADER
+    }
+}
+
+impl VertexShader for Lighting {
+    fn ptr(&self) -> &Ptr<Shader> {
+        &LIGHTING_SHADER
+    }
+}
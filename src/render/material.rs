@@ -1,17 +1,21 @@
-use glam::Vec4;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use glam::{Vec2, Vec4};
 
 use crate::assets::assets::BACKGROUND_SHADER;
 use crate::assets::texture::Texture2D;
-use crate::render::types::BindGroupEntry;
+use crate::render::types::{BackgroundUniform, BindGroupEntry};
 
 use crate::{
     assets::{
-        assets::Ptr,
+        assets::{AssetType, Assets, GenPtr, Ptr},
         buffer::UniformBuffer,
         shader::Shader,
         texture::{Sampler, TextureArray},
     },
     context::VisContext,
+    utils::Guid,
 };
 
 use super::types::{
@@ -68,6 +72,41 @@ impl SkyboxMaterial {
         }
     }
 
+    //The built-in skybox shader, both stages are in the same module.
+    pub fn default_shader(context: &VisContext, assets: &mut Assets) -> Ptr<Shader> {
+        assets.consume_asset(
+            AssetType::Shader(
+                Shader::new(
+                    context,
+                    Guid::dead(),
+                    wgpu::ShaderSource::Wgsl(include_str!("../assets/skybox.wgsl").into()),
+                    what::ShaderStages::VERTEX | what::ShaderStages::FRAGMENT,
+                )
+                .unwrap(),
+            ),
+            None::<&str>,
+        )
+    }
+
+    //A skybox from an equirectangular panorama. The cube map is registered as an asset of its own.
+    pub fn from_equirect(
+        context: &VisContext, assets: &mut Assets, ptr: &Ptr<Texture2D>,
+    ) -> Option<Self> {
+        let cube = {
+            let panorama = assets.get(ptr)?;
+            //A face covers a quarter of the panorama horizontally.
+            let size = (panorama.size().0 / 4).max(1);
+            TextureArray::from_equirect(context, panorama, size)
+        };
+
+        let shader = SkyboxMaterial::default_shader(context, assets);
+        let material = SkyboxMaterial::new(context, shader, shader, &cube);
+        let _: Ptr<TextureArray> =
+            assets.consume_asset(AssetType::TextureArray(cube), None::<&str>);
+
+        Some(material)
+    }
+
     pub fn update_buffer(
         &mut self, context: &VisContext, view: [[f32; 4]; 4], projection: [[f32; 4]; 4],
     ) {
@@ -166,12 +205,16 @@ pub struct Background2DMaterial {
 
     //Buffer and uniform
     buffer: UniformBuffer,
+    uniform: BackgroundUniform,
 }
 
 impl Background2DMaterial {
     pub fn new(context: &VisContext, texture: &Texture2D, tint: Vec4) -> Self {
-        let mut buffer = UniformBuffer::new(context, std::mem::size_of::<[f32; 4]>());
-        buffer.update_buffer(context, bytemuck::cast_slice(&tint.to_array()));
+        let uniform =
+            BackgroundUniform { tint: tint.to_array(), uv_offset: [0.0; 2], uv_scale: [1.0; 2] };
+
+        let mut buffer = UniformBuffer::new(context, std::mem::size_of::<BackgroundUniform>());
+        buffer.update_buffer(context, bytemuck::bytes_of(&uniform));
 
         let bind_layout =
             context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -199,6 +242,7 @@ impl Background2DMaterial {
             bind_layout: [bind_layout],
             bind_group: [bind_group],
             buffer,
+            uniform,
         }
     }
 
@@ -215,7 +259,24 @@ impl Background2DMaterial {
     }
 
     pub fn update_tint(&mut self, context: &VisContext, tint: Vec4) {
-        self.buffer.update_buffer(context, bytemuck::cast_slice(&tint.to_array()));
+        self.uniform.tint = tint.to_array();
+        self.buffer.update_buffer(context, bytemuck::bytes_of(&self.uniform));
+    }
+
+    pub fn tint(&self) -> Vec4 {
+        Vec4::from_array(self.uniform.tint)
+    }
+
+    //Offset and scale, see update_uv.
+    pub fn uv(&self) -> (Vec2, Vec2) {
+        (Vec2::from_array(self.uniform.uv_offset), Vec2::from_array(self.uniform.uv_scale))
+    }
+
+    //The part of the image shown, in uv of the texture. Default is all of it.
+    pub fn update_uv(&mut self, context: &VisContext, offset: Vec2, scale: Vec2) {
+        self.uniform.uv_offset = offset.to_array();
+        self.uniform.uv_scale = scale.to_array();
+        self.buffer.update_buffer(context, bytemuck::bytes_of(&self.uniform));
     }
 }
 
@@ -251,6 +312,18 @@ impl VertexShader for Background2DMaterial {
     }
 }
 
+//Warn once a fingerprint has this many live materials.
+#[cfg(debug_assertions)]
+const DUPLICATE_MATERIAL_THRESHOLD: usize = 16;
+
+//Live materials per fingerprint, with the first creation site and whether we already warned.
+#[cfg(debug_assertions)]
+static MATERIAL_FINGERPRINTS: once_cell::sync::Lazy<
+    std::sync::Mutex<
+        hashbrown::HashMap<u64, (usize, &'static std::panic::Location<'static>, bool)>,
+    >,
+> = once_cell::sync::Lazy::new(Default::default);
+
 pub struct GenericMaterial {
     //Shader
     vertex: Ptr<Shader>,
@@ -259,13 +332,32 @@ pub struct GenericMaterial {
     //Bind group layout and bind group
     bind_layout: [wgpu::BindGroupLayout; 1],
     bind_group: [wgpu::BindGroup; 1],
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+
+    base_config: Option<PipelineBaseConfig>,
+    fingerprint: u64,
 }
 
 impl GenericMaterial {
+    //Assets are the ones bound in groups, e.g. the texture. Other resources only count by slot.
+    #[track_caller]
     pub fn new(
         context: &VisContext, vertex: Ptr<Shader>, fragment: Ptr<Shader>,
-        entries: &[wgpu::BindGroupLayoutEntry], groups: &[wgpu::BindGroupEntry],
+        entries: &[wgpu::BindGroupLayoutEntry], groups: &[wgpu::BindGroupEntry], assets: &[GenPtr],
+        base_config: Option<PipelineBaseConfig>,
     ) -> Self {
+        let fingerprint = GenericMaterial::fingerprint_of(
+            &vertex,
+            &fragment,
+            entries,
+            groups,
+            assets,
+            &base_config,
+        );
+
+        #[cfg(debug_assertions)]
+        GenericMaterial::track(fingerprint, std::panic::Location::caller());
+
         let bind_layout = context
             .device
             .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries });
@@ -276,21 +368,118 @@ impl GenericMaterial {
             entries: groups,
         });
 
-        GenericMaterial { vertex, fragment, bind_layout: [bind_layout], bind_group: [bind_group] }
+        GenericMaterial {
+            vertex,
+            fragment,
+            bind_layout: [bind_layout],
+            bind_group: [bind_group],
+            entries: entries.to_vec(),
+            base_config,
+            fingerprint,
+        }
     }
 
-    pub fn update_group(&mut self, context: &VisContext, group: &[wgpu::BindGroupEntry]) {
+    #[track_caller]
+    pub fn update_group(
+        &mut self, context: &VisContext, group: &[wgpu::BindGroupEntry], assets: &[GenPtr],
+    ) {
         self.bind_group[0] = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.bind_layout[0],
             entries: group,
         });
+
+        let fingerprint = GenericMaterial::fingerprint_of(
+            &self.vertex,
+            &self.fragment,
+            &self.entries,
+            group,
+            assets,
+            &self.base_config,
+        );
+
+        if fingerprint != self.fingerprint {
+            #[cfg(debug_assertions)]
+            {
+                GenericMaterial::untrack(self.fingerprint);
+                GenericMaterial::track(fingerprint, std::panic::Location::caller());
+            }
+
+            self.fingerprint = fingerprint;
+        }
+    }
+
+    //Materials with equal fingerprints use the same shaders, layout and assets and could be shared.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    fn fingerprint_of(
+        vertex: &Ptr<Shader>, fragment: &Ptr<Shader>, entries: &[wgpu::BindGroupLayoutEntry],
+        groups: &[wgpu::BindGroupEntry], assets: &[GenPtr],
+        base_config: &Option<PipelineBaseConfig>,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        //Pointers hash their asset guid.
+        vertex.hash(&mut hasher);
+        fragment.hash(&mut hasher);
+        entries.hash(&mut hasher);
+        base_config.hash(&mut hasher);
+
+        //Buffers, views and samplers are created per user, only their slots can match.
+        for group in groups {
+            group.binding.hash(&mut hasher);
+            std::mem::discriminant(&group.resource).hash(&mut hasher);
+        }
+
+        assets.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    #[cfg(debug_assertions)]
+    fn track(fingerprint: u64, location: &'static std::panic::Location<'static>) {
+        let Ok(mut fingerprints) = MATERIAL_FINGERPRINTS.lock() else {
+            return;
+        };
+
+        let entry = fingerprints.entry(fingerprint).or_insert((0, location, false));
+        entry.0 += 1;
+
+        if entry.0 >= DUPLICATE_MATERIAL_THRESHOLD && !entry.2 {
+            entry.2 = true;
+            log::warn!(
+                "{} identical materials created at {}. Consider sharing one material between them.",
+                entry.0,
+                entry.1
+            );
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn untrack(fingerprint: u64) {
+        if let Ok(mut fingerprints) = MATERIAL_FINGERPRINTS.lock() {
+            if let Some(entry) = fingerprints.get_mut(&fingerprint) {
+                entry.0 -= 1;
+
+                if entry.0 == 0 {
+                    fingerprints.remove(&fingerprint);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for GenericMaterial {
+    fn drop(&mut self) {
+        GenericMaterial::untrack(self.fingerprint);
     }
 }
 
 impl MaterialLayout for GenericMaterial {
     fn base_config(&self) -> Option<PipelineBaseConfig> {
-        None
+        self.base_config.clone()
     }
 }
 
@@ -0,0 +1,241 @@
+use glam::{Mat4, Vec2, Vec4};
+use hashbrown::HashMap;
+
+use crate::assets::assets::{AssetType, Assets, Ptr};
+use crate::assets::texture::Texture2D;
+use crate::context::VisContext;
+use crate::entities::layer::RenderLayer;
+use crate::entities::sprite::Sprite;
+use crate::entities::transform2d::Transform2D;
+
+use super::camera::{CameraBuffer, OPENGL_TO_WGPU};
+use super::framebuffer::DEPTH_FORMAT;
+
+//Shows the entity as a marker on every minimap. Size is in minimap pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MinimapIcon {
+    pub texture: Ptr<Texture2D>,
+    pub color: Vec4,
+    pub size: f32,
+}
+
+//Renders a region of the world into a small texture and puts the icons of MinimapIcon entities on top.
+//Draw it with Renderer2D::render_minimap every frame, it only does work every update_interval frames.
+//The world itself is only drawn again after the region changed, the icons on every update.
+pub struct Minimap {
+    texture: Ptr<Texture2D>,
+    //The world without icons, copied into the texture before the icons are drawn.
+    base: wgpu::Texture,
+    depth: wgpu::Texture,
+    width: u32,
+    height: u32,
+    camera: CameraBuffer,
+    center: Vec2,
+    half_height: f32,
+    view_projection: Mat4,
+    layers: Option<Vec<RenderLayer>>,
+    clear_color: Vec4,
+    update_interval: u32,
+    frame: u32,
+    redraw_world: bool,
+    icons: HashMap<hecs::Entity, (Sprite<'static>, Transform2D)>,
+}
+
+impl Minimap {
+    pub fn new(context: &VisContext, assets: &mut Assets, width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+
+        let texture = Texture2D::new_render_target(context, Some("Minimap"), (width, height));
+        let texture = assets.consume_asset(AssetType::Texture2D(texture), None::<&str>);
+
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+
+        let base = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Minimap Base"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let depth = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Minimap Depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let mut camera = CameraBuffer::new(context, "Minimap Camera");
+        camera.update_viewport((0.0, 0.0, width as f32, height as f32));
+
+        let mut minimap = Minimap {
+            texture,
+            base,
+            depth,
+            width,
+            height,
+            camera,
+            center: Vec2::ZERO,
+            half_height: 10.0,
+            view_projection: Mat4::IDENTITY,
+            layers: None,
+            clear_color: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            update_interval: 10,
+            frame: 0,
+            redraw_world: true,
+            icons: HashMap::new(),
+        };
+
+        minimap.calc_view_projection();
+        minimap
+    }
+
+    //Only draw sprites and tilemaps on these layers. Entities without a RenderLayer are on layer 0.
+    pub fn with_layers(mut self, layers: &[RenderLayer]) -> Self {
+        self.set_layers(Some(layers.to_vec()));
+        self
+    }
+
+    //Update every n frames, 1 updates every frame.
+    pub fn with_update_interval(mut self, frames: u32) -> Self {
+        self.update_interval = frames.max(1);
+        self
+    }
+
+    pub fn with_clear_color(mut self, color: Vec4) -> Self {
+        self.clear_color = color;
+        self.redraw_world = true;
+        self
+    }
+
+    //The texture to show, e.g. on a sprite or through Renderer2D::egui_texture.
+    pub fn texture(&self) -> Ptr<Texture2D> {
+        self.texture
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_layers(&mut self, layers: Option<Vec<RenderLayer>>) {
+        self.layers = layers;
+        self.redraw_world = true;
+    }
+
+    //Shows the world around center, half_height world units up and down. The width follows the aspect ratio.
+    pub fn set_region(&mut self, center: Vec2, half_height: f32) {
+        if self.center != center || self.half_height != half_height {
+            self.center = center;
+            self.half_height = half_height.max(f32::EPSILON);
+            self.calc_view_projection();
+            self.redraw_world = true;
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.center
+    }
+
+    pub fn half_height(&self) -> f32 {
+        self.half_height
+    }
+
+    //Draw the world again on the next update, e.g. after the level changed.
+    pub fn invalidate(&mut self) {
+        self.redraw_world = true;
+    }
+
+    //World space rectangle covered by the minimap.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        (self.minimap_to_world(Vec2::new(0.0, 1.0)), self.minimap_to_world(Vec2::new(1.0, 0.0)))
+    }
+
+    //Maps a world position to the minimap, (0, 0) is the top left and (1, 1) the bottom right corner.
+    pub fn world_to_minimap(&self, position: Vec2) -> Vec2 {
+        let ndc = self.view_projection.project_point3(position.extend(0.0));
+        Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) / 2.0
+    }
+
+    //The inverse of world_to_minimap, e.g. to turn a click on the minimap into a world position.
+    //The mapping is affine on the z = 0 plane, so two projected points are enough to invert it.
+    pub fn minimap_to_world(&self, uv: Vec2) -> Vec2 {
+        let origin = self.world_to_minimap(self.center);
+        let step = self.world_to_minimap(self.center + Vec2::ONE) - origin;
+        self.center + (uv - origin) / step
+    }
+
+    //World units per minimap pixel.
+    pub fn units_per_pixel(&self) -> f32 {
+        let (min, max) = self.bounds();
+        (max.y - min.y) / self.height as f32
+    }
+
+    fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    //Same setup as the OrthographicCamera, just with the aspect ratio of the texture.
+    fn calc_view_projection(&mut self) {
+        let (half_width, half_height) = (self.half_height * self.aspect_ratio(), self.half_height);
+        let projection =
+            Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, 0.1, 100.0);
+        let view = Mat4::from_translation(self.center.extend(1.0)).inverse();
+
+        self.view_projection = OPENGL_TO_WGPU * projection * view;
+    }
+
+    pub(crate) fn shows(&self, layer: Option<&RenderLayer>) -> bool {
+        let layer = layer.copied().unwrap_or_default();
+        self.layers.as_ref().map_or(true, |layers| layers.contains(&layer))
+    }
+
+    //Counts frames, true if an update is due.
+    pub(crate) fn tick(&mut self) -> bool {
+        let due = self.frame % self.update_interval == 0;
+        self.frame = self.frame.wrapping_add(1);
+        due
+    }
+
+    pub(crate) fn take_redraw_world(&mut self) -> bool {
+        std::mem::take(&mut self.redraw_world)
+    }
+
+    pub(crate) fn update_camera(&mut self, context: &VisContext, bands: usize) {
+        self.camera.update_buffer(context, self.view_projection.to_cols_array_2d());
+        self.camera.update_bands(context, bands);
+    }
+
+    pub(crate) fn camera(&self) -> &CameraBuffer {
+        &self.camera
+    }
+
+    pub(crate) fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = self.clear_color.to_array().map(|c| c as f64);
+        wgpu::Color { r, g, b, a }
+    }
+
+    pub(crate) fn base(&self) -> &wgpu::Texture {
+        &self.base
+    }
+
+    pub(crate) fn depth_view(&self) -> wgpu::TextureView {
+        self.depth.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub(crate) fn icons(&self) -> &HashMap<hecs::Entity, (Sprite<'static>, Transform2D)> {
+        &self.icons
+    }
+
+    pub(crate) fn icons_mut(
+        &mut self,
+    ) -> &mut HashMap<hecs::Entity, (Sprite<'static>, Transform2D)> {
+        &mut self.icons
+    }
+}
@@ -1,8 +1,17 @@
 pub mod camera;
+pub mod camera_controller;
+pub mod capture;
+pub mod cursor;
 pub mod factory;
+pub mod frame;
 pub mod framebuffer;
+pub mod lighting;
 pub mod material;
 pub mod mesh;
+pub mod minimap;
+pub mod postprocess;
+pub mod profiler;
 pub mod render2d;
 pub mod renderer;
+pub mod transition;
 pub mod types;
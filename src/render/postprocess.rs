@@ -0,0 +1,175 @@
+use crate::assets::assets::{Ptr, POST_PROCESS_SHADER};
+use crate::assets::buffer::UniformBuffer;
+use crate::assets::shader::Shader;
+use crate::assets::texture::{Sampler, Texture2D};
+use crate::context::{Context, VisContext};
+use crate::render::types::BindGroupEntry;
+
+use super::types::{
+    BindGroup, BindLayout, FragmentShader, Material, MaterialLayout, PipelineBaseConfig,
+    VertexShader,
+};
+
+//Fullscreen effect between the world and the gui. The world is rendered into an offscreen texture,
+//which the shader samples at @group(0) @binding(0) and (1). Optional uniforms are at binding 2.
+pub struct PostProcess {
+    shader: Ptr<Shader>,
+    uniforms: Option<UniformBuffer>,
+    target: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: Sampler,
+    bind_layout: [wgpu::BindGroupLayout; 1],
+    bind_group: [wgpu::BindGroup; 1],
+}
+
+impl PostProcess {
+    pub fn new(context: &Context, shader: Ptr<Shader>, uniforms: Option<UniformBuffer>) -> Self {
+        let (width, height) = (context.surface_config.width, context.surface_config.height);
+        let graphics = context.graphics.as_ref();
+
+        let target = Self::create_target(graphics, width, height);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Sampler::two_dim(graphics);
+        let bind_layout = Self::create_layout(graphics, uniforms.is_some());
+        let bind_group =
+            Self::create_group(graphics, &bind_layout, &view, &sampler, uniforms.as_ref());
+
+        PostProcess {
+            shader,
+            uniforms,
+            target,
+            view,
+            sampler,
+            bind_layout: [bind_layout],
+            bind_group: [bind_group],
+        }
+    }
+
+    //The same effect on a new device, after the old one was lost. The uniforms are created with
+    //the same size but are empty until they are updated again.
+    pub fn recreate(&self, context: &Context) -> Self {
+        let uniforms = self
+            .uniforms
+            .as_ref()
+            .map(|uniforms| UniformBuffer::new(&context.graphics, uniforms.size()));
+
+        Self::new(context, self.shader, uniforms)
+    }
+
+    pub fn passthrough(context: &Context) -> Self {
+        Self::new(context, *POST_PROCESS_SHADER, None)
+    }
+
+    pub fn resize(&mut self, context: &VisContext, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.target = Self::create_target(context, width, height);
+        self.view = self.target.create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group[0] = Self::create_group(
+            context,
+            &self.bind_layout[0],
+            &self.view,
+            &self.sampler,
+            self.uniforms.as_ref(),
+        );
+    }
+
+    //The world passes render (or resolve) into this view.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    //An owned view of the same texture, for passes that need the renderer mutably.
+    pub(crate) fn create_scene_view(&self) -> wgpu::TextureView {
+        self.target.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn shader(&self) -> &Ptr<Shader> {
+        &self.shader
+    }
+
+    //Update these every frame, e.g. with time and resolution.
+    pub fn uniforms_mut(&mut self) -> Option<&mut UniformBuffer> {
+        self.uniforms.as_mut()
+    }
+
+    fn create_target(context: &VisContext, width: u32, height: u32) -> wgpu::Texture {
+        context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Post Process Target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn create_layout(context: &VisContext, uniforms: bool) -> wgpu::BindGroupLayout {
+        let mut entries = vec![Texture2D::layout_entry(0), Sampler::layout_entry(1)];
+
+        if uniforms {
+            entries.push(UniformBuffer::layout_entry(2));
+        }
+
+        context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Process Layout"),
+            entries: &entries,
+        })
+    }
+
+    fn create_group(
+        context: &VisContext, layout: &wgpu::BindGroupLayout, view: &wgpu::TextureView,
+        sampler: &Sampler, uniforms: Option<&UniformBuffer>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(view) },
+            sampler.group_entry(1),
+        ];
+
+        if let Some(uniforms) = uniforms {
+            entries.push(uniforms.group_entry(2));
+        }
+
+        context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Process Group"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
+
+impl MaterialLayout for PostProcess {
+    fn base_config(&self) -> Option<PipelineBaseConfig> {
+        None
+    }
+}
+
+impl Material for PostProcess {}
+
+impl BindLayout for PostProcess {
+    fn layouts(&self) -> &[wgpu::BindGroupLayout] {
+        &self.bind_layout
+    }
+}
+
+impl BindGroup for PostProcess {
+    fn groups(&self) -> &[wgpu::BindGroup] {
+        &self.bind_group
+    }
+}
+
+impl FragmentShader for PostProcess {
+    fn ptr(&self) -> &Ptr<Shader> {
+        &self.shader
+    }
+}
+
+impl VertexShader for PostProcess {
+    fn ptr(&self) -> &Ptr<Shader> {
+        &self.shader
+    }
+}
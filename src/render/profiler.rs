@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::context::VisContext;
+
+//Passes that can be timed per frame, the rest goes untimed.
+const MAX_SCOPES: u32 = 32;
+//Frames that can be in flight before results are read back.
+const READBACKS: usize = 3;
+
+enum ReadbackState {
+    Free,
+    //The copy is recorded, it can be mapped once the frame is submitted.
+    Copied,
+    Mapping(Arc<AtomicBool>),
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    labels: Vec<String>,
+    state: ReadbackState,
+}
+
+//Times render passes with timestamp queries. Needs wgpu::Features::TIMESTAMP_QUERY.
+//Results arrive a frame or two late, as the timestamps are only read back once the gpu is done.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readbacks: Vec<Readback>,
+    labels: Vec<String>,
+    //Nanoseconds per tick.
+    period: f32,
+    timings: Vec<(String, f32)>,
+}
+
+impl GpuProfiler {
+    pub fn new(context: &VisContext) -> Option<Self> {
+        if !context.device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let size = (MAX_SCOPES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+
+        let query_set = context.device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_SCOPES * 2,
+        });
+
+        let resolve = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readbacks = (0..READBACKS)
+            .map(|_| Readback {
+                buffer: context.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                labels: Vec::new(),
+                state: ReadbackState::Free,
+            })
+            .collect();
+
+        Some(GpuProfiler {
+            query_set,
+            resolve,
+            readbacks,
+            labels: Vec::new(),
+            period: context.queue.get_timestamp_period(),
+            timings: Vec::new(),
+        })
+    }
+
+    //Picks up finished results and starts reading back the ones submitted with the last frame.
+    pub fn begin_frame(&mut self, context: &VisContext) {
+        self.labels.clear();
+
+        for readback in self.readbacks.iter_mut() {
+            if let ReadbackState::Copied = readback.state {
+                let ready = Arc::new(AtomicBool::new(false));
+                let flag = ready.clone();
+
+                readback.buffer.slice(..).map_async(
+                    wgpu::MapMode::Read,
+                    move |result| match result {
+                        Ok(_) => flag.store(true, Ordering::Release),
+                        Err(e) => log::error!("Could not map the profiler timestamps. {}", e),
+                    },
+                );
+
+                readback.state = ReadbackState::Mapping(ready);
+            }
+        }
+
+        context.device.poll(wgpu::Maintain::Poll);
+
+        for readback in self.readbacks.iter_mut() {
+            let ReadbackState::Mapping(ready) = &readback.state else {
+                continue;
+            };
+
+            if !ready.load(Ordering::Acquire) {
+                continue;
+            }
+
+            {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                let ticks: &[u64] = bytemuck::cast_slice(&data);
+
+                self.timings = readback
+                    .labels
+                    .drain(..)
+                    .zip(ticks.chunks_exact(2))
+                    .map(|(label, pair)| {
+                        let nanos = pair[1].saturating_sub(pair[0]) as f32 * self.period;
+                        (label, nanos / 1_000_000.0)
+                    })
+                    .collect();
+            }
+
+            readback.buffer.unmap();
+            readback.state = ReadbackState::Free;
+        }
+    }
+
+    //Timestamp writes for a pass, None once all scopes of the frame are used up.
+    pub fn scope(&mut self, label: &str) -> Option<wgpu::RenderPassTimestampWrites> {
+        let index = self.labels.len() as u32;
+
+        if index >= MAX_SCOPES {
+            return None;
+        }
+
+        self.labels.push(label.to_string());
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    //Records the resolve of this frame's timestamps. Skipped if all readbacks are still in flight.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.labels.is_empty() {
+            return;
+        }
+
+        let Some(readback) = self
+            .readbacks
+            .iter_mut()
+            .find(|readback| matches!(readback.state, ReadbackState::Free))
+        else {
+            return;
+        };
+
+        let count = self.labels.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve,
+            0,
+            &readback.buffer,
+            0,
+            count as u64 * wgpu::QUERY_SIZE as u64,
+        );
+
+        readback.labels = std::mem::take(&mut self.labels);
+        readback.state = ReadbackState::Copied;
+    }
+
+    //Label and milliseconds of every timed pass, in the order they ran.
+    pub fn timings(&self) -> &[(String, f32)] {
+        &self.timings
+    }
+}
@@ -1,25 +1,52 @@
-use glam::Vec4;
+use glam::{Mat2, Mat4, Vec2, Vec3, Vec4};
+use hashbrown::HashMap;
+use hecs::Or;
+use wgpu::util::StagingBelt;
 use wgpu::TextureView;
 use winit::window::Window;
 
-use crate::assets::assets::{Assets, BACKGROUND_SHADER};
-use crate::assets::buffer::Vertices;
-use crate::assets::shader::ShaderVariant;
+use crate::assets::assets::{
+    Assets, Ptr, BACKGROUND_SHADER, LIGHTING_SHADER, LOADING_TEXTURE, PARTICLE_SHADER,
+    POST_PROCESS_SHADER, SOFT_SPRITE_SHADER, SOFT_SPRITE_SHADER_MS, SPRITE_SHADER,
+    TRANSITION_SHADER,
+};
+use crate::assets::buffer::{UniformBuffer, Vertices};
+use crate::assets::shader::{Shader, ShaderVariant};
 use crate::assets::texture::Texture2D;
 use crate::context::{Context, VisContext};
-use crate::entities::animation2d::Animation2D;
+use crate::entities::animation2d::{self, Animation2D};
+use crate::entities::animator2d::Animator2D;
 use crate::entities::entities::Worlds;
-use crate::entities::sprite::Sprite;
+use crate::entities::layer::{self, OrderInLayer, RenderLayer};
+use crate::entities::ldtk_entity::LevelBackground;
+use crate::entities::opacity::effective_opacity;
+use crate::entities::particles::{ParticleBlend, ParticleEmitter2D};
+use crate::entities::sprite::{Hidden, Sprite};
+use crate::entities::tilemap::Tilemap;
 use crate::entities::transform2d::Transform2D;
 use crate::event::{self, EventSubscriber};
 use crate::render::renderer::Renderer;
-use crate::utils::Timestep;
+use crate::utils::{Guid, Timestep};
 
 use super::camera::CameraBuffer;
-use super::factory::{PipelineFactory, RenderPipelineConfig};
+use super::cursor::{CursorOverlay, SoftwareCursor};
+use super::factory::{PipelineConfigKey, PipelineFactory, RenderPipelineConfig};
+use super::frame::FrameEncoder;
 use super::framebuffer::Framebuffer;
-use super::material::Background2DMaterial;
-use super::types::{BindGroup, FragmentShader, IndexBuffer, VertexBuffer, VertexShader};
+use super::lighting::Lighting;
+use super::material::{Background2DMaterial, GenericMaterial};
+use super::mesh::GenericMesh;
+use super::minimap::{Minimap, MinimapIcon};
+use super::postprocess::PostProcess;
+use super::profiler::GpuProfiler;
+use super::transition::{Transition, WorldTransition};
+use super::types::{
+    BindGroup, FragmentShader, IndexBuffer, MaterialLayout, PipelineBaseConfig, VertexBuffer,
+    VertexShader,
+};
+
+//Enough for the transforms of a few hundred sprites per chunk, the belt adds chunks as needed.
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 64 * 1024;
 
 pub struct Renderer2D {
     framebuffer: Framebuffer,
@@ -27,13 +54,73 @@ pub struct Renderer2D {
     camera_buffer: Option<CameraBuffer>,
     egui_renderer: egui_wgpu::Renderer,
     background: Option<Background2DMaterial>,
+    //The asset the background shows, if it was set from one. Only those survive a device loss.
+    background_source: Option<Ptr<Texture2D>>,
+    clear_color: wgpu::Color,
+    cursor: CursorOverlay,
+    post: Option<PostProcess>,
+    sort_translucent: bool,
+    transition: Option<Transition>,
+    //Background (texture, tint, uv offset and scale) of the lost device, set up again once the
+    //texture is back.
+    lost_background: Option<(Ptr<Texture2D>, Vec4, Vec2, Vec2)>,
+    //Transition (outgoing world, config, elapsed) of the lost device or the old window size,
+    //started again on the next frame.
+    restart_transition: Option<(Option<Guid>, WorldTransition, f32)>,
+    //View projection and viewport of the last presented frame.
+    last_camera: Option<(Mat4, (f32, f32, f32, f32))>,
+    culling: bool,
+    cull_stats: CullStats,
+    lighting: Lighting,
+    profiler: Option<GpuProfiler>,
+    stats: RenderStats,
+    //Per frame uniform writes, copied into the frame encoder before the passes.
+    belt: StagingBelt,
+    //Interpolated transforms are drawn this far into the fixed step, see Context::fixed_alpha.
+    alpha: f32,
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+//Counted over one call of render. Copy, so frames can be kept and compared.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: usize,
+    pub sprites: usize,
+    pub particles: usize,
+    //Sprites and tilemap chunks outside of the camera.
+    pub culled: usize,
+    pub pipeline_hits: usize,
+    pub pipeline_misses: usize,
+    //Vertex and index data, of tilemap chunks and the gui.
+    pub uploaded_bytes: usize,
+    pub paint_jobs: usize,
 }
 
 impl EventSubscriber for Renderer2D {
     fn on_event(&mut self, event: &crate::event::Event, context: &mut Context) -> bool {
+        self.cursor.on_event(event);
+
         match event {
             event::Event::Resized { width, height } => {
                 self.framebuffer.resize(context, *width, *height);
+
+                if let Some(post) = &mut self.post {
+                    post.resize(&context.graphics, *width, *height);
+                }
+
+                //The target has the old size, the outgoing world is rendered again at the new one.
+                if let Some(transition) = self.transition.take() {
+                    self.restart_transition = Some(transition.restart());
+                }
+                false
+            }
+            event::Event::DeviceRestored => {
+                self.recreate(context);
                 false
             }
             _ => false,
@@ -44,16 +131,86 @@ impl EventSubscriber for Renderer2D {
 impl Renderer2D {
     pub fn new(context: &Context, _assets: &mut Assets) -> Self {
         //Renderable setup
-        let sample_count = 4;
+        let sample_count = context.features.sample_count();
         let pipelines = PipelineFactory::new();
         let framebuffer = Framebuffer::new(context, sample_count);
         let camera_buffer = Some(CameraBuffer::new(&context.graphics, "Default Camera"));
         let egui_renderer = Renderer::recreate_gui(context, sample_count);
 
-        Renderer2D { framebuffer, pipelines, camera_buffer, egui_renderer, background: None }
+        let cursor = CursorOverlay::new(&context.graphics);
+        let [r, g, b, a] = context.config.engine_config().graphics.clear_color;
+
+        Renderer2D {
+            framebuffer,
+            pipelines,
+            camera_buffer,
+            egui_renderer,
+            background: None,
+            background_source: None,
+            clear_color: wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: a as f64 },
+            cursor,
+            post: None,
+            sort_translucent: true,
+            transition: None,
+            lost_background: None,
+            restart_transition: None,
+            last_camera: None,
+            culling: true,
+            cull_stats: CullStats::default(),
+            lighting: Lighting::new(&context.graphics),
+            profiler: None,
+            stats: RenderStats::default(),
+            belt: StagingBelt::new(STAGING_CHUNK_SIZE),
+            alpha: 1.0,
+        }
     }
 
+    //Everything of the lost device is dropped, the settings carry over. The background comes back
+    //once its texture is loaded again, backgrounds set from a texture without asset have to be set
+    //again. A running transition renders its outgoing world once more and goes on.
+    fn recreate(&mut self, context: &Context) {
+        let sample_count = context.features.sample_count();
+        let cursor = self.cursor.cursor().cloned();
+        let ambient = self.lighting.ambient();
+
+        if let Some(background) = self.background.take() {
+            let (offset, scale) = background.uv();
+
+            match self.background_source {
+                Some(texture) => {
+                    self.lost_background = Some((texture, background.tint(), offset, scale))
+                }
+                None => log::warn!("The background has no asset, it is gone with the device."),
+            }
+        }
+
+        if let Some(transition) = self.transition.take() {
+            self.restart_transition = Some(transition.restart());
+        }
+        self.post = self.post.as_ref().map(|post| post.recreate(context));
+
+        self.framebuffer = Framebuffer::new(context, sample_count);
+        self.pipelines = PipelineFactory::new();
+        self.camera_buffer = Some(CameraBuffer::new(&context.graphics, "Default Camera"));
+        self.egui_renderer = Renderer::recreate_gui(context, sample_count);
+        self.belt = StagingBelt::new(STAGING_CHUNK_SIZE);
+
+        self.cursor = CursorOverlay::new(&context.graphics);
+        self.cursor.set_cursor(cursor);
+
+        self.lighting = Lighting::new(&context.graphics);
+        self.lighting.set_ambient(ambient);
+
+        if self.profiler.is_some() {
+            self.profiler = GpuProfiler::new(&context.graphics);
+        }
+    }
+
+    //Isn't restored after a device loss, see set_background_asset.
     pub fn set_background(&mut self, context: &VisContext, texture: &Texture2D, tint: Vec4) {
+        self.background_source = None;
+        self.lost_background = None;
+
         match self.background {
             Some(ref mut background) => {
                 background.update_texture(context, texture);
@@ -66,10 +223,221 @@ impl Renderer2D {
         }
     }
 
-    pub fn update_camera_buffer(&mut self, context: &VisContext, camera: [[f32; 4]; 4]) {
+    //Like set_background, but the asset is kept to restore the background after a device loss.
+    //Returns false while the texture is still loading, call it again later then.
+    pub fn set_background_asset(
+        &mut self, context: &VisContext, assets: &Assets, texture: Ptr<Texture2D>, tint: Vec4,
+    ) -> bool {
+        let Some(loaded) = assets.try_get(&texture) else {
+            return false;
+        };
+
+        self.set_background(context, loaded, tint);
+        self.background_source = Some(texture);
+        true
+    }
+
+    pub fn clear_background(&mut self) {
+        self.background = None;
+        self.background_source = None;
+        self.lost_background = None;
+    }
+
+    //The part of the background image shown, in uv of the texture.
+    pub fn set_background_uv(&mut self, context: &VisContext, offset: Vec2, scale: Vec2) {
+        if let Some(background) = &mut self.background {
+            background.update_uv(context, offset, scale);
+        }
+    }
+
+    //Samples per pixel of the world and the gui, 1 turns msaa off. False if the adapter doesn't
+    //support that many or the compatibility list disabled msaa. Also sets
+    //GraphicsConfiguration::msaa, to be kept with Config::save_engine_config.
+    pub fn set_sample_count(&mut self, context: &mut Context, samples: u32) -> bool {
+        if !context.features.supports_samples(samples)
+            || !self.framebuffer.change_sample_count(context, samples)
+        {
+            return false;
+        }
+
+        self.egui_renderer = Renderer::recreate_gui(context, samples);
+        context.features.msaa = samples > 1;
+        context.features.samples = samples;
+        context.config.engine_config_mut().graphics.msaa = samples;
+        true
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.framebuffer.sample_count()
+    }
+
+    //What is seen where neither background nor world is drawn. Starts as
+    //GraphicsConfiguration::clear_color.
+    pub fn set_clear_color(&mut self, color: Vec4) {
+        self.clear_color = wgpu::Color {
+            r: color.x as f64,
+            g: color.y as f64,
+            b: color.z as f64,
+            a: color.w as f64,
+        };
+    }
+
+    //Clear color of a ldtk level. Its image is a sprite of the level, the screen space background
+    //is cleared so it doesn't cover it.
+    pub fn apply_level_background(&mut self, background: &LevelBackground) {
+        self.set_clear_color(background.color);
+        self.clear_background();
+    }
+
+    //Run a fullscreen shader over the world before the gui is drawn. See assets/postprocess.wgsl for the bindings.
+    pub fn set_post_process(
+        &mut self, context: &Context, shader: Ptr<Shader>, uniforms: Option<UniformBuffer>,
+    ) {
+        self.post = Some(PostProcess::new(context, shader, uniforms));
+    }
+
+    pub fn clear_post_process(&mut self) {
+        self.post = None;
+    }
+
+    pub fn post_process_mut(&mut self) -> Option<&mut PostProcess> {
+        self.post.as_mut()
+    }
+
+    //Replace the OS cursor by a texture drawn on top of everything. None goes back to the OS cursor.
+    pub fn set_software_cursor(&mut self, cursor: Option<SoftwareCursor>) {
+        self.cursor.set_cursor(cursor);
+    }
+
+    pub fn software_cursor(&self) -> Option<&SoftwareCursor> {
+        self.cursor.cursor()
+    }
+
+    //The whole presented frame, including gui and cursor, as rgba.
+    pub fn capture_frame(
+        &self, context: &mut Context, callback: impl FnOnce(image::RgbaImage) + Send + 'static,
+    ) {
+        context.capture_frame(callback);
+    }
+
+    pub fn save_screenshot(&self, context: &mut Context, path: impl Into<std::path::PathBuf>) {
+        context.save_screenshot(path);
+    }
+
+    //Makes a texture usable in egui, e.g. a minimap in an egui::Image. Free it again with free_egui_texture.
+    pub fn egui_texture(&mut self, context: &VisContext, texture: &Texture2D) -> egui::TextureId {
+        self.egui_renderer.register_native_texture(
+            &context.device,
+            texture.view(),
+            wgpu::FilterMode::Linear,
+        )
+    }
+
+    pub fn free_egui_texture(&mut self, id: egui::TextureId) {
+        self.egui_renderer.free_texture(&id);
+    }
+
+    //Progress of the running world transition from 0 to 1, see Worlds::transition_to.
+    pub fn transition_progress(&self) -> Option<f32> {
+        self.transition.as_ref().map(Transition::progress)
+    }
+
+    //Translucent sprites are sorted back to front by z. Turn off if the order doesn't matter for your scene.
+    pub fn set_sort_translucent(&mut self, sort: bool) {
+        self.sort_translucent = sort;
+    }
+
+    //The buffer itself is written during render, together with the other uniforms.
+    pub fn update_camera_buffer(&mut self, _context: &VisContext, camera: [[f32; 4]; 4]) {
+        if let Some(camera_buffer) = &mut self.camera_buffer {
+            camera_buffer.set_view_projection(camera);
+        }
+    }
+
+    //See OrthographicCamera::set_shear.
+    pub fn update_camera_shear(&mut self, _context: &VisContext, shear: f32) {
         if let Some(camera_buffer) = &mut self.camera_buffer {
-            camera_buffer.update_buffer(context, camera);
+            camera_buffer.set_shear(shear);
+        }
+    }
+
+    //Light everything gets without any PointLight2D. White, the default, leaves unlit worlds as they are.
+    pub fn set_ambient_light(&mut self, color: Vec3) {
+        self.lighting.set_ambient(color);
+    }
+
+    pub fn ambient_light(&self) -> Vec3 {
+        self.lighting.ambient()
+    }
+
+    //Times every pass on the gpu. Does nothing if the device has no timestamp queries.
+    pub fn set_gpu_profiling(&mut self, context: &VisContext, enabled: bool) {
+        if !enabled {
+            self.profiler = None;
+        } else if self.profiler.is_none() {
+            self.profiler = GpuProfiler::new(context);
+
+            if self.profiler.is_none() {
+                log::warn!("GPU profiling is not supported on this device.");
+            }
+        }
+    }
+
+    //Milliseconds per pass of a recent frame, empty without gpu profiling.
+    pub fn gpu_timings(&self) -> &[(String, f32)] {
+        self.profiler.as_ref().map_or(&[], |profiler| profiler.timings())
+    }
+
+    //Skip sprites outside of the camera. On by default.
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    //Sprites drawn and culled in the last frame.
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+
+    //Of the last render call.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    //World space rectangle visible through the view projection, intersected with the z = 0 plane.
+    fn view_bounds(view_projection: Mat4) -> Option<(Vec2, Vec2)> {
+        let (x, y, w) = (view_projection.x_axis, view_projection.y_axis, view_projection.w_axis);
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+
+        for ndc in [Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0), Vec2::new(-1.0, 1.0), Vec2::ONE] {
+            //Solve clip.xy - ndc * clip.w = 0 for the world position.
+            let system = Mat2::from_cols(
+                Vec2::new(x.x - ndc.x * x.w, x.y - ndc.y * x.w),
+                Vec2::new(y.x - ndc.x * y.w, y.y - ndc.y * y.w),
+            );
+
+            if system.determinant().abs() < f32::EPSILON {
+                return None;
+            }
+
+            let corner = system.inverse() * Vec2::new(ndc.x * w.w - w.x, ndc.y * w.w - w.y);
+            min = min.min(corner);
+            max = max.max(corner);
         }
+
+        Some((min, max))
+    }
+
+    fn is_visible(bounds: Option<(Vec2, Vec2)>, transform: &Transform2D) -> bool {
+        Self::overlaps(bounds, transform.bounds())
+    }
+
+    fn overlaps(bounds: Option<(Vec2, Vec2)>, (min, max): (Vec2, Vec2)) -> bool {
+        let Some((view_min, view_max)) = bounds else {
+            return true;
+        };
+
+        min.x <= view_max.x && max.x >= view_min.x && min.y <= view_max.y && max.y >= view_min.y
     }
 
     pub fn update_viewport(&mut self, viewport: (f32, f32, f32, f32)) {
@@ -83,135 +451,471 @@ impl Renderer2D {
     ) {
         if let Some(world) = worlds.get_mut() {
             for (_entity, (sprite, animation)) in
-                world.query_mut::<(&mut Sprite, &mut Animation2D)>()
+                world.query_mut::<(&mut Sprite, &mut Animation2D)>().without::<&Hidden>()
             {
                 animation.update(context, delta, sprite);
             }
+
+            for (_entity, (sprite, animator)) in
+                world.query_mut::<(&mut Sprite, &mut Animator2D)>().without::<&Hidden>()
+            {
+                animator.update(context, delta, sprite);
+            }
         }
     }
 
-    pub fn render(
-        &mut self, assets: &mut Assets, worlds: &mut Worlds, ctx: &mut Context, view: &TextureView,
-        window: &Window,
+    //With staging, the uniform writes are recorded into the encoder instead of queued one by one.
+    fn update_transforms(
+        context: &VisContext, world: &hecs::World, alpha: f32,
+        mut staging: Option<(&mut wgpu::CommandEncoder, &mut StagingBelt)>,
     ) {
-        let context = ctx.graphics.as_ref();
+        for (_, transform) in world.query::<&mut Transform2D>().iter() {
+            transform.set_alpha(alpha);
+        }
+
+        //Iterate over all entities with a transform component but do not borrow.
+        for (entity, _) in world.query::<()>().with::<&Transform2D>().iter() {
+            if let Ok(mut transform) = world.get::<&mut Transform2D>(entity) {
+                match &mut staging {
+                    Some((encoder, belt)) => {
+                        transform.update_staged(context, encoder, belt, entity, world)
+                    }
+                    None => transform.update(context, entity, world),
+                }
+            }
+        }
+    }
+
+    //Every (layer, order) pair in use gets its own depth band, in sorted order. Returns the number of bands.
+    fn assign_sort_bands(world: &mut hecs::World) -> usize {
+        let mut keys: Vec<(i32, i32)> = world
+            .query_mut::<(Or<&Sprite, &Tilemap>, Option<&RenderLayer>, Option<&OrderInLayer>)>()
+            .into_iter()
+            .map(|(_, (_, layer, order))| layer::sort_key(layer, order))
+            .collect();
+
+        keys.sort_unstable();
+        keys.dedup();
+
+        for (_, (transform, _, layer, order)) in world.query_mut::<(
+            &mut Transform2D,
+            Or<&Sprite, &Tilemap>,
+            Option<&RenderLayer>,
+            Option<&OrderInLayer>,
+        )>() {
+            if let Ok(band) = keys.binary_search(&layer::sort_key(layer, order)) {
+                transform.set_sort_band(band);
+            }
+        }
+
+        keys.len()
+    }
+
+    fn report_texture_usage(
+        assets: &mut Assets, sprite: &Sprite, transform: &Transform2D, pixels_per_unit: Option<f32>,
+    ) {
+        let Some(texture) = assets.try_get_mut(sprite.texture()) else {
+            return;
+        };
+
+        if let Some(pixels_per_unit) = pixels_per_unit.filter(|_| texture.is_streamed()) {
+            let (min, max) = transform.bounds();
+            texture.report_usage((max.x - min.x) * pixels_per_unit);
+        }
+    }
+
+    //Loading textures show the checkerboard, failed ones the error texture.
+    fn bind_texture(context: &VisContext, assets: &Assets, sprite: &mut Sprite) {
+        if let Some(texture) = assets.try_get(sprite.texture()) {
+            sprite.update(context, texture);
+        } else if assets.is_failed(&(*sprite.texture()).into()) {
+            sprite.update(context, Texture2D::error_texture(context));
+        } else if let Some(loading) = assets.try_get(&LOADING_TEXTURE) {
+            sprite.update_placeholder(context, loading);
+        }
+    }
+
+    fn bind_tilemap_texture(context: &VisContext, assets: &Assets, tilemap: &mut Tilemap) {
+        if let Some(texture) = assets.try_get(tilemap.texture()) {
+            tilemap.update(context, texture);
+        } else if assets.is_failed(&(*tilemap.texture()).into()) {
+            tilemap.update(context, Texture2D::error_texture(context));
+        } else if let Some(loading) = assets.try_get(&LOADING_TEXTURE) {
+            tilemap.update_placeholder(context, loading);
+        }
+    }
+
+    fn bind_particle_texture(
+        context: &VisContext, assets: &Assets, emitter: &mut ParticleEmitter2D,
+    ) {
+        if let Some(texture) = assets.try_get(emitter.texture()) {
+            emitter.bind(context, texture);
+        } else if assets.is_failed(&(*emitter.texture()).into()) {
+            emitter.bind(context, Texture2D::error_texture(context));
+        } else if let Some(loading) = assets.try_get(&LOADING_TEXTURE) {
+            emitter.bind_placeholder(context, loading);
+        }
+    }
+
+    //One draw call per visible chunk. Returns the number of draw calls.
+    fn draw_tilemap<'r>(
+        render_pass: &mut wgpu::RenderPass<'r>, transform: &'r Transform2D, tilemap: &'r Tilemap,
+        camera_buffer: &'r CameraBuffer, bounds: Option<(Vec2, Vec2)>, stats: &mut CullStats,
+    ) -> usize {
+        let mut draws = 0;
+
+        for (i, bind_group) in tilemap.material().groups().iter().enumerate() {
+            render_pass.set_bind_group(i as u32, bind_group, &[]);
+        }
+
+        render_pass.set_bind_group(1, transform.group(), &[]);
+        render_pass.set_bind_group(2, camera_buffer.bind_group(), &[]);
+
+        for (mesh, min, max) in tilemap.chunks() {
+            if !Self::overlaps(bounds, transform.transform_bounds(min, max)) {
+                stats.culled += 1;
+                continue;
+            }
+
+            stats.drawn += 1;
+            draws += 1;
+
+            render_pass.set_vertex_buffer(0, VertexBuffer::buffer(mesh).unwrap().slice(..));
+            let (buffer, format) = IndexBuffer::buffer(mesh).unwrap();
+            render_pass.set_index_buffer(buffer.slice(..), format);
+            render_pass.draw_indexed(0..mesh.num_indices(), 0, 0..1);
+        }
+
+        draws
+    }
+
+    //The scene depth binding, if the sprite is soft and the depth can be sampled here.
+    fn soft_depth<'f>(
+        fbo: &'f Framebuffer, sprite: &Sprite,
+    ) -> Option<(&'f wgpu::BindGroupLayout, &'f wgpu::BindGroup)> {
+        sprite.soft_distance().and_then(|_| fbo.depth_binding())
+    }
+
+    fn draw_sprite<'r>(
+        render_pass: &mut wgpu::RenderPass<'r>, transform: &'r Transform2D, sprite: &'r Sprite,
+        camera_buffer: &'r CameraBuffer,
+    ) {
+        //Set material
+        for (i, bind_group) in sprite.material().groups().iter().enumerate() {
+            render_pass.set_bind_group(i as u32, bind_group, &[]);
+        }
+
+        //Set transform buffer
+        render_pass.set_bind_group(1, transform.group(), &[]);
+
+        //Set camera buffer
+        render_pass.set_bind_group(2, camera_buffer.bind_group(), &[]);
+
+        //Set vertex buffer
+        render_pass.set_vertex_buffer(0, VertexBuffer::buffer(sprite.mesh()).unwrap().slice(..));
+
+        //Set index buffer
+        let (buffer, format) = IndexBuffer::buffer(sprite.mesh()).unwrap();
+        render_pass.set_index_buffer(buffer.slice(..), format);
+
+        //Draw the quad.
+        render_pass.draw_indexed(0..sprite.mesh().num_indices(), 0, 0..1);
+    }
+
+    //Background and world passes into target. Returns true if some textures are still loading.
+    fn render_world(
+        &mut self, context: &VisContext, assets: &mut Assets, mut world: Option<&mut hecs::World>,
+        camera_buffer: &mut CameraBuffer, target: &TextureView, encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
         let fbo = &self.framebuffer;
-        let fbo_view: TextureView = (&self.framebuffer).into();
+        let fbo_view: TextureView = fbo.into();
         let sample_count = fbo.sample_count();
-        let _ = assets.update();
+        let view_bounds = Self::view_bounds(camera_buffer.view_projection());
+        let mut loading = false;
 
-        let mut encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Renderer2D Render Encoder"),
-        });
+        if let Some(world) = world.as_deref_mut() {
+            camera_buffer.set_bands(Self::assign_sort_bands(world));
+        }
 
-        if let Some(camera_buffer) = &self.camera_buffer {
-            //Background render pass---------------------------------------------------------------------
-            {
-                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: Some("Background Render Pass"),
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: match fbo.sample_count() {
-                            1 => view,
-                            _ => &fbo_view,
-                        },
-                        resolve_target: match fbo.sample_count() {
-                            1 => None,
-                            _ => Some(view),
-                        },
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                    ..Default::default()
-                });
+        camera_buffer.update_buffer_staged(context, encoder, &mut self.belt);
 
-                let (x, y, w, h) = camera_buffer.viewport();
-                render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+        let camera_buffer: &CameraBuffer = camera_buffer;
 
-                if let Some(background) = &self.background {
-                    let shader = ShaderVariant::Single(assets.try_get(&BACKGROUND_SHADER).unwrap());
+        //Background render pass---------------------------------------------------------------------
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Background Render Pass"),
+                timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Background")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: match fbo.sample_count() {
+                        1 => target,
+                        _ => &fbo_view,
+                    },
+                    resolve_target: match fbo.sample_count() {
+                        1 => None,
+                        _ => Some(target),
+                    },
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
 
-                    let config =
-                        RenderPipelineConfig::new(&shader, None::<&Vertices>, background, &[]);
+            let (x, y, w, h) = camera_buffer.viewport();
+            render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
 
-                    let pipeline = self.pipelines.get_or_create(context, &config);
+            if let Some(background) = &self.background {
+                let shader = ShaderVariant::Single(assets.try_get(&BACKGROUND_SHADER).unwrap());
 
-                    render_pass.set_pipeline(pipeline);
+                let mut config =
+                    RenderPipelineConfig::new(&shader, None::<&Vertices>, background, &[]);
+                config
+                    .set_config(PipelineBaseConfig { samples: sample_count, ..Default::default() });
 
-                    for (i, bind_group) in background.groups().iter().enumerate() {
-                        render_pass.set_bind_group(i as u32, bind_group, &[]);
-                    }
+                let pipeline = self.pipelines.get_or_create(context, &config);
+
+                render_pass.set_pipeline(pipeline);
 
-                    render_pass.draw(0..3, 0..1);
+                for (i, bind_group) in background.groups().iter().enumerate() {
+                    render_pass.set_bind_group(i as u32, bind_group, &[]);
                 }
+
+                render_pass.draw(0..3, 0..1);
+                self.stats.draw_calls += 1;
             }
+        }
 
-            //------------------------------------------------------------------------------------------
-            //Prepare World Render Pass--------------------------------------------------------------------------
-            if let Some(world) = worlds.get_mut() {
-                let mut config_keys = Vec::new();
-
-                //Iterate over all entities with a transform component but do not borrow.
-                for (entity, _) in world.query::<()>().with::<&Transform2D>().iter() {
-                    if let Ok(mut transform) = world.get::<&mut Transform2D>(entity) {
-                        transform.update(context, entity, world);
-                    }
+        //------------------------------------------------------------------------------------------
+        //Prepare World Render Pass--------------------------------------------------------------------------
+        if let Some(world) = world {
+            let mut config_keys = HashMap::new();
+
+            Self::update_transforms(
+                context,
+                world,
+                self.alpha,
+                Some((&mut *encoder, &mut self.belt)),
+            );
+
+            let bounds = if self.culling { view_bounds } else { None };
+
+            //Screen pixels per world unit, to tell streamed textures how much detail is visible.
+            let pixels_per_unit = view_bounds.map(|(min, max)| {
+                let (_, _, width, _) = camera_buffer.viewport();
+                width / (max.x - min.x).max(f32::EPSILON)
+            });
+            self.cull_stats = CullStats::default();
+
+            for (entity, (transform, sprite)) in
+                world.query::<(&mut Transform2D, &mut Sprite)>().without::<&Hidden>().iter()
+            {
+                if !sprite.is_visible() {
+                    continue;
+                }
+
+                if !Self::is_visible(bounds, transform) {
+                    self.cull_stats.culled += 1;
+                    continue;
+                }
+
+                //Fully transparent hierarchies are not drawn at all.
+                let opacity = effective_opacity(world, entity);
+                if opacity <= 0.0 {
+                    continue;
                 }
 
-                for (_, (transform, sprite)) in
-                    world.query::<(&mut Transform2D, &mut Sprite)>().iter()
-                {
-                    if let Some(texture) = assets.try_get(sprite.texture()) {
-                        sprite.update(context, texture);
+                sprite.set_opacity(context, opacity);
+
+                self.cull_stats.drawn += 1;
+                Self::bind_texture(context, assets, sprite);
+                loading |= assets.is_pending(&(*sprite.texture()).into());
+                Self::report_texture_usage(assets, sprite, transform, pixels_per_unit);
+
+                let material = sprite.material();
+                let vertex = assets.try_get(VertexShader::ptr(material)).unwrap();
+                let mut fragment = assets.try_get(FragmentShader::ptr(material)).unwrap();
+                let mut layouts = vec![transform.layout(), CameraBuffer::layout(context)];
+
+                //Soft sprites get the scene depth as group 3. The default shader is swapped
+                //for its soft version, custom ones have to handle it themselves.
+                if let Some((depth_layout, _)) = Self::soft_depth(fbo, sprite) {
+                    layouts.push(depth_layout);
+
+                    if *FragmentShader::ptr(material) == *SPRITE_SHADER {
+                        let soft = if sample_count > 1 {
+                            &SOFT_SPRITE_SHADER_MS
+                        } else {
+                            &SOFT_SPRITE_SHADER
+                        };
+                        fragment = assets.try_get(soft).unwrap();
                     }
+                }
+
+                let shader = ShaderVariant::Double(vertex, fragment);
+
+                let mut config =
+                    RenderPipelineConfig::new(&shader, Some(sprite.mesh()), material, &layouts);
+
+                //Blend mode, culling etc. come from the material. Samples and depth are up to the pass.
+                //Opaque sprites write depth, translucent ones only test against it.
+                let base = material.base_config().unwrap_or_default();
+
+                config.set_config(PipelineBaseConfig {
+                    samples: sample_count,
+                    depth: Some(Framebuffer::material_depth(&base, true, "world pass")),
+                    ..base.clone()
+                });
+                self.pipelines.prepare(context, &config);
+                let opaque_key = config.key();
+
+                config.set_config(PipelineBaseConfig {
+                    samples: sample_count,
+                    depth: Some(Framebuffer::material_depth(&base, false, "world pass")),
+                    ..base
+                });
+                self.pipelines.prepare(context, &config);
+
+                config_keys.insert(entity, (opaque_key, config.key()));
+            }
+
+            let mut tilemap_keys = HashMap::new();
+
+            for (entity, (transform, tilemap)) in
+                world.query::<(&Transform2D, &mut Tilemap)>().iter()
+            {
+                self.stats.uploaded_bytes += tilemap.prepare(context);
+                tilemap.set_opacity(context, effective_opacity(world, entity));
+                Self::bind_tilemap_texture(context, assets, tilemap);
+                loading |= assets.is_pending(&(*tilemap.texture()).into());
+
+                //All chunks share the vertex layout, any of them will do for the pipeline.
+                let Some((mesh, _, _)) = tilemap.chunks().next() else {
+                    continue;
+                };
+
+                let material = tilemap.material();
+                let vertex = assets.try_get(VertexShader::ptr(material)).unwrap();
+                let fragment = assets.try_get(FragmentShader::ptr(material)).unwrap();
+                let shader = ShaderVariant::Double(vertex, fragment);
+
+                let mut config = RenderPipelineConfig::new(
+                    &shader,
+                    Some(mesh),
+                    material,
+                    &[transform.layout(), CameraBuffer::layout(context)],
+                );
+
+                config.set_config(PipelineBaseConfig {
+                    samples: sample_count,
+                    depth: Some(Framebuffer::depth_state(!tilemap.is_translucent())),
+                    ..Default::default()
+                });
+                self.pipelines.prepare(context, &config);
 
-                    let material = sprite.material();
-                    let vertex = assets.try_get(VertexShader::ptr(material)).unwrap();
-                    let fragment = assets.try_get(FragmentShader::ptr(material)).unwrap();
-                    let shader = ShaderVariant::Double(vertex, fragment);
+                tilemap_keys.insert(entity, config.key());
+            }
 
-                    let config = RenderPipelineConfig::new(
-                        &shader,
-                        Some(sprite.mesh()),
-                        material,
-                        &[transform.layout(), CameraBuffer::layout(context)],
-                    );
+            //Outgoing frames of running cross-fades share the sprite pipeline.
+            if animation2d::any_fading() {
+                for (entity, animation) in world.query::<&mut Animation2D>().iter() {
+                    if let Some(fade) = animation.fade_mut() {
+                        Self::bind_texture(context, assets, fade.sprite_mut());
+                        fade.sprite_mut().set_opacity(context, effective_opacity(world, entity));
+                    }
+                }
 
-                    self.pipelines.prepare(context, &config);
-                    config_keys.push(config.key());
+                for (entity, animator) in world.query::<&mut Animator2D>().iter() {
+                    if let Some(fade) = animator.animation_mut().and_then(Animation2D::fade_mut) {
+                        Self::bind_texture(context, assets, fade.sprite_mut());
+                        fade.sprite_mut().set_opacity(context, effective_opacity(world, entity));
+                    }
                 }
+            }
 
-                {
-                    let mut renderables = world.query::<(&Transform2D, &Sprite)>();
-                    let mut entities: Vec<(hecs::Entity, (&Transform2D, &Sprite<'_>))> =
-                        renderables.iter().collect();
-                    entities.sort_by(|(_, (a, _)), (_, (b, _))| {
-                        a.position()
-                            .z
-                            .partial_cmp(&b.position().z)
-                            .unwrap_or(std::cmp::Ordering::Equal)
+            {
+                let mut renderables = world.query::<(
+                    &Transform2D,
+                    &Sprite,
+                    Option<&Animation2D>,
+                    Option<&Animator2D>,
+                )>();
+                let (mut translucent, opaque): (Vec<_>, Vec<_>) = renderables
+                    .iter()
+                    .map(|(entity, (transform, sprite, animation, animator))| {
+                        let animation = animation.or(animator.and_then(Animator2D::animation));
+                        (entity, (transform, sprite, animation))
+                    })
+                    .filter(|(entity, (_, sprite, _))| {
+                        sprite.is_visible() && config_keys.contains_key(entity)
+                    })
+                    .partition(|(_, (_, sprite, animation))| {
+                        sprite.is_translucent()
+                            || animation.is_some_and(|animation| animation.is_fading())
                     });
 
+                //Blending needs back to front order, the depth buffer can't help there.
+                //The sort is stable, so equal keys keep their order between frames.
+                if self.sort_translucent {
+                    translucent.sort_by(|(_, (a, _, _)), (_, (b, _, _))| {
+                        a.sort_band()
+                            .total_cmp(&b.sort_band())
+                            .then(a.position().z.total_cmp(&b.position().z))
+                    });
+                }
+
+                let mut tilemap_query = world.query::<(&Transform2D, &Tilemap)>();
+                let mut tilemaps: Vec<_> = tilemap_query
+                    .iter()
+                    .filter(|(entity, _)| tilemap_keys.contains_key(entity))
+                    .collect();
+
+                tilemaps
+                    .sort_by(|(_, (a, _)), (_, (b, _))| a.sort_band().total_cmp(&b.sort_band()));
+
+                let depth_view = fbo.depth_view();
+
+                //Opaque sprites first and in any order, translucent ones on top of them.
+                //Translucent ones get a second pass, there the depth is read only and can be sampled.
+                for (entities, opaque) in [(&opaque, true), (&translucent, false)] {
                     //World Render Pass---------------------------------------------------------------------
                     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: Some("World Render Pass"),
+                        label: Some(match opaque {
+                            true => "World Render Pass",
+                            false => "Translucent Render Pass",
+                        }),
+                        timestamp_writes: self
+                            .profiler
+                            .as_mut()
+                            .and_then(|p| p.scope(if opaque { "World" } else { "Translucent" })),
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                             view: match fbo.sample_count() {
-                                1 => view,
+                                1 => target,
                                 _ => &fbo_view,
                             },
                             resolve_target: match fbo.sample_count() {
                                 1 => None,
-                                _ => Some(view),
+                                _ => Some(target),
                             },
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Load,
                                 store: wgpu::StoreOp::Store,
                             },
                         })],
-                        depth_stencil_attachment: None,
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: &depth_view,
+                            depth_ops: opaque.then_some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: wgpu::StoreOp::Store,
+                            }),
+                            //Read only in the translucent pass, like the depth, so it can be sampled.
+                            stencil_ops: opaque.then_some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(0),
+                                store: wgpu::StoreOp::Store,
+                            }),
+                        }),
                         ..Default::default()
                     });
 
@@ -219,45 +923,414 @@ impl Renderer2D {
                     let (x, y, w, h) = camera_buffer.viewport();
                     render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
 
-                    for (i, renderable) in entities.iter().enumerate() {
-                        let (transform, sprite) = renderable.1;
+                    let pass_tilemaps: Vec<_> = tilemaps
+                        .iter()
+                        .filter(|(_, (_, tilemap))| tilemap.is_translucent() != opaque)
+                        .collect();
+                    let mut next_tilemap = 0;
+
+                    //Tilemaps go in with the sprites of the same kind. Opaque ones before them,
+                    //translucent ones in between by band, as they blend with what is below.
+                    //The trailing None draws the tilemaps above all sprites.
+                    for drawn in entities.iter().map(Some).chain([None]) {
+                        let band = match drawn {
+                            Some((_, (transform, _, _))) if !opaque => transform.sort_band(),
+                            _ => f32::INFINITY,
+                        };
 
-                        let material = sprite.material();
+                        while let Some((entity, (transform, tilemap))) = pass_tilemaps
+                            .get(next_tilemap)
+                            .filter(|(_, (transform, _))| transform.sort_band() <= band)
+                        {
+                            let pipeline = self.pipelines.get_key(&tilemap_keys[entity]).unwrap();
+                            render_pass.set_pipeline(pipeline);
+
+                            self.stats.draw_calls += Self::draw_tilemap(
+                                &mut render_pass,
+                                transform,
+                                tilemap,
+                                camera_buffer,
+                                bounds,
+                                &mut self.cull_stats,
+                            );
+                            next_tilemap += 1;
+                        }
 
-                        let pipeline = self
-                            .pipelines
-                            .get_key(unsafe { config_keys.get_unchecked(i) })
-                            .unwrap();
+                        let Some((entity, (transform, sprite, animation))) = drawn else {
+                            continue;
+                        };
 
+                        let (opaque_key, translucent_key) = &config_keys[entity];
+                        let key = if opaque { opaque_key } else { translucent_key };
+
+                        let pipeline = self.pipelines.get_key(key).unwrap();
                         render_pass.set_pipeline(pipeline);
 
-                        //Set material
-                        for (i, bind_group) in material.groups().iter().enumerate() {
-                            render_pass.set_bind_group(i as u32, bind_group, &[]);
+                        if let Some((_, depth_group)) = Self::soft_depth(fbo, sprite) {
+                            render_pass.set_bind_group(3, depth_group, &[]);
+                        }
+
+                        Self::draw_sprite(&mut render_pass, transform, sprite, camera_buffer);
+                        self.stats.sprites += 1;
+                        self.stats.draw_calls += 1;
+
+                        //Draw the fading out frame on top.
+                        if let Some(fade) = animation.and_then(|animation| animation.fade()) {
+                            Self::draw_sprite(
+                                &mut render_pass,
+                                transform,
+                                fade.sprite(),
+                                camera_buffer,
+                            );
+                            self.stats.draw_calls += 1;
                         }
+                    }
+                }
+            }
+
+            //Prepare Particle Render Pass------------------------------------------------------------
+            let mut particle_keys = HashMap::new();
+
+            for (entity, emitter) in world.query_mut::<&mut ParticleEmitter2D>() {
+                self.stats.uploaded_bytes += emitter.prepare(context);
+                Self::bind_particle_texture(context, assets, emitter);
+                loading |= assets.is_pending(&(*emitter.texture()).into());
+
+                let Some((instances, _)) = emitter.instances() else {
+                    continue;
+                };
 
-                        //Set transform buffer
-                        render_pass.set_bind_group(1, transform.group(), &[]);
+                let shader = ShaderVariant::Single(assets.try_get(&PARTICLE_SHADER).unwrap());
+                let mut config = RenderPipelineConfig::new(
+                    &shader,
+                    Some(instances),
+                    emitter.material(),
+                    &[CameraBuffer::layout(context)],
+                );
 
-                        //Set camera buffer
-                        render_pass.set_bind_group(2, camera_buffer.bind_group(), &[]);
+                let base = match emitter.blend {
+                    ParticleBlend::Alpha => PipelineBaseConfig::default(),
+                    ParticleBlend::Additive => PipelineBaseConfig::additive(),
+                };
 
-                        //Set vertex buffer
-                        render_pass.set_vertex_buffer(
-                            0,
-                            VertexBuffer::buffer(sprite.mesh()).unwrap().slice(..),
-                        );
+                config.set_config(PipelineBaseConfig { samples: sample_count, ..base });
+                self.pipelines.prepare(context, &config);
 
-                        //Set index buffer
-                        let (buffer, format) = IndexBuffer::buffer(sprite.mesh()).unwrap();
-                        render_pass.set_index_buffer(buffer.slice(..), format);
+                particle_keys.insert(entity, config.key());
+            }
+
+            //Particle Render Pass--------------------------------------------------------------------
+            //On top of the world, ordered like sprites among themselves.
+            if !particle_keys.is_empty() {
+                let mut query = world.query::<(
+                    &ParticleEmitter2D,
+                    Option<&Transform2D>,
+                    Option<&RenderLayer>,
+                    Option<&OrderInLayer>,
+                )>();
+
+                let mut emitters: Vec<_> =
+                    query.iter().filter(|(entity, _)| particle_keys.contains_key(entity)).collect();
+
+                let z =
+                    |transform: &Option<&Transform2D>| transform.map_or(0.0, |t| t.position().z);
+                emitters.sort_by(|(_, (_, a, a_layer, a_order)), (_, (_, b, b_layer, b_order))| {
+                    layer::sort_key(*a_layer, *a_order)
+                        .cmp(&layer::sort_key(*b_layer, *b_order))
+                        .then(z(a).total_cmp(&z(b)))
+                });
 
-                        //Draw the quad.
-                        render_pass.draw_indexed(0..sprite.mesh().num_indices(), 0, 0..1);
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Particle Render Pass"),
+                    timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Particles")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: match fbo.sample_count() {
+                            1 => target,
+                            _ => &fbo_view,
+                        },
+                        resolve_target: match fbo.sample_count() {
+                            1 => None,
+                            _ => Some(target),
+                        },
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    ..Default::default()
+                });
+
+                let (x, y, w, h) = camera_buffer.viewport();
+                render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+
+                for (entity, (emitter, _, _, _)) in emitters.iter() {
+                    let Some((instances, count)) = emitter.instances() else {
+                        continue;
+                    };
+
+                    let pipeline = self.pipelines.get_key(&particle_keys[entity]).unwrap();
+                    render_pass.set_pipeline(pipeline);
+
+                    for (i, bind_group) in emitter.material().groups().iter().enumerate() {
+                        render_pass.set_bind_group(i as u32, bind_group, &[]);
                     }
+
+                    render_pass.set_bind_group(1, camera_buffer.bind_group(), &[]);
+                    render_pass
+                        .set_vertex_buffer(0, VertexBuffer::buffer(instances).unwrap().slice(..));
+
+                    //Six vertices of a quad per particle.
+                    render_pass.draw(0..6, 0..count);
+                    self.stats.particles += count as usize;
+                    self.stats.draw_calls += 1;
+                }
+            }
+
+            //Lighting Render Pass--------------------------------------------------------------------
+            let lit = self.lighting.prepare(
+                context,
+                encoder,
+                &mut self.belt,
+                world,
+                camera_buffer.view_projection(),
+                view_bounds,
+            );
+
+            if lit {
+                let shader = ShaderVariant::Single(assets.try_get(&LIGHTING_SHADER).unwrap());
+                let mut config =
+                    RenderPipelineConfig::new(&shader, None::<&Vertices>, &self.lighting, &[]);
+                config.set_config(PipelineBaseConfig {
+                    samples: sample_count,
+                    ..self.lighting.base_config().unwrap_or_default()
+                });
+
+                let pipeline = self.pipelines.get_or_create(context, &config);
+
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Lighting Render Pass"),
+                    timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Lighting")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: match fbo.sample_count() {
+                            1 => target,
+                            _ => &fbo_view,
+                        },
+                        resolve_target: match fbo.sample_count() {
+                            1 => None,
+                            _ => Some(target),
+                        },
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    ..Default::default()
+                });
+
+                let (x, y, w, h) = camera_buffer.viewport();
+                render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+                render_pass.set_pipeline(pipeline);
+
+                for (i, bind_group) in self.lighting.groups().iter().enumerate() {
+                    render_pass.set_bind_group(i as u32, bind_group, &[]);
                 }
-                //------------------------------------------------------------------------------------------
+
+                render_pass.draw(0..3, 0..1);
+                self.stats.draw_calls += 1;
             }
+            //------------------------------------------------------------------------------------------
+        }
+
+        loading
+    }
+
+    pub fn render(
+        &mut self, assets: &mut Assets, worlds: &mut Worlds, ctx: &mut Context, view: &TextureView,
+        window: &Window,
+    ) {
+        self.alpha = ctx.fixed_alpha();
+        let delta = ctx.frame_delta();
+        let context = ctx.graphics.as_ref();
+        let fbo_view: TextureView = (&self.framebuffer).into();
+        //With a post process, the world goes into its offscreen texture first.
+        let post_view = self.post.as_ref().map(|post| post.create_scene_view());
+        let scene_view = post_view.as_ref().unwrap_or(view);
+        let sample_count = self.framebuffer.sample_count();
+        let _ = assets.update();
+        assets.update_streaming();
+
+        self.stats = RenderStats::default();
+        self.pipelines.reset_stats();
+
+        //The chunks of the last frame are submitted by now.
+        self.belt.recall();
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame(context);
+        }
+
+        let encoder = ctx.frame.encoder();
+
+        if let Some((texture, tint, offset, scale)) = self.lost_background {
+            if self.set_background_asset(context, assets, texture, tint) {
+                self.set_background_uv(context, offset, scale);
+            }
+        }
+
+        //The outgoing world of a new transition is frozen from now on. It is rendered once, through
+        //the camera of the last presented frame, as the app may already have moved on to the new one.
+        let restart = self.restart_transition.take();
+        let started =
+            worlds.take_transition().map(|(from, config)| (from, config, 0.0)).or(restart);
+
+        if let Some((from, config, elapsed)) = started {
+            let mut transition = Transition::new(
+                context,
+                (ctx.surface_config.width, ctx.surface_config.height),
+                from,
+                config,
+            )
+            .with_elapsed(elapsed);
+            let last_camera = self.last_camera.or_else(|| {
+                self.camera_buffer
+                    .as_ref()
+                    .map(|camera| (camera.view_projection(), camera.viewport()))
+            });
+
+            if let Some((camera, viewport)) = last_camera {
+                let outgoing = transition.camera_mut();
+                outgoing.set_view_projection(camera.to_cols_array_2d());
+                outgoing.update_viewport(viewport);
+
+                let target = transition.target_view();
+                let world = from.and_then(|guid| worlds.get_world_mut(guid));
+                self.render_world(
+                    context,
+                    assets,
+                    world,
+                    transition.camera_mut(),
+                    &target,
+                    encoder,
+                );
+            }
+
+            self.transition = Some(transition);
+        }
+
+        let mut camera_buffer = self.camera_buffer.take();
+        let loading = match &mut camera_buffer {
+            Some(camera_buffer) => self.render_world(
+                context,
+                assets,
+                worlds.get_mut(),
+                camera_buffer,
+                scene_view,
+                encoder,
+            ),
+            None => false,
+        };
+        self.camera_buffer = camera_buffer;
+
+        //------------------------------------------------------------------------------------------
+        //Transition Pass-----------------------------------------------------------------------------
+        if let Some(transition) = &mut self.transition {
+            let (width, height) = (ctx.surface_config.width, ctx.surface_config.height);
+            let aspect_ratio = width as f32 / height.max(1) as f32;
+            transition.advance(context, &delta, loading, aspect_ratio);
+            let transition = &*transition;
+
+            let shader = assets
+                .try_get(FragmentShader::ptr(transition))
+                .or_else(|| assets.try_get(&TRANSITION_SHADER))
+                .unwrap();
+            let shader = ShaderVariant::Single(shader);
+
+            //Alpha blended on top of the incoming world.
+            let mut config = RenderPipelineConfig::new(&shader, None::<&Vertices>, transition, &[]);
+            config.set_config(PipelineBaseConfig { samples: sample_count, ..Default::default() });
+
+            let pipeline = self.pipelines.get_or_create(context, &config);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transition Render Pass"),
+                timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Transition")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: match sample_count {
+                        1 => scene_view,
+                        _ => &fbo_view,
+                    },
+                    resolve_target: match sample_count {
+                        1 => None,
+                        _ => Some(scene_view),
+                    },
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(pipeline);
+
+            for (i, bind_group) in transition.groups().iter().enumerate() {
+                render_pass.set_bind_group(i as u32, bind_group, &[]);
+            }
+
+            render_pass.draw(0..3, 0..1);
+            self.stats.draw_calls += 1;
+        }
+
+        if self.transition.as_ref().is_some_and(Transition::is_finished) {
+            self.transition = None;
+        }
+
+        self.last_camera =
+            self.camera_buffer.as_ref().map(|camera| (camera.view_projection(), camera.viewport()));
+
+        //------------------------------------------------------------------------------------------
+        //Post Process Pass---------------------------------------------------------------------------
+        if let Some(post) = &self.post {
+            let shader = assets
+                .try_get(post.shader())
+                .or_else(|| assets.try_get(&POST_PROCESS_SHADER))
+                .unwrap();
+            let shader = ShaderVariant::Single(shader);
+
+            let mut config = RenderPipelineConfig::new(&shader, None::<&Vertices>, post, &[]);
+            config.set_config(PipelineBaseConfig { samples: sample_count, ..Default::default() });
+
+            let pipeline = self.pipelines.get_or_create(context, &config);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Process Render Pass"),
+                timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Post Process")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: match sample_count {
+                        1 => view,
+                        _ => &fbo_view,
+                    },
+                    resolve_target: match sample_count {
+                        1 => None,
+                        _ => Some(view),
+                    },
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(pipeline);
+
+            for (i, bind_group) in post.groups().iter().enumerate() {
+                render_pass.set_bind_group(i as u32, bind_group, &[]);
+            }
+
+            render_pass.draw(0..3, 0..1);
+            self.stats.draw_calls += 1;
         }
 
         //------------------------------------------------------------------------------------------
@@ -268,9 +1341,21 @@ impl Renderer2D {
             let paint_jobs = egui_ctx.tessellate(output.shapes, egui_ctx.pixels_per_point());
             let texture_delta = output.textures_delta;
 
+            self.stats.paint_jobs = paint_jobs.len();
+            self.stats.uploaded_bytes += paint_jobs
+                .iter()
+                .map(|job| match &job.primitive {
+                    egui::epaint::Primitive::Mesh(mesh) => {
+                        std::mem::size_of_val(mesh.vertices.as_slice())
+                            + std::mem::size_of_val(mesh.indices.as_slice())
+                    }
+                    egui::epaint::Primitive::Callback(_) => 0,
+                })
+                .sum::<usize>();
+
             let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
                 size_in_pixels: [ctx.surface_config.width, ctx.surface_config.height],
-                pixels_per_point: window.scale_factor() as f32,
+                pixels_per_point: ctx.scale_factor() as f32,
             };
 
             let device = &ctx.graphics.device;
@@ -279,7 +1364,7 @@ impl Renderer2D {
             self.egui_renderer.update_buffers(
                 device,
                 queue,
-                &mut encoder,
+                encoder,
                 &paint_jobs,
                 &screen_descriptor,
             );
@@ -291,6 +1376,7 @@ impl Renderer2D {
             {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("GUI RenderPass"),
+                    timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("GUI")),
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                         view: match sample_count {
                             1 => view,
@@ -315,6 +1401,341 @@ impl Renderer2D {
                 self.egui_renderer.free_texture(&id);
             }
         }
-        context.queue.submit(std::iter::once(encoder.finish()));
+
+        //Software cursor goes last, so it is on top of the gui as well.
+        self.cursor.sync_os_cursor(window);
+
+        let (width, height) = (ctx.surface_config.width, ctx.surface_config.height);
+        if self.cursor.prepare(context, assets, width, height) {
+            let sprite = self.cursor.sprite();
+            let material = sprite.material();
+            let vertex = assets.try_get(VertexShader::ptr(material)).unwrap();
+            let fragment = assets.try_get(FragmentShader::ptr(material)).unwrap();
+            let shader = ShaderVariant::Double(vertex, fragment);
+
+            let mut config = RenderPipelineConfig::new(
+                &shader,
+                Some(sprite.mesh()),
+                material,
+                &[self.cursor.transform().layout(), CameraBuffer::layout(context)],
+            );
+            config.set_config(PipelineBaseConfig { samples: sample_count, ..Default::default() });
+
+            let pipeline = self.pipelines.get_or_create(context, &config);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Cursor RenderPass"),
+                timestamp_writes: self.profiler.as_mut().and_then(|p| p.scope("Cursor")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: match sample_count {
+                        1 => view,
+                        _ => &fbo_view,
+                    },
+                    resolve_target: match sample_count {
+                        1 => None,
+                        _ => Some(view),
+                    },
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
+
+            let (x, y, w, h) = self.cursor.camera().viewport();
+            render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+            render_pass.set_pipeline(pipeline);
+
+            Self::draw_sprite(
+                &mut render_pass,
+                self.cursor.transform(),
+                sprite,
+                self.cursor.camera(),
+            );
+            self.stats.draw_calls += 1;
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame(encoder);
+        }
+
+        let pipelines = self.pipelines.stats();
+        self.stats.pipeline_hits = pipelines.hits;
+        self.stats.pipeline_misses = pipelines.misses;
+        self.stats.culled = self.cull_stats.culled;
+
+        //The writes are all recorded, the frame is submitted by the engine.
+        self.belt.finish();
+    }
+
+    //Updates the minimap if it is due. Call it once per frame before render, see Minimap.
+    pub fn render_minimap(
+        &mut self, context: &VisContext, assets: &Assets, worlds: &mut Worlds,
+        minimap: &mut Minimap, frame: &mut FrameEncoder,
+    ) {
+        if !minimap.tick() {
+            return;
+        }
+
+        let Some(world) = worlds.get_mut() else {
+            return;
+        };
+
+        let Some(target) = assets.try_get(&minimap.texture()) else {
+            return;
+        };
+
+        Self::update_transforms(context, world, self.alpha, None);
+        let bands = Self::assign_sort_bands(world);
+        minimap.update_camera(context, bands);
+
+        let encoder = frame.encoder();
+
+        //Placeholders would stay in the cached world, so try again next time.
+        if minimap.take_redraw_world()
+            && self.render_minimap_world(context, assets, world, minimap, encoder)
+        {
+            minimap.invalidate();
+        }
+
+        encoder.copy_texture_to_texture(
+            minimap.base().as_image_copy(),
+            target.texture().as_image_copy(),
+            target.texture().size(),
+        );
+
+        self.render_minimap_icons(context, assets, world, minimap, target, encoder);
+    }
+
+    //Pipeline for meshes drawn with the sprite bind groups into a single sampled target.
+    fn prepare_flat_pipeline(
+        &mut self, context: &VisContext, assets: &Assets, material: &GenericMaterial,
+        mesh: &GenericMesh, transform: &Transform2D, base: PipelineBaseConfig,
+    ) -> PipelineConfigKey {
+        let vertex = assets.try_get(VertexShader::ptr(material)).unwrap();
+        let fragment = assets.try_get(FragmentShader::ptr(material)).unwrap();
+        let shader = ShaderVariant::Double(vertex, fragment);
+
+        let mut config = RenderPipelineConfig::new(
+            &shader,
+            Some(mesh),
+            material,
+            &[transform.layout(), CameraBuffer::layout(context)],
+        );
+
+        config.set_config(PipelineBaseConfig { samples: 1, ..base });
+        self.pipelines.prepare(context, &config);
+        config.key()
+    }
+
+    //Returns true if some textures were still loading.
+    fn render_minimap_world(
+        &mut self, context: &VisContext, assets: &Assets, world: &mut hecs::World,
+        minimap: &Minimap, encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
+        let bounds = Some(minimap.bounds());
+        let mut loading = false;
+
+        //Entity, is a tilemap, is translucent, band, z and the pipeline.
+        let mut draws = Vec::new();
+
+        for (entity, (transform, sprite, layer)) in world
+            .query::<(&Transform2D, &mut Sprite, Option<&RenderLayer>)>()
+            .without::<&Hidden>()
+            .iter()
+        {
+            if !sprite.is_visible() || !minimap.shows(layer) || !Self::is_visible(bounds, transform)
+            {
+                continue;
+            }
+
+            let opacity = effective_opacity(world, entity);
+            if opacity <= 0.0 {
+                continue;
+            }
+
+            sprite.set_opacity(context, opacity);
+            Self::bind_texture(context, assets, sprite);
+            loading |= assets.is_pending(&(*sprite.texture()).into());
+
+            let translucent = sprite.is_translucent();
+            let base = sprite.material().base_config().unwrap_or_default();
+            let key = self.prepare_flat_pipeline(
+                context,
+                assets,
+                sprite.material(),
+                sprite.mesh(),
+                transform,
+                PipelineBaseConfig {
+                    depth: Some(Framebuffer::material_depth(&base, !translucent, "minimap pass")),
+                    ..base
+                },
+            );
+
+            let (band, z) = (transform.sort_band(), transform.position().z);
+            draws.push((entity, false, translucent, band, z, key));
+        }
+
+        for (entity, (transform, tilemap, layer)) in
+            world.query::<(&Transform2D, &mut Tilemap, Option<&RenderLayer>)>().iter()
+        {
+            if !minimap.shows(layer) {
+                continue;
+            }
+
+            tilemap.prepare(context);
+            tilemap.set_opacity(context, effective_opacity(world, entity));
+            Self::bind_tilemap_texture(context, assets, tilemap);
+            loading |= assets.is_pending(&(*tilemap.texture()).into());
+
+            let Some((mesh, _, _)) = tilemap.chunks().next() else {
+                continue;
+            };
+
+            let translucent = tilemap.is_translucent();
+            let key = self.prepare_flat_pipeline(
+                context,
+                assets,
+                tilemap.material(),
+                mesh,
+                transform,
+                PipelineBaseConfig {
+                    depth: Some(Framebuffer::depth_state(!translucent)),
+                    ..Default::default()
+                },
+            );
+
+            let (band, z) = (transform.sort_band(), transform.position().z);
+            draws.push((entity, true, translucent, band, z, key));
+        }
+
+        //Opaque ones first, translucent ones back to front on top.
+        draws.sort_by(|a, b| a.2.cmp(&b.2).then(a.3.total_cmp(&b.3)).then(a.4.total_cmp(&b.4)));
+
+        let mut sprite_query = world.query::<(&Transform2D, &Sprite)>();
+        let sprites: HashMap<_, _> = sprite_query.iter().collect();
+        let mut tilemap_query = world.query::<(&Transform2D, &Tilemap)>();
+        let tilemaps: HashMap<_, _> = tilemap_query.iter().collect();
+
+        let base_view = minimap.base().create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = minimap.depth_view();
+        let camera = minimap.camera();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Minimap Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &base_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(minimap.clear_color()),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            ..Default::default()
+        });
+
+        let (x, y, w, h) = camera.viewport();
+        render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+
+        for (entity, is_tilemap, _, _, _, key) in &draws {
+            render_pass.set_pipeline(self.pipelines.get_key(key).unwrap());
+
+            if *is_tilemap {
+                let (transform, tilemap) = tilemaps[entity];
+                let mut stats = CullStats::default();
+                Self::draw_tilemap(
+                    &mut render_pass,
+                    transform,
+                    tilemap,
+                    camera,
+                    bounds,
+                    &mut stats,
+                );
+            } else {
+                let (transform, sprite) = sprites[entity];
+                Self::draw_sprite(&mut render_pass, transform, sprite, camera);
+            }
+        }
+
+        loading
+    }
+
+    fn render_minimap_icons(
+        &mut self, context: &VisContext, assets: &Assets, world: &hecs::World,
+        minimap: &mut Minimap, target: &Texture2D, encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let units_per_pixel = minimap.units_per_pixel();
+
+        let mut query = world.query::<(&Transform2D, &MinimapIcon)>();
+        let entities: HashMap<_, _> = query.iter().collect();
+
+        //Forget the icons of despawned entities.
+        minimap.icons_mut().retain(|entity, _| entities.contains_key(entity));
+
+        let mut keys = Vec::new();
+
+        for (entity, (transform, icon)) in &entities {
+            let (sprite, icon_transform) =
+                minimap.icons_mut().entry(*entity).or_insert_with(|| {
+                    let sprite = Sprite::new(context, icon.texture, icon.color, None, None);
+                    (sprite, Transform2D::new(context, Vec3::ZERO, 0.0, Vec2::ONE))
+                });
+
+            sprite.set_texture(icon.texture);
+            sprite.set_tint(context, icon.color);
+            Self::bind_texture(context, assets, sprite);
+
+            //On top of the entity, the quad spans -1..1 so scale by half the size.
+            let position = transform.global().w_axis.truncate().truncate().extend(0.0);
+            let scale = Vec2::splat(icon.size * units_per_pixel / 2.0);
+
+            if icon_transform.position() != position || icon_transform.scale() != scale {
+                icon_transform.set_position(position);
+                icon_transform.set_scale(scale);
+            }
+
+            icon_transform.update_detached(context);
+
+            let key = self.prepare_flat_pipeline(
+                context,
+                assets,
+                sprite.material(),
+                sprite.mesh(),
+                icon_transform,
+                PipelineBaseConfig::default(),
+            );
+
+            keys.push((*entity, key));
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Minimap Icon Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view(),
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            ..Default::default()
+        });
+
+        let (x, y, w, h) = minimap.camera().viewport();
+        render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+
+        for (entity, key) in &keys {
+            let (sprite, transform) = &minimap.icons()[entity];
+            render_pass.set_pipeline(self.pipelines.get_key(key).unwrap());
+            Self::draw_sprite(&mut render_pass, transform, sprite, minimap.camera());
+        }
     }
 }
@@ -3,25 +3,23 @@ use std::default::Default;
 use wgpu::TextureView;
 
 use crate::{
-    assets::{
-        assets::{AssetType, Assets},
-        buffer::Vertices,
-        shader::{Shader, ShaderVariant},
-    },
+    assets::{assets::Assets, buffer::Vertices, shader::ShaderVariant},
     context::{Context, VisContext},
+    entities::{entities::Worlds, mesh3d::Mesh3D, transform::Transform3D},
     event::{self, EventSubscriber},
-    utils::Guid,
 };
 
 use super::camera::CameraBuffer;
 use super::factory::{PipelineFactory, RenderPipelineConfig};
-use super::framebuffer::Framebuffer;
+use super::framebuffer::{Framebuffer, DEPTH_FORMAT};
 use super::material::SkyboxMaterial;
-use super::types::{BindGroup, FragmentShader, VertexShader};
+use super::types::{
+    BindGroup, FragmentShader, IndexBuffer, MaterialLayout, PipelineBaseConfig, VertexBuffer,
+    VertexShader,
+};
 
 pub(crate) struct Renderer {
     framebuffer: Framebuffer,
-    assets: Assets,
     pipelines: PipelineFactory,
     camera_buffer: CameraBuffer,
     skybox: Option<SkyboxMaterial>,
@@ -41,39 +39,45 @@ impl EventSubscriber for Renderer {
 }
 
 impl Renderer {
-    pub fn new(context: &Context, mut assets: Assets) -> Self {
+    pub fn new(context: &Context, assets: &mut Assets) -> Self {
         //Renderable setup
-        let sample_count = 4;
+        let sample_count = context.features.sample_count();
 
         let pipelines = PipelineFactory::new();
 
         let framebuffer = Framebuffer::new(context, sample_count);
 
-        let sky_shader = assets.consume_asset(
-            AssetType::Shader(
-                Shader::new(
-                    &context.graphics,
-                    Guid::dead(),
-                    wgpu::ShaderSource::Wgsl(include_str!("../assets/skybox.wgsl").into()),
-                    what::ShaderStages::VERTEX | what::ShaderStages::FRAGMENT,
-                )
-                .unwrap(),
-            ),
-            None::<&str>,
-        );
+        let skybox = Renderer::load_skybox(&context.graphics, assets);
+
+        let camera_buffer = CameraBuffer::new(&context.graphics, "Default Camera");
+
+        let egui_renderer = Renderer::recreate_gui(context, sample_count);
+
+        Renderer { framebuffer, pipelines, camera_buffer, skybox, egui_renderer }
+    }
+
+    fn load_skybox(context: &VisContext, assets: &mut Assets) -> Option<SkyboxMaterial> {
+        let sky_shader = SkyboxMaterial::default_shader(context, assets);
 
         //TODO add capability to what for material asset type containing textures, shaders, etc.
         let sky_tex = assets.request_asset("data/skybox.fur", 0);
 
-        let skybox = assets
+        assets
             .get(&sky_tex)
-            .map(|sky_tex| SkyboxMaterial::new(&context.graphics, sky_shader, sky_shader, sky_tex));
-
-        let camera_buffer = CameraBuffer::new(&context.graphics, "Default Camera");
-
-        let egui_renderer = Renderer::recreate_gui(context, sample_count);
+            .map(|sky_tex| SkyboxMaterial::new(context, sky_shader, sky_shader, sky_tex))
+    }
 
-        Renderer { framebuffer, assets, pipelines, camera_buffer, skybox, egui_renderer }
+    //After the device was lost, the assets are loaded again and everything else created anew.
+    //Called by the app on Event::DeviceRestored, it owns the assets.
+    pub fn recreate(&mut self, context: &Context, assets: &mut Assets) {
+        let sample_count = context.features.sample_count();
+
+        assets.rebuild(context.graphics.clone());
+        self.framebuffer = Framebuffer::new(context, sample_count);
+        self.pipelines = PipelineFactory::new();
+        self.camera_buffer = CameraBuffer::new(&context.graphics, "Default Camera");
+        self.skybox = Renderer::load_skybox(&context.graphics, assets);
+        self.egui_renderer = Renderer::recreate_gui(context, sample_count);
     }
 
     pub(crate) fn recreate_gui(context: &Context, sample_count: u32) -> egui_wgpu::Renderer {
@@ -105,18 +109,125 @@ impl Renderer {
         }
     }
 
+    fn update_transforms(context: &VisContext, world: &hecs::World) {
+        //Iterate over all entities with a transform component but do not borrow.
+        for (entity, _) in world.query::<()>().with::<&Transform3D>().iter() {
+            if let Ok(mut transform) = world.get::<&mut Transform3D>(entity) {
+                transform.update(context, entity, world);
+            }
+        }
+    }
+
+    //Every Mesh3D with a Transform3D of the current world, after the skybox.
+    fn render_meshes(
+        &mut self, context: &VisContext, assets: &Assets, world: &hecs::World,
+        encoder: &mut wgpu::CommandEncoder, view: &TextureView,
+    ) {
+        Self::update_transforms(context, world);
+
+        let sample_count = self.framebuffer.sample_count();
+        let mut keys = hashbrown::HashMap::new();
+
+        for (entity, (transform, mesh)) in world.query::<(&Transform3D, &Mesh3D)>().iter() {
+            let material = mesh.material();
+
+            //Shaders that are still loading, the mesh shows up once they are there.
+            let (Some(vertex), Some(fragment)) = (
+                assets.try_get(VertexShader::ptr(material)),
+                assets.try_get(FragmentShader::ptr(material)),
+            ) else {
+                continue;
+            };
+
+            let shader = ShaderVariant::Double(vertex, fragment);
+            let layouts = [transform.layout(), CameraBuffer::layout(context)];
+            let mut config =
+                RenderPipelineConfig::new(&shader, Some(mesh.mesh()), material, &layouts);
+
+            config.set_config(PipelineBaseConfig {
+                samples: sample_count,
+                depth: Some(Framebuffer::depth_state(true)),
+                ..material.base_config().unwrap_or_default()
+            });
+
+            self.pipelines.prepare(context, &config);
+            keys.insert(entity, config.key());
+        }
+
+        if keys.is_empty() {
+            return;
+        }
+
+        let framebuffer_view: TextureView = (&self.framebuffer).into();
+        let depth_view = self.framebuffer.depth_view();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mesh Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: match sample_count {
+                    1 => view,
+                    _ => &framebuffer_view,
+                },
+                resolve_target: match sample_count {
+                    1 => None,
+                    _ => Some(view),
+                },
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Store,
+                }),
+            }),
+            ..Default::default()
+        });
+
+        let mut query = world.query::<(&Transform3D, &Mesh3D)>();
+
+        for (entity, (transform, mesh)) in query.iter() {
+            let Some(key) = keys.get(&entity) else {
+                continue;
+            };
+
+            render_pass.set_pipeline(self.pipelines.get_key(key).unwrap());
+
+            for (i, bind_group) in mesh.material().groups().iter().enumerate() {
+                render_pass.set_bind_group(i as u32, bind_group, &[]);
+            }
+
+            render_pass.set_bind_group(1, transform.group(), &[]);
+            render_pass.set_bind_group(2, self.camera_buffer.bind_group(), &[]);
+
+            render_pass.set_vertex_buffer(0, VertexBuffer::buffer(mesh.mesh()).unwrap().slice(..));
+            let (buffer, format) = IndexBuffer::buffer(mesh.mesh()).unwrap();
+            render_pass.set_index_buffer(buffer.slice(..), format);
+            render_pass.draw_indexed(0..mesh.mesh().num_indices(), 0, 0..1);
+        }
+    }
+
     pub fn render(
-        &mut self, context: &mut Context, view: &TextureView, window: &winit::window::Window,
+        &mut self, context: &mut Context, assets: &mut Assets, worlds: &mut Worlds,
+        view: &TextureView,
     ) {
         let gpu = context.graphics.as_ref();
-        let assets = &mut self.assets;
 
         let _ = assets.update();
         let framebuffer_view: TextureView = (&self.framebuffer).into();
         let sample_count = self.framebuffer.sample_count();
 
-        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
+        let encoder = context.frame.encoder();
+
+        //The framebuffer depth is only attached if the skybox material asks for depth testing.
+        let sky_base = self.skybox.as_ref().and_then(|skybox| skybox.base_config());
+        let depth_view = sky_base.as_ref().and_then(|base| {
+            base.check_depth_attachment(Some(DEPTH_FORMAT), "skybox pass");
+            base.depth.as_ref().map(|_| self.framebuffer.depth_view())
         });
 
         {
@@ -136,7 +247,19 @@ impl Renderer {
                         store: wgpu::StoreOp::Store,
                     },
                 })],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: depth_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                    }
+                }),
                 ..Default::default()
             });
 
@@ -146,7 +269,12 @@ impl Renderer {
                     assets.try_get(FragmentShader::ptr(skybox)).unwrap(),
                 );
 
-                let sky_config = RenderPipelineConfig::new(&shader, None::<&Vertices>, skybox, &[]);
+                let mut sky_config =
+                    RenderPipelineConfig::new(&shader, None::<&Vertices>, skybox, &[]);
+                sky_config.set_config(PipelineBaseConfig {
+                    samples: sample_count,
+                    ..sky_base.unwrap_or_default()
+                });
                 let sky_pipeline = self.pipelines.get_or_create(gpu, &sky_config);
 
                 render_pass.set_pipeline(sky_pipeline);
@@ -158,6 +286,11 @@ impl Renderer {
                 render_pass.draw(0..3, 0..1);
             }
         }
+
+        if let Some(world) = worlds.get() {
+            self.render_meshes(gpu, assets, world, encoder, view);
+        }
+
         {
             let egui_ctx = context.egui.egui_ctx();
             let output = egui_ctx.end_frame();
@@ -166,13 +299,13 @@ impl Renderer {
 
             let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
                 size_in_pixels: [context.surface_config.width, context.surface_config.height],
-                pixels_per_point: window.scale_factor() as f32,
+                pixels_per_point: context.scale_factor() as f32,
             };
 
             self.egui_renderer.update_buffers(
                 &gpu.device,
                 &gpu.queue,
-                &mut (encoder),
+                encoder,
                 &paint_jobs,
                 &screen_descriptor,
             );
@@ -208,7 +341,5 @@ impl Renderer {
                 self.egui_renderer.free_texture(&id);
             }
         }
-
-        gpu.queue.submit(std::iter::once(encoder.finish()));
     }
 }
@@ -0,0 +1,218 @@
+use crate::assets::assets::{Ptr, TRANSITION_SHADER};
+use crate::assets::buffer::UniformBuffer;
+use crate::assets::shader::Shader;
+use crate::assets::texture::{Sampler, Texture2D};
+use crate::context::VisContext;
+use crate::render::types::BindGroupEntry;
+use crate::utils::{Guid, Timestep};
+
+use super::camera::CameraBuffer;
+use super::types::{
+    BindGroup, BindLayout, FragmentShader, Material, MaterialLayout, PipelineBaseConfig,
+    TransitionUniform, VertexShader,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionStyle {
+    Fade,
+    WipeLeftToRight,
+    WipeCircle,
+    //Also what every transition falls back to if the incoming world is still loading.
+    FadeThroughBlack,
+    //Gets the same bindings as assets/transition.wgsl and is blended on top of the incoming world.
+    Custom(Ptr<Shader>),
+}
+
+//How Worlds::transition_to blends the outgoing world into the incoming one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldTransition {
+    //In seconds.
+    pub duration: f32,
+    pub style: TransitionStyle,
+}
+
+impl WorldTransition {
+    pub fn new(duration: f32, style: TransitionStyle) -> Self {
+        WorldTransition { duration, style }
+    }
+
+    pub fn fade(duration: f32) -> Self {
+        Self::new(duration, TransitionStyle::Fade)
+    }
+}
+
+//A running transition. The outgoing world is frozen, so it is rendered once into the offscreen
+//target and composited over the incoming world every frame until the duration is over.
+pub(crate) struct Transition {
+    //The outgoing world, None fades in over the background.
+    from: Option<Guid>,
+    config: WorldTransition,
+    elapsed: f32,
+    camera: CameraBuffer,
+    target: wgpu::Texture,
+    uniforms: UniformBuffer,
+    bind_layout: [wgpu::BindGroupLayout; 1],
+    bind_group: [wgpu::BindGroup; 1],
+}
+
+impl Transition {
+    pub fn new(
+        graphics: &VisContext, (width, height): (u32, u32), from: Option<Guid>,
+        config: WorldTransition,
+    ) -> Self {
+        let target = graphics.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Transition Target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: graphics.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Sampler::new(graphics);
+        let uniforms = UniformBuffer::new(graphics, std::mem::size_of::<TransitionUniform>());
+
+        let bind_layout =
+            graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Transition Layout"),
+                entries: &[
+                    Texture2D::layout_entry(0),
+                    Sampler::layout_entry(1),
+                    UniformBuffer::layout_entry(2),
+                ],
+            });
+
+        let bind_group = graphics.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Transition Group"),
+            layout: &bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                sampler.group_entry(1),
+                uniforms.group_entry(2),
+            ],
+        });
+
+        Transition {
+            from,
+            config: WorldTransition { duration: config.duration.max(0.0), ..config },
+            elapsed: 0.0,
+            camera: CameraBuffer::new(graphics, "Transition Camera"),
+            target,
+            uniforms,
+            bind_layout: [bind_layout],
+            bind_group: [bind_group],
+        }
+    }
+
+    //Picks up where a previous transition left off, see restart.
+    pub fn with_elapsed(mut self, elapsed: f32) -> Self {
+        self.elapsed = elapsed;
+        self
+    }
+
+    //What is needed to start it again on a new device or at a new size. The outgoing world is
+    //rendered once more.
+    pub fn restart(&self) -> (Option<Guid>, WorldTransition, f32) {
+        (self.from, self.config, self.elapsed)
+    }
+
+    //Camera of the outgoing world, set to what it was last seen through.
+    pub fn camera_mut(&mut self) -> &mut CameraBuffer {
+        &mut self.camera
+    }
+
+    //The outgoing world renders (or resolves) into this.
+    pub fn target_view(&self) -> wgpu::TextureView {
+        self.target.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.config.duration > 0.0 {
+            (self.elapsed / self.config.duration).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    //Moves on by the frame delta, so it follows the time scale. While the incoming world is loading,
+    //a blend would show its placeholders, so it switches to a fade through black and holds on black
+    //until it is ready.
+    pub fn advance(
+        &mut self, context: &VisContext, delta: &Timestep, loading: bool, aspect_ratio: f32,
+    ) {
+        self.elapsed += delta.seconds() as f32;
+
+        let half = self.config.duration / 2.0;
+
+        //Past the middle the incoming world is mostly visible already, switching would flash black.
+        if loading && self.config.style != TransitionStyle::FadeThroughBlack && self.elapsed < half
+        {
+            log::info!("The incoming world is still loading, fading through black instead.");
+            self.config.style = TransitionStyle::FadeThroughBlack;
+        }
+
+        if loading && self.config.style == TransitionStyle::FadeThroughBlack {
+            self.elapsed = self.elapsed.min(half);
+        }
+
+        let style = match self.config.style {
+            TransitionStyle::Fade | TransitionStyle::Custom(_) => 0,
+            TransitionStyle::WipeLeftToRight => 1,
+            TransitionStyle::WipeCircle => 2,
+            TransitionStyle::FadeThroughBlack => 3,
+        };
+
+        let uniform =
+            TransitionUniform { progress: self.progress(), style, aspect_ratio, padding: 0.0 };
+        self.uniforms.update_buffer(context, bytemuck::cast_slice(&[uniform]));
+    }
+
+    fn shader(&self) -> &Ptr<Shader> {
+        match &self.config.style {
+            TransitionStyle::Custom(shader) => shader,
+            _ => &*TRANSITION_SHADER,
+        }
+    }
+}
+
+impl MaterialLayout for Transition {
+    fn base_config(&self) -> Option<PipelineBaseConfig> {
+        None
+    }
+}
+
+impl Material for Transition {}
+
+impl BindLayout for Transition {
+    fn layouts(&self) -> &[wgpu::BindGroupLayout] {
+        &self.bind_layout
+    }
+}
+
+impl BindGroup for Transition {
+    fn groups(&self) -> &[wgpu::BindGroup] {
+        &self.bind_group
+    }
+}
+
+impl FragmentShader for Transition {
+    fn ptr(&self) -> &Ptr<Shader> {
+        self.shader()
+    }
+}
+
+impl VertexShader for Transition {
+    fn ptr(&self) -> &Ptr<Shader> {
+        self.shader()
+    }
+}
@@ -23,18 +23,116 @@ impl Default for SpriteUniform {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteColorUniform {
+    pub tint: [f32; 4],
+    pub opacity: f32,
+    //0 for hard edges.
+    pub soft_distance: f32,
+    pub padding: [f32; 2],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub view_projection: [[f32; 4]; 4],
+    //x: shear, how much depth one unit of world y adds. Used for 2.5D views.
+    //y: number of sorting bands in use.
+    pub params: [f32; 4],
 }
 
 impl Default for CameraUniform {
     fn default() -> Self {
-        CameraUniform { view_projection: glam::Mat4::IDENTITY.to_cols_array_2d() }
+        CameraUniform { view_projection: glam::Mat4::IDENTITY.to_cols_array_2d(), params: [0.0; 4] }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TransitionUniform {
+    pub progress: f32,
+    //See assets/transition.wgsl.
+    pub style: u32,
+    pub aspect_ratio: f32,
+    pub padding: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightUniform {
+    pub position: [f32; 2],
+    pub radius: f32,
+    pub falloff: f32,
+    //Color times intensity, w is unused.
+    pub color: [f32; 4],
+}
+
+//See assets/lighting.wgsl, the light count there has to match.
+pub const MAX_LIGHTS: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+    pub inverse_view_projection: [[f32; 4]; 4],
+    pub ambient: [f32; 4],
+    pub count: u32,
+    pub padding: [u32; 3],
+    pub lights: [PointLightUniform; MAX_LIGHTS],
+}
+
+//One particle, per instance vertex data of the particle shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ParticleInstance {
+    pub position: [f32; 3],
+    //Width and height in world units.
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl ParticleInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32, 2 => Float32x4];
+
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<ParticleInstance>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &Self::ATTRIBUTES,
+    };
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BackgroundUniform {
+    pub tint: [f32; 4],
+    //Screen uv are scaled, then offset. Crops the image.
+    pub uv_offset: [f32; 2],
+    pub uv_scale: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TransformUniform {
+    pub transform: [[f32; 4]; 4],
+    //x: billboard enabled, y: local y of the billboard foot, z: sorting band (see RenderLayer).
+    pub billboard: [f32; 4],
+}
+
+impl Default for TransformUniform {
+    fn default() -> Self {
+        TransformUniform { transform: glam::Mat4::IDENTITY.to_cols_array_2d(), billboard: [0.0; 4] }
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelUniform {
+    pub model: [[f32; 4]; 4],
+    //Inverse transpose of the model, for the normals.
+    pub normal: [[f32; 4]; 4],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SplitCameraUniform {
@@ -51,13 +149,14 @@ impl Default for SplitCameraUniform {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
 pub struct PipelineBaseConfig {
     pub cull: bool,
     pub polygon_mode: wgpu::PolygonMode,
     pub blend: Option<wgpu::BlendState>,
     pub write_mask: wgpu::ColorWrites,
     pub samples: u32,
+    pub depth: Option<wgpu::DepthStencilState>,
 }
 
 impl Default for PipelineBaseConfig {
@@ -68,6 +167,106 @@ impl Default for PipelineBaseConfig {
             blend: Some(wgpu::BlendState::ALPHA_BLENDING),
             write_mask: wgpu::ColorWrites::ALL,
             samples: 4,
+            depth: None,
+        }
+    }
+}
+
+impl PipelineBaseConfig {
+    //Tests against a depth attachment of the given format. Keeps the stencil state if one is set.
+    pub fn with_depth(
+        mut self, format: wgpu::TextureFormat, compare: wgpu::CompareFunction, write: bool,
+    ) -> Self {
+        let stencil = self.depth.take().map(|depth| depth.stencil).unwrap_or_default();
+
+        self.depth = Some(wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: write,
+            depth_compare: compare,
+            stencil,
+            bias: wgpu::DepthBiasState::default(),
+        });
+        self
+    }
+
+    //Stencil masking. Switches to Depth24PlusStencil8 if the depth format has no stencil aspect.
+    //Without with_depth the depth test always passes.
+    pub fn with_stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        let depth = self.depth.take().unwrap_or(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth24PlusStencil8,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+
+        let format = match depth.format.has_stencil_aspect() {
+            true => depth.format,
+            false => wgpu::TextureFormat::Depth24PlusStencil8,
+        };
+
+        self.depth = Some(wgpu::DepthStencilState { format, stencil, ..depth });
+        self
+    }
+
+    //Renderers call this with the depth attachment of their pass. A pipeline built for another
+    //attachment would only fail later inside wgpu with a much less helpful message.
+    pub fn check_depth_attachment(&self, attachment: Option<wgpu::TextureFormat>, pass: &str) {
+        let Some(depth) = &self.depth else {
+            return;
+        };
+
+        match attachment {
+            None => panic!(
+                "The pipeline asks for a {:?} depth attachment, but the {} has none.",
+                depth.format, pass
+            ),
+            Some(format) if format != depth.format => panic!(
+                "The pipeline asks for a {:?} depth attachment, but the {} uses {:?}.",
+                depth.format, pass, format
+            ),
+            _ => (),
+        }
+
+        if depth.stencil.is_enabled() && !depth.format.has_stencil_aspect() {
+            panic!(
+                "The pipeline uses stencil tests, but the {} depth attachment {:?} has no stencil.",
+                pass, depth.format
+            );
+        }
+    }
+
+    //Adds the color on top of what is already there, e.g. for glow and light sprites.
+    pub fn additive() -> Self {
+        Self {
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+            ..Default::default()
+        }
+    }
+
+    //Multiplies what is already there with the color, e.g. for shadows and light maps.
+    pub fn multiply() -> Self {
+        Self {
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }),
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,420 @@
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::assets::assets::Assets;
+use crate::context::Context;
+use crate::entities::animation2d::Animation2D;
+use crate::entities::animator2d::Animator2D;
+use crate::entities::audio::AudioSystem;
+use crate::entities::entities::Worlds;
+use crate::entities::navigation::Navigation;
+use crate::entities::physics2d::PhysicsSystem;
+use crate::entities::sprite::Sprite;
+use crate::entities::timer::TimerSystem;
+use crate::entities::tween::TweenSystem;
+use crate::input::InputState;
+use crate::render::camera_controller::CameraFollow;
+use crate::sound::AudioEngine;
+use crate::utils::Timestep;
+
+//PreUpdate and Update run before Application::update, PostUpdate after it. PreRender runs right before rendering.
+//FixedUpdate runs with every fixed step, before Application::fixed_update.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Stage {
+    PreUpdate,
+    FixedUpdate,
+    Update,
+    PostUpdate,
+    PreRender,
+}
+
+pub struct SystemParams<'p, 'c> {
+    pub context: &'p mut Context<'c>,
+    pub worlds: &'p mut Worlds,
+    pub assets: &'p mut Assets,
+    pub input: &'p InputState,
+    pub delta: &'p Timestep,
+    pub resources: &'p mut Resources,
+}
+
+//State of the systems, one value per type. The engine systems skip their work while theirs is
+//missing, e.g. insert a Navigation to have the PathFollowers walk.
+#[derive(Default)]
+pub struct Resources {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Resources {
+    //Returns the value it replaced.
+    pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+        let old = self.values.insert(TypeId::of::<T>(), Box::new(value))?;
+        old.downcast().ok().map(|old| *old)
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let value = self.values.remove(&TypeId::of::<T>())?;
+        value.downcast().ok().map(|value| *value)
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+}
+
+type SystemFn = Box<dyn FnMut(&mut SystemParams)>;
+
+pub struct System {
+    name: &'static str,
+    stage: Stage,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    run: SystemFn,
+}
+
+impl System {
+    pub fn new(
+        name: &'static str, stage: Stage, run: impl FnMut(&mut SystemParams) + 'static,
+    ) -> Self {
+        System { name, stage, before: Vec::new(), after: Vec::new(), run: Box::new(run) }
+    }
+
+    //Ordering constraints only apply between systems of the same stage.
+    pub fn before(mut self, name: &'static str) -> Self {
+        self.before.push(name);
+        self
+    }
+
+    pub fn after(mut self, name: &'static str) -> Self {
+        self.after.push(name);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    Duplicate(&'static str),
+    Cycle(Stage, Vec<&'static str>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Duplicate(name) => write!(f, "System {} is registered twice", name),
+            ScheduleError::Cycle(stage, names) => {
+                write!(f, "Systems in {:?} depend on each other: {}", stage, names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+//Runs systems in stage order. Within a stage, systems run in registration order unless
+//constrained by before/after.
+pub struct Schedule {
+    systems: Vec<System>,
+    order: BTreeMap<Stage, Vec<usize>>,
+    resources: Resources,
+    built: bool,
+}
+
+//Comes with the engine systems registered. Only the TimerSystem is there from the start, the
+//others wait for their resource.
+impl Default for Schedule {
+    fn default() -> Self {
+        let mut schedule = Schedule::empty();
+        schedule.resources.insert(TimerSystem::new());
+
+        schedule.add(System::new("physics", Stage::FixedUpdate, step_physics));
+        //Before Application::update, so scripts see what fired this frame.
+        schedule.add(System::new("timers", Stage::Update, update_timers));
+        schedule.add(System::new("navigation", Stage::Update, update_navigation));
+        //A tween on a walking entity has the last word.
+        schedule.add(System::new("tweens", Stage::Update, update_tweens).after("navigation"));
+        schedule.add(System::new("animations", Stage::Update, update_animations).after("tweens"));
+        //After Application::update, the target has moved by then.
+        schedule.add(System::new("camera_follow", Stage::PostUpdate, update_camera_follow));
+        //The listener usually sits on the camera.
+        schedule.add(System::new("audio", Stage::PostUpdate, update_audio).after("camera_follow"));
+        schedule
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Without any engine systems.
+    pub fn empty() -> Self {
+        Schedule {
+            systems: Vec::new(),
+            order: BTreeMap::new(),
+            resources: Resources::default(),
+            built: false,
+        }
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    pub fn add(&mut self, system: System) {
+        self.systems.push(system);
+        self.built = false;
+    }
+
+    //E.g. to replace an engine system by your own.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.systems.len();
+        self.systems.retain(|system| system.name != name);
+        self.built = false;
+        self.systems.len() != len
+    }
+
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        for (i, system) in self.systems.iter().enumerate() {
+            if self.systems[..i].iter().any(|other| other.name == system.name) {
+                return Err(ScheduleError::Duplicate(system.name));
+            }
+
+            for name in system.before.iter().chain(system.after.iter()) {
+                if !self.systems.iter().any(|other| other.name == *name) {
+                    log::warn!("System {} is ordered against unknown system {}", system.name, name);
+                }
+            }
+        }
+
+        let mut order = BTreeMap::new();
+
+        for stage in [
+            Stage::PreUpdate,
+            Stage::FixedUpdate,
+            Stage::Update,
+            Stage::PostUpdate,
+            Stage::PreRender,
+        ] {
+            order.insert(stage, self.sort_stage(stage)?);
+        }
+
+        self.order = order;
+        self.built = true;
+        Ok(())
+    }
+
+    //Kahn's algorithm, always taking the earliest registered system that is ready.
+    fn sort_stage(&self, stage: Stage) -> Result<Vec<usize>, ScheduleError> {
+        let members: Vec<usize> =
+            (0..self.systems.len()).filter(|i| self.systems[*i].stage == stage).collect();
+
+        let index = |name: &str| members.iter().copied().find(|i| self.systems[*i].name == name);
+
+        //edges[a] contains b if a has to run before b.
+        let mut edges: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut incoming: BTreeMap<usize, usize> = members.iter().map(|i| (*i, 0)).collect();
+
+        for i in members.iter().copied() {
+            let system = &self.systems[i];
+            let before = system.before.iter().filter_map(|name| index(name)).map(|j| (i, j));
+            let after = system.after.iter().filter_map(|name| index(name)).map(|j| (j, i));
+
+            for (from, to) in before.chain(after) {
+                edges.entry(from).or_default().push(to);
+                *incoming.entry(to).or_default() += 1;
+            }
+        }
+
+        let mut sorted = Vec::with_capacity(members.len());
+
+        while let Some(next) = members.iter().copied().find(|i| incoming.get(i) == Some(&0)) {
+            incoming.remove(&next);
+            sorted.push(next);
+
+            for to in edges.get(&next).into_iter().flatten() {
+                if let Some(count) = incoming.get_mut(to) {
+                    *count -= 1;
+                }
+            }
+        }
+
+        if !incoming.is_empty() {
+            let names = incoming.keys().map(|i| self.systems[*i].name).collect();
+            return Err(ScheduleError::Cycle(stage, names));
+        }
+
+        Ok(sorted)
+    }
+
+    pub fn run(
+        &mut self, stage: Stage, context: &mut Context, worlds: &mut Worlds, assets: &mut Assets,
+        input: &InputState, delta: &Timestep,
+    ) {
+        if !self.built {
+            if let Err(error) = self.build() {
+                log::error!("Not running systems: {}", error);
+                return;
+            }
+        }
+
+        let Schedule { systems, order, resources, .. } = self;
+        let mut params = SystemParams { context, worlds, assets, input, delta, resources };
+
+        for i in order.get(&stage).into_iter().flatten() {
+            (systems[*i].run)(&mut params);
+        }
+    }
+}
+
+fn step_physics(params: &mut SystemParams) {
+    if let Some(world) = params.worlds.get_mut() {
+        PhysicsSystem::step(world, params.delta);
+    }
+}
+
+fn update_timers(params: &mut SystemParams) {
+    if let (Some(timers), Some(world)) =
+        (params.resources.get_mut::<TimerSystem>(), params.worlds.get_mut())
+    {
+        timers.update(world, params.delta);
+    }
+}
+
+//The PathEvents arrive as Event::Custom at the start of the next frame.
+fn update_navigation(params: &mut SystemParams) {
+    if let (Some(navigation), Some(world)) =
+        (params.resources.get_mut::<Navigation>(), params.worlds.get_mut())
+    {
+        for event in navigation.update(world, params.delta) {
+            params.context.emit(event);
+        }
+    }
+}
+
+fn update_tweens(params: &mut SystemParams) {
+    if let Some(world) = params.worlds.get_mut() {
+        TweenSystem::update(&params.context.graphics, world, params.delta);
+    }
+}
+
+fn update_camera_follow(params: &mut SystemParams) {
+    if let Some(follow) = params.resources.get_mut::<CameraFollow>() {
+        follow.update(params.delta, params.worlds);
+    }
+}
+
+fn update_audio(params: &mut SystemParams) {
+    let Some(audio) = params.resources.get_mut::<AudioEngine>() else {
+        return;
+    };
+
+    audio.update(params.delta);
+
+    if let Some(world) = params.worlds.get_mut() {
+        AudioSystem::update(world, params.assets, audio);
+    }
+}
+
+fn update_animations(params: &mut SystemParams) {
+    if let Some(world) = params.worlds.get_mut() {
+        for (_entity, (sprite, animation)) in world.query_mut::<(&mut Sprite, &mut Animation2D)>() {
+            animation.update(&params.context.graphics, params.delta, sprite);
+        }
+
+        for (_entity, (sprite, animator)) in world.query_mut::<(&mut Sprite, &mut Animator2D)>() {
+            animator.update(&params.context.graphics, params.delta, sprite);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_params: &mut SystemParams) {}
+
+    fn order(schedule: &Schedule, stage: Stage) -> Vec<&'static str> {
+        schedule.order[&stage].iter().map(|i| schedule.systems[*i].name).collect()
+    }
+
+    #[test]
+    fn before_and_after_override_the_registration_order() {
+        let mut schedule = Schedule::empty();
+        schedule.add(System::new("c", Stage::Update, noop));
+        schedule.add(System::new("b", Stage::Update, noop).after("a").before("c"));
+        schedule.add(System::new("a", Stage::Update, noop).before("c"));
+        schedule.add(System::new("d", Stage::Update, noop));
+        //Other stages don't take part.
+        schedule.add(System::new("x", Stage::PreUpdate, noop).after("d"));
+
+        schedule.build().unwrap();
+        assert_eq!(order(&schedule, Stage::Update), ["a", "b", "c", "d"]);
+        assert_eq!(order(&schedule, Stage::PreUpdate), ["x"]);
+    }
+
+    #[test]
+    fn the_same_name_twice_is_rejected() {
+        let mut schedule = Schedule::new();
+        schedule.add(System::new("physics", Stage::Update, noop));
+
+        assert!(matches!(schedule.build(), Err(ScheduleError::Duplicate("physics"))));
+
+        assert!(schedule.remove("physics"));
+        schedule.build().unwrap();
+    }
+
+    #[test]
+    fn cycles_name_the_systems_involved() {
+        let mut schedule = Schedule::empty();
+        schedule.add(System::new("a", Stage::PostUpdate, noop).after("c"));
+        schedule.add(System::new("b", Stage::PostUpdate, noop).after("a"));
+        schedule.add(System::new("c", Stage::PostUpdate, noop).after("b"));
+        schedule.add(System::new("free", Stage::PostUpdate, noop));
+
+        match schedule.build() {
+            Err(ScheduleError::Cycle(Stage::PostUpdate, names)) => {
+                assert_eq!(names, ["a", "b", "c"])
+            }
+            other => panic!("Expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn engine_systems_are_registered_in_order() {
+        let mut schedule = Schedule::default();
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::FixedUpdate), ["physics"]);
+        assert_eq!(
+            order(&schedule, Stage::Update),
+            ["timers", "navigation", "tweens", "animations"]
+        );
+        assert_eq!(order(&schedule, Stage::PostUpdate), ["camera_follow", "audio"]);
+        assert!(schedule.resources().get::<TimerSystem>().is_some());
+    }
+
+    #[test]
+    fn resources_hold_one_value_per_type() {
+        let mut resources = Resources::default();
+        assert_eq!(resources.insert(1u32), None);
+        assert_eq!(resources.insert(2u32), Some(1));
+        resources.insert("name");
+
+        *resources.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(resources.get::<u32>(), Some(&3));
+        assert_eq!(resources.remove::<&str>(), Some("name"));
+        assert_eq!(resources.get::<&str>(), None);
+    }
+}
@@ -1,66 +1,714 @@
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
 #[cfg(not(target_arch = "wasm32"))]
 use kira::{
     manager::{backend::DefaultBackend, AudioManager, AudioManagerSettings},
     sound::{
-        static_sound::{StaticSoundData, StaticSoundSettings},
-        streaming::{StreamingSoundData, StreamingSoundSettings},
+        static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings},
+        streaming::{StreamingSoundData, StreamingSoundHandle, StreamingSoundSettings},
+        FromFileError, PlaybackState,
     },
+    track::{TrackBuilder, TrackHandle},
+    tween::Tween,
 };
 
-use crate::environment::config::ThemeConfiguration;
+use crate::assets::assets::{Assets, Ptr};
+use crate::assets::audio::AudioClip;
+use crate::context::Context;
+use crate::environment::config::{AudioConfiguration, Config, ThemeConfiguration};
+use crate::event::{Event, EventSubscriber};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::random::RandomStream;
+use crate::random::Rng;
+use crate::utils::Timestep;
+
+//How AudioEngine::play starts a sound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayOptions {
+    //Amplitude, 1 is the volume of the sound.
+    pub volume: f32,
+    //0 is left, 0.5 center and 1 right.
+    pub panning: f32,
+    //Speed and pitch together, 2 plays an octave higher in half the time.
+    pub playback_rate: f32,
+    //Plays until stopped.
+    pub looped: bool,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions { volume: 1.0, panning: 0.5, playback_rate: 1.0, looped: false }
+    }
+}
+
+impl PlayOptions {
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
 
+    pub fn panning(mut self, panning: f32) -> Self {
+        self.panning = panning;
+        self
+    }
+
+    pub fn playback_rate(mut self, rate: f32) -> Self {
+        self.playback_rate = rate;
+        self
+    }
+
+    pub fn looped(mut self) -> Self {
+        self.looped = true;
+        self
+    }
+}
+
+//A sound started by AudioEngine::play. Dropping the handle doesn't stop the sound. Once the sound
+//is stopped or played to the end, everything but the state queries does nothing.
 #[cfg(not(target_arch = "wasm32"))]
-pub struct AudioEngine {
+pub struct SoundHandle {
+    handle: StaticSoundHandle,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SoundHandle {
+    //Amplitude, 1 is the volume of the clip.
+    pub fn set_volume(&mut self, volume: f32) {
+        let _ = self.handle.set_volume(kira::Volume::Amplitude(volume as f64), Tween::default());
+    }
+
+    //0 is left, 0.5 center and 1 right.
+    pub fn set_panning(&mut self, panning: f32) {
+        let _ = self.handle.set_panning(panning as f64, Tween::default());
+    }
+
+    pub fn set_playback_rate(&mut self, rate: f32) {
+        let rate = kira::sound::PlaybackRate::Factor(rate as f64);
+        let _ = self.handle.set_playback_rate(rate, Tween::default());
+    }
+
+    pub fn pause(&mut self) {
+        let _ = self.handle.pause(Tween::default());
+    }
+
+    pub fn resume(&mut self) {
+        let _ = self.handle.resume(Tween::default());
+    }
+
+    //Fades out over that many seconds, 0 cuts it off.
+    pub fn stop(&mut self, fade: f32) {
+        let tween =
+            Tween { duration: Duration::from_secs_f32(fade.max(0.0)), ..Default::default() };
+        let _ = self.handle.stop(tween);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.handle.state() == PlaybackState::Paused
+    }
+
+    //Played to the end or stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.handle.state() == PlaybackState::Stopped
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct SoundHandle {}
+
+#[cfg(target_arch = "wasm32")]
+impl SoundHandle {
+    pub fn set_volume(&mut self, _volume: f32) {}
+
+    pub fn set_panning(&mut self, _panning: f32) {}
+
+    pub fn set_playback_rate(&mut self, _rate: f32) {}
+
+    pub fn pause(&mut self) {}
+
+    pub fn resume(&mut self) {}
+
+    pub fn stop(&mut self, _fade: f32) {}
+
+    pub fn is_paused(&self) -> bool {
+        false
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        true
+    }
+}
+
+//Volume changes fade in over this long instead of clicking.
+#[cfg(not(target_arch = "wasm32"))]
+const VOLUME_FADE: Duration = Duration::from_millis(100);
+
+//Bus volumes from 0 to 1 are mapped to -60 dB to 0 dB, that feels linear on a slider. 0 is silent.
+#[cfg(not(target_arch = "wasm32"))]
+fn bus_volume(volume: f32) -> kira::Volume {
+    if volume <= 0.0 {
+        kira::Volume::Amplitude(0.0)
+    } else {
+        kira::Volume::Decibels(60.0 * (volume.min(1.0) as f64 - 1.0))
+    }
+}
+
+//The audio device and the buses on it.
+#[cfg(not(target_arch = "wasm32"))]
+struct Output {
     manager: kira::manager::AudioManager,
-    background_music: String,
-    //click_sound: StaticSoundData,
+    music: TrackHandle,
+    sfx: TrackHandle,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Output {
+    fn new(volumes: &AudioConfiguration) -> Result<Output, String> {
+        let mut manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
+            .map_err(|error| error.to_string())?;
+
+        let music = manager
+            .add_sub_track(
+                TrackBuilder::new()
+                    .volume(bus_volume(volumes.master_volume * volumes.music_volume)),
+            )
+            .map_err(|error| error.to_string())?;
+        let sfx = manager
+            .add_sub_track(
+                TrackBuilder::new().volume(bus_volume(volumes.master_volume * volumes.sfx_volume)),
+            )
+            .map_err(|error| error.to_string())?;
+
+        Ok(Output { manager, music, sfx })
+    }
+}
+
+//Plays the background music on the music bus and clips on the sfx bus. Both go through the master
+//volume. Sounds started later pick up the current volumes. Without an audio device everything
+//quietly does nothing, see is_available.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct AudioEngine {
+    output: Option<Output>,
+    playlist: Vec<String>,
+    shuffle: bool,
+    repeat: bool,
+    //Indices into the playlist in the order they play, reshuffled every time around.
+    order: Vec<usize>,
+    //Where in the order the playlist is. None while it isn't playing, e.g. after a crossfade.
+    track: Option<usize>,
+    folders: Vec<PathBuf>,
+    rng: RandomStream,
+    background: Option<StreamingSoundHandle<FromFileError>>,
+    //The track faded out by play_background_crossfade and the seconds left until it is silent.
+    fading: Option<(StreamingSoundHandle<FromFileError>, f32)>,
+    volumes: AudioConfiguration,
+    click_sound: Option<AudioClip>,
+    pause_on_focus_loss: bool,
+    //Everything is paused at once on the manager. Sounds the user paused or stopped keep their own
+    //state and stay that way on resume.
+    focus_paused: bool,
 }
 
 #[cfg(target_arch = "wasm32")]
-pub struct AudioEngine {}
+pub struct AudioEngine {
+    volumes: AudioConfiguration,
+}
 
 #[cfg(not(target_arch = "wasm32"))]
 impl AudioEngine {
-    pub fn new(theme_conf: &ThemeConfiguration) -> Self {
+    //Looks up the files of the theme in the themes folder only. The playlist is shuffled with a
+    //stream of rng, usually Context::rng.
+    pub fn new(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, rng: &Rng,
+    ) -> Self {
+        Self::open(theme_conf, audio_conf, vec![PathBuf::from("themes")], rng.derive("music"))
+    }
+
+    //The theme and volumes of the config, with the files of the project before the themes folder.
+    pub fn from_config(config: &Config, rng: &Rng) -> Self {
+        let folders = config.theme_folders();
+        Self::open(config.theme_config(), config.audio_config(), folders, rng.derive("music"))
+    }
+
+    //Never opens the audio device, e.g. for servers.
+    pub fn silent(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, rng: &Rng,
+    ) -> Self {
+        let volumes = Self::clamped(*audio_conf);
+        Self::with_output(theme_conf, volumes, None, Vec::new(), rng.derive("music"))
+    }
+
+    fn open(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, folders: Vec<PathBuf>,
+        rng: RandomStream,
+    ) -> Self {
+        Self::open_with(theme_conf, audio_conf, folders, rng, Output::new)
+    }
+
+    //connect opens the device, tests hand in one that fails.
+    fn open_with(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, folders: Vec<PathBuf>,
+        rng: RandomStream, connect: impl FnOnce(&AudioConfiguration) -> Result<Output, String>,
+    ) -> Self {
+        let volumes = Self::clamped(*audio_conf);
+
+        let output = match connect(&volumes) {
+            Ok(output) => Some(output),
+            Err(error) => {
+                log::warn!("No audio output, playing nothing. Error: {}", error);
+                None
+            }
+        };
+
+        Self::with_output(theme_conf, volumes, output, folders, rng)
+    }
+
+    fn with_output(
+        theme_conf: &ThemeConfiguration, volumes: AudioConfiguration, output: Option<Output>,
+        folders: Vec<PathBuf>, rng: RandomStream,
+    ) -> Self {
+        let click_sound = theme_conf.click_sound.as_ref().and_then(|click| {
+            AudioClip::from_file(&Self::resolve(&folders, click).to_string_lossy())
+                .map_err(|error| log::error!("{}", error))
+                .ok()
+        });
+
         AudioEngine {
-            manager: AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).unwrap(),
-            background_music: theme_conf.background_music.clone(),
+            output,
+            playlist: theme_conf.playlist.clone(),
+            shuffle: theme_conf.shuffle,
+            repeat: theme_conf.repeat,
+            order: Vec::new(),
+            track: None,
+            folders,
+            rng,
+            background: None,
+            fading: None,
+            click_sound,
+            pause_on_focus_loss: false,
+            focus_paused: false,
+            volumes,
+        }
+    }
+
+    //False without an audio device, e.g. to hide the volume settings.
+    pub fn is_available(&self) -> bool {
+        self.output.is_some()
+    }
+
+    //The first folder that has the file. Missing files resolve against the last folder, so the
+    //error names the themes folder.
+    fn resolve(folders: &[PathBuf], file: &str) -> PathBuf {
+        folders
+            .iter()
+            .map(|folder| folder.join(file))
+            .find(|path| path.is_file())
+            .or_else(|| folders.last().map(|folder| folder.join(file)))
+            .unwrap_or_else(|| PathBuf::from(file))
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.volumes.master_volume = volume.clamp(0.0, 1.0);
+        self.apply_volumes();
+    }
+
+    pub fn set_music_volume(&mut self, volume: f32) {
+        self.volumes.music_volume = volume.clamp(0.0, 1.0);
+        self.apply_volumes();
+    }
+
+    pub fn set_sfx_volume(&mut self, volume: f32) {
+        self.volumes.sfx_volume = volume.clamp(0.0, 1.0);
+        self.apply_volumes();
+    }
+
+    //Off by default. Turning it off while the window is in the background resumes right away.
+    pub fn set_pause_on_focus_loss(&mut self, pause: bool) {
+        self.pause_on_focus_loss = pause;
+
+        if !pause {
+            self.set_focused(true);
+        }
+    }
+
+    //Called with Event::Focused, if the AudioEngine is subscribed that happens by itself.
+    pub fn set_focused(&mut self, focused: bool) {
+        let Some(output) = self.output.as_mut() else {
+            return;
+        };
+
+        let tween = Tween { duration: VOLUME_FADE, ..Default::default() };
+
+        if focused && self.focus_paused {
+            let _ = output.manager.resume(tween);
+            self.focus_paused = false;
+        } else if !focused && self.pause_on_focus_loss && !self.focus_paused {
+            let _ = output.manager.pause(tween);
+            self.focus_paused = true;
+        }
+    }
+
+    //Hand it to Config::set_audio_config to keep the volumes.
+    pub fn configuration(&self) -> AudioConfiguration {
+        self.volumes
+    }
+
+    fn clamped(volumes: AudioConfiguration) -> AudioConfiguration {
+        AudioConfiguration {
+            master_volume: volumes.master_volume.clamp(0.0, 1.0),
+            music_volume: volumes.music_volume.clamp(0.0, 1.0),
+            sfx_volume: volumes.sfx_volume.clamp(0.0, 1.0),
         }
     }
 
+    fn apply_volumes(&mut self) {
+        let Some(output) = self.output.as_mut() else {
+            return;
+        };
+
+        let tween = Tween { duration: VOLUME_FADE, ..Default::default() };
+        let master = self.volumes.master_volume;
+
+        let _ = output.music.set_volume(bus_volume(master * self.volumes.music_volume), tween);
+        let _ = output.sfx.set_volume(bus_volume(master * self.volumes.sfx_volume), tween);
+    }
+
+    //The click sound of the theme, if it has one.
     pub fn play_click(&mut self) {
-        //let mut sound = self.manager.play(self.click_sound.clone()).unwrap();
-        //let _ = sound.set_volume(0.1, kira::tween::Tween::default());
+        if let Some(click) = self.click_sound.as_ref().map(|click| click.data().clone()) {
+            self.play(click, PlayOptions::default().volume(0.3));
+        }
     }
 
+    //Plays the playlist from the start, over whatever music is playing. update moves on to the
+    //next track once one ended.
     pub fn play_background(&mut self) {
-        let sound_data_res = StreamingSoundData::from_file(
-            format!("themes/{}", self.background_music),
-            StreamingSoundSettings::new().loop_region(0.0..),
-        );
+        self.track = None;
+        self.order.clear();
+        self.next_track();
+    }
+
+    //Skips to the next track of the playlist. After the last one it starts over or goes silent,
+    //depending on repeat. After a crossfade it picks the playlist up again from the start.
+    pub fn next_track(&mut self) {
+        self.stop_fading();
+
+        if let Some(mut background) = self.background.take() {
+            let _ = background.stop(Tween::default());
+        }
+
+        //Tracks that can't be played are skipped, but every one is tried at most once.
+        for _ in 0..self.playlist.len() {
+            let next = self.track.map_or(0, |track| track + 1);
+
+            if next >= self.order.len() {
+                if !self.order.is_empty() && !self.repeat {
+                    self.track = None;
+                    return;
+                }
+
+                self.reorder();
+                self.track = Some(0);
+            } else {
+                self.track = Some(next);
+            }
+
+            if self.play_track() {
+                return;
+            }
+        }
+
+        self.track = None;
+    }
+
+    fn reorder(&mut self) {
+        self.order = (0..self.playlist.len()).collect();
+
+        if self.shuffle {
+            self.rng.shuffle(&mut self.order);
+        }
+    }
+
+    fn play_track(&mut self) -> bool {
+        let Some(output) = self.output.as_mut() else {
+            return false;
+        };
+
+        let Some(&index) = self.track.and_then(|track| self.order.get(track)) else {
+            return false;
+        };
+
+        let path = Self::resolve(&self.folders, &self.playlist[index]);
+        let mut settings = StreamingSoundSettings::new().output_destination(&output.music);
+
+        //A single track loops seamlessly instead of restarting through update.
+        if self.playlist.len() == 1 && self.repeat {
+            settings = settings.loop_region(0.0..);
+        }
+
+        let sound_data = match StreamingSoundData::from_file(&path, settings) {
+            Ok(sound_data) => sound_data,
+            Err(error) => {
+                log::error!("Could not load background music {}. Error: {}", path.display(), error);
+                return false;
+            }
+        };
+
+        match output.manager.play(sound_data) {
+            Ok(background) => {
+                self.background = Some(background);
+                true
+            }
+            Err(error) => {
+                log::error!("Could not play background music {}. Error: {}", path.display(), error);
+                false
+            }
+        }
+    }
+
+    //Fades the background music out over fade seconds while the file fades in, e.g. when the level
+    //changes. Starting another crossfade before it is done cuts the track that is fading out, so
+    //there are never more than two playing. Keeps playing the current music if the file can't be
+    //loaded.
+    pub fn play_background_crossfade(&mut self, path: impl AsRef<Path>, fade: f32) {
+        let Some(output) = self.output.as_mut() else {
+            return;
+        };
+
+        let path = path.as_ref();
+        let fade = fade.max(0.0);
+
+        let settings = StreamingSoundSettings::new()
+            .loop_region(0.0..)
+            .volume(kira::Volume::Amplitude(0.0))
+            .output_destination(&output.music);
+
+        let sound_data = match StreamingSoundData::from_file(path, settings) {
+            Ok(sound_data) => sound_data,
+            Err(error) => {
+                log::error!("Could not load background music {}. Error: {}", path.display(), error);
+                return;
+            }
+        };
+
+        let mut next = match output.manager.play(sound_data) {
+            Ok(next) => next,
+            Err(error) => {
+                log::error!("Could not play background music {}. Error: {}", path.display(), error);
+                return;
+            }
+        };
+
+        let tween = Tween { duration: Duration::from_secs_f32(fade), ..Default::default() };
+        let _ = next.set_volume(kira::Volume::Amplitude(1.0), tween);
+
+        self.stop_fading();
+
+        self.track = None;
+
+        //kira stops it once the fade is done, update only forgets the handle then.
+        if let Some(mut current) = self.background.replace(next) {
+            let _ = current.stop(tween);
+            self.fading = Some((current, fade));
+        }
+    }
+
+    //Call it once per frame, it finishes crossfades and moves the playlist along.
+    pub fn update(&mut self, delta: &Timestep) {
+        if let Some((_, remaining)) = self.fading.as_mut() {
+            *remaining -= delta.seconds() as f32;
+
+            if *remaining <= 0.0 {
+                self.fading = None;
+            }
+        }
+
+        let ended = self
+            .background
+            .as_ref()
+            .is_some_and(|background| background.state() == PlaybackState::Stopped);
+
+        if ended && self.track.is_some() {
+            self.next_track();
+        }
+    }
+
+    fn stop_fading(&mut self) {
+        if let Some((mut fading, _)) = self.fading.take() {
+            let _ = fading.stop(Tween::default());
+        }
+    }
 
-        if let Ok(sound_data) = sound_data_res {
-            let mut sound = self.manager.play(sound_data).unwrap();
-            let _ = sound.set_volume(kira::Volume::Decibels(-20.0), kira::tween::Tween::default());
-        } else {
-            log::error!("Could not load background music {}. Silence.", self.background_music);
+    //Plays on the sfx bus. None if kira has no room for another sound.
+    pub fn play(&mut self, data: StaticSoundData, options: PlayOptions) -> Option<SoundHandle> {
+        let output = self.output.as_mut()?;
+
+        let mut settings = StaticSoundSettings::new()
+            .volume(kira::Volume::Amplitude(options.volume as f64))
+            .panning(options.panning as f64)
+            .playback_rate(kira::sound::PlaybackRate::Factor(options.playback_rate as f64))
+            .output_destination(&output.sfx);
+
+        if options.looped {
+            settings = settings.loop_region(0.0..);
+        }
+
+        match output.manager.play(data.with_settings(settings)) {
+            Ok(handle) => Some(SoundHandle { handle }),
+            Err(error) => {
+                log::error!("Could not play sound. Error: {}", error);
+                None
+            }
         }
     }
+
+    pub fn play_clip(&mut self, clip: &AudioClip, options: PlayOptions) -> Option<SoundHandle> {
+        self.play(clip.data().clone(), options)
+    }
+
+    //Nothing happens while the clip is still loading.
+    pub fn play_asset(
+        &mut self, assets: &Assets, clip: &Ptr<AudioClip>, options: PlayOptions,
+    ) -> Option<SoundHandle> {
+        assets.try_get(clip).and_then(|clip| self.play_clip(clip, options))
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
 impl AudioEngine {
-    pub fn new(theme_conf: &ThemeConfiguration) -> Self {
-        AudioEngine {}
+    pub fn new(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, _rng: &Rng,
+    ) -> Self {
+        AudioEngine { volumes: *audio_conf }
+    }
+
+    pub fn from_config(config: &Config, rng: &Rng) -> Self {
+        Self::new(config.theme_config(), config.audio_config(), rng)
+    }
+
+    pub fn silent(
+        theme_conf: &ThemeConfiguration, audio_conf: &AudioConfiguration, rng: &Rng,
+    ) -> Self {
+        Self::new(theme_conf, audio_conf, rng)
+    }
+
+    pub fn is_available(&self) -> bool {
+        false
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.volumes.master_volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn set_music_volume(&mut self, volume: f32) {
+        self.volumes.music_volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn set_sfx_volume(&mut self, volume: f32) {
+        self.volumes.sfx_volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn configuration(&self) -> AudioConfiguration {
+        self.volumes
     }
 
     pub fn play_click(&mut self) {}
 
     pub fn play_background(&mut self) {}
+
+    pub fn next_track(&mut self) {}
+
+    pub fn play_background_crossfade(&mut self, _path: impl AsRef<Path>, _fade: f32) {}
+
+    pub fn set_pause_on_focus_loss(&mut self, _pause: bool) {}
+
+    pub fn set_focused(&mut self, _focused: bool) {}
+
+    pub fn update(&mut self, _delta: &Timestep) {}
+
+    pub fn play_clip(&mut self, _clip: &AudioClip, _options: PlayOptions) -> Option<SoundHandle> {
+        None
+    }
+
+    pub fn play_asset(
+        &mut self, _assets: &Assets, _clip: &Ptr<AudioClip>, _options: PlayOptions,
+    ) -> Option<SoundHandle> {
+        None
+    }
 }
 
+impl EventSubscriber for AudioEngine {
+    fn on_event(&mut self, event: &Event, _context: &mut Context) -> bool {
+        if let Event::Focused(focused) = event {
+            self.set_focused(*focused);
+        }
+
+        false
+    }
+}
+
+//Music picks come from an unseeded stream, use from_config for reproducible ones.
 impl Default for AudioEngine {
     fn default() -> Self {
-        Self::new(&ThemeConfiguration::default())
+        let rng = Rng::from_seed(None);
+        Self::new(&ThemeConfiguration::default(), &AudioConfiguration::default(), &rng)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    fn without_device() -> AudioEngine {
+        let theme = ThemeConfiguration {
+            playlist: vec!["missing.ogg".to_string(), "missing_too.ogg".to_string()],
+            ..Default::default()
+        };
+
+        let rng = Rng::new(7).derive("music");
+
+        AudioEngine::open_with(&theme, &AudioConfiguration::default(), Vec::new(), rng, |_| {
+            Err("No output device available".to_string())
+        })
+    }
+
+    #[test]
+    fn failing_backend_leaves_a_silent_engine() {
+        let engine = without_device();
+
+        assert!(!engine.is_available());
+    }
+
+    #[test]
+    fn playback_is_a_no_op_without_device() {
+        let mut engine = without_device();
+
+        engine.play_click();
+        engine.play_background();
+        engine.next_track();
+        engine.play_background_crossfade("missing.ogg", 1.0);
+        engine.update(&Timestep::from(16.0));
+
+        assert!(engine.background.is_none());
+        assert!(engine.fading.is_none());
+        assert_eq!(engine.track, None);
+    }
+
+    #[test]
+    fn volumes_are_kept_without_device() {
+        let mut engine = without_device();
+        engine.set_pause_on_focus_loss(true);
+        engine.set_focused(false);
+
+        engine.set_master_volume(0.5);
+        engine.set_music_volume(2.0);
+        engine.set_sfx_volume(-1.0);
+
+        let volumes = engine.configuration();
+        assert_eq!(volumes.master_volume, 0.5);
+        assert_eq!(volumes.music_volume, 1.0);
+        assert_eq!(volumes.sfx_volume, 0.0);
+        assert!(!engine.focus_paused);
     }
 }
@@ -5,8 +5,15 @@ use std::path::{Path, PathBuf};
 
 use instant::Instant;
 
+use crate::random::RandomStream;
+
+//Default upper bound for a single frame delta in ms.
+pub const MAX_DELTA: f64 = 100.0;
+
 pub struct Timestep {
     delta: f64,
+    raw: f64,
+    max_delta: f64,
     last: Instant,
     begin: Instant,
 }
@@ -15,17 +22,37 @@ impl Default for Timestep {
     fn default() -> Self {
         let begin = Instant::now();
 
-        Timestep { delta: 0.0, last: begin, begin }
+        Timestep { delta: 0.0, raw: 0.0, max_delta: MAX_DELTA, last: begin, begin }
     }
 }
 
 impl Timestep {
+    //Longer frames (window drags, breakpoints) are cut down to max_delta ms.
+    pub fn with_max_delta(mut self, max_delta: f64) -> Self {
+        self.max_delta = max_delta;
+        self
+    }
+
     pub fn step_fwd(&mut self) -> &mut Self {
-        self.delta = self.last.elapsed().as_nanos() as f64 / 1000000.0;
+        self.raw = self.last.elapsed().as_nanos() as f64 / 1000000.0;
+        self.delta = self.raw.min(self.max_delta);
         self.last = Instant::now();
         self
     }
 
+    //The real wall time of the last frame in ms, not clamped.
+    pub fn raw_millis(&self) -> f64 {
+        self.raw
+    }
+
+    pub fn max_delta(&self) -> f64 {
+        self.max_delta
+    }
+
+    pub fn clamped(&self) -> bool {
+        self.raw > self.delta
+    }
+
     pub fn norm(&self) -> f32 {
         (self.delta / 10.0) as f32
     }
@@ -51,7 +78,7 @@ impl From<f64> for Timestep {
     fn from(delta: f64) -> Timestep {
         let begin = Instant::now();
 
-        Timestep { delta, last: begin, begin }
+        Timestep { delta, raw: delta, max_delta: MAX_DELTA, last: begin, begin }
     }
 }
 
@@ -85,6 +112,40 @@ impl ops::DivAssign<f64> for Timestep {
     }
 }
 
+//Accumulates frame time and hands out fixed steps. Fed with the clamped delta,
+//so a hitch never queues more than max_delta worth of substeps.
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    //step in ms.
+    pub fn new(step: f64) -> Self {
+        FixedTimestep { step, accumulator: 0.0 }
+    }
+
+    //Returns how many fixed steps to run this frame.
+    pub fn advance(&mut self, delta: &Timestep) -> u32 {
+        let max_steps = (delta.max_delta() / self.step).ceil().max(1.0);
+
+        self.accumulator += delta.millis();
+        let steps = (self.accumulator / self.step).floor().min(max_steps);
+        self.accumulator = (self.accumulator - steps * self.step).min(self.step);
+
+        steps as u32
+    }
+
+    pub fn step(&self) -> Timestep {
+        Timestep::from(self.step)
+    }
+
+    //How far we are into the next step, for interpolating between states.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+}
+
 pub struct FileUtils {}
 
 impl FileUtils {
@@ -130,9 +191,23 @@ impl FileUtils {
 
         file.extension().and_then(|s| s.to_str()).is_some_and(|extension| extension.eq(ext))
     }
+
+    //The part of path below root. A file as root means its directory. Both are canonicalized, so
+    //an absolute path (e.g. dropped by winit) resolves against a relative project location.
+    pub fn relative_to(path: &Path, root: &Path) -> Option<PathBuf> {
+        let root = if root.is_file() { root.parent()? } else { root };
+
+        if let Ok(relative) = path.strip_prefix(root) {
+            return Some(relative.to_path_buf());
+        }
+
+        let path = path.canonicalize().ok()?;
+        let root = root.canonicalize().ok()?;
+        path.strip_prefix(root).ok().map(Path::to_path_buf)
+    }
 }
 
-#[derive(Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, serde::Serialize)]
 pub struct Guid {
     id: u64,
 }
@@ -151,23 +226,58 @@ impl Guid {
     }
 }
 
-#[derive(Default)]
 pub struct GuidGenerator {
     used: HashSet<u64>,
+    stream: RandomStream,
 }
 
 impl GuidGenerator {
-    pub fn new() -> GuidGenerator {
-        Default::default()
+    //Derive the stream from the engine Rng (see Rng::derive) to get the same ids in every replay.
+    pub fn with_stream(stream: RandomStream) -> GuidGenerator {
+        GuidGenerator { used: HashSet::new(), stream }
     }
 
     pub fn generate(&mut self) -> Guid {
-        let mut id = rand::random::<u64>();
+        let mut id = self.stream.next_u64();
         const RESERVED_IDS: u64 = 10;
-        while self.used.contains(&id) && id < RESERVED_IDS {
-            id = rand::random::<u64>();
+        while self.used.contains(&id) || id < RESERVED_IDS {
+            id = self.stream.next_u64();
         }
         self.used.insert(id);
         Guid::new(id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_path_resolves_against_relative_root() {
+        let absolute = Path::new("src/utils.rs").canonicalize().unwrap();
+
+        assert_eq!(FileUtils::relative_to(&absolute, Path::new("src")), Some("utils.rs".into()));
+        assert_eq!(FileUtils::relative_to(&absolute, Path::new("./src/")), Some("utils.rs".into()));
+    }
+
+    #[test]
+    fn file_root_resolves_against_its_directory() {
+        let dir = std::env::temp_dir().join(format!("rustybear_relative_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        std::fs::write(dir.join("project.json"), "{}").unwrap();
+        std::fs::write(dir.join("textures/tiles.fur"), "").unwrap();
+
+        let dropped = dir.join("textures/tiles.fur");
+        let relative = FileUtils::relative_to(&dropped, &dir.join("textures/../project.json"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(relative, Some(PathBuf::from("textures/tiles.fur")));
+    }
+
+    #[test]
+    fn path_outside_root_is_none() {
+        let absolute = Path::new("Cargo.toml").canonicalize().unwrap();
+
+        assert_eq!(FileUtils::relative_to(&absolute, Path::new("src")), None);
+    }
+}
@@ -18,6 +18,24 @@ pub struct WindowConfig {
     pub border: bool,
 }
 
+impl WindowConfig {
+    pub fn from_window(window: &winit::window::Window) -> Self {
+        let size = window.inner_size();
+        let position =
+            window.outer_position().map(|p| (p.x as f64, p.y as f64)).unwrap_or_default();
+
+        WindowConfig {
+            size: (size.width, size.height),
+            title: window.title(),
+            position,
+            resizeable: window.is_resizable(),
+            fullscreen: window.fullscreen().is_some(),
+            visible: true,
+            border: window.is_decorated(),
+        }
+    }
+}
+
 impl Default for WindowConfig {
     fn default() -> Self {
         WindowConfig {
@@ -91,7 +109,7 @@ impl Window {
         Window { native: Arc::new(window), event_loop }
     }
 
-    fn toggle_fullscreen(window: &winit::window::Window) {
+    pub fn toggle_fullscreen(window: &winit::window::Window) {
         if window.fullscreen().is_some() {
             window.set_fullscreen(None);
         } else {